@@ -0,0 +1,365 @@
+//! AES coprocessor
+//!
+//! AES-128 and AES-256 in ECB, CBC and CTR mode on STM32F423, blocking or streamed by DMA.
+//! Parts with the CRYP processor use the `cryp` module instead, which also has GCM, CCM and the
+//! `rustcrypto` traits.
+//!
+//! ```rust,ignore
+//! let mut aes = Aes::new(dp.AES);
+//! let mut session = aes.start(Mode::Cbc, Direction::Encrypt, &key, &iv)?;
+//! session.process_in_place(&mut buffer)?;
+//! ```
+
+use core::marker::PhantomData;
+
+use vcell::VolatileCell;
+
+use crate::dma::traits::{DMASet, PeriAddress, AES_IN, AES_OUT};
+use crate::dma::{MemoryToPeripheral, PeripheralToMemory};
+use crate::pac::AES;
+use crate::rcc::{Enable, Reset};
+
+/// Chaining mode
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Ecb = 0b00,
+    Cbc = 0b01,
+    Ctr = 0b10,
+}
+
+impl Mode {
+    /// IV length in bytes, 0 for ECB
+    pub const fn iv_size(self) -> usize {
+        match self {
+            Self::Ecb => 0,
+            Self::Cbc | Self::Ctr => 16,
+        }
+    }
+}
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Encrypt,
+    Decrypt,
+}
+
+/// AES error
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The key is neither 16 nor 32 bytes long
+    KeySize,
+    /// The IV length does not match the mode
+    IvSize,
+    /// The data is not a whole number of blocks, or input and output differ in length
+    DataSize,
+}
+
+/// Value of `KEYSIZE` and the key registers `KEYR0` to `KEYR7` for `key`.
+///
+/// The first bytes of the key go into the highest register: `KEYR3` for AES-128, `KEYR7` for
+/// AES-256.
+pub fn pack_key(key: &[u8]) -> Result<(bool, [u32; 8]), Error> {
+    let keysize = match key.len() {
+        16 => false,
+        32 => true,
+        _ => return Err(Error::KeySize),
+    };
+    let mut words = [0; 8];
+    for (word, bytes) in words[..key.len() / 4]
+        .iter_mut()
+        .rev()
+        .zip(key.chunks_exact(4))
+    {
+        *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    Ok((keysize, words))
+}
+
+/// Initialization vector registers `IVR0` to `IVR3` for `iv`, the first bytes go into `IVR3`.
+pub fn pack_iv(iv: &[u8; 16]) -> [u32; 4] {
+    let mut words = [0; 4];
+    for (word, bytes) in words.iter_mut().rev().zip(iv.chunks_exact(4)) {
+        *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    words
+}
+
+/// Data register words for a block, with the AES swapping bytes (`DATATYPE` = 8 bit).
+pub fn pack_block(block: &[u8]) -> [u32; 4] {
+    let mut words = [0; 4];
+    for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    words
+}
+
+// CR
+const CR_EN: u32 = 1 << 0;
+const CR_DATATYPE_8: u32 = 0b10 << 1;
+const CR_MODE_KEY_DERIVATION: u32 = 0b01 << 3;
+const CR_MODE_DECRYPT: u32 = 0b10 << 3;
+const CR_CHMOD_SHIFT: u32 = 5;
+const CR_CCFC: u32 = 1 << 7;
+const CR_DMAINEN: u32 = 1 << 11;
+const CR_DMAOUTEN: u32 = 1 << 12;
+const CR_KEYSIZE: u32 = 1 << 18;
+// SR
+const SR_CCF: u32 = 1 << 0;
+
+#[repr(C)]
+struct Registers {
+    cr: VolatileCell<u32>,
+    sr: VolatileCell<u32>,
+    dinr: VolatileCell<u32>,
+    doutr: VolatileCell<u32>,
+    keyr: [VolatileCell<u32>; 4],
+    ivr: [VolatileCell<u32>; 4],
+    keyr_high: [VolatileCell<u32>; 4],
+}
+
+impl Registers {
+    fn modify_cr(&self, f: impl FnOnce(u32) -> u32) {
+        self.cr.set(f(self.cr.get()));
+    }
+
+    /// Waits for the computation to complete and clears the flag.
+    fn wait_complete(&self) {
+        while self.sr.get() & SR_CCF == 0 {}
+        self.modify_cr(|r| r | CR_CCFC);
+    }
+}
+
+/// AES coprocessor
+pub struct Aes {
+    aes: AES,
+}
+
+impl Aes {
+    pub fn new(aes: AES) -> Self {
+        unsafe {
+            AES::enable_unchecked();
+            AES::reset_unchecked();
+        }
+        Self { aes }
+    }
+
+    pub fn release(self) -> AES {
+        self.regs().cr.set(0);
+        self.aes
+    }
+
+    fn regs(&self) -> &'static Registers {
+        // NOTE(unsafe) the AES registers are owned by `self`
+        unsafe { &*(AES::ptr() as *const Registers) }
+    }
+
+    /// Loads `key`, `iv` and the mode and returns a session processing whole blocks.
+    ///
+    /// `iv` is empty in ECB mode. For CTR mode, it holds the nonce and the initial counter.
+    pub fn start(
+        &mut self,
+        mode: Mode,
+        direction: Direction,
+        key: &[u8],
+        iv: &[u8],
+    ) -> Result<Session<'_>, Error> {
+        let (keysize, key) = pack_key(key)?;
+        if iv.len() != mode.iv_size() {
+            return Err(Error::IvSize);
+        }
+        let keysize = if keysize { CR_KEYSIZE } else { 0 };
+        let regs = self.regs();
+
+        // ECB and CBC decryption need the last round key, CTR decrypts by encrypting
+        let decrypt = direction == Direction::Decrypt && mode != Mode::Ctr;
+        if decrypt {
+            regs.cr.set(keysize | CR_MODE_KEY_DERIVATION);
+            self.load_key(&key);
+            regs.modify_cr(|r| r | CR_EN);
+            regs.wait_complete();
+            regs.modify_cr(|r| r & !CR_EN);
+        }
+
+        let mode_bits = if decrypt { CR_MODE_DECRYPT } else { 0 };
+        regs.cr
+            .set(keysize | ((mode as u32) << CR_CHMOD_SHIFT) | CR_DATATYPE_8 | mode_bits);
+        if !decrypt {
+            self.load_key(&key);
+        }
+        if let Ok(iv) = iv.try_into() {
+            for (reg, word) in regs.ivr.iter().zip(pack_iv(iv)) {
+                reg.set(word);
+            }
+        }
+        regs.modify_cr(|r| r | CR_EN);
+        Ok(Session { aes: self })
+    }
+
+    fn load_key(&mut self, key: &[u32; 8]) {
+        let regs = self.regs();
+        for (reg, word) in regs.keyr.iter().chain(&regs.keyr_high).zip(key) {
+            reg.set(*word);
+        }
+    }
+
+    fn process_block(&mut self, input: &[u8], output: &mut [u8]) {
+        let regs = self.regs();
+        for word in pack_block(input) {
+            regs.dinr.set(word);
+        }
+        regs.wait_complete();
+        for bytes in output.chunks_exact_mut(4) {
+            bytes.copy_from_slice(&regs.doutr.get().to_le_bytes());
+        }
+    }
+}
+
+/// Running ECB, CBC or CTR operation
+pub struct Session<'a> {
+    aes: &'a mut Aes,
+}
+
+impl<'a> Session<'a> {
+    /// Processes whole blocks from `input` into `output`.
+    pub fn process(&mut self, input: &[u8], output: &mut [u8]) -> Result<(), Error> {
+        if input.len() != output.len() || input.len() % 16 != 0 {
+            return Err(Error::DataSize);
+        }
+        for (i, o) in input.chunks_exact(16).zip(output.chunks_exact_mut(16)) {
+            self.aes.process_block(i, o);
+        }
+        Ok(())
+    }
+
+    /// Processes whole blocks in place.
+    pub fn process_in_place(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        if buffer.len() % 16 != 0 {
+            return Err(Error::DataSize);
+        }
+        let mut block = [0; 16];
+        for chunk in buffer.chunks_exact_mut(16) {
+            block.copy_from_slice(chunk);
+            self.aes.process_block(&block, chunk);
+        }
+        Ok(())
+    }
+
+    /// Hands the data registers to DMA.
+    ///
+    /// Use [`DmaIn`] with a memory to peripheral and [`DmaOut`] with a peripheral to memory
+    /// [`Transfer`](crate::dma::Transfer) of 32-bit words. The output transfer should be started
+    /// first. Dropping [`DmaOut`] ends the session.
+    pub fn into_dma(self) -> (DmaIn<'a>, DmaOut<'a>) {
+        self.aes.regs().modify_cr(|r| r | CR_DMAINEN | CR_DMAOUTEN);
+        // The session stays enabled until `DmaOut` is dropped
+        core::mem::forget(self);
+        (DmaIn { _aes: PhantomData }, DmaOut { _aes: PhantomData })
+    }
+}
+
+impl Drop for Session<'_> {
+    fn drop(&mut self) {
+        self.aes.regs().modify_cr(|r| r & !CR_EN);
+    }
+}
+
+/// Input of a session streamed by DMA
+pub struct DmaIn<'a> {
+    _aes: PhantomData<&'a mut Aes>,
+}
+
+/// Output of a session streamed by DMA
+pub struct DmaOut<'a> {
+    _aes: PhantomData<&'a mut Aes>,
+}
+
+impl Drop for DmaOut<'_> {
+    fn drop(&mut self) {
+        // NOTE(unsafe) the session is borrowed for 'a
+        let regs = unsafe { &*(AES::ptr() as *const Registers) };
+        regs.modify_cr(|r| r & !(CR_DMAINEN | CR_DMAOUTEN | CR_EN));
+    }
+}
+
+unsafe impl PeriAddress for DmaIn<'_> {
+    #[inline(always)]
+    fn address(&self) -> u32 {
+        unsafe { (*(AES::ptr() as *const Registers)).dinr.as_ptr() as u32 }
+    }
+
+    type MemSize = u32;
+}
+
+unsafe impl PeriAddress for DmaOut<'_> {
+    #[inline(always)]
+    fn address(&self) -> u32 {
+        unsafe { (*(AES::ptr() as *const Registers)).doutr.as_ptr() as u32 }
+    }
+
+    type MemSize = u32;
+}
+
+unsafe impl<STREAM, const CHANNEL: u8> DMASet<STREAM, CHANNEL, MemoryToPeripheral> for DmaIn<'_> where
+    AES_IN: DMASet<STREAM, CHANNEL, MemoryToPeripheral>
+{
+}
+
+unsafe impl<STREAM, const CHANNEL: u8> DMASet<STREAM, CHANNEL, PeripheralToMemory> for DmaOut<'_> where
+    AES_OUT: DMASet<STREAM, CHANNEL, PeripheralToMemory>
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_256: [u8; 32] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d,
+        0x1e, 0x1f,
+    ];
+
+    #[test]
+    fn keys() {
+        // FIPS-197 appendix C
+        assert_eq!(
+            pack_key(&KEY_256[..16]),
+            Ok((
+                false,
+                [0x0c0d0e0f, 0x08090a0b, 0x04050607, 0x00010203, 0, 0, 0, 0]
+            ))
+        );
+        assert_eq!(
+            pack_key(&KEY_256),
+            Ok((
+                true,
+                [
+                    0x1c1d1e1f, 0x18191a1b, 0x14151617, 0x10111213, 0x0c0d0e0f, 0x08090a0b,
+                    0x04050607, 0x00010203
+                ]
+            ))
+        );
+        assert_eq!(pack_key(&KEY_256[..24]), Err(Error::KeySize));
+        assert_eq!(pack_key(&[]), Err(Error::KeySize));
+    }
+
+    #[test]
+    fn iv_and_blocks() {
+        // SP 800-38A F.5.1 initial counter block
+        let ctr = [
+            0xf0, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa, 0xfb, 0xfc, 0xfd,
+            0xfe, 0xff,
+        ];
+        assert_eq!(
+            pack_iv(&ctr),
+            [0xfcfdfeff, 0xf8f9fafb, 0xf4f5f6f7, 0xf0f1f2f3]
+        );
+        assert_eq!(
+            pack_block(&KEY_256[..16]),
+            [0x03020100, 0x07060504, 0x0b0a0908, 0x0f0e0d0c]
+        );
+    }
+}
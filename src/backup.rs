@@ -0,0 +1,243 @@
+//! Backup registers and backup SRAM
+//!
+//! The 20 RTC backup registers and the 4 KB backup SRAM keep their contents in Stop and Standby
+//! modes and, when VBAT is connected, while VDD is off. A backup domain reset, as done by
+//! [`Rtc::new`](crate::rtc::Rtc::new), clears the backup registers.
+//!
+//! ```rust,ignore
+//! let mut bkp = BackupRegisters::new(&mut dp.PWR);
+//! let boots = bkp.read(BackupRegister::Bkp0);
+//! bkp.write(BackupRegister::Bkp0, boots + 1);
+//!
+//! let mut sram = BackupSram::new(&mut dp.PWR).unwrap();
+//! if !sram.is_retained(0xB007_0001) {
+//!     // First start or VBAT was lost
+//! }
+//! let state: &mut State = sram.get_or_init(0xB007_0001, State::default);
+//!
+//! #[derive(Clone, Copy, Default)]
+//! #[repr(C)]
+//! struct State {
+//!     boots: u32,
+//!     energy: [u16; 2],
+//! }
+//! // NOTE(unsafe) only integers, without padding
+//! unsafe impl BackupData for State {}
+//! ```
+
+use crate::pac::{PWR, RTC};
+use crate::rcc::Enable;
+
+/// Offset of RTC_BKP0R
+const BKPR_OFFSET: usize = 0x50;
+
+/// RTC backup register
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum BackupRegister {
+    Bkp0 = 0,
+    Bkp1 = 1,
+    Bkp2 = 2,
+    Bkp3 = 3,
+    Bkp4 = 4,
+    Bkp5 = 5,
+    Bkp6 = 6,
+    Bkp7 = 7,
+    Bkp8 = 8,
+    Bkp9 = 9,
+    Bkp10 = 10,
+    Bkp11 = 11,
+    Bkp12 = 12,
+    Bkp13 = 13,
+    Bkp14 = 14,
+    Bkp15 = 15,
+    Bkp16 = 16,
+    Bkp17 = 17,
+    Bkp18 = 18,
+    Bkp19 = 19,
+}
+
+/// Enables the PWR clock and write access to the backup domain.
+fn unlock(pwr: &mut PWR) {
+    unsafe {
+        PWR::enable_unchecked();
+    }
+    pwr.cr.modify(|_, w| w.dbp().set_bit());
+}
+
+/// Access to the RTC backup registers
+///
+/// The registers are part of the RTC, but do not depend on its clock, so they can be used with
+/// or without [`Rtc`](crate::rtc::Rtc).
+pub struct BackupRegisters {
+    _private: (),
+}
+
+impl BackupRegisters {
+    /// Enables write access to the backup domain.
+    pub fn new(pwr: &mut PWR) -> Self {
+        unlock(pwr);
+        Self { _private: () }
+    }
+
+    fn ptr(reg: BackupRegister) -> *mut u32 {
+        unsafe { (RTC::ptr() as *mut u8).add(BKPR_OFFSET + 4 * reg as usize) as *mut u32 }
+    }
+
+    pub fn read(&self, reg: BackupRegister) -> u32 {
+        unsafe { core::ptr::read_volatile(Self::ptr(reg)) }
+    }
+
+    pub fn write(&mut self, reg: BackupRegister, value: u32) {
+        unsafe { core::ptr::write_volatile(Self::ptr(reg), value) }
+    }
+
+    pub fn modify(&mut self, reg: BackupRegister, f: impl FnOnce(u32) -> u32) {
+        let value = self.read(reg);
+        self.write(reg, f(value));
+    }
+}
+
+#[cfg(any(
+    feature = "gpio-f417",
+    feature = "gpio-f427",
+    feature = "gpio-f446",
+    feature = "gpio-f469"
+))]
+pub use sram::*;
+
+#[cfg(any(
+    feature = "gpio-f417",
+    feature = "gpio-f427",
+    feature = "gpio-f446",
+    feature = "gpio-f469"
+))]
+mod sram {
+    use super::*;
+    use crate::bb;
+    use crate::pac::RCC;
+    use core::mem::{align_of, size_of};
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    /// Base address of the backup SRAM
+    pub const BKPSRAM_BASE: usize = 0x4002_4000;
+    /// Size of the backup SRAM in bytes
+    pub const BKPSRAM_SIZE: usize = 4 * 1024;
+    /// Bytes available to the application, the last word holds the validity marker
+    pub const BKPSRAM_DATA_SIZE: usize = BKPSRAM_SIZE - 4;
+
+    // RCC_AHB1ENR
+    const BKPSRAMEN: u8 = 18;
+    // PWR_CSR
+    const BRE: u8 = 9;
+    const BRR: u32 = 1 << 3;
+
+    static TAKEN: AtomicBool = AtomicBool::new(false);
+
+    fn marker() -> *mut u32 {
+        (BKPSRAM_BASE + BKPSRAM_DATA_SIZE) as *mut u32
+    }
+
+    /// Types valid for any bit pattern, which can be read back from the backup SRAM
+    ///
+    /// # Safety
+    ///
+    /// The type must have no padding, no pointers or references, and no invalid values (such
+    /// as `bool`, `char` or enums), as the memory may hold anything after a partial loss.
+    pub unsafe trait BackupData: Copy {}
+
+    macro_rules! backup_data {
+        ($($T:ty),+) => {
+            $(unsafe impl BackupData for $T {})+
+        };
+    }
+
+    backup_data!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize, f32, f64);
+    unsafe impl<T: BackupData, const N: usize> BackupData for [T; N] {}
+
+    /// Owned handle to the backup SRAM
+    ///
+    /// There is only one handle, as it hands out mutable references to the memory.
+    pub struct BackupSram {
+        _private: (),
+    }
+
+    impl BackupSram {
+        /// Enables the backup SRAM clock and the backup regulator, and waits for the regulator.
+        ///
+        /// With the backup regulator on, the contents are retained in Standby mode and on VBAT.
+        /// Returns `None` if the handle was already taken.
+        pub fn new(pwr: &mut PWR) -> Option<Self> {
+            if TAKEN.swap(true, Ordering::AcqRel) {
+                return None;
+            }
+            unlock(pwr);
+            let rcc = unsafe { &*RCC::ptr() };
+            unsafe {
+                bb::set(&rcc.ahb1enr, BKPSRAMEN);
+                bb::set(&pwr.csr, BRE);
+            }
+            // Stall the pipeline to work around erratum 2.1.13 (DM00037591)
+            cortex_m::asm::dsb();
+            while pwr.csr.read().bits() & BRR == 0 {}
+            Some(Self { _private: () })
+        }
+
+        /// Returns `true` if the marker written by [`Self::set_retained`] is still there, so the
+        /// contents survived since then.
+        pub fn is_retained(&self, magic: u32) -> bool {
+            unsafe { core::ptr::read_volatile(marker()) == magic }
+        }
+
+        /// Writes the validity marker checked by [`Self::is_retained`].
+        pub fn set_retained(&mut self, magic: u32) {
+            unsafe { core::ptr::write_volatile(marker(), magic) }
+        }
+
+        /// Clears the validity marker.
+        pub fn invalidate(&mut self) {
+            unsafe { core::ptr::write_volatile(marker(), !core::ptr::read_volatile(marker())) }
+        }
+
+        /// Returns the data area, without the validity marker.
+        pub fn as_slice_mut(&mut self) -> &mut [u8] {
+            unsafe { core::slice::from_raw_parts_mut(BKPSRAM_BASE as *mut u8, BKPSRAM_DATA_SIZE) }
+        }
+
+        /// Consumes the handle and returns the data area for the rest of the program.
+        pub fn into_slice(self) -> &'static mut [u8] {
+            unsafe { core::slice::from_raw_parts_mut(BKPSRAM_BASE as *mut u8, BKPSRAM_DATA_SIZE) }
+        }
+
+        /// Returns a typed view of the start of the data area.
+        ///
+        /// If the marker does not match `magic`, the value is set to `init()` and the marker is
+        /// written. Change `magic` whenever the layout of `T` changes.
+        ///
+        /// # Panics
+        ///
+        /// If `T` does not fit in the data area.
+        pub fn get_or_init<T: BackupData>(
+            &mut self,
+            magic: u32,
+            init: impl FnOnce() -> T,
+        ) -> &mut T {
+            assert!(size_of::<T>() <= BKPSRAM_DATA_SIZE && BKPSRAM_BASE % align_of::<T>() == 0);
+            let ptr = BKPSRAM_BASE as *mut T;
+            if !self.is_retained(magic) {
+                unsafe { ptr.write_volatile(init()) };
+                self.set_retained(magic);
+            }
+            unsafe { &mut *ptr }
+        }
+
+        /// Turns the backup regulator off, the contents are then only kept while VDD is on.
+        ///
+        /// The handle can be taken again with [`Self::new`].
+        pub fn disable_regulator(self, pwr: &mut PWR) {
+            unsafe { bb::clear(&pwr.csr, BRE) };
+            TAKEN.store(false, Ordering::Release);
+        }
+    }
+}
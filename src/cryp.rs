@@ -0,0 +1,882 @@
+//! Cryptographic processor
+//!
+//! AES-128/192/256 in ECB, CBC and CTR mode and DES/TDES in ECB and CBC mode, plus AES-GCM and
+//! AES-CCM on STM32F437/439/479.
+//!
+//! This is the CRYP of STM32F415/417/437/439/479. The AES coprocessor of STM32F423 has different
+//! registers and its own `aes` driver.
+//!
+//! ```rust,ignore
+//! let mut cryp = Cryp::new(dp.CRYP);
+//! let mut session = cryp.start(Mode::AesCbc, Direction::Encrypt, &key, &iv)?;
+//! session.process_in_place(&mut buffer)?;
+//!
+//! let tag = cryp.gcm_encrypt(&key, &nonce, &aad, &mut message)?;
+//! ```
+//!
+//! With the `rustcrypto` feature, [`rustcrypto`] provides `cipher` and `aead` trait
+//! implementations.
+
+use core::marker::PhantomData;
+
+use vcell::VolatileCell;
+
+use crate::dma::traits::{DMASet, PeriAddress, CRYP_IN, CRYP_OUT};
+use crate::dma::{MemoryToPeripheral, PeripheralToMemory};
+use crate::pac::CRYP;
+use crate::rcc::{Enable, Reset};
+
+#[cfg(feature = "rustcrypto")]
+pub mod rustcrypto;
+
+/// Cipher and chaining mode
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    TdesEcb = 0b000,
+    TdesCbc = 0b001,
+    DesEcb = 0b010,
+    DesCbc = 0b011,
+    AesEcb = 0b100,
+    AesCbc = 0b101,
+    AesCtr = 0b110,
+}
+
+impl Mode {
+    /// Block size in bytes
+    pub const fn block_size(self) -> usize {
+        if self.is_aes() {
+            16
+        } else {
+            8
+        }
+    }
+
+    pub const fn is_aes(self) -> bool {
+        matches!(self, Self::AesEcb | Self::AesCbc | Self::AesCtr)
+    }
+
+    /// IV length in bytes, 0 for ECB
+    pub const fn iv_size(self) -> usize {
+        match self {
+            Self::TdesEcb | Self::DesEcb | Self::AesEcb => 0,
+            Self::TdesCbc | Self::DesCbc => 8,
+            Self::AesCbc | Self::AesCtr => 16,
+        }
+    }
+}
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Encrypt,
+    Decrypt,
+}
+
+/// CRYP error
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The key length does not match the mode
+    KeySize,
+    /// The IV or nonce length does not match the mode
+    IvSize,
+    /// The data is not a whole number of blocks, or input and output differ in length
+    DataSize,
+    /// The tag length is not supported
+    TagSize,
+    /// The authentication tag does not match
+    Authentication,
+}
+
+/// Value of `KEYSIZE` and the key registers `K0LR` to `K3RR` for `key`.
+///
+/// Keys are right aligned in the key registers: AES-128 fills `K2LR` to `K3RR`, AES-192 and
+/// TDES fill `K1LR` to `K3RR`. A single DES key goes into `K1LR`/`K1RR`.
+pub fn pack_key(mode: Mode, key: &[u8]) -> Result<(u32, [u32; 8]), Error> {
+    let (keysize, first) = match (mode, key.len()) {
+        (Mode::DesEcb | Mode::DesCbc, 8) => (0b00, 2),
+        (Mode::TdesEcb | Mode::TdesCbc, 24) => (0b00, 2),
+        (_, 16) if mode.is_aes() => (0b00, 4),
+        (_, 24) if mode.is_aes() => (0b01, 2),
+        (_, 32) if mode.is_aes() => (0b10, 0),
+        _ => return Err(Error::KeySize),
+    };
+    let mut words = [0; 8];
+    for (word, bytes) in words[first..].iter_mut().zip(key.chunks_exact(4)) {
+        *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    Ok((keysize, words))
+}
+
+/// Initialization vector registers `IV0LR` to `IV1RR` for `iv`, left aligned.
+pub fn pack_iv(iv: &[u8]) -> [u32; 4] {
+    let mut words = [0; 4];
+    for (word, bytes) in words.iter_mut().zip(iv.chunks_exact(4)) {
+        *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    words
+}
+
+/// Data register words for a block of bytes, with the CRYP swapping bytes (`DATATYPE` = 8 bit).
+pub fn pack_block(block: &[u8]) -> [u32; 4] {
+    let mut words = [0; 4];
+    for (word, bytes) in words.iter_mut().zip(block.chunks(4)) {
+        let mut b = [0; 4];
+        b[..bytes.len()].copy_from_slice(bytes);
+        *word = u32::from_le_bytes(b);
+    }
+    words
+}
+
+/// GCM initial counter for a 96-bit nonce.
+///
+/// The CRYP is loaded with the counter of the first payload block, `J0 + 1`.
+pub fn gcm_iv(nonce: &[u8; 12]) -> [u8; 16] {
+    let mut iv = [0; 16];
+    iv[..12].copy_from_slice(nonce);
+    iv[15] = 2;
+    iv
+}
+
+/// GCM/CCM final block: lengths in bits of the associated data and the message, big endian.
+pub fn length_block(aad_len: usize, msg_len: usize) -> [u8; 16] {
+    let mut block = [0; 16];
+    block[..8].copy_from_slice(&(aad_len as u64 * 8).to_be_bytes());
+    block[8..].copy_from_slice(&(msg_len as u64 * 8).to_be_bytes());
+    block
+}
+
+/// Length in bytes of the CCM length field `q` for `nonce`, which is 7 to 13 bytes long.
+fn ccm_q(nonce: &[u8]) -> Result<usize, Error> {
+    if (7..=13).contains(&nonce.len()) {
+        Ok(15 - nonce.len())
+    } else {
+        Err(Error::IvSize)
+    }
+}
+
+/// CCM first block `B0`.
+///
+/// `nonce` is 7 to 13 bytes long, `tag_len` one of 4, 6, 8, 10, 12, 14 or 16, and `msg_len`
+/// must fit the `15 - nonce.len()` bytes left for it.
+pub fn ccm_b0(
+    nonce: &[u8],
+    aad_len: usize,
+    msg_len: usize,
+    tag_len: usize,
+) -> Result<[u8; 16], Error> {
+    let q = ccm_q(nonce)?;
+    if !(4..=16).contains(&tag_len) || tag_len % 2 != 0 {
+        return Err(Error::TagSize);
+    }
+    let len = (msg_len as u64).to_be_bytes();
+    if len[..8 - q].iter().any(|&b| b != 0) {
+        return Err(Error::DataSize);
+    }
+    let mut b0 = [0; 16];
+    b0[0] = (u8::from(aad_len > 0) << 6) | ((((tag_len - 2) / 2) as u8) << 3) | (q as u8 - 1);
+    b0[1..=nonce.len()].copy_from_slice(nonce);
+    b0[16 - q..].copy_from_slice(&len[8 - q..]);
+    Ok(b0)
+}
+
+/// CCM counter block `CTRi`, `nonce` is 7 to 13 bytes long.
+pub fn ccm_ctr(nonce: &[u8], counter: u32) -> Result<[u8; 16], Error> {
+    let q = ccm_q(nonce)?;
+    let mut ctr = [0; 16];
+    ctr[0] = q as u8 - 1;
+    ctr[1..=nonce.len()].copy_from_slice(nonce);
+    let counter = u64::from(counter).to_be_bytes();
+    ctr[16 - q..].copy_from_slice(&counter[8 - q..]);
+    Ok(ctr)
+}
+
+/// Length prefix of CCM associated data, returns the prefix and its length.
+pub fn ccm_aad_prefix(aad_len: usize) -> ([u8; 6], usize) {
+    let mut prefix = [0; 6];
+    if aad_len < 0xff00 {
+        prefix[..2].copy_from_slice(&(aad_len as u16).to_be_bytes());
+        (prefix, 2)
+    } else {
+        prefix[..2].copy_from_slice(&[0xff, 0xfe]);
+        prefix[2..].copy_from_slice(&(aad_len as u32).to_be_bytes());
+        (prefix, 6)
+    }
+}
+
+// CR
+const CR_ALGODIR: u32 = 1 << 2;
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+const CR_ALGOMODE_MASK: u32 = (0b111 << 3) | (1 << 19);
+const CR_ALGOMODE_KEY_PREPARE: u32 = 0b111 << 3;
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+const CR_ALGOMODE_GCM: u32 = 1 << 19;
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+const CR_ALGOMODE_CCM: u32 = (1 << 19) | (0b001 << 3);
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+const CR_ALGOMODE_CTR: u32 = 0b110 << 3;
+const CR_DATATYPE_8: u32 = 0b10 << 6;
+const CR_FFLUSH: u32 = 1 << 14;
+const CR_CRYPEN: u32 = 1 << 15;
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+const CR_GCM_CCMPH_MASK: u32 = 0b11 << 16;
+// SR
+const SR_IFNF: u32 = 1 << 1;
+const SR_OFNE: u32 = 1 << 2;
+const SR_BUSY: u32 = 1 << 4;
+// DMACR
+const DMACR_DIEN: u32 = 1 << 0;
+const DMACR_DOEN: u32 = 1 << 1;
+
+#[repr(C)]
+struct Registers {
+    cr: VolatileCell<u32>,
+    sr: VolatileCell<u32>,
+    din: VolatileCell<u32>,
+    dout: VolatileCell<u32>,
+    dmacr: VolatileCell<u32>,
+    _reserved0: [u32; 3],
+    k: [VolatileCell<u32>; 8],
+    iv: [VolatileCell<u32>; 4],
+    #[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+    csgcmccm: [VolatileCell<u32>; 8],
+}
+
+impl Registers {
+    fn modify_cr(&self, f: impl FnOnce(u32) -> u32) {
+        self.cr.set(f(self.cr.get()));
+    }
+}
+
+/// Cryptographic processor
+pub struct Cryp {
+    cryp: CRYP,
+}
+
+impl Cryp {
+    pub fn new(cryp: CRYP) -> Self {
+        unsafe {
+            CRYP::enable_unchecked();
+            CRYP::reset_unchecked();
+        }
+        Self { cryp }
+    }
+
+    pub fn release(self) -> CRYP {
+        self.regs().cr.set(0);
+        self.cryp
+    }
+
+    fn regs(&self) -> &'static Registers {
+        // NOTE(unsafe) the CRYP registers are owned by `self`
+        unsafe { &*(CRYP::ptr() as *const Registers) }
+    }
+
+    /// Loads `key`, `iv` and the mode and returns a session processing whole blocks.
+    ///
+    /// `iv` is empty in ECB mode. For CTR mode, it holds the nonce and the initial counter.
+    pub fn start(
+        &mut self,
+        mode: Mode,
+        direction: Direction,
+        key: &[u8],
+        iv: &[u8],
+    ) -> Result<Session<'_>, Error> {
+        let (keysize, key) = pack_key(mode, key)?;
+        if iv.len() != mode.iv_size() {
+            return Err(Error::IvSize);
+        }
+        let regs = self.regs();
+        regs.cr.set(0);
+        self.load_key(keysize, &key);
+
+        // AES decryption in ECB and CBC mode needs the decryption key schedule
+        let decrypt = direction == Direction::Decrypt;
+        if decrypt && matches!(mode, Mode::AesEcb | Mode::AesCbc) {
+            regs.cr
+                .set((keysize << 8) | CR_ALGOMODE_KEY_PREPARE | CR_CRYPEN);
+            while regs.sr.get() & SR_BUSY != 0 {}
+        }
+
+        regs.cr.set(0);
+        for (reg, word) in regs.iv.iter().zip(pack_iv(iv)) {
+            reg.set(word);
+        }
+        let algodir = if decrypt { CR_ALGODIR } else { 0 };
+        regs.cr
+            .set((keysize << 8) | ((mode as u32) << 3) | CR_DATATYPE_8 | algodir | CR_FFLUSH);
+        regs.modify_cr(|r| r | CR_CRYPEN);
+        Ok(Session {
+            cryp: self,
+            block_size: mode.block_size(),
+        })
+    }
+
+    fn load_key(&mut self, keysize: u32, key: &[u32; 8]) {
+        let regs = self.regs();
+        regs.cr.set(keysize << 8);
+        for (reg, word) in regs.k.iter().zip(key) {
+            reg.set(*word);
+        }
+    }
+
+    /// Writes `input` and reads the same number of bytes into `output`, up to one block.
+    fn process_block(&mut self, input: &[u8], output: &mut [u8], words: usize) {
+        let regs = self.regs();
+        for word in &pack_block(input)[..words] {
+            while regs.sr.get() & SR_IFNF == 0 {}
+            regs.din.set(*word);
+        }
+        let mut out = [0; 16];
+        for bytes in out.chunks_exact_mut(4).take(words) {
+            while regs.sr.get() & SR_OFNE == 0 {}
+            bytes.copy_from_slice(&regs.dout.get().to_le_bytes());
+        }
+        let len = output.len();
+        output.copy_from_slice(&out[..len]);
+    }
+}
+
+/// Running ECB, CBC or CTR operation
+pub struct Session<'c> {
+    cryp: &'c mut Cryp,
+    block_size: usize,
+}
+
+impl<'c> Session<'c> {
+    /// Processes whole blocks from `input` into `output`.
+    pub fn process(&mut self, input: &[u8], output: &mut [u8]) -> Result<(), Error> {
+        if input.len() != output.len() || input.len() % self.block_size != 0 {
+            return Err(Error::DataSize);
+        }
+        let words = self.block_size / 4;
+        for (i, o) in input
+            .chunks_exact(self.block_size)
+            .zip(output.chunks_exact_mut(self.block_size))
+        {
+            self.cryp.process_block(i, o, words);
+        }
+        Ok(())
+    }
+
+    /// Processes whole blocks in place.
+    pub fn process_in_place(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        if buffer.len() % self.block_size != 0 {
+            return Err(Error::DataSize);
+        }
+        let words = self.block_size / 4;
+        let mut block = [0; 16];
+        for chunk in buffer.chunks_exact_mut(self.block_size) {
+            let block = &mut block[..self.block_size];
+            block.copy_from_slice(chunk);
+            self.cryp.process_block(block, chunk, words);
+        }
+        Ok(())
+    }
+
+    /// Hands the data registers to DMA.
+    ///
+    /// Use [`DmaIn`] with a memory to peripheral and [`DmaOut`] with a peripheral to memory
+    /// [`Transfer`](crate::dma::Transfer) of 32-bit words. The output transfer should be started
+    /// first. Dropping [`DmaOut`] ends the session.
+    pub fn into_dma(self) -> (DmaIn<'c>, DmaOut<'c>) {
+        self.cryp.regs().dmacr.set(DMACR_DIEN | DMACR_DOEN);
+        // The session stays enabled until `DmaOut` is dropped
+        core::mem::forget(self);
+        (DmaIn { _cryp: PhantomData }, DmaOut { _cryp: PhantomData })
+    }
+}
+
+impl Drop for Session<'_> {
+    fn drop(&mut self) {
+        self.cryp.regs().modify_cr(|r| r & !CR_CRYPEN);
+    }
+}
+
+/// Input FIFO of a session streamed by DMA
+pub struct DmaIn<'c> {
+    _cryp: PhantomData<&'c mut Cryp>,
+}
+
+/// Output FIFO of a session streamed by DMA
+pub struct DmaOut<'c> {
+    _cryp: PhantomData<&'c mut Cryp>,
+}
+
+impl Drop for DmaOut<'_> {
+    fn drop(&mut self) {
+        // NOTE(unsafe) the session is borrowed for 'c
+        let regs = unsafe { &*(CRYP::ptr() as *const Registers) };
+        regs.dmacr.set(0);
+        regs.modify_cr(|r| r & !CR_CRYPEN);
+    }
+}
+
+unsafe impl PeriAddress for DmaIn<'_> {
+    #[inline(always)]
+    fn address(&self) -> u32 {
+        unsafe { (*(CRYP::ptr() as *const Registers)).din.as_ptr() as u32 }
+    }
+
+    type MemSize = u32;
+}
+
+unsafe impl PeriAddress for DmaOut<'_> {
+    #[inline(always)]
+    fn address(&self) -> u32 {
+        unsafe { (*(CRYP::ptr() as *const Registers)).dout.as_ptr() as u32 }
+    }
+
+    type MemSize = u32;
+}
+
+unsafe impl<STREAM, const CHANNEL: u8> DMASet<STREAM, CHANNEL, MemoryToPeripheral> for DmaIn<'_> where
+    CRYP_IN: DMASet<STREAM, CHANNEL, MemoryToPeripheral>
+{
+}
+
+unsafe impl<STREAM, const CHANNEL: u8> DMASet<STREAM, CHANNEL, PeripheralToMemory> for DmaOut<'_> where
+    CRYP_OUT: DMASet<STREAM, CHANNEL, PeripheralToMemory>
+{
+}
+
+/// Compares tags in constant time.
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+fn tag_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Aead {
+    Gcm,
+    Ccm,
+}
+
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+impl Cryp {
+    /// Encrypts `buffer` in place with AES-GCM and returns the tag.
+    pub fn gcm_encrypt(
+        &mut self,
+        key: &[u8],
+        nonce: &[u8; 12],
+        aad: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<[u8; 16], Error> {
+        self.gcm(Direction::Encrypt, key, nonce, aad, buffer)
+    }
+
+    /// Decrypts `buffer` in place with AES-GCM and checks `tag`.
+    ///
+    /// On error, `buffer` holds unauthenticated data and should be discarded.
+    pub fn gcm_decrypt(
+        &mut self,
+        key: &[u8],
+        nonce: &[u8; 12],
+        aad: &[u8],
+        buffer: &mut [u8],
+        tag: &[u8; 16],
+    ) -> Result<(), Error> {
+        let computed = self.gcm(Direction::Decrypt, key, nonce, aad, buffer)?;
+        if tag_eq(&computed, tag) {
+            Ok(())
+        } else {
+            Err(Error::Authentication)
+        }
+    }
+
+    /// Encrypts `buffer` in place with AES-CCM and writes the tag into `tag`.
+    ///
+    /// `nonce` is 7 to 13 bytes long, `tag` 4, 6, 8, 10, 12, 14 or 16 bytes.
+    pub fn ccm_encrypt(
+        &mut self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        buffer: &mut [u8],
+        tag: &mut [u8],
+    ) -> Result<(), Error> {
+        let computed = self.ccm(Direction::Encrypt, key, nonce, aad, buffer, tag.len())?;
+        tag.copy_from_slice(&computed[..tag.len()]);
+        Ok(())
+    }
+
+    /// Decrypts `buffer` in place with AES-CCM and checks `tag`.
+    ///
+    /// On error, `buffer` holds unauthenticated data and should be discarded.
+    pub fn ccm_decrypt(
+        &mut self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        buffer: &mut [u8],
+        tag: &[u8],
+    ) -> Result<(), Error> {
+        let computed = self.ccm(Direction::Decrypt, key, nonce, aad, buffer, tag.len())?;
+        if tag_eq(&computed[..tag.len()], tag) {
+            Ok(())
+        } else {
+            Err(Error::Authentication)
+        }
+    }
+
+    fn gcm(
+        &mut self,
+        direction: Direction,
+        key: &[u8],
+        nonce: &[u8; 12],
+        aad: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<[u8; 16], Error> {
+        let (keysize, key) = pack_key(Mode::AesEcb, key)?;
+        self.init_phase(Aead::Gcm, direction, keysize, &key, &gcm_iv(nonce), None);
+
+        for chunk in aad.chunks(16) {
+            let mut block = [0; 16];
+            block[..chunk.len()].copy_from_slice(chunk);
+            self.header_block(&block);
+        }
+        self.payload(Aead::Gcm, direction, buffer);
+        Ok(self.final_phase(&length_block(aad.len(), buffer.len())))
+    }
+
+    fn ccm(
+        &mut self,
+        direction: Direction,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        buffer: &mut [u8],
+        tag_len: usize,
+    ) -> Result<[u8; 16], Error> {
+        let b0 = ccm_b0(nonce, aad.len(), buffer.len(), tag_len)?;
+        let (keysize, key) = pack_key(Mode::AesEcb, key)?;
+        self.init_phase(
+            Aead::Ccm,
+            direction,
+            keysize,
+            &key,
+            &ccm_ctr(nonce, 1)?,
+            Some(&b0),
+        );
+
+        if !aad.is_empty() {
+            // Associated data is prefixed with its length and padded to whole blocks
+            let (prefix, prefix_len) = ccm_aad_prefix(aad.len());
+            let mut block = [0; 16];
+            block[..prefix_len].copy_from_slice(&prefix[..prefix_len]);
+            let mut filled = prefix_len;
+            for &byte in aad {
+                block[filled] = byte;
+                filled += 1;
+                if filled == 16 {
+                    self.header_block(&block);
+                    block = [0; 16];
+                    filled = 0;
+                }
+            }
+            if filled > 0 {
+                self.header_block(&block);
+            }
+        }
+        self.payload(Aead::Ccm, direction, buffer);
+        Ok(self.final_phase(&ccm_ctr(nonce, 0)?))
+    }
+
+    fn set_phase(&mut self, phase: u32) {
+        let regs = self.regs();
+        regs.modify_cr(|r| r & !CR_CRYPEN);
+        regs.modify_cr(|r| (r & !CR_GCM_CCMPH_MASK) | (phase << 16));
+        regs.modify_cr(|r| r | CR_CRYPEN);
+    }
+
+    fn init_phase(
+        &mut self,
+        aead: Aead,
+        direction: Direction,
+        keysize: u32,
+        key: &[u32; 8],
+        iv: &[u8; 16],
+        b0: Option<&[u8; 16]>,
+    ) {
+        let regs = self.regs();
+        regs.cr.set(0);
+        self.load_key(keysize, key);
+        for (reg, word) in regs.iv.iter().zip(pack_iv(iv)) {
+            reg.set(word);
+        }
+        let algomode = match aead {
+            Aead::Gcm => CR_ALGOMODE_GCM,
+            Aead::Ccm => CR_ALGOMODE_CCM,
+        };
+        let algodir = match direction {
+            Direction::Encrypt => 0,
+            Direction::Decrypt => CR_ALGODIR,
+        };
+        regs.cr
+            .set((keysize << 8) | algomode | algodir | CR_DATATYPE_8 | CR_FFLUSH);
+        regs.modify_cr(|r| r | CR_CRYPEN);
+        if let Some(b0) = b0 {
+            for word in pack_block(b0) {
+                regs.din.set(word);
+            }
+        }
+        // The CRYP disables itself at the end of the init phase
+        while regs.cr.get() & CR_CRYPEN != 0 {}
+        self.set_phase(0b01);
+    }
+
+    fn header_block(&mut self, block: &[u8; 16]) {
+        let regs = self.regs();
+        for word in pack_block(block) {
+            while regs.sr.get() & SR_IFNF == 0 {}
+            regs.din.set(word);
+        }
+        while regs.sr.get() & SR_BUSY != 0 {}
+    }
+
+    fn payload(&mut self, aead: Aead, direction: Direction, buffer: &mut [u8]) {
+        let regs = self.regs();
+        while regs.sr.get() & SR_BUSY != 0 {}
+        self.set_phase(0b10);
+
+        let full = buffer.len() / 16 * 16;
+        let (blocks, last) = buffer.split_at_mut(full);
+        let mut block = [0; 16];
+        for chunk in blocks.chunks_exact_mut(16) {
+            block.copy_from_slice(chunk);
+            self.process_block(&block, chunk, 4);
+        }
+        if last.is_empty() {
+            return;
+        }
+
+        // The CRYP authenticates the padding of a partial last block of GCM plaintext and
+        // CCM ciphertext, so it is processed in CTR mode and authenticated separately
+        let workaround = matches!(
+            (aead, direction),
+            (Aead::Gcm, Direction::Encrypt) | (Aead::Ccm, Direction::Decrypt)
+        );
+        if !workaround {
+            block = [0; 16];
+            block[..last.len()].copy_from_slice(last);
+            self.process_block(&block, last, 4);
+            return;
+        }
+
+        let mac = if aead == Aead::Ccm {
+            let mut mac = [0; 4];
+            for (m, reg) in mac.iter_mut().zip(&regs.csgcmccm[..4]) {
+                *m = reg.get();
+            }
+            mac
+        } else {
+            [0; 4]
+        };
+        regs.modify_cr(|r| r & !CR_CRYPEN);
+        regs.iv[3].set(regs.csgcmccm[7].get().wrapping_sub(1));
+        regs.modify_cr(|r| (r & !CR_ALGOMODE_MASK) | CR_ALGOMODE_CTR);
+        regs.modify_cr(|r| r | CR_CRYPEN);
+
+        block = [0; 16];
+        block[..last.len()].copy_from_slice(last);
+        let mut out = [0; 16];
+        self.process_block(&block, &mut out, 4);
+        last.copy_from_slice(&out[..last.len()]);
+        while regs.sr.get() & SR_BUSY != 0 {}
+
+        // Authenticate the output, GCM ciphertext or CCM plaintext, with the padding cleared
+        let mut masked = [0; 16];
+        masked[..last.len()].copy_from_slice(&out[..last.len()]);
+        let mut words = pack_block(&masked);
+        regs.modify_cr(|r| r & !CR_CRYPEN);
+        match aead {
+            Aead::Gcm => {
+                regs.modify_cr(|r| {
+                    (r & !(CR_ALGOMODE_MASK | CR_GCM_CCMPH_MASK)) | CR_ALGOMODE_GCM | (0b11 << 16)
+                });
+                regs.modify_cr(|r| r | CR_CRYPEN);
+                for word in words {
+                    regs.din.set(word);
+                }
+                while regs.sr.get() & SR_OFNE == 0 {}
+                for _ in 0..4 {
+                    regs.dout.get();
+                }
+            }
+            Aead::Ccm => {
+                for (word, (m, reg)) in words.iter_mut().zip(mac.iter().zip(&regs.csgcmccm[..4])) {
+                    *word ^= m ^ reg.get();
+                }
+                regs.modify_cr(|r| {
+                    (r & !(CR_ALGOMODE_MASK | CR_GCM_CCMPH_MASK)) | CR_ALGOMODE_CCM | (0b01 << 16)
+                });
+                regs.modify_cr(|r| r | CR_CRYPEN);
+                for word in words {
+                    regs.din.set(word);
+                }
+                while regs.sr.get() & SR_BUSY != 0 {}
+            }
+        }
+    }
+
+    fn final_phase(&mut self, block: &[u8; 16]) -> [u8; 16] {
+        let regs = self.regs();
+        while regs.sr.get() & SR_BUSY != 0 {}
+        regs.modify_cr(|r| r & !CR_CRYPEN);
+        // The final phase runs in the encryption direction
+        regs.modify_cr(|r| (r & !(CR_GCM_CCMPH_MASK | CR_ALGODIR)) | (0b11 << 16));
+        regs.modify_cr(|r| r | CR_CRYPEN);
+        let mut tag = [0; 16];
+        self.process_block(block, &mut tag, 4);
+        regs.cr.set(0);
+        tag
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_256: [u8; 32] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d,
+        0x1e, 0x1f,
+    ];
+
+    #[test]
+    fn aes_keys() {
+        // FIPS-197 appendix C
+        assert_eq!(
+            pack_key(Mode::AesEcb, &KEY_256[..16]),
+            Ok((
+                0b00,
+                [0, 0, 0, 0, 0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f]
+            ))
+        );
+        assert_eq!(
+            pack_key(Mode::AesCbc, &KEY_256[..24]),
+            Ok((
+                0b01,
+                [0, 0, 0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f, 0x10111213, 0x14151617]
+            ))
+        );
+        assert_eq!(
+            pack_key(Mode::AesCtr, &KEY_256),
+            Ok((
+                0b10,
+                [
+                    0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f, 0x10111213, 0x14151617,
+                    0x18191a1b, 0x1c1d1e1f
+                ]
+            ))
+        );
+        assert_eq!(pack_key(Mode::AesEcb, &KEY_256[..20]), Err(Error::KeySize));
+    }
+
+    #[test]
+    fn des_keys() {
+        // SP 800-67 appendix B
+        let key = [
+            0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd,
+            0xef, 0x01, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0x01, 0x23,
+        ];
+        assert_eq!(
+            pack_key(Mode::TdesEcb, &key),
+            Ok((
+                0b00,
+                [0, 0, 0x01234567, 0x89abcdef, 0x23456789, 0xabcdef01, 0x456789ab, 0xcdef0123]
+            ))
+        );
+        assert_eq!(
+            pack_key(Mode::DesCbc, &key[..8]),
+            Ok((0b00, [0, 0, 0x01234567, 0x89abcdef, 0, 0, 0, 0]))
+        );
+        assert_eq!(pack_key(Mode::DesEcb, &key[..16]), Err(Error::KeySize));
+        assert_eq!(pack_key(Mode::TdesCbc, &KEY_256[..16]), Err(Error::KeySize));
+    }
+
+    #[test]
+    fn iv_and_blocks() {
+        // SP 800-38A F.2.1, CBC-AES128 IV and first plaintext block
+        assert_eq!(
+            pack_iv(&KEY_256[..16]),
+            [0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f]
+        );
+        assert_eq!(pack_iv(&KEY_256[..8]), [0x00010203, 0x04050607, 0, 0]);
+        let block = [
+            0x6b, 0xc1, 0xbe, 0xe2, 0x2e, 0x40, 0x9f, 0x96, 0xe9, 0x3d, 0x7e, 0x11, 0x73, 0x93,
+            0x17, 0x2a,
+        ];
+        assert_eq!(
+            pack_block(&block),
+            [0xe2bec16b, 0x969f402e, 0x117e3de9, 0x2a179373]
+        );
+        // Partial blocks are padded with zeros
+        assert_eq!(pack_block(&[1, 2, 3, 4, 5]), [0x04030201, 5, 0, 0]);
+    }
+
+    #[test]
+    fn gcm_blocks() {
+        // GCM spec test case 4
+        let nonce = [
+            0xca, 0xfe, 0xba, 0xbe, 0xfa, 0xce, 0xdb, 0xad, 0xde, 0xca, 0xf8, 0x88,
+        ];
+        assert_eq!(
+            gcm_iv(&nonce),
+            [0xca, 0xfe, 0xba, 0xbe, 0xfa, 0xce, 0xdb, 0xad, 0xde, 0xca, 0xf8, 0x88, 0, 0, 0, 2]
+        );
+        assert_eq!(
+            length_block(20, 60),
+            [0, 0, 0, 0, 0, 0, 0, 0xa0, 0, 0, 0, 0, 0, 0, 0x01, 0xe0]
+        );
+    }
+
+    #[test]
+    fn ccm_blocks() {
+        // SP 800-38C C.1
+        let nonce = [0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16];
+        assert_eq!(
+            ccm_b0(&nonce, 8, 4, 4),
+            Ok([0x4f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0, 0, 0, 0, 0, 0, 0, 4])
+        );
+        assert_eq!(
+            ccm_ctr(&nonce, 0),
+            Ok([0x07, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0, 0, 0, 0, 0, 0, 0, 0])
+        );
+        assert_eq!(ccm_aad_prefix(8), ([0, 8, 0, 0, 0, 0], 2));
+
+        // SP 800-38C C.2
+        let nonce = [0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17];
+        assert_eq!(
+            ccm_b0(&nonce, 16, 16, 6),
+            Ok([0x56, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0, 0, 0, 0, 0, 0, 0x10])
+        );
+        assert_eq!(
+            ccm_ctr(&nonce, 1),
+            Ok([0x06, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0, 0, 0, 0, 0, 0, 1])
+        );
+
+        // Without associated data, and with the long length prefix
+        assert_eq!(ccm_b0(&nonce, 0, 16, 16).map(|b0| b0[0]), Ok(0x3e));
+        assert_eq!(ccm_aad_prefix(0x10000), ([0xff, 0xfe, 0, 1, 0, 0], 6));
+    }
+
+    #[test]
+    fn ccm_limits() {
+        let nonce = [0; 16];
+        assert_eq!(ccm_b0(&nonce[..6], 0, 0, 4), Err(Error::IvSize));
+        assert_eq!(ccm_b0(&nonce[..14], 0, 0, 4), Err(Error::IvSize));
+        assert_eq!(ccm_b0(&nonce, 0, 0, 4), Err(Error::IvSize));
+        assert_eq!(ccm_ctr(&nonce[..14], 0), Err(Error::IvSize));
+        assert_eq!(ccm_ctr(&nonce[..6], 0), Err(Error::IvSize));
+        assert!(ccm_ctr(&nonce[..13], 0).is_ok());
+        for tag_len in [0, 1, 2, 3, 5, 18] {
+            assert_eq!(ccm_b0(&nonce[..7], 0, 0, tag_len), Err(Error::TagSize));
+        }
+        // A 13 byte nonce leaves two bytes for the length
+        assert!(ccm_b0(&nonce[..13], 0, 0xffff, 4).is_ok());
+        assert_eq!(ccm_b0(&nonce[..13], 0, 0x10000, 4), Err(Error::DataSize));
+    }
+}
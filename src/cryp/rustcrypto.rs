@@ -0,0 +1,415 @@
+//! [RustCrypto](https://github.com/RustCrypto) trait implementations
+//!
+//! The ciphers borrow the [`Cryp`] and load their key for every call, so they can be used in
+//! place of software implementations, e.g. with the `cbc` or `ctr` crates.
+//!
+//! ```rust,ignore
+//! use cipher::BlockEncrypt;
+//!
+//! let aes = Aes128::new(&mut cryp, &key.into());
+//! aes.encrypt_block(&mut block);
+//! ```
+//!
+//! [`KeyInit`] can not be given the [`Cryp`], so ciphers created with it take the one lent to
+//! [`share`] until they are dropped. Creating a second one before that panics:
+//!
+//! ```rust,ignore
+//! static mut CRYP: Option<Cryp> = None;
+//!
+//! rustcrypto::share(unsafe { CRYP.insert(Cryp::new(dp.CRYP)) });
+//! let mut cbc = cbc::Encryptor::<Aes128>::new(&key.into(), &iv.into());
+//! ```
+
+use core::cell::RefCell;
+use core::marker::PhantomData;
+use core::ops::Deref;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+use aead::{AeadCore, AeadInPlace, Nonce, Tag};
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+use cipher::consts::{U0, U12};
+use cipher::consts::{U1, U16, U24, U32, U8};
+use cipher::generic_array::{ArrayLength, GenericArray};
+use cipher::inout::InOut;
+use cipher::{
+    Block, BlockBackend, BlockClosure, BlockDecrypt, BlockEncrypt, BlockSizeUser, Key, KeyInit,
+    KeySizeUser, ParBlocksSizeUser,
+};
+
+use super::{Cryp, Direction, Mode, Session};
+
+/// AES key length
+pub trait KeySize: ArrayLength<u8> + crate::Sealed {}
+impl crate::Sealed for U16 {}
+impl KeySize for U16 {}
+impl crate::Sealed for U24 {}
+impl KeySize for U24 {}
+impl crate::Sealed for U32 {}
+impl KeySize for U32 {}
+
+static SHARED: AtomicPtr<Cryp> = AtomicPtr::new(ptr::null_mut());
+
+/// Lends `cryp` to the ciphers created with [`KeyInit`].
+///
+/// One such cipher can exist at a time, it takes the CRYP until it is dropped.
+///
+/// # Panics
+///
+/// [`KeyInit::new`] panics while another cipher created with it holds the CRYP, or when
+/// nothing has been shared. Ciphers built with `new` on a borrowed [`Cryp`] don't have this
+/// limit.
+pub fn share(cryp: &'static mut Cryp) {
+    SHARED.store(cryp, Ordering::Release);
+}
+
+/// CRYP borrowed by a cipher
+struct CrypRef<'c> {
+    cryp: RefCell<&'c mut Cryp>,
+    /// Taken from [`share`], where it goes back on drop
+    shared: bool,
+}
+
+impl<'c> CrypRef<'c> {
+    fn new(cryp: &'c mut Cryp) -> Self {
+        Self {
+            cryp: RefCell::new(cryp),
+            shared: false,
+        }
+    }
+}
+
+impl CrypRef<'static> {
+    /// # Panics
+    ///
+    /// When no CRYP has been lent with [`share`] or another cipher holds it.
+    fn take_shared() -> Self {
+        let cryp = SHARED.swap(ptr::null_mut(), Ordering::AcqRel);
+        assert!(!cryp.is_null(), "no CRYP shared");
+        Self {
+            // NOTE(unsafe) the pointer comes from the `&'static mut` given to `share`, and was
+            // removed from there
+            cryp: RefCell::new(unsafe { &mut *cryp }),
+            shared: true,
+        }
+    }
+}
+
+impl<'c> Deref for CrypRef<'c> {
+    type Target = RefCell<&'c mut Cryp>;
+    fn deref(&self) -> &Self::Target {
+        &self.cryp
+    }
+}
+
+impl Drop for CrypRef<'_> {
+    fn drop(&mut self) {
+        if self.shared {
+            SHARED.store(&mut **self.cryp.get_mut(), Ordering::Release);
+        }
+    }
+}
+
+/// Block cipher backend running on the CRYP
+struct Backend<'s, BS> {
+    session: Session<'s>,
+    _block_size: PhantomData<BS>,
+}
+
+impl<BS: ArrayLength<u8> + 'static> BlockSizeUser for Backend<'_, BS> {
+    type BlockSize = BS;
+}
+
+impl<BS: ArrayLength<u8> + 'static> ParBlocksSizeUser for Backend<'_, BS> {
+    type ParBlocksSize = U1;
+}
+
+impl<BS: ArrayLength<u8> + 'static> BlockBackend for Backend<'_, BS> {
+    fn proc_block(&mut self, mut block: InOut<'_, '_, Block<Self>>) {
+        let input = block.clone_in();
+        // Blocks always have the size of the session
+        self.session.process(&input, block.get_out()).ok();
+    }
+}
+
+fn run<BS: ArrayLength<u8> + 'static>(
+    cryp: &RefCell<&mut Cryp>,
+    mode: Mode,
+    direction: Direction,
+    key: &[u8],
+    f: impl BlockClosure<BlockSize = BS>,
+) {
+    let mut cryp = cryp.borrow_mut();
+    let session = cryp
+        .start(mode, direction, key, &[])
+        .expect("key size checked by type");
+    f.call(&mut Backend {
+        session,
+        _block_size: PhantomData,
+    });
+}
+
+/// AES block cipher
+pub struct Aes<'c, KS: KeySize> {
+    cryp: CrypRef<'c>,
+    key: GenericArray<u8, KS>,
+}
+
+pub type Aes128<'c> = Aes<'c, U16>;
+pub type Aes192<'c> = Aes<'c, U24>;
+pub type Aes256<'c> = Aes<'c, U32>;
+
+impl<'c, KS: KeySize> Aes<'c, KS> {
+    pub fn new(cryp: &'c mut Cryp, key: &GenericArray<u8, KS>) -> Self {
+        Self {
+            cryp: CrypRef::new(cryp),
+            key: key.clone(),
+        }
+    }
+}
+
+impl<KS: KeySize> KeySizeUser for Aes<'_, KS> {
+    type KeySize = KS;
+}
+
+impl<KS: KeySize> KeyInit for Aes<'static, KS> {
+    /// # Panics
+    ///
+    /// When no CRYP has been lent with [`share`] or another cipher holds it.
+    fn new(key: &Key<Self>) -> Self {
+        Self {
+            cryp: CrypRef::take_shared(),
+            key: key.clone(),
+        }
+    }
+}
+
+impl<KS: KeySize> BlockSizeUser for Aes<'_, KS> {
+    type BlockSize = U16;
+}
+
+impl<KS: KeySize> BlockEncrypt for Aes<'_, KS> {
+    fn encrypt_with_backend(&self, f: impl BlockClosure<BlockSize = U16>) {
+        run(&self.cryp, Mode::AesEcb, Direction::Encrypt, &self.key, f);
+    }
+}
+
+impl<KS: KeySize> BlockDecrypt for Aes<'_, KS> {
+    fn decrypt_with_backend(&self, f: impl BlockClosure<BlockSize = U16>) {
+        run(&self.cryp, Mode::AesEcb, Direction::Decrypt, &self.key, f);
+    }
+}
+
+/// Triple DES block cipher with three keys
+pub struct TdesEde3<'c> {
+    cryp: CrypRef<'c>,
+    key: [u8; 24],
+}
+
+impl<'c> TdesEde3<'c> {
+    pub fn new(cryp: &'c mut Cryp, key: &GenericArray<u8, U24>) -> Self {
+        Self {
+            cryp: CrypRef::new(cryp),
+            key: (*key).into(),
+        }
+    }
+}
+
+impl KeySizeUser for TdesEde3<'_> {
+    type KeySize = U24;
+}
+
+impl KeyInit for TdesEde3<'static> {
+    /// # Panics
+    ///
+    /// When no CRYP has been lent with [`share`] or another cipher holds it.
+    fn new(key: &Key<Self>) -> Self {
+        Self {
+            cryp: CrypRef::take_shared(),
+            key: (*key).into(),
+        }
+    }
+}
+
+impl BlockSizeUser for TdesEde3<'_> {
+    type BlockSize = U8;
+}
+
+impl BlockEncrypt for TdesEde3<'_> {
+    fn encrypt_with_backend(&self, f: impl BlockClosure<BlockSize = U8>) {
+        run(&self.cryp, Mode::TdesEcb, Direction::Encrypt, &self.key, f);
+    }
+}
+
+impl BlockDecrypt for TdesEde3<'_> {
+    fn decrypt_with_backend(&self, f: impl BlockClosure<BlockSize = U8>) {
+        run(&self.cryp, Mode::TdesEcb, Direction::Decrypt, &self.key, f);
+    }
+}
+
+/// AES-GCM with a 96-bit nonce
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+pub struct AesGcm<'c, KS: KeySize> {
+    cryp: CrypRef<'c>,
+    key: GenericArray<u8, KS>,
+}
+
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+pub type Aes128Gcm<'c> = AesGcm<'c, U16>;
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+pub type Aes256Gcm<'c> = AesGcm<'c, U32>;
+
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+impl<'c, KS: KeySize> AesGcm<'c, KS> {
+    pub fn new(cryp: &'c mut Cryp, key: &GenericArray<u8, KS>) -> Self {
+        Self {
+            cryp: CrypRef::new(cryp),
+            key: key.clone(),
+        }
+    }
+}
+
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+impl<KS: KeySize> KeySizeUser for AesGcm<'_, KS> {
+    type KeySize = KS;
+}
+
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+impl<KS: KeySize> KeyInit for AesGcm<'static, KS> {
+    /// # Panics
+    ///
+    /// When no CRYP has been lent with [`share`] or another cipher holds it.
+    fn new(key: &Key<Self>) -> Self {
+        Self {
+            cryp: CrypRef::take_shared(),
+            key: key.clone(),
+        }
+    }
+}
+
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+impl<KS: KeySize> AeadCore for AesGcm<'_, KS> {
+    type NonceSize = U12;
+    type TagSize = U16;
+    type CiphertextOverhead = U0;
+}
+
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+impl<KS: KeySize> AeadInPlace for AesGcm<'_, KS> {
+    fn encrypt_in_place_detached(
+        &self,
+        nonce: &Nonce<Self>,
+        associated_data: &[u8],
+        buffer: &mut [u8],
+    ) -> aead::Result<Tag<Self>> {
+        let tag = self
+            .cryp
+            .borrow_mut()
+            .gcm_encrypt(&self.key, &(*nonce).into(), associated_data, buffer)
+            .map_err(|_| aead::Error)?;
+        Ok(tag.into())
+    }
+
+    fn decrypt_in_place_detached(
+        &self,
+        nonce: &Nonce<Self>,
+        associated_data: &[u8],
+        buffer: &mut [u8],
+        tag: &Tag<Self>,
+    ) -> aead::Result<()> {
+        self.cryp
+            .borrow_mut()
+            .gcm_decrypt(
+                &self.key,
+                &(*nonce).into(),
+                associated_data,
+                buffer,
+                &(*tag).into(),
+            )
+            .map_err(|_| aead::Error)
+    }
+}
+
+/// AES-CCM with an `M` byte tag and an `N` byte nonce
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+pub struct AesCcm<'c, KS: KeySize, M, N> {
+    cryp: CrypRef<'c>,
+    key: GenericArray<u8, KS>,
+    _sizes: PhantomData<(M, N)>,
+}
+
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+impl<'c, KS: KeySize, M: ArrayLength<u8>, N: ArrayLength<u8>> AesCcm<'c, KS, M, N> {
+    /// # Panics
+    ///
+    /// When `M` is not 4, 6, 8, 10, 12, 14 or 16 or `N` is not between 7 and 13.
+    pub fn new(cryp: &'c mut Cryp, key: &GenericArray<u8, KS>) -> Self {
+        assert!((4..=16).contains(&M::USIZE) && M::USIZE % 2 == 0);
+        assert!((7..=13).contains(&N::USIZE));
+        Self {
+            cryp: CrypRef::new(cryp),
+            key: key.clone(),
+            _sizes: PhantomData,
+        }
+    }
+}
+
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+impl<KS: KeySize, M: ArrayLength<u8>, N: ArrayLength<u8>> KeySizeUser for AesCcm<'_, KS, M, N> {
+    type KeySize = KS;
+}
+
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+impl<KS: KeySize, M: ArrayLength<u8>, N: ArrayLength<u8>> KeyInit for AesCcm<'static, KS, M, N> {
+    /// # Panics
+    ///
+    /// When no CRYP has been lent with [`share`] or another cipher holds it, when `M` is not 4,
+    /// 6, 8, 10, 12, 14 or 16 or `N` is not between 7 and 13.
+    fn new(key: &Key<Self>) -> Self {
+        assert!((4..=16).contains(&M::USIZE) && M::USIZE % 2 == 0);
+        assert!((7..=13).contains(&N::USIZE));
+        Self {
+            cryp: CrypRef::take_shared(),
+            key: key.clone(),
+            _sizes: PhantomData,
+        }
+    }
+}
+
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+impl<KS: KeySize, M: ArrayLength<u8>, N: ArrayLength<u8>> AeadCore for AesCcm<'_, KS, M, N> {
+    type NonceSize = N;
+    type TagSize = M;
+    type CiphertextOverhead = U0;
+}
+
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+impl<KS: KeySize, M: ArrayLength<u8>, N: ArrayLength<u8>> AeadInPlace for AesCcm<'_, KS, M, N> {
+    fn encrypt_in_place_detached(
+        &self,
+        nonce: &Nonce<Self>,
+        associated_data: &[u8],
+        buffer: &mut [u8],
+    ) -> aead::Result<Tag<Self>> {
+        let mut tag = Tag::<Self>::default();
+        self.cryp
+            .borrow_mut()
+            .ccm_encrypt(&self.key, nonce, associated_data, buffer, &mut tag)
+            .map_err(|_| aead::Error)?;
+        Ok(tag)
+    }
+
+    fn decrypt_in_place_detached(
+        &self,
+        nonce: &Nonce<Self>,
+        associated_data: &[u8],
+        buffer: &mut [u8],
+        tag: &Tag<Self>,
+    ) -> aead::Result<()> {
+        self.cryp
+            .borrow_mut()
+            .ccm_decrypt(&self.key, nonce, associated_data, buffer, tag)
+            .map_err(|_| aead::Error)
+    }
+}
@@ -0,0 +1,846 @@
+//! Digital filter for sigma-delta modulators
+//!
+//! A DFSDM has serial input channels, which receive a 1-bit stream from a sigma-delta modulator
+//! or a PDM microphone, and filters, which decimate the stream of one or more channels into
+//! 24-bit samples.
+//!
+//! Two PDM microphones sharing a clock and a data line, one sampled on the rising and one on the
+//! falling edge, can be read with two filters running in lock step:
+//!
+//! ```rust,ignore
+//! let clocks = rcc.cfgr.freeze();
+//! let (mut dfsdm, (mut ch0, mut ch1, ..), (mut flt0, mut flt1)) = dp.DFSDM1.split();
+//!
+//! // 1.024 MHz microphone clock, 16 kHz with 64 times oversampling
+//! dfsdm.clock_output(gpioc.pc2, ClockSource::System, 1024.kHz(), &clocks).unwrap();
+//! ch1.connect_data(gpiob.pb12);
+//! ch1.configure(&ChannelConfig::new(Interface::SpiRising, SerialClock::Internal));
+//! // Channel 0 samples the data pin of channel 1 on the other edge
+//! ch0.configure(
+//!     &ChannelConfig::new(Interface::SpiFalling, SerialClock::Internal).input(Input::Next),
+//! );
+//!
+//! let config = FilterConfig::new(SincOrder::Sinc3, 64, 1);
+//! flt0.configure(&config);
+//! flt0.regular(0, true);
+//! flt1.configure(&config);
+//! flt1.regular(1, true);
+//! flt1.sync_regular(true);
+//!
+//! flt0.set_dma(true);
+//! flt1.set_dma(true);
+//! let mut left = Transfer::init_peripheral_to_memory(stream0, flt0, left_buf, None, dma_config);
+//! let mut right = Transfer::init_peripheral_to_memory(stream1, flt1, right_buf, None, dma_config);
+//! right.start(|flt| flt.enable());
+//! // Starting filter 0 also starts filter 1
+//! left.start(|flt| {
+//!     flt.enable();
+//!     flt.start_regular();
+//! });
+//! ```
+//!
+//! Samples in the DMA buffers are raw data register values, see [`sample`] and [`to_pcm16`].
+//! DMA is only available on DFSDM1.
+
+use core::marker::PhantomData;
+
+use enumflags2::BitFlags;
+use vcell::VolatileCell;
+
+use crate::dma::traits::PeriAddress;
+#[cfg(feature = "dfsdm1")]
+use crate::dma::traits::{DMASet, FLT};
+#[cfg(feature = "dfsdm1")]
+use crate::dma::PeripheralToMemory;
+use crate::gpio::alt::{DfsdmAdvanced, DfsdmBasic, DfsdmGeneral};
+use crate::pac;
+use crate::rcc::{Clocks, Enable, Reset};
+use crate::time::Hertz;
+
+#[cfg(all(feature = "dfsdm1", feature = "gpio-f412"))]
+use pac::DFSDM as DFSDM1;
+#[cfg(all(feature = "dfsdm1", feature = "gpio-f413"))]
+use pac::DFSDM1;
+
+/// DFSDM peripheral
+pub trait Instance: crate::Sealed + Enable + Reset + DfsdmGeneral + Sized {
+    /// Tuple of all channels
+    type Channels;
+    /// Tuple of all filters
+    type Filters;
+
+    #[doc(hidden)]
+    fn ptr() -> *const u8;
+    #[doc(hidden)]
+    fn parts() -> (Self::Channels, Self::Filters);
+}
+
+#[cfg(feature = "dfsdm1")]
+impl Instance for DFSDM1 {
+    type Channels = (
+        Channel<Self, 0>,
+        Channel<Self, 1>,
+        Channel<Self, 2>,
+        Channel<Self, 3>,
+    );
+    type Filters = (Filter<Self, 0>, Filter<Self, 1>);
+
+    fn ptr() -> *const u8 {
+        DFSDM1::ptr() as *const _
+    }
+
+    fn parts() -> (Self::Channels, Self::Filters) {
+        (
+            (
+                Channel::new(),
+                Channel::new(),
+                Channel::new(),
+                Channel::new(),
+            ),
+            (Filter::new(), Filter::new()),
+        )
+    }
+}
+
+#[cfg(feature = "dfsdm2")]
+impl Instance for pac::DFSDM2 {
+    type Channels = (
+        Channel<Self, 0>,
+        Channel<Self, 1>,
+        Channel<Self, 2>,
+        Channel<Self, 3>,
+        Channel<Self, 4>,
+        Channel<Self, 5>,
+        Channel<Self, 6>,
+        Channel<Self, 7>,
+    );
+    type Filters = (
+        Filter<Self, 0>,
+        Filter<Self, 1>,
+        Filter<Self, 2>,
+        Filter<Self, 3>,
+    );
+
+    fn ptr() -> *const u8 {
+        pac::DFSDM2::ptr() as *const _
+    }
+
+    fn parts() -> (Self::Channels, Self::Filters) {
+        (
+            (
+                Channel::new(),
+                Channel::new(),
+                Channel::new(),
+                Channel::new(),
+                Channel::new(),
+                Channel::new(),
+                Channel::new(),
+                Channel::new(),
+            ),
+            (Filter::new(), Filter::new(), Filter::new(), Filter::new()),
+        )
+    }
+}
+
+/// Splits a DFSDM into its channels and filters.
+pub trait DfsdmExt: Instance {
+    fn split(self) -> (Dfsdm<Self>, Self::Channels, Self::Filters);
+}
+
+impl<DFSDM: Instance> DfsdmExt for DFSDM {
+    fn split(self) -> (Dfsdm<Self>, Self::Channels, Self::Filters) {
+        unsafe {
+            DFSDM::enable_unchecked();
+            DFSDM::reset_unchecked();
+        }
+        let (channels, filters) = DFSDM::parts();
+        let dfsdm = Dfsdm { dfsdm: self };
+        // The global enable bit lives in channel 0
+        dfsdm.ch0().modify_cfgr1(|r| r | CFGR1_DFSDMEN);
+        (dfsdm, channels, filters)
+    }
+}
+
+/// Source of the serial clock output
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSource {
+    /// DFSDM kernel clock (PCLK2)
+    System,
+    /// I2S APB1 clock
+    Audio,
+}
+
+/// Serial input type and sampling edge
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interface {
+    /// SPI, data sampled on the rising clock edge
+    SpiRising = 0b00,
+    /// SPI, data sampled on the falling clock edge
+    SpiFalling = 0b01,
+    /// Manchester coded, a rising edge is a 0
+    ManchesterRising = 0b10,
+    /// Manchester coded, a falling edge is a 0
+    ManchesterFalling = 0b11,
+}
+
+/// SPI clock of a channel
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SerialClock {
+    /// `CKINy` pin, also used for Manchester input
+    External = 0b00,
+    /// Clock output of the DFSDM
+    Internal = 0b01,
+    /// Clock output divided by 2, sampled on falling edges
+    InternalHalfFalling = 0b10,
+    /// Clock output divided by 2, sampled on rising edges
+    InternalHalfRising = 0b11,
+}
+
+/// Data pin of a channel
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    /// `DATINy`
+    Own,
+    /// `DATINy+1`, to sample two modulators sharing a data line
+    Next,
+}
+
+/// Channel configuration
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelConfig {
+    pub interface: Interface,
+    pub clock: SerialClock,
+    pub input: Input,
+    /// 24-bit offset subtracted from the filter output
+    pub offset: i32,
+    /// Right shift of the filter output, 0 to 31
+    pub right_shift: u8,
+}
+
+impl ChannelConfig {
+    pub const fn new(interface: Interface, clock: SerialClock) -> Self {
+        Self {
+            interface,
+            clock,
+            input: Input::Own,
+            offset: 0,
+            right_shift: 0,
+        }
+    }
+
+    pub const fn input(mut self, input: Input) -> Self {
+        self.input = input;
+        self
+    }
+
+    pub const fn offset(mut self, offset: i32) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub const fn right_shift(mut self, right_shift: u8) -> Self {
+        self.right_shift = right_shift;
+        self
+    }
+
+    /// Value of `CHyCFGR1`, without the enable bits.
+    pub const fn cfgr1(&self) -> u32 {
+        let chinsel = match self.input {
+            Input::Own => 0,
+            Input::Next => 1,
+        };
+        self.interface as u32 | ((self.clock as u32) << 2) | (chinsel << 8)
+    }
+
+    /// Value of `CHyCFGR2`.
+    pub const fn cfgr2(&self) -> u32 {
+        (((self.right_shift & 0x1f) as u32) << 3) | ((self.offset as u32) << 8)
+    }
+}
+
+/// Filter type
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SincOrder {
+    FastSinc = 0,
+    Sinc1 = 1,
+    Sinc2 = 2,
+    Sinc3 = 3,
+    Sinc4 = 4,
+    Sinc5 = 5,
+}
+
+/// Filter configuration
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilterConfig {
+    pub order: SincOrder,
+    /// Sinc filter oversampling ratio, 1 to 1024
+    pub oversampling: u16,
+    /// Integrator oversampling ratio, 1 to 256
+    pub integrator: u16,
+}
+
+impl FilterConfig {
+    pub const fn new(order: SincOrder, oversampling: u16, integrator: u16) -> Self {
+        Self {
+            order,
+            oversampling,
+            integrator,
+        }
+    }
+
+    pub const fn is_valid(&self) -> bool {
+        self.oversampling >= 1
+            && self.oversampling <= 1024
+            && self.integrator >= 1
+            && self.integrator <= 256
+    }
+
+    /// Value of `FLTxFCR`.
+    pub const fn fcr(&self) -> u32 {
+        ((self.order as u32) << 29)
+            | ((self.oversampling as u32 - 1) << 16)
+            | (self.integrator as u32 - 1)
+    }
+
+    /// Magnitude of the output for a full scale 1-bit input, before the channel right shift.
+    pub const fn gain(&self) -> u64 {
+        let fosr = self.oversampling as u64;
+        let sinc = match self.order {
+            SincOrder::FastSinc => 2 * fosr * fosr,
+            order => {
+                let mut gain = 1;
+                let mut i = 0;
+                while i < order as u32 {
+                    gain *= fosr;
+                    i += 1;
+                }
+                gain
+            }
+        };
+        sinc * self.integrator as u64
+    }
+
+    /// Signed width of the output, before the channel right shift.
+    pub const fn output_bits(&self) -> u32 {
+        // Bits for the magnitude and the sign
+        64 - self.gain().leading_zeros() + 1
+    }
+
+    /// Smallest channel right shift that fits the output into the 24-bit data registers.
+    pub const fn right_shift(&self) -> u8 {
+        self.output_bits().saturating_sub(24) as u8
+    }
+
+    /// Output rate for a serial clock of `clock`.
+    pub const fn output_rate(&self, clock: Hertz) -> Hertz {
+        Hertz::from_raw(clock.raw() / (self.oversampling as u32 * self.integrator as u32))
+    }
+}
+
+/// Divider of `source` closest to `target`, as the `CKOUTDIV` value.
+pub const fn clock_output_divider(source: u32, target: u32) -> Option<u8> {
+    if target == 0 {
+        return None;
+    }
+    let div = (source + target / 2) / target;
+    if div < 2 || div > 256 {
+        None
+    } else {
+        Some((div - 1) as u8)
+    }
+}
+
+/// Signed sample in a data register value.
+pub const fn sample(raw: u32) -> i32 {
+    raw as i32 >> 8
+}
+
+/// Converts data register values to 16-bit PCM, keeping the top 16 bits of each 24-bit sample.
+pub fn to_pcm16(raw: &[u32], pcm: &mut [i16]) {
+    for (pcm, raw) in pcm.iter_mut().zip(raw) {
+        *pcm = (*raw as i32 >> 16) as i16;
+    }
+}
+
+/// DFSDM error
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The requested clock output frequency cannot be derived from the source
+    ClockOutput,
+    /// The audio clock is not enabled in `rcc::CFGR`
+    NoClock,
+    /// A conversion was lost because the previous one was not read
+    Overrun,
+}
+
+/// Filter interrupt events
+#[enumflags2::bitflags]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Event {
+    InjectedEnd = 1 << 0,
+    RegularEnd = 1 << 1,
+    InjectedOverrun = 1 << 2,
+    RegularOverrun = 1 << 3,
+    AnalogWatchdog = 1 << 4,
+    ShortCircuit = 1 << 5,
+    ClockAbsence = 1 << 6,
+}
+
+// CHyCFGR1
+const CFGR1_CHEN: u32 = 1 << 7;
+const CFGR1_CKOUTDIV_MASK: u32 = 0xff << 16;
+const CFGR1_CKOUTSRC: u32 = 1 << 30;
+const CFGR1_DFSDMEN: u32 = 1 << 31;
+// FLTxCR1
+const CR1_DFEN: u32 = 1 << 0;
+const CR1_JSWSTART: u32 = 1 << 1;
+const CR1_JSCAN: u32 = 1 << 4;
+const CR1_JDMAEN: u32 = 1 << 5;
+const CR1_RSWSTART: u32 = 1 << 17;
+const CR1_RCONT: u32 = 1 << 18;
+const CR1_RSYNC: u32 = 1 << 19;
+const CR1_RDMAEN: u32 = 1 << 21;
+const CR1_RCH_MASK: u32 = 0b111 << 24;
+// FLTxISR
+const ISR_JEOCF: u32 = 1 << 0;
+const ISR_REOCF: u32 = 1 << 1;
+const ISR_JOVRF: u32 = 1 << 2;
+const ISR_ROVRF: u32 = 1 << 3;
+
+#[repr(C)]
+struct ChannelRegisters {
+    cfgr1: VolatileCell<u32>,
+    cfgr2: VolatileCell<u32>,
+    awscdr: VolatileCell<u32>,
+    wdatr: VolatileCell<u32>,
+    datinr: VolatileCell<u32>,
+}
+
+#[repr(C)]
+struct FilterRegisters {
+    cr1: VolatileCell<u32>,
+    cr2: VolatileCell<u32>,
+    isr: VolatileCell<u32>,
+    icr: VolatileCell<u32>,
+    jchgr: VolatileCell<u32>,
+    fcr: VolatileCell<u32>,
+    jdatar: VolatileCell<u32>,
+    rdatar: VolatileCell<u32>,
+}
+
+impl ChannelRegisters {
+    fn get<DFSDM: Instance>(y: u8) -> &'static Self {
+        // NOTE(unsafe) channel y registers start at 0x20 * y
+        unsafe { &*(DFSDM::ptr().add(0x20 * y as usize) as *const Self) }
+    }
+
+    fn modify_cfgr1(&self, f: impl FnOnce(u32) -> u32) {
+        self.cfgr1.set(f(self.cfgr1.get()));
+    }
+}
+
+impl FilterRegisters {
+    fn get<DFSDM: Instance>(x: u8) -> &'static Self {
+        // NOTE(unsafe) filter x registers start at 0x100 + 0x80 * x
+        unsafe { &*(DFSDM::ptr().add(0x100 + 0x80 * x as usize) as *const Self) }
+    }
+
+    fn modify_cr1(&self, f: impl FnOnce(u32) -> u32) {
+        self.cr1.set(f(self.cr1.get()));
+    }
+}
+
+/// Global DFSDM settings
+pub struct Dfsdm<DFSDM> {
+    dfsdm: DFSDM,
+}
+
+impl<DFSDM: Instance> Dfsdm<DFSDM> {
+    fn ch0(&self) -> &'static ChannelRegisters {
+        ChannelRegisters::get::<DFSDM>(0)
+    }
+
+    /// Outputs a serial clock for the modulators on `pin` and returns its real frequency.
+    ///
+    /// Must be called before enabling channels.
+    pub fn clock_output(
+        &mut self,
+        pin: impl Into<DFSDM::Ckout>,
+        source: ClockSource,
+        freq: Hertz,
+        clocks: &Clocks,
+    ) -> Result<Hertz, Error> {
+        let source_clk = match source {
+            ClockSource::System => clocks.pclk2(),
+            ClockSource::Audio => clocks.i2s_apb1_clk().ok_or(Error::NoClock)?,
+        };
+        let div = clock_output_divider(source_clk.raw(), freq.raw()).ok_or(Error::ClockOutput)?;
+        let _pin = pin.into();
+        let src = match source {
+            ClockSource::System => 0,
+            ClockSource::Audio => CFGR1_CKOUTSRC,
+        };
+        let ch0 = self.ch0();
+        // CKOUT settings can only be changed with the DFSDM disabled
+        ch0.modify_cfgr1(|r| r & !CFGR1_DFSDMEN);
+        ch0.modify_cfgr1(|r| {
+            (r & !(CFGR1_CKOUTDIV_MASK | CFGR1_CKOUTSRC)) | src | (u32::from(div) << 16)
+        });
+        ch0.modify_cfgr1(|r| r | CFGR1_DFSDMEN);
+        Ok(Hertz::from_raw(source_clk.raw() / (u32::from(div) + 1)))
+    }
+
+    /// Disables the DFSDM and returns the peripheral.
+    ///
+    /// Channels and filters should be dropped first.
+    pub fn release(self) -> DFSDM {
+        self.ch0().modify_cfgr1(|r| r & !CFGR1_DFSDMEN);
+        self.dfsdm
+    }
+}
+
+/// Pin types of a channel
+pub trait ChannelPins {
+    type Ckin;
+    type Datin;
+}
+
+macro_rules! channel_pins {
+    ($Trait:ident: $($Y:literal => ($Ckin:ident, $Datin:ident),)+) => {
+        $(
+            impl<DFSDM: Instance + $Trait> ChannelPins for Channel<DFSDM, $Y> {
+                type Ckin = DFSDM::$Ckin;
+                type Datin = DFSDM::$Datin;
+            }
+        )+
+    };
+}
+
+channel_pins!(DfsdmBasic:
+    0 => (Ckin0, Datin0),
+    1 => (Ckin1, Datin1),
+);
+channel_pins!(DfsdmGeneral:
+    2 => (Ckin2, Datin2),
+    3 => (Ckin3, Datin3),
+);
+channel_pins!(DfsdmAdvanced:
+    4 => (Ckin4, Datin4),
+    5 => (Ckin5, Datin5),
+    6 => (Ckin6, Datin6),
+    7 => (Ckin7, Datin7),
+);
+
+/// Serial input channel `Y`
+pub struct Channel<DFSDM, const Y: u8> {
+    _dfsdm: PhantomData<DFSDM>,
+}
+
+impl<DFSDM: Instance, const Y: u8> Channel<DFSDM, Y> {
+    fn new() -> Self {
+        Self {
+            _dfsdm: PhantomData,
+        }
+    }
+
+    fn regs(&self) -> &'static ChannelRegisters {
+        ChannelRegisters::get::<DFSDM>(Y)
+    }
+
+    /// Switches `pin` to the data input of this channel.
+    pub fn connect_data(&mut self, pin: impl Into<<Self as ChannelPins>::Datin>)
+    where
+        Self: ChannelPins,
+    {
+        let _pin = pin.into();
+    }
+
+    /// Switches `pin` to the clock input of this channel.
+    pub fn connect_clock(&mut self, pin: impl Into<<Self as ChannelPins>::Ckin>)
+    where
+        Self: ChannelPins,
+    {
+        let _pin = pin.into();
+    }
+
+    /// Configures and enables the channel.
+    pub fn configure(&mut self, config: &ChannelConfig) {
+        let regs = self.regs();
+        self.disable();
+        regs.cfgr2.set(config.cfgr2());
+        // Channel 0 also holds the global settings
+        regs.modify_cfgr1(|r| (r & 0xffff_0000) | config.cfgr1());
+        self.enable();
+    }
+
+    pub fn enable(&mut self) {
+        self.regs().modify_cfgr1(|r| r | CFGR1_CHEN);
+    }
+
+    pub fn disable(&mut self) {
+        self.regs().modify_cfgr1(|r| r & !CFGR1_CHEN);
+    }
+
+    /// Sets the 24-bit offset subtracted from conversions.
+    pub fn set_offset(&mut self, offset: i32) {
+        let regs = self.regs();
+        regs.cfgr2
+            .set((regs.cfgr2.get() & 0xff) | ((offset as u32) << 8));
+    }
+}
+
+/// Digital filter `X`
+pub struct Filter<DFSDM, const X: u8> {
+    _dfsdm: PhantomData<DFSDM>,
+}
+
+impl<DFSDM: Instance, const X: u8> Filter<DFSDM, X> {
+    fn new() -> Self {
+        Self {
+            _dfsdm: PhantomData,
+        }
+    }
+
+    fn regs(&self) -> &'static FilterRegisters {
+        FilterRegisters::get::<DFSDM>(X)
+    }
+
+    /// Sets the filter order and oversampling ratios. Disables the filter.
+    ///
+    /// # Panics
+    ///
+    /// When `config` is out of range.
+    pub fn configure(&mut self, config: &FilterConfig) {
+        assert!(config.is_valid());
+        self.disable();
+        self.regs().fcr.set(config.fcr());
+    }
+
+    pub fn enable(&mut self) {
+        self.regs().modify_cr1(|r| r | CR1_DFEN);
+    }
+
+    pub fn disable(&mut self) {
+        self.regs().modify_cr1(|r| r & !CR1_DFEN);
+    }
+
+    /// Selects the channel of regular conversions, which run once per
+    /// [`start_regular`](Self::start_regular) or back to back with `continuous`.
+    pub fn regular(&mut self, channel: u8, continuous: bool) {
+        let cont = if continuous { CR1_RCONT } else { 0 };
+        self.regs().modify_cr1(|r| {
+            (r & !(CR1_RCH_MASK | CR1_RCONT)) | (u32::from(channel & 0b111) << 24) | cont
+        });
+    }
+
+    /// Selects the channels of injected conversions. With `scan`, all of them are converted
+    /// per trigger, otherwise one after the other.
+    pub fn injected(&mut self, channels: u8, scan: bool) {
+        let regs = self.regs();
+        regs.jchgr.set(channels.into());
+        regs.modify_cr1(|r| if scan { r | CR1_JSCAN } else { r & !CR1_JSCAN });
+    }
+
+    /// Starts regular conversions together with filter 0. Has no effect on filter 0.
+    pub fn sync_regular(&mut self, sync: bool) {
+        self.regs()
+            .modify_cr1(|r| if sync { r | CR1_RSYNC } else { r & !CR1_RSYNC });
+    }
+
+    pub fn start_regular(&mut self) {
+        self.regs().modify_cr1(|r| r | CR1_RSWSTART);
+    }
+
+    pub fn start_injected(&mut self) {
+        self.regs().modify_cr1(|r| r | CR1_JSWSTART);
+    }
+
+    /// Requests DMA for regular conversions, which may not be changed with the filter enabled.
+    pub fn set_dma(&mut self, enable: bool) {
+        self.regs().modify_cr1(|r| {
+            if enable {
+                r | CR1_RDMAEN
+            } else {
+                r & !CR1_RDMAEN
+            }
+        });
+    }
+
+    /// Requests DMA for injected conversions instead of regular ones.
+    pub fn set_injected_dma(&mut self, enable: bool) {
+        self.regs().modify_cr1(|r| {
+            if enable {
+                r | CR1_JDMAEN
+            } else {
+                r & !CR1_JDMAEN
+            }
+        });
+    }
+
+    /// Takes a regular conversion and its channel.
+    pub fn read_regular(&mut self) -> nb::Result<(i32, u8), Error> {
+        let regs = self.regs();
+        let isr = regs.isr.get();
+        if isr & ISR_ROVRF != 0 {
+            regs.icr.set(ISR_ROVRF);
+            Err(Error::Overrun.into())
+        } else if isr & ISR_REOCF != 0 {
+            let raw = regs.rdatar.get();
+            Ok((sample(raw), (raw & 0b111) as u8))
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    /// Takes an injected conversion and its channel.
+    pub fn read_injected(&mut self) -> nb::Result<(i32, u8), Error> {
+        let regs = self.regs();
+        let isr = regs.isr.get();
+        if isr & ISR_JOVRF != 0 {
+            regs.icr.set(ISR_JOVRF);
+            Err(Error::Overrun.into())
+        } else if isr & ISR_JEOCF != 0 {
+            let raw = regs.jdatar.get();
+            Ok((sample(raw), (raw & 0b111) as u8))
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    fn listen_event(&mut self, disable: Option<BitFlags<Event>>, enable: Option<BitFlags<Event>>) {
+        let cr2 = &self.regs().cr2;
+        let mut bits = cr2.get();
+        if let Some(d) = disable {
+            bits &= !d.bits();
+        }
+        if let Some(e) = enable {
+            bits |= e.bits();
+        }
+        cr2.set(bits);
+    }
+}
+
+impl<DFSDM: Instance, const X: u8> crate::Listen for Filter<DFSDM, X> {
+    type Event = Event;
+
+    fn listen(&mut self, event: impl Into<BitFlags<Self::Event>>) {
+        self.listen_event(None, Some(event.into()));
+    }
+
+    fn listen_only(&mut self, event: impl Into<BitFlags<Self::Event>>) {
+        self.listen_event(Some(BitFlags::ALL), Some(event.into()));
+    }
+
+    fn unlisten(&mut self, event: impl Into<BitFlags<Self::Event>>) {
+        self.listen_event(Some(event.into()), None);
+    }
+}
+
+impl<DFSDM: Instance, const X: u8> crate::ReadFlags for Filter<DFSDM, X> {
+    type Flag = Event;
+
+    fn flags(&self) -> BitFlags<Self::Flag> {
+        let isr = self.regs().isr.get();
+        let mut flags = BitFlags::from_bits_truncate(isr & 0b1_1111);
+        if isr & (0xff << 24) != 0 {
+            flags |= Event::ShortCircuit;
+        }
+        if isr & (0xff << 16) != 0 {
+            flags |= Event::ClockAbsence;
+        }
+        flags
+    }
+}
+
+impl<DFSDM: Instance, const X: u8> crate::ClearFlags for Filter<DFSDM, X> {
+    type Flag = Event;
+
+    /// End of conversion flags are cleared by reading the data, the analog watchdog flags
+    /// in `FLTxAWCFR`.
+    fn clear_flags(&mut self, flags: impl Into<BitFlags<Self::Flag>>) {
+        let flags = flags.into();
+        let mut icr = flags.bits() & (ISR_JOVRF | ISR_ROVRF);
+        if flags.contains(Event::ShortCircuit) {
+            icr |= 0xff << 24;
+        }
+        if flags.contains(Event::ClockAbsence) {
+            icr |= 0xff << 16;
+        }
+        self.regs().icr.set(icr);
+    }
+}
+
+unsafe impl<DFSDM: Instance, const X: u8> PeriAddress for Filter<DFSDM, X> {
+    #[inline(always)]
+    fn address(&self) -> u32 {
+        self.regs().rdatar.as_ptr() as u32
+    }
+
+    type MemSize = u32;
+}
+
+#[cfg(feature = "dfsdm1")]
+unsafe impl<const X: u8, STREAM, const CHANNEL: u8> DMASet<STREAM, CHANNEL, PeripheralToMemory>
+    for Filter<DFSDM1, X>
+where
+    FLT<DFSDM1, X>: DMASet<STREAM, CHANNEL, PeripheralToMemory>,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_gain() {
+        let filter = |order, fosr, iosr| FilterConfig::new(order, fosr, iosr);
+        // Sinc3 with FOSR 64: ±2^18, signed 20 bits
+        let f = filter(SincOrder::Sinc3, 64, 1);
+        assert_eq!(f.gain(), 1 << 18);
+        assert_eq!(f.output_bits(), 20);
+        assert_eq!(f.right_shift(), 0);
+        // FastSinc: 2 FOSR^2
+        let f = filter(SincOrder::FastSinc, 64, 1);
+        assert_eq!(f.gain(), 8192);
+        assert_eq!(f.output_bits(), 15);
+        assert_eq!(filter(SincOrder::FastSinc, 1, 1).output_bits(), 3);
+        assert_eq!(filter(SincOrder::Sinc1, 1, 1).output_bits(), 2);
+        // The integrator multiplies the gain
+        let f = filter(SincOrder::Sinc2, 16, 4);
+        assert_eq!(f.gain(), 1024);
+        assert_eq!(f.output_bits(), 12);
+        // Not a power of two
+        let f = filter(SincOrder::Sinc4, 100, 1);
+        assert_eq!(f.gain(), 100_000_000);
+        assert_eq!(f.output_bits(), 28);
+        assert_eq!(f.right_shift(), 4);
+        let f = filter(SincOrder::Sinc5, 32, 1);
+        assert_eq!(f.output_bits(), 27);
+        assert_eq!(f.right_shift(), 3);
+        // Largest configuration still fits in a u64
+        let f = filter(SincOrder::Sinc5, 1024, 256);
+        assert_eq!(f.gain(), 1 << 58);
+        assert_eq!(f.output_bits(), 60);
+    }
+
+    #[test]
+    fn filter_registers() {
+        let f = FilterConfig::new(SincOrder::Sinc3, 64, 4);
+        assert!(f.is_valid());
+        assert_eq!(f.fcr(), (3 << 29) | (63 << 16) | 3);
+        assert_eq!(f.output_rate(Hertz::MHz(2)), Hertz::from_raw(7812));
+        assert!(!FilterConfig::new(SincOrder::Sinc3, 0, 1).is_valid());
+        assert!(!FilterConfig::new(SincOrder::Sinc3, 1025, 1).is_valid());
+        assert!(!FilterConfig::new(SincOrder::Sinc3, 64, 257).is_valid());
+    }
+}
@@ -0,0 +1,218 @@
+//! Futures for one-shot DMA transfers.
+//!
+//! The async drivers (`serial`, `spi` and `i2c`) start a transfer on a stream and await its
+//! [`TransferFuture`]. The future is woken from the stream interrupt, so the `DMAx_STREAMy`
+//! interrupt of every stream given to an async driver must be unmasked in the NVIC and its handler
+//! must call [`StreamX::on_interrupt`]:
+//!
+//! ```rust,ignore
+//! #[interrupt]
+//! fn DMA2_STREAM7() {
+//!     dma::Stream7::<pac::DMA2>::on_interrupt();
+//! }
+//! ```
+//!
+//! Dropping a [`TransferFuture`] before it completes aborts the transfer.
+
+use core::{
+    future::Future,
+    mem,
+    pin::Pin,
+    sync::atomic::{compiler_fence, Ordering},
+    task::{Context, Poll, Waker},
+};
+
+use enumflags2::BitFlags;
+
+use super::{
+    stream_disable,
+    traits::{Channel, Instance, Stream},
+    ChannelX, DmaDataSize, DmaDirection, DmaEvent, DmaFlag, DmaFlowController, StreamX,
+};
+use crate::{pac, waker::WakerCell, Listen};
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NEW_WAKER: WakerCell = WakerCell::new();
+static WAKERS: [WakerCell; 16] = [NEW_WAKER; 16];
+
+/// The stream reported a transfer error or a direct mode error.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferError;
+
+/// DMA stream able to wake a task from its interrupt.
+pub trait AsyncStream: Stream {
+    #[doc(hidden)]
+    fn register_waker(waker: &Waker);
+    #[doc(hidden)]
+    fn wake();
+}
+
+impl<DMA: Instance, const S: u8> AsyncStream for StreamX<DMA, S>
+where
+    Self: Stream,
+{
+    #[inline(always)]
+    fn register_waker(waker: &Waker) {
+        WAKERS[Self::waker_index()].register(waker)
+    }
+
+    #[inline(always)]
+    fn wake() {
+        WAKERS[Self::waker_index()].wake()
+    }
+}
+
+impl<DMA: Instance, const S: u8> StreamX<DMA, S>
+where
+    Self: Stream,
+{
+    fn waker_index() -> usize {
+        let dma = if DMA::ptr() == <pac::DMA1 as Instance>::ptr() {
+            0
+        } else {
+            1
+        };
+        dma * 8 + S as usize
+    }
+
+    /// Handles the stream interrupt for the async drivers.
+    ///
+    /// Masks the stream interrupts and wakes the task waiting on the transfer.
+    pub fn on_interrupt() {
+        Self::new().unlisten(TRANSFER_EVENTS);
+        Self::wake();
+    }
+}
+
+const TRANSFER_EVENTS: BitFlags<DmaEvent> = enumflags2::make_bitflags!(DmaEvent::{
+    TransferComplete | TransferError | DirectModeError
+});
+
+/// State of a one-shot transfer given the stream flags.
+pub(crate) fn transfer_state(flags: BitFlags<DmaFlag>) -> Poll<Result<(), TransferError>> {
+    if flags.intersects(DmaFlag::TransferError | DmaFlag::DirectModeError) {
+        Poll::Ready(Err(TransferError))
+    } else if flags.contains(DmaFlag::TransferComplete) {
+        Poll::Ready(Ok(()))
+    } else {
+        Poll::Pending
+    }
+}
+
+/// Future of a one-shot transfer started with [`start_transfer`].
+pub struct TransferFuture<'a, STREAM: AsyncStream> {
+    stream: &'a mut STREAM,
+    running: bool,
+}
+
+impl<'a, STREAM: AsyncStream> TransferFuture<'a, STREAM> {
+    /// Number of items not transferred yet.
+    pub fn remaining(&self) -> u16 {
+        self.stream.number_of_transfers()
+    }
+
+    /// Stops the transfer and returns the number of items that were not transferred.
+    pub fn abort(&mut self) -> u16 {
+        self.stop();
+        self.stream.number_of_transfers()
+    }
+
+    fn stop(&mut self) {
+        if self.running {
+            self.running = false;
+            stream_disable(self.stream);
+            self.stream.unlisten(TRANSFER_EVENTS);
+            self.stream.clear_all_flags();
+            // "No re-ordering of reads and writes across this point is allowed"
+            compiler_fence(Ordering::SeqCst);
+        }
+    }
+}
+
+impl<'a, STREAM: AsyncStream> Future for TransferFuture<'a, STREAM> {
+    type Output = Result<(), TransferError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        if !this.running {
+            return Poll::Ready(Ok(()));
+        }
+        STREAM::register_waker(cx.waker());
+        match transfer_state(this.stream.flags()) {
+            Poll::Ready(result) => {
+                this.stop();
+                Poll::Ready(result)
+            }
+            Poll::Pending => {
+                // A flag raised after the check above fires the interrupt as soon as it is unmasked
+                this.stream.listen(TRANSFER_EVENTS);
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<'a, STREAM: AsyncStream> Drop for TransferFuture<'a, STREAM> {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Configures `stream` for a single transfer of `len` words between `memory` and the
+/// peripheral register at `peripheral` and enables it.
+///
+/// # Safety
+///
+/// `memory` must be valid for `len` words (or for one word if `memory_increment` is `false`)
+/// until the returned future completes or is dropped, and the peripheral must be set up to issue
+/// the DMA requests of `CHANNEL` on `stream`.
+///
+/// # Panics
+///
+/// When `len` is zero or does not fit in the 16-bit NDTR register.
+pub(crate) unsafe fn start_transfer<STREAM, const CHANNEL: u8, W>(
+    stream: &mut STREAM,
+    direction: DmaDirection,
+    peripheral: u32,
+    memory: *const W,
+    len: usize,
+    memory_increment: bool,
+) -> TransferFuture<'_, STREAM>
+where
+    STREAM: AsyncStream,
+    ChannelX<CHANNEL>: Channel,
+{
+    assert!(len > 0 && len <= u16::MAX as usize);
+    let size = match mem::size_of::<W>() {
+        1 => DmaDataSize::Byte,
+        2 => DmaDataSize::HalfWord,
+        _ => DmaDataSize::Word,
+    };
+
+    stream_disable(stream);
+    stream.set_channel(ChannelX::<CHANNEL>::VALUE);
+    stream.set_direction(direction);
+    stream.set_flow_controller(DmaFlowController::Dma);
+    stream.set_peripheral_address(peripheral);
+    stream.set_memory_address(memory as u32);
+    stream.set_number_of_transfers(len as u16);
+    stream.set_memory_size(size);
+    stream.set_peripheral_size(size);
+    stream.set_memory_increment(memory_increment);
+    stream.set_peripheral_increment(false);
+    stream.set_circular_mode(false);
+    stream.set_double_buffer(false);
+    stream.set_fifo_enable(false);
+    stream.clear_all_flags();
+    stream.listen_only(TRANSFER_EVENTS);
+
+    // "Preceding reads and writes cannot be moved past subsequent writes"
+    compiler_fence(Ordering::Release);
+    stream.enable();
+
+    TransferFuture {
+        stream,
+        running: true,
+    }
+}
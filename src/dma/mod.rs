@@ -20,6 +20,8 @@ use enumflags2::BitFlags;
 
 use crate::{pac, rcc};
 
+#[cfg(feature = "async")]
+pub mod future;
 pub mod traits;
 use crate::serial::RxISR;
 use traits::{
@@ -0,0 +1,821 @@
+//! Chrom-ART accelerator (DMA2D)
+//!
+//! The DMA2D fills and copies rectangles between frame buffers, converting pixel formats and
+//! blending two sources on the way. [`Dma2d::fill`], [`Dma2d::copy`], [`Dma2d::blend`] and
+//! [`Dma2d::blend_onto`] block until the operation completes:
+//!
+//! ```rust,ignore
+//! let mut dma2d = Dma2d::new(dp.DMA2D);
+//! let mut screen = ImageMut::new(unsafe { &mut FRAME_BUFFER }, 480, OutputFormat::Rgb565);
+//! dma2d.fill(&mut screen, Rect::new(0, 0, 480, 272), 0x001f).unwrap();
+//! ```
+//!
+//! Their `start_` variants return a [`Transfer`] borrowing the buffers until it completes: call
+//! [`Transfer::wait`], poll [`Transfer::is_complete`], or with the `async` feature `.await` it
+//! after unmasking the `DMA2D` interrupt whose handler calls [`on_interrupt`]. They are `unsafe`
+//! because leaking the transfer, e.g. with `mem::forget`, ends the borrows while the DMA2D is
+//! still accessing the buffers:
+//!
+//! ```rust,ignore
+//! #[interrupt]
+//! fn DMA2D() {
+//!     dma2d::on_interrupt();
+//! }
+//!
+//! let icon = Image::new(&ICON, 32, InputFormat::Argb8888);
+//! let fg = Foreground::new(&icon);
+//! // NOTE(unsafe) the transfer is awaited and not leaked
+//! unsafe { dma2d.start_blend(&fg, (0, 0), &mut screen, Rect::new(10, 10, 32, 32)) }.await?;
+//! ```
+//!
+//! With the `embedded-graphics` feature, [`Dma2dTarget`] implements `DrawTarget` and sends
+//! solid fills and image blits to the accelerator.
+
+use core::marker::PhantomData;
+use core::ptr;
+
+use enumflags2::BitFlags;
+
+use crate::pac::DMA2D;
+use crate::rcc::{Enable, Reset};
+
+#[cfg(feature = "embedded-graphics")]
+mod graphics;
+#[cfg(feature = "embedded-graphics")]
+pub use graphics::{Dma2dColor, Dma2dTarget};
+
+/// Color mode of a source image
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    Argb8888 = 0,
+    Rgb888 = 1,
+    Rgb565 = 2,
+    Argb1555 = 3,
+    Argb4444 = 4,
+    /// 8-bit index into the CLUT
+    L8 = 5,
+    /// 4-bit alpha, 4-bit CLUT index
+    Al44 = 6,
+    /// 8-bit alpha, 8-bit CLUT index
+    Al88 = 7,
+    /// 4-bit index into the CLUT
+    L4 = 8,
+    /// 8-bit alpha, the color comes from [`Foreground::color`]
+    A8 = 9,
+    /// 4-bit alpha, the color comes from [`Foreground::color`]
+    A4 = 10,
+}
+
+impl InputFormat {
+    pub const fn bits_per_pixel(self) -> usize {
+        match self {
+            Self::Argb8888 => 32,
+            Self::Rgb888 => 24,
+            Self::Rgb565 | Self::Argb1555 | Self::Argb4444 | Self::Al88 => 16,
+            Self::L8 | Self::Al44 | Self::A8 => 8,
+            Self::L4 | Self::A4 => 4,
+        }
+    }
+}
+
+/// Color mode of the destination
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Argb8888 = 0,
+    Rgb888 = 1,
+    Rgb565 = 2,
+    Argb1555 = 3,
+    Argb4444 = 4,
+}
+
+impl OutputFormat {
+    pub const fn bits_per_pixel(self) -> usize {
+        match self {
+            Self::Argb8888 => 32,
+            Self::Rgb888 => 24,
+            Self::Rgb565 | Self::Argb1555 | Self::Argb4444 => 16,
+        }
+    }
+
+    /// Input format with the same memory layout
+    pub const fn as_input(self) -> InputFormat {
+        match self {
+            Self::Argb8888 => InputFormat::Argb8888,
+            Self::Rgb888 => InputFormat::Rgb888,
+            Self::Rgb565 => InputFormat::Rgb565,
+            Self::Argb1555 => InputFormat::Argb1555,
+            Self::Argb4444 => InputFormat::Argb4444,
+        }
+    }
+}
+
+/// How the alpha channel of a source is modified
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alpha {
+    /// Pixel alpha as is
+    Keep,
+    /// Pixel alpha replaced with the given value
+    Replace(u8),
+    /// Pixel alpha multiplied with the given value
+    Multiply(u8),
+}
+
+impl Alpha {
+    const fn bits(self) -> u32 {
+        match self {
+            Self::Keep => 0,
+            Self::Replace(a) => (0b01 << 16) | ((a as u32) << 24),
+            Self::Multiply(a) => (0b10 << 16) | ((a as u32) << 24),
+        }
+    }
+}
+
+/// Rectangle in pixels
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Rect {
+    pub const fn new(x: u16, y: u16, width: u16, height: u16) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+/// Byte offset of `rect` and the line offset in pixels for a buffer of `len` bytes with
+/// `stride` pixels per line.
+///
+/// Returns `None` if `rect` does not fit, or starts in the middle of a byte (4-bit formats).
+pub const fn layout(
+    len: usize,
+    stride: u16,
+    bits_per_pixel: usize,
+    rect: &Rect,
+) -> Option<(usize, u16)> {
+    if rect.width == 0 || rect.height == 0 || rect.x as u32 + rect.width as u32 > stride as u32 {
+        return None;
+    }
+    let start = (rect.y as usize * stride as usize + rect.x as usize) * bits_per_pixel;
+    let end = ((rect.y as usize + rect.height as usize - 1) * stride as usize
+        + rect.x as usize
+        + rect.width as usize)
+        * bits_per_pixel;
+    if start % 8 != 0 || (end + 7) / 8 > len {
+        return None;
+    }
+    Some((start / 8, stride - rect.width))
+}
+
+/// Source image in memory
+pub struct Image<'a> {
+    data: &'a [u8],
+    stride: u16,
+    format: InputFormat,
+}
+
+impl<'a> Image<'a> {
+    /// Image with `stride` pixels per line.
+    pub const fn new(data: &'a [u8], stride: u16, format: InputFormat) -> Self {
+        Self {
+            data,
+            stride,
+            format,
+        }
+    }
+
+    pub fn format(&self) -> InputFormat {
+        self.format
+    }
+
+    /// Address of `rect` and the line offset in pixels.
+    ///
+    /// # Panics
+    ///
+    /// When `rect` is outside of the image.
+    fn window(&self, rect: &Rect) -> (u32, u16) {
+        let (offset, line_offset) = layout(
+            self.data.len(),
+            self.stride,
+            self.format.bits_per_pixel(),
+            rect,
+        )
+        .expect("rectangle outside of the image");
+        (self.data[offset..].as_ptr() as u32, line_offset)
+    }
+}
+
+/// Destination image in memory
+pub struct ImageMut<'a> {
+    data: &'a mut [u8],
+    stride: u16,
+    format: OutputFormat,
+}
+
+impl<'a> ImageMut<'a> {
+    /// Image with `stride` pixels per line.
+    pub fn new(data: &'a mut [u8], stride: u16, format: OutputFormat) -> Self {
+        Self {
+            data,
+            stride,
+            format,
+        }
+    }
+
+    pub fn format(&self) -> OutputFormat {
+        self.format
+    }
+
+    /// Pixels per line
+    pub fn stride(&self) -> u16 {
+        self.stride
+    }
+
+    /// Lines covered by the buffer
+    pub fn height(&self) -> u16 {
+        (self.data.len() * 8 / self.format.bits_per_pixel() / self.stride as usize) as u16
+    }
+
+    pub fn release(self) -> &'a mut [u8] {
+        self.data
+    }
+
+    /// # Panics
+    ///
+    /// When `rect` is outside of the image.
+    fn window(&mut self, rect: &Rect) -> (u32, u16) {
+        let (offset, line_offset) = layout(
+            self.data.len(),
+            self.stride,
+            self.format.bits_per_pixel(),
+            rect,
+        )
+        .expect("rectangle outside of the image");
+        (self.data[offset..].as_mut_ptr() as u32, line_offset)
+    }
+}
+
+/// Foreground source of a blend
+pub struct Foreground<'a, 'b> {
+    pub image: &'a Image<'b>,
+    pub alpha: Alpha,
+    /// RGB888 color of the `A8` and `A4` formats
+    pub color: u32,
+}
+
+impl<'a, 'b> Foreground<'a, 'b> {
+    pub fn new(image: &'a Image<'b>) -> Self {
+        Self {
+            image,
+            alpha: Alpha::Keep,
+            color: 0,
+        }
+    }
+}
+
+/// Source whose color lookup table is loaded
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Clut {
+    Foreground,
+    Background,
+}
+
+/// Transfer error
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// Bus error while accessing an image
+    Transfer,
+    /// Invalid configuration, e.g. misaligned addresses for the color mode
+    Configuration,
+}
+
+/// DMA2D interrupt events
+#[enumflags2::bitflags]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Event {
+    TransferError = 1 << 0,
+    TransferComplete = 1 << 1,
+    /// The line set with [`Dma2d::set_watermark`] has been written
+    Watermark = 1 << 2,
+    ClutAccessError = 1 << 3,
+    ClutTransferComplete = 1 << 4,
+    ConfigurationError = 1 << 5,
+}
+
+// CR
+const CR_START: u32 = 1 << 0;
+const CR_ABORT: u32 = 1 << 2;
+const CR_EVENTS_SHIFT: u32 = 8;
+const CR_MODE_SHIFT: u32 = 16;
+
+#[derive(Clone, Copy)]
+enum Mode {
+    MemoryToMemory = 0b00,
+    Convert = 0b01,
+    Blend = 0b10,
+    Fill = 0b11,
+}
+
+/// Byte offset of the foreground and background CLUT memories
+const FGCLUT_OFFSET: usize = 0x400;
+const BGCLUT_OFFSET: usize = 0x800;
+
+/// Events that end a transfer
+#[cfg(feature = "async")]
+fn completion() -> BitFlags<Event> {
+    Event::TransferComplete | Event::TransferError | Event::ConfigurationError
+}
+
+#[cfg(feature = "async")]
+fn unlisten_completion(dma2d: &crate::pac::dma2d::RegisterBlock) {
+    dma2d
+        .cr
+        .modify(|r, w| unsafe { w.bits(r.bits() & !(completion().bits() << CR_EVENTS_SHIFT)) });
+}
+
+/// Waker of the task awaiting a transfer
+#[cfg(feature = "async")]
+static WAKER: crate::waker::WakerCell = crate::waker::WakerCell::new();
+
+/// Handles the DMA2D interrupt for [`Transfer`] futures.
+///
+/// Masks the completion interrupts and wakes the waiting task.
+#[cfg(feature = "async")]
+pub fn on_interrupt() {
+    // NOTE(unsafe) only clears interrupt enables owned by the pending transfer
+    unlisten_completion(unsafe { &*DMA2D::ptr() });
+    WAKER.wake();
+}
+
+/// Chrom-ART accelerator
+pub struct Dma2d {
+    dma2d: DMA2D,
+}
+
+impl Dma2d {
+    pub fn new(dma2d: DMA2D) -> Self {
+        unsafe {
+            DMA2D::enable_unchecked();
+            DMA2D::reset_unchecked();
+        }
+        Self { dma2d }
+    }
+
+    pub fn release(self) -> DMA2D {
+        self.dma2d
+    }
+
+    /// Sets the line after which [`Event::Watermark`] fires.
+    pub fn set_watermark(&mut self, line: u16) {
+        self.dma2d.lwr.write(|w| unsafe { w.bits(line.into()) });
+    }
+
+    /// Limits bus usage by waiting `cycles` AHB cycles between accesses, or disables the limit for 0.
+    pub fn set_dead_time(&mut self, cycles: u8) {
+        self.dma2d
+            .amtcr
+            .write(|w| unsafe { w.bits((u32::from(cycles) << 8) | u32::from(cycles != 0)) });
+    }
+
+    /// Loads the color lookup table of the foreground or background with ARGB8888 colors,
+    /// starting at index 0.
+    ///
+    /// # Panics
+    ///
+    /// When `colors` has more than 256 entries.
+    pub fn load_clut(&mut self, clut: Clut, colors: &[u32]) {
+        assert!(colors.len() <= 256);
+        let offset = match clut {
+            Clut::Foreground => FGCLUT_OFFSET,
+            Clut::Background => BGCLUT_OFFSET,
+        };
+        // NOTE(unsafe) the CLUT memories are part of the DMA2D we own, no transfer is running
+        let base = unsafe { (DMA2D::ptr() as *mut u8).add(offset) as *mut u32 };
+        for (i, color) in colors.iter().enumerate() {
+            unsafe { ptr::write_volatile(base.add(i), *color) };
+        }
+    }
+
+    /// Fills `rect` of `dst` with `color`, given in the output format, and waits for the end
+    /// of the transfer.
+    ///
+    /// # Panics
+    ///
+    /// When `rect` is outside of `dst`.
+    pub fn fill(&mut self, dst: &mut ImageMut<'_>, rect: Rect, color: u32) -> Result<(), Error> {
+        // NOTE(unsafe) the transfer is not leaked
+        unsafe { self.start_fill(dst, rect, color) }.wait()
+    }
+
+    /// Copies a `rect` sized area at `src_pos` of `src` to `rect` of `dst`, converting the pixel
+    /// format if the formats differ, and waits for the end of the transfer.
+    ///
+    /// # Panics
+    ///
+    /// When either area is outside of its image.
+    pub fn copy(
+        &mut self,
+        src: &Image<'_>,
+        src_pos: (u16, u16),
+        dst: &mut ImageMut<'_>,
+        rect: Rect,
+    ) -> Result<(), Error> {
+        // NOTE(unsafe) the transfer is not leaked
+        unsafe { self.start_copy(src, src_pos, dst, rect) }.wait()
+    }
+
+    /// Blends a `rect` sized area at `fg_pos` of `fg` over `rect` of `dst` and waits for the
+    /// end of the transfer.
+    ///
+    /// # Panics
+    ///
+    /// When either area is outside of its image.
+    pub fn blend(
+        &mut self,
+        fg: &Foreground<'_, '_>,
+        fg_pos: (u16, u16),
+        dst: &mut ImageMut<'_>,
+        rect: Rect,
+    ) -> Result<(), Error> {
+        // NOTE(unsafe) the transfer is not leaked
+        unsafe { self.start_blend(fg, fg_pos, dst, rect) }.wait()
+    }
+
+    /// Blends a `rect` sized area at `fg_pos` of `fg` over the one at `bg_pos` of `bg`, writes
+    /// the result to `rect` of `dst` and waits for the end of the transfer.
+    ///
+    /// # Panics
+    ///
+    /// When any area is outside of its image.
+    #[allow(clippy::too_many_arguments)]
+    pub fn blend_onto(
+        &mut self,
+        fg: &Foreground<'_, '_>,
+        fg_pos: (u16, u16),
+        bg: &Image<'_>,
+        bg_pos: (u16, u16),
+        bg_alpha: Alpha,
+        dst: &mut ImageMut<'_>,
+        rect: Rect,
+    ) -> Result<(), Error> {
+        // NOTE(unsafe) the transfer is not leaked
+        unsafe { self.start_blend_onto(fg, fg_pos, bg, bg_pos, bg_alpha, dst, rect) }.wait()
+    }
+
+    /// Starts filling `rect` of `dst` with `color`, given in the output format.
+    ///
+    /// # Panics
+    ///
+    /// When `rect` is outside of `dst`.
+    ///
+    /// # Safety
+    ///
+    /// The returned transfer must be dropped or completed, not leaked.
+    pub unsafe fn start_fill<'t>(
+        &'t mut self,
+        dst: &'t mut ImageMut<'_>,
+        rect: Rect,
+        color: u32,
+    ) -> Transfer<'t> {
+        self.set_output(dst, &rect);
+        self.dma2d.ocolr.write(|w| unsafe { w.bits(color) });
+        self.start(Mode::Fill)
+    }
+
+    /// Starts copying a `rect` sized area at `src_pos` of `src` to `rect` of `dst`, converting
+    /// the pixel format if the formats differ.
+    ///
+    /// # Panics
+    ///
+    /// When either area is outside of its image.
+    ///
+    /// # Safety
+    ///
+    /// The returned transfer must be dropped or completed, not leaked.
+    pub unsafe fn start_copy<'t>(
+        &'t mut self,
+        src: &'t Image<'_>,
+        src_pos: (u16, u16),
+        dst: &'t mut ImageMut<'_>,
+        rect: Rect,
+    ) -> Transfer<'t> {
+        let mode = if src.format == dst.format.as_input() {
+            Mode::MemoryToMemory
+        } else {
+            Mode::Convert
+        };
+        self.set_foreground(&Foreground::new(src), src_pos, &rect);
+        self.set_output(dst, &rect);
+        self.start(mode)
+    }
+
+    /// Starts blending a `rect` sized area at `fg_pos` of `fg` over `rect` of `dst`.
+    ///
+    /// # Panics
+    ///
+    /// When either area is outside of its image.
+    ///
+    /// # Safety
+    ///
+    /// The returned transfer must be dropped or completed, not leaked.
+    pub unsafe fn start_blend<'t>(
+        &'t mut self,
+        fg: &'t Foreground<'_, '_>,
+        fg_pos: (u16, u16),
+        dst: &'t mut ImageMut<'_>,
+        rect: Rect,
+    ) -> Transfer<'t> {
+        self.set_foreground(fg, fg_pos, &rect);
+        let (address, line_offset) = dst.window(&rect);
+        self.set_background(address, line_offset, dst.format.as_input(), Alpha::Keep);
+        self.set_output(dst, &rect);
+        self.start(Mode::Blend)
+    }
+
+    /// Starts blending a `rect` sized area at `fg_pos` of `fg` over the one at `bg_pos` of `bg`
+    /// and writing the result to `rect` of `dst`.
+    ///
+    /// # Panics
+    ///
+    /// When any area is outside of its image.
+    ///
+    /// # Safety
+    ///
+    /// The returned transfer must be dropped or completed, not leaked.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn start_blend_onto<'t>(
+        &'t mut self,
+        fg: &'t Foreground<'_, '_>,
+        fg_pos: (u16, u16),
+        bg: &'t Image<'_>,
+        bg_pos: (u16, u16),
+        bg_alpha: Alpha,
+        dst: &'t mut ImageMut<'_>,
+        rect: Rect,
+    ) -> Transfer<'t> {
+        self.set_foreground(fg, fg_pos, &rect);
+        let (address, line_offset) =
+            bg.window(&Rect::new(bg_pos.0, bg_pos.1, rect.width, rect.height));
+        self.set_background(address, line_offset, bg.format, bg_alpha);
+        self.set_output(dst, &rect);
+        self.start(Mode::Blend)
+    }
+
+    fn set_foreground(&mut self, fg: &Foreground<'_, '_>, pos: (u16, u16), rect: &Rect) {
+        let (address, line_offset) =
+            fg.image
+                .window(&Rect::new(pos.0, pos.1, rect.width, rect.height));
+        self.dma2d.fgmar.write(|w| unsafe { w.bits(address) });
+        self.dma2d
+            .fgor
+            .write(|w| unsafe { w.bits(line_offset.into()) });
+        self.dma2d
+            .fgcolr
+            .write(|w| unsafe { w.bits(fg.color & 0xff_ffff) });
+        self.dma2d
+            .fgpfccr
+            .write(|w| unsafe { w.bits(fg.alpha.bits() | fg.image.format as u32) });
+    }
+
+    fn set_background(
+        &mut self,
+        address: u32,
+        line_offset: u16,
+        format: InputFormat,
+        alpha: Alpha,
+    ) {
+        self.dma2d.bgmar.write(|w| unsafe { w.bits(address) });
+        self.dma2d
+            .bgor
+            .write(|w| unsafe { w.bits(line_offset.into()) });
+        self.dma2d
+            .bgpfccr
+            .write(|w| unsafe { w.bits(alpha.bits() | format as u32) });
+    }
+
+    fn set_output(&mut self, dst: &mut ImageMut<'_>, rect: &Rect) {
+        let (address, line_offset) = dst.window(rect);
+        self.dma2d
+            .opfccr
+            .write(|w| unsafe { w.bits(dst.format as u32) });
+        self.dma2d.omar.write(|w| unsafe { w.bits(address) });
+        self.dma2d
+            .oor
+            .write(|w| unsafe { w.bits(line_offset.into()) });
+        self.dma2d
+            .nlr
+            .write(|w| unsafe { w.bits((u32::from(rect.width) << 16) | u32::from(rect.height)) });
+    }
+
+    fn start(&mut self, mode: Mode) -> Transfer<'_> {
+        self.dma2d
+            .ifcr
+            .write(|w| unsafe { w.bits(BitFlags::<Event>::ALL.bits()) });
+        self.dma2d.cr.modify(|r, w| unsafe {
+            w.bits(
+                (r.bits() & !(0b11 << CR_MODE_SHIFT)) | ((mode as u32) << CR_MODE_SHIFT) | CR_START,
+            )
+        });
+        Transfer {
+            dma2d: &self.dma2d,
+            _buffers: PhantomData,
+        }
+    }
+
+    fn listen_event(&mut self, disable: Option<BitFlags<Event>>, enable: Option<BitFlags<Event>>) {
+        self.dma2d.cr.modify(|r, w| unsafe {
+            w.bits({
+                let mut bits = r.bits();
+                if let Some(d) = disable {
+                    bits &= !(d.bits() << CR_EVENTS_SHIFT);
+                }
+                if let Some(e) = enable {
+                    bits |= e.bits() << CR_EVENTS_SHIFT;
+                }
+                bits
+            })
+        });
+    }
+}
+
+impl crate::Listen for Dma2d {
+    type Event = Event;
+
+    fn listen(&mut self, event: impl Into<BitFlags<Self::Event>>) {
+        self.listen_event(None, Some(event.into()));
+    }
+
+    fn listen_only(&mut self, event: impl Into<BitFlags<Self::Event>>) {
+        self.listen_event(Some(BitFlags::ALL), Some(event.into()));
+    }
+
+    fn unlisten(&mut self, event: impl Into<BitFlags<Self::Event>>) {
+        self.listen_event(Some(event.into()), None);
+    }
+}
+
+impl crate::ReadFlags for Dma2d {
+    type Flag = Event;
+
+    fn flags(&self) -> BitFlags<Self::Flag> {
+        BitFlags::from_bits_truncate(self.dma2d.isr.read().bits())
+    }
+}
+
+impl crate::ClearFlags for Dma2d {
+    type Flag = Event;
+
+    fn clear_flags(&mut self, flags: impl Into<BitFlags<Self::Flag>>) {
+        self.dma2d
+            .ifcr
+            .write(|w| unsafe { w.bits(flags.into().bits()) });
+    }
+}
+
+/// Running DMA2D operation
+///
+/// Dropping an unfinished transfer aborts it. The buffers are only protected by this drop, which
+/// is why the functions returning a transfer are `unsafe`.
+pub struct Transfer<'t> {
+    dma2d: &'t DMA2D,
+    _buffers: PhantomData<&'t mut [u8]>,
+}
+
+impl Transfer<'_> {
+    fn result(&self) -> Option<Result<(), Error>> {
+        let flags = BitFlags::<Event>::from_bits_truncate(self.dma2d.isr.read().bits());
+        if flags.contains(Event::ConfigurationError) {
+            Some(Err(Error::Configuration))
+        } else if flags.contains(Event::TransferError) {
+            Some(Err(Error::Transfer))
+        } else if flags.contains(Event::TransferComplete) {
+            Some(Ok(()))
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` once the transfer has completed or failed.
+    pub fn is_complete(&self) -> bool {
+        self.result().is_some()
+    }
+
+    /// Blocks until the transfer has completed.
+    pub fn wait(self) -> Result<(), Error> {
+        loop {
+            if let Some(result) = self.result() {
+                return result;
+            }
+        }
+    }
+
+    /// Stops the transfer, leaving the destination partially written.
+    pub fn abort(self) {}
+}
+
+impl Drop for Transfer<'_> {
+    fn drop(&mut self) {
+        if self.result().is_none() {
+            self.dma2d
+                .cr
+                .modify(|r, w| unsafe { w.bits(r.bits() | CR_ABORT) });
+            while self.dma2d.cr.read().bits() & CR_START != 0 {}
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl core::future::Future for Transfer<'_> {
+    type Output = Result<(), Error>;
+
+    fn poll(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Self::Output> {
+        WAKER.register(cx.waker());
+        match self.result() {
+            Some(result) => core::task::Poll::Ready(result),
+            None => {
+                self.dma2d.cr.modify(|r, w| unsafe {
+                    w.bits(r.bits() | (completion().bits() << CR_EVENTS_SHIFT))
+                });
+                // Completed before the interrupt was unmasked
+                if self.is_complete() {
+                    unlisten_completion(self.dma2d);
+                    cx.waker().wake_by_ref();
+                }
+                core::task::Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rect_layout() {
+        let len = 480 * 272 * 2;
+        assert_eq!(
+            layout(len, 480, 16, &Rect::new(0, 0, 480, 272)),
+            Some((0, 0))
+        );
+        assert_eq!(
+            layout(len, 480, 16, &Rect::new(10, 20, 100, 50)),
+            Some(((20 * 480 + 10) * 2, 380))
+        );
+        // Bottom right pixel
+        assert_eq!(
+            layout(len, 480, 16, &Rect::new(479, 271, 1, 1)),
+            Some((len - 2, 479))
+        );
+        assert_eq!(
+            layout(480 * 3 * 2, 480, 24, &Rect::new(1, 1, 2, 1)),
+            Some((481 * 3, 478))
+        );
+    }
+
+    #[test]
+    fn rect_layout_outside() {
+        let len = 480 * 272 * 2;
+        assert_eq!(layout(len, 480, 16, &Rect::new(0, 0, 481, 1)), None);
+        assert_eq!(layout(len, 480, 16, &Rect::new(400, 0, 81, 1)), None);
+        assert_eq!(layout(len, 480, 16, &Rect::new(0, 0, 480, 273)), None);
+        assert_eq!(layout(len, 480, 16, &Rect::new(0, 272, 1, 1)), None);
+        assert_eq!(layout(len, 480, 16, &Rect::new(0, 0, 0, 1)), None);
+        assert_eq!(layout(len, 480, 16, &Rect::new(0, 0, 1, 0)), None);
+        // A line shorter than the stride fits at the end of the buffer
+        assert_eq!(
+            layout(480 * 2 + 20, 480, 16, &Rect::new(0, 0, 10, 2)),
+            Some((0, 470))
+        );
+        assert_eq!(layout(480 * 2 + 20, 480, 16, &Rect::new(0, 0, 11, 2)), None);
+    }
+
+    #[test]
+    fn rect_layout_4bit() {
+        // 32 pixels per line, 16 bytes
+        assert_eq!(layout(64, 32, 4, &Rect::new(2, 1, 4, 2)), Some((17, 28)));
+        assert_eq!(layout(64, 32, 4, &Rect::new(1, 0, 4, 1)), None);
+        // The last byte is only half used
+        assert_eq!(layout(64, 32, 4, &Rect::new(30, 3, 1, 1)), Some((63, 31)));
+        assert_eq!(layout(64, 32, 4, &Rect::new(30, 3, 2, 1)), Some((63, 30)));
+        assert_eq!(layout(63, 32, 4, &Rect::new(30, 3, 1, 1)), None);
+    }
+}
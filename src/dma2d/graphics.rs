@@ -0,0 +1,154 @@
+//! [`embedded_graphics_core::draw_target::DrawTarget`] with DMA2D fills and blits
+
+use embedded_graphics_core::draw_target::DrawTarget;
+use embedded_graphics_core::geometry::{Dimensions, OriginDimensions, Point, Size};
+use embedded_graphics_core::pixelcolor::{IntoStorage, PixelColor, Rgb565, Rgb888, RgbColor};
+use embedded_graphics_core::primitives::Rectangle;
+use embedded_graphics_core::Pixel;
+
+use super::{Dma2d, Error, Foreground, Image, ImageMut, OutputFormat, Rect};
+
+/// Color with a matching DMA2D output format
+pub trait Dma2dColor: PixelColor {
+    const FORMAT: OutputFormat;
+
+    /// Value of the color in the output format
+    fn to_raw(self) -> u32;
+}
+
+impl Dma2dColor for Rgb565 {
+    const FORMAT: OutputFormat = OutputFormat::Rgb565;
+
+    fn to_raw(self) -> u32 {
+        self.into_storage().into()
+    }
+}
+
+impl Dma2dColor for Rgb888 {
+    const FORMAT: OutputFormat = OutputFormat::Rgb888;
+
+    fn to_raw(self) -> u32 {
+        (u32::from(self.r()) << 16) | (u32::from(self.g()) << 8) | u32::from(self.b())
+    }
+}
+
+/// Frame buffer drawn to with `embedded-graphics`, accelerated by the DMA2D
+///
+/// Single pixels are written by the CPU, [`fill_solid`](DrawTarget::fill_solid),
+/// [`clear`](DrawTarget::clear) and [`blit`](Self::blit) run on the DMA2D.
+pub struct Dma2dTarget<'a, 'b, C> {
+    dma2d: &'a mut Dma2d,
+    image: ImageMut<'b>,
+    size: Size,
+    _color: core::marker::PhantomData<C>,
+}
+
+impl<'a, 'b, C: Dma2dColor> Dma2dTarget<'a, 'b, C> {
+    /// # Panics
+    ///
+    /// When the format of `image` does not match `C`.
+    pub fn new(dma2d: &'a mut Dma2d, image: ImageMut<'b>) -> Self {
+        assert_eq!(image.format(), C::FORMAT);
+        let size = Size::new(image.stride().into(), image.height().into());
+        Self {
+            dma2d,
+            image,
+            size,
+            _color: core::marker::PhantomData,
+        }
+    }
+
+    pub fn release(self) -> ImageMut<'b> {
+        self.image
+    }
+
+    /// Draws `src` with its top left corner at `position`, converting the pixel format and
+    /// blending it with the frame buffer.
+    pub fn blit(
+        &mut self,
+        src: &Foreground<'_, '_>,
+        size: Size,
+        position: Point,
+    ) -> Result<(), Error> {
+        let area = Rectangle::new(position, size).intersection(&self.bounding_box());
+        match to_rect(&area) {
+            Some(rect) => {
+                let src_pos = source_position(&area, position);
+                self.dma2d.blend(src, src_pos, &mut self.image, rect)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Copies `src` with its top left corner at `position`, converting the pixel format.
+    pub fn copy(&mut self, src: &Image<'_>, size: Size, position: Point) -> Result<(), Error> {
+        let area = Rectangle::new(position, size).intersection(&self.bounding_box());
+        match to_rect(&area) {
+            Some(rect) => {
+                let src_pos = source_position(&area, position);
+                self.dma2d.copy(src, src_pos, &mut self.image, rect)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// Position in the source of the visible `area` of an image drawn at `position`
+fn source_position(area: &Rectangle, position: Point) -> (u16, u16) {
+    (
+        (area.top_left.x - position.x) as u16,
+        (area.top_left.y - position.y) as u16,
+    )
+}
+
+fn to_rect(area: &Rectangle) -> Option<Rect> {
+    if area.is_zero_sized() {
+        return None;
+    }
+    Some(Rect::new(
+        area.top_left.x as u16,
+        area.top_left.y as u16,
+        area.size.width as u16,
+        area.size.height as u16,
+    ))
+}
+
+impl<C: Dma2dColor> OriginDimensions for Dma2dTarget<'_, '_, C> {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl<C: Dma2dColor> DrawTarget for Dma2dTarget<'_, '_, C> {
+    type Color = C;
+    type Error = Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let bpp = C::FORMAT.bits_per_pixel() / 8;
+        let stride = self.image.stride() as usize;
+        let bounds = self.bounding_box();
+        for Pixel(point, color) in pixels {
+            if bounds.contains(point) {
+                let offset = (point.y as usize * stride + point.x as usize) * bpp;
+                let raw = color.to_raw().to_le_bytes();
+                self.image.data[offset..offset + bpp].copy_from_slice(&raw[..bpp]);
+            }
+        }
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        let area = area.intersection(&self.bounding_box());
+        match to_rect(&area) {
+            Some(rect) => self.dma2d.fill(&mut self.image, rect, color.to_raw()),
+            None => Ok(()),
+        }
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.fill_solid(&self.bounding_box(), color)
+    }
+}
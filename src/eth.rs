@@ -0,0 +1,470 @@
+//! Ethernet MAC with DMA descriptor rings
+//!
+//! The frame buffers and descriptors live in user provided [`RxRingEntry`] and [`TxRingEntry`]
+//! arrays, which must be in RAM reachable by the Ethernet DMA (not in CCM RAM).
+//!
+//! ```rust,ignore
+//! static mut RX_RING: [RxRingEntry; 4] = [RxRingEntry::INIT; 4];
+//! static mut TX_RING: [TxRingEntry; 2] = [TxRingEntry::INIT; 2];
+//!
+//! let gpioa = dp.GPIOA.split();
+//! // ...
+//! let mut eth = Ethernet::new(
+//!     (dp.ETHERNET_MAC, dp.ETHERNET_MMC, dp.ETHERNET_DMA),
+//!     (gpioa.pa1, gpioa.pa7, gpiog.pg11, gpiog.pg13, gpiob.pb13, gpioc.pc4, gpioc.pc5),
+//!     (gpioc.pc1, gpioa.pa2),
+//!     unsafe { &mut RX_RING },
+//!     unsafe { &mut TX_RING },
+//!     [0x02, 0x00, 0x11, 0x22, 0x33, 0x44],
+//!     &clocks,
+//!     &mut syscfg,
+//! )
+//! .unwrap();
+//!
+//! let mut phy = Lan8742::new(0);
+//! phy.init(&mut eth.mac.smi()).unwrap();
+//! while eth.poll_link(&mut phy).is_none() {}
+//! ```
+//!
+//! With the `smoltcp` feature, [`EthernetDma`] implements `smoltcp::phy::Device`.
+
+use enumflags2::BitFlags;
+
+use crate::gpio::alt::eth as alt;
+use crate::pac::{self, ETHERNET_DMA, ETHERNET_MAC, ETHERNET_MMC};
+use crate::rcc::{Clocks, Enable, Reset};
+use crate::syscfg::SysCfg;
+
+mod desc;
+pub mod phy;
+#[cfg(feature = "smoltcp")]
+mod smoltcp_phy;
+
+pub use desc::{RxError, RxPacket, RxRingEntry, TxError, TxRingEntry, BUFFER_SIZE};
+use desc::{RxRing, TxRing};
+pub use phy::{Dp83848, Duplex, Lan8742, Link, Mdio, Phy, Smi, Speed};
+
+/// Media independent interface between the MAC and the PHY
+pub trait Pins {
+    /// `true` for the reduced interface
+    const RMII: bool;
+
+    type EthPins;
+    fn convert(self) -> Self::EthPins;
+}
+
+/// RMII pins: `(REF_CLK, CRS_DV, TX_EN, TXD0, TXD1, RXD0, RXD1)`
+impl<REFCLK, CRSDV, TXEN, TXD0, TXD1, RXD0, RXD1> Pins
+    for (REFCLK, CRSDV, TXEN, TXD0, TXD1, RXD0, RXD1)
+where
+    REFCLK: Into<alt::RefClk>,
+    CRSDV: Into<alt::CrsDv>,
+    TXEN: Into<alt::TxEn>,
+    TXD0: Into<alt::Txd0>,
+    TXD1: Into<alt::Txd1>,
+    RXD0: Into<alt::Rxd0>,
+    RXD1: Into<alt::Rxd1>,
+{
+    const RMII: bool = true;
+
+    type EthPins = (
+        alt::RefClk,
+        alt::CrsDv,
+        alt::TxEn,
+        alt::Txd0,
+        alt::Txd1,
+        alt::Rxd0,
+        alt::Rxd1,
+    );
+    fn convert(self) -> Self::EthPins {
+        (
+            self.0.into(),
+            self.1.into(),
+            self.2.into(),
+            self.3.into(),
+            self.4.into(),
+            self.5.into(),
+            self.6.into(),
+        )
+    }
+}
+
+/// MII pins: `(TX_CLK, TX_EN, TXD0, TXD1, TXD2, TXD3, RX_CLK, RX_DV, RXD0, RXD1, RXD2, RXD3)`
+///
+/// `COL` and `CRS` are only used in half duplex mode and can be configured separately.
+impl<TXCLK, TXEN, TXD0, TXD1, TXD2, TXD3, RXCLK, RXDV, RXD0, RXD1, RXD2, RXD3> Pins
+    for (
+        TXCLK,
+        TXEN,
+        TXD0,
+        TXD1,
+        TXD2,
+        TXD3,
+        RXCLK,
+        RXDV,
+        RXD0,
+        RXD1,
+        RXD2,
+        RXD3,
+    )
+where
+    TXCLK: Into<alt::TxClk>,
+    TXEN: Into<alt::TxEn>,
+    TXD0: Into<alt::Txd0>,
+    TXD1: Into<alt::Txd1>,
+    TXD2: Into<alt::Txd2>,
+    TXD3: Into<alt::Txd3>,
+    RXCLK: Into<alt::RxClk>,
+    RXDV: Into<alt::RxDv>,
+    RXD0: Into<alt::Rxd0>,
+    RXD1: Into<alt::Rxd1>,
+    RXD2: Into<alt::Rxd2>,
+    RXD3: Into<alt::Rxd3>,
+{
+    const RMII: bool = false;
+
+    type EthPins = (
+        alt::TxClk,
+        alt::TxEn,
+        alt::Txd0,
+        alt::Txd1,
+        alt::Txd2,
+        alt::Txd3,
+        alt::RxClk,
+        alt::RxDv,
+        alt::Rxd0,
+        alt::Rxd1,
+        alt::Rxd2,
+        alt::Rxd3,
+    );
+    fn convert(self) -> Self::EthPins {
+        (
+            self.0.into(),
+            self.1.into(),
+            self.2.into(),
+            self.3.into(),
+            self.4.into(),
+            self.5.into(),
+            self.6.into(),
+            self.7.into(),
+            self.8.into(),
+            self.9.into(),
+            self.10.into(),
+            self.11.into(),
+        )
+    }
+}
+
+/// Ethernet error
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// HCLK is below the 25 MHz needed by the MAC
+    WrongClock,
+    /// The DMA and MAC software reset did not finish, usually because the PHY provides no
+    /// REF_CLK or RX/TX clocks
+    ResetTimeout,
+    /// The PHY did not finish its reset, or no PHY answers at its address
+    PhyTimeout,
+}
+
+/// Polls of `DMABMR.SR` before [`Ethernet::new`] gives up
+pub const RESET_TIMEOUT: u32 = 0x5000;
+
+/// Ethernet DMA interrupt events
+#[enumflags2::bitflags]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[repr(u32)]
+pub enum Event {
+    /// A frame has been transmitted
+    Transmit = 1 << 0,
+    /// The transmit ring is empty
+    TransmitBufferUnavailable = 1 << 2,
+    /// A frame has been received
+    Receive = 1 << 6,
+    /// The receive ring is full
+    ReceiveBufferUnavailable = 1 << 7,
+    /// A bus error stopped the DMA
+    FatalBusError = 1 << 13,
+}
+
+/// Normal interrupt summary bit in `DMASR` and enable bit in `DMAIER`
+const NORMAL_SUMMARY: u32 = 1 << 16;
+/// Abnormal interrupt summary bit in `DMASR` and enable bit in `DMAIER`
+const ABNORMAL_SUMMARY: u32 = 1 << 15;
+
+fn summary_bits(events: BitFlags<Event>) -> u32 {
+    let mut bits = 0;
+    if events.intersects(Event::Transmit | Event::TransmitBufferUnavailable | Event::Receive) {
+        bits |= NORMAL_SUMMARY;
+    }
+    if events.intersects(Event::ReceiveBufferUnavailable | Event::FatalBusError) {
+        bits |= ABNORMAL_SUMMARY;
+    }
+    bits
+}
+
+/// Ethernet MAC and PHY management
+pub struct EthernetMac {
+    eth_mac: ETHERNET_MAC,
+    _eth_mmc: ETHERNET_MMC,
+    _pins: (alt::Mdc, alt::Mdio),
+}
+
+impl EthernetMac {
+    /// Station management interface for talking to the PHY.
+    pub fn smi(&mut self) -> Smi<'_> {
+        Smi::new(&self.eth_mac)
+    }
+
+    /// Applies the speed and duplex mode negotiated by the PHY.
+    pub fn set_link(&mut self, link: Link) {
+        self.eth_mac.maccr.modify(|_, w| {
+            w.fes().bit(link.speed == Speed::Mbps100);
+            w.dm().bit(link.duplex == Duplex::Full)
+        });
+    }
+
+    #[allow(unused_unsafe)]
+    fn set_mac_address(&mut self, addr: [u8; 6]) {
+        self.eth_mac
+            .maca0hr
+            .write(|w| unsafe { w.maca0h().bits(u16::from_le_bytes([addr[4], addr[5]])) });
+        self.eth_mac.maca0lr.write(|w| unsafe {
+            w.maca0l()
+                .bits(u32::from_le_bytes([addr[0], addr[1], addr[2], addr[3]]))
+        });
+    }
+}
+
+/// Ethernet DMA with its descriptor rings
+pub struct EthernetDma<'rx, 'tx> {
+    eth_dma: ETHERNET_DMA,
+    rx_ring: RxRing<'rx>,
+    tx_ring: TxRing<'tx>,
+}
+
+impl<'rx, 'tx> EthernetDma<'rx, 'tx> {
+    /// Takes the next received frame.
+    ///
+    /// The frame's buffer is given back to the DMA when the packet is dropped.
+    pub fn recv_next(&mut self) -> Result<RxPacket<'_>, RxError> {
+        let packet = self.rx_ring.recv_next();
+        // Resume reception if it stopped on a full ring
+        self.eth_dma.dmarpdr.write(|w| unsafe { w.rpd().bits(1) });
+        packet
+    }
+
+    /// Sends a frame of `len` bytes, filled in by `f`.
+    pub fn send<R>(&mut self, len: usize, f: impl FnOnce(&mut [u8]) -> R) -> Result<R, TxError> {
+        let result = self.tx_ring.send(len, f)?;
+        self.eth_dma.dmatpdr.write(|w| unsafe { w.tpd().bits(1) });
+        Ok(result)
+    }
+
+    /// Returns `true` if a received frame is waiting.
+    pub fn rx_is_available(&self) -> bool {
+        self.rx_ring.is_available()
+    }
+
+    /// Returns `true` if a frame can be queued for transmission.
+    pub fn tx_is_available(&self) -> bool {
+        self.tx_ring.is_available()
+    }
+
+    fn listen_event(&mut self, disable: Option<BitFlags<Event>>, enable: Option<BitFlags<Event>>) {
+        self.eth_dma.dmaier.modify(|r, w| unsafe {
+            w.bits({
+                let mut bits = r.bits();
+                if let Some(d) = disable {
+                    bits &= !d.bits();
+                }
+                if let Some(e) = enable {
+                    bits |= e.bits();
+                }
+                // Keep the summary enables in line with the remaining events
+                let events = BitFlags::<Event>::from_bits_truncate(bits);
+                (bits & !(NORMAL_SUMMARY | ABNORMAL_SUMMARY)) | summary_bits(events)
+            })
+        });
+    }
+}
+
+impl crate::Listen for EthernetDma<'_, '_> {
+    type Event = Event;
+
+    fn listen(&mut self, event: impl Into<BitFlags<Self::Event>>) {
+        self.listen_event(None, Some(event.into()));
+    }
+
+    fn listen_only(&mut self, event: impl Into<BitFlags<Self::Event>>) {
+        self.listen_event(Some(BitFlags::ALL), Some(event.into()));
+    }
+
+    fn unlisten(&mut self, event: impl Into<BitFlags<Self::Event>>) {
+        self.listen_event(Some(event.into()), None);
+    }
+}
+
+impl crate::ReadFlags for EthernetDma<'_, '_> {
+    type Flag = Event;
+
+    fn flags(&self) -> BitFlags<Self::Flag> {
+        BitFlags::from_bits_truncate(self.eth_dma.dmasr.read().bits())
+    }
+}
+
+impl crate::ClearFlags for EthernetDma<'_, '_> {
+    type Flag = Event;
+
+    fn clear_flags(&mut self, flags: impl Into<BitFlags<Self::Flag>>) {
+        let flags = flags.into();
+        // Status bits are cleared by writing 1, the summary bits along with the last event
+        self.eth_dma
+            .dmasr
+            .write(|w| unsafe { w.bits(flags.bits() | summary_bits(flags)) });
+    }
+}
+
+/// Ethernet peripheral
+pub struct Ethernet<'rx, 'tx> {
+    pub mac: EthernetMac,
+    pub dma: EthernetDma<'rx, 'tx>,
+}
+
+impl<'rx, 'tx> Ethernet<'rx, 'tx> {
+    /// Initializes the MAC and the DMA and starts receiving and transmitting.
+    ///
+    /// The link is configured for 100 Mbit/s full duplex until [`poll_link`](Self::poll_link)
+    /// or [`EthernetMac::set_link`] applies what the PHY negotiated.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new<PINS: Pins>(
+        eth: (ETHERNET_MAC, ETHERNET_MMC, ETHERNET_DMA),
+        pins: PINS,
+        mdio_pins: (impl Into<alt::Mdc>, impl Into<alt::Mdio>),
+        rx_buffer: &'rx mut [RxRingEntry],
+        tx_buffer: &'tx mut [TxRingEntry],
+        mac_address: [u8; 6],
+        clocks: &Clocks,
+        syscfg: &mut SysCfg,
+    ) -> Result<Self, Error> {
+        let (eth_mac, eth_mmc, eth_dma) = eth;
+        let clock_range = mdc_clock_range(clocks.hclk().raw())?;
+
+        // The interface has to be selected while the MAC is in reset
+        syscfg.pmc.modify(|_, w| w.mii_rmii_sel().bit(PINS::RMII));
+        unsafe {
+            ETHERNET_MAC::enable_unchecked();
+            ETHERNET_MAC::reset_unchecked();
+            let rcc = &(*pac::RCC::ptr());
+            rcc.ahb1enr
+                .modify(|_, w| w.ethmactxen().set_bit().ethmacrxen().set_bit());
+        }
+        let _pins = pins.convert();
+
+        // Software reset of the DMA and MAC, needs the PHY clock to finish
+        eth_dma.dmabmr.modify(|_, w| w.sr().set_bit());
+        (0..RESET_TIMEOUT)
+            .any(|_| eth_dma.dmabmr.read().sr().bit_is_clear())
+            .then(|| ())
+            .ok_or(Error::ResetTimeout)?;
+
+        eth_mac
+            .macmiiar
+            .modify(|_, w| unsafe { w.cr().bits(clock_range) });
+
+        eth_mac.maccr.modify(|_, w| {
+            // 100 Mbit/s full duplex until the PHY tells otherwise
+            w.fes().set_bit();
+            w.dm().set_bit();
+            // Check IPv4 header and TCP/UDP/ICMP checksums of received frames
+            w.ipco().set_bit()
+        });
+        // Only pass frames to our address, broadcasts and multicasts
+        eth_mac
+            .macffr
+            .modify(|_, w| w.ra().clear_bit().pm().set_bit());
+
+        // Mask the MMC counter interrupts, nobody services them
+        eth_mmc
+            .mmcrimr
+            .write(|w| unsafe { w.bits((1 << 17) | (1 << 6) | (1 << 5)) });
+        eth_mmc
+            .mmctimr
+            .write(|w| unsafe { w.bits((1 << 21) | (1 << 15) | (1 << 14)) });
+
+        eth_dma.dmaomr.modify(|_, w| {
+            // Store and forward is required for checksum offload
+            w.tsf().set_bit();
+            w.rsf().set_bit();
+            w.osf().set_bit()
+        });
+        eth_dma.dmabmr.modify(|_, w| unsafe {
+            w.aab().set_bit();
+            w.fb().set_bit();
+            w.usp().set_bit();
+            w.rdp().bits(32);
+            w.pbl().bits(32);
+            // Normal descriptors
+            w.edfe().clear_bit()
+        });
+
+        let rx_ring = RxRing::new(rx_buffer);
+        let tx_ring = TxRing::new(tx_buffer);
+        eth_dma
+            .dmardlar
+            .write(|w| unsafe { w.srl().bits(rx_ring.address()) });
+        eth_dma
+            .dmatdlar
+            .write(|w| unsafe { w.stl().bits(tx_ring.address()) });
+
+        let mut mac = EthernetMac {
+            eth_mac,
+            _eth_mmc: eth_mmc,
+            _pins: (mdio_pins.0.into(), mdio_pins.1.into()),
+        };
+        mac.set_mac_address(mac_address);
+
+        // Start the MAC, then the DMA
+        mac.eth_mac
+            .maccr
+            .modify(|_, w| w.te().set_bit().re().set_bit());
+        eth_dma.dmaomr.modify(|_, w| w.ftf().set_bit());
+        while eth_dma.dmaomr.read().ftf().bit_is_set() {}
+        eth_dma
+            .dmaomr
+            .modify(|_, w| w.st().set_bit().sr().set_bit());
+        eth_dma.dmarpdr.write(|w| unsafe { w.rpd().bits(1) });
+
+        Ok(Self {
+            mac,
+            dma: EthernetDma {
+                eth_dma,
+                rx_ring,
+                tx_ring,
+            },
+        })
+    }
+
+    /// Polls the PHY and applies the link to the MAC when it is up.
+    pub fn poll_link(&mut self, phy: &mut impl Phy) -> Option<Link> {
+        let link = phy.link(&mut self.mac.smi());
+        if let Some(link) = link {
+            self.mac.set_link(link);
+        }
+        link
+    }
+}
+
+/// `CR` value of `MACMIIAR` keeping MDC below 2.5 MHz.
+fn mdc_clock_range(hclk: u32) -> Result<u8, Error> {
+    Ok(match hclk {
+        0..=24_999_999 => return Err(Error::WrongClock),
+        25_000_000..=34_999_999 => 0b010,
+        35_000_000..=59_999_999 => 0b011,
+        60_000_000..=99_999_999 => 0b000,
+        100_000_000..=149_999_999 => 0b001,
+        _ => 0b100,
+    })
+}
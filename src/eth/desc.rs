@@ -0,0 +1,489 @@
+//! DMA descriptor rings
+//!
+//! Descriptors use the normal (not enhanced) format in chained mode: each descriptor points to
+//! its buffer and to the next descriptor, and the last one points back to the first. A
+//! descriptor belongs to the DMA while its `OWN` bit is set; the rings below only touch
+//! descriptors they own and hand them back by setting `OWN` again.
+
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+use core::sync::atomic::{fence, Ordering};
+
+/// Size of a frame buffer, large enough for a VLAN tagged frame including CRC
+pub const BUFFER_SIZE: usize = 1524;
+
+// RDES0 and TDES0
+const DES0_OWN: u32 = 1 << 31;
+// RDES0
+const RDES0_FL_SHIFT: u32 = 16;
+const RDES0_FL_MASK: u32 = 0x3fff;
+const RDES0_ES: u32 = 1 << 15;
+const RDES0_FS: u32 = 1 << 9;
+const RDES0_LS: u32 = 1 << 8;
+// RDES1
+const RDES1_RCH: u32 = 1 << 14;
+const RDES1_RBS1_MASK: u32 = 0x1fff;
+
+// TDES0
+const TDES0_IC: u32 = 1 << 30;
+const TDES0_LS: u32 = 1 << 29;
+const TDES0_FS: u32 = 1 << 28;
+const TDES0_CIC_FULL: u32 = 0b11 << 22;
+const TDES0_TCH: u32 = 1 << 20;
+// TDES1
+const TDES1_TBS1_MASK: u32 = 0x1fff;
+
+/// Length of the frame check sequence the MAC leaves at the end of received frames
+const CRC_SIZE: usize = 4;
+
+#[repr(C, align(4))]
+struct Descriptor {
+    words: [u32; 4],
+}
+
+impl Descriptor {
+    const fn new() -> Self {
+        Self { words: [0; 4] }
+    }
+
+    #[inline(always)]
+    fn read(&self, n: usize) -> u32 {
+        unsafe { ptr::read_volatile(&self.words[n]) }
+    }
+
+    #[inline(always)]
+    fn write(&mut self, n: usize, value: u32) {
+        unsafe { ptr::write_volatile(&mut self.words[n], value) }
+    }
+
+    #[inline(always)]
+    fn is_owned_by_dma(&self) -> bool {
+        self.read(0) & DES0_OWN != 0
+    }
+
+    fn address(&self) -> u32 {
+        dma_address(self)
+    }
+}
+
+/// Bus address of `ptr` for the DMA
+///
+/// Pointers are 32-bit on the MCU. On a 64-bit host, where the rings are only tested against
+/// plain memory, the address is truncated and never dereferenced.
+fn dma_address<T>(ptr: *const T) -> u32 {
+    ptr as usize as u32
+}
+
+/// Receive descriptor with its frame buffer
+#[repr(C, align(4))]
+pub struct RxRingEntry {
+    desc: Descriptor,
+    buffer: [u8; BUFFER_SIZE],
+}
+
+impl RxRingEntry {
+    /// Initial value, for use in array initializers
+    pub const INIT: Self = Self::new();
+
+    pub const fn new() -> Self {
+        Self {
+            desc: Descriptor::new(),
+            buffer: [0; BUFFER_SIZE],
+        }
+    }
+
+    fn setup(&mut self, next: u32) {
+        self.desc
+            .write(1, RDES1_RCH | (BUFFER_SIZE as u32 & RDES1_RBS1_MASK));
+        self.desc.write(2, dma_address(self.buffer.as_ptr()));
+        self.desc.write(3, next);
+        self.give_to_dma();
+    }
+
+    fn give_to_dma(&mut self) {
+        // The buffer must be released before the descriptor
+        fence(Ordering::SeqCst);
+        self.desc.write(0, DES0_OWN);
+        fence(Ordering::SeqCst);
+    }
+}
+
+impl Default for RxRingEntry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Transmit descriptor with its frame buffer
+#[repr(C, align(4))]
+pub struct TxRingEntry {
+    desc: Descriptor,
+    buffer: [u8; BUFFER_SIZE],
+}
+
+impl TxRingEntry {
+    /// Initial value, for use in array initializers
+    pub const INIT: Self = Self::new();
+
+    pub const fn new() -> Self {
+        Self {
+            desc: Descriptor::new(),
+            buffer: [0; BUFFER_SIZE],
+        }
+    }
+
+    fn setup(&mut self, next: u32) {
+        self.desc.write(0, TDES0_TCH);
+        self.desc.write(1, 0);
+        self.desc.write(2, dma_address(self.buffer.as_ptr()));
+        self.desc.write(3, next);
+    }
+}
+
+impl Default for TxRingEntry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Receive error
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RxError {
+    /// No frame has been received
+    WouldBlock,
+    /// The frame did not fit in a single buffer and was dropped
+    Truncated,
+    /// The MAC reported an error (CRC, overflow, ...) and the frame was dropped
+    Frame,
+}
+
+/// Transmit error
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxError {
+    /// All descriptors are waiting to be sent
+    WouldBlock,
+    /// The frame does not fit in a buffer
+    TooLong,
+}
+
+pub(crate) struct RxRing<'a> {
+    entries: &'a mut [RxRingEntry],
+    next: usize,
+}
+
+impl<'a> RxRing<'a> {
+    /// Chains `entries` and hands all of them to the DMA.
+    ///
+    /// # Panics
+    ///
+    /// When `entries` is empty.
+    pub(crate) fn new(entries: &'a mut [RxRingEntry]) -> Self {
+        assert!(!entries.is_empty());
+        let len = entries.len();
+        for i in 0..len {
+            let next = entries[(i + 1) % len].desc.address();
+            entries[i].setup(next);
+        }
+        Self { entries, next: 0 }
+    }
+
+    /// Address of the first descriptor, for `DMARDLAR`
+    pub(crate) fn address(&self) -> u32 {
+        self.entries[0].desc.address()
+    }
+
+    /// Returns `true` if a received frame (or an error) is waiting.
+    pub(crate) fn is_available(&self) -> bool {
+        !self.entries[self.next].desc.is_owned_by_dma()
+    }
+
+    /// Takes the next received frame.
+    ///
+    /// The descriptor goes back to the DMA when the returned packet is dropped, or immediately
+    /// when the frame is dropped because of an error.
+    pub(crate) fn recv_next(&mut self) -> Result<RxPacket<'_>, RxError> {
+        let index = self.next;
+        if self.entries[index].desc.is_owned_by_dma() {
+            return Err(RxError::WouldBlock);
+        }
+        // Don't read the buffer before the descriptor
+        fence(Ordering::SeqCst);
+        self.next = (index + 1) % self.entries.len();
+
+        let entry = &mut self.entries[index];
+        let status = entry.desc.read(0);
+        if let Some(error) = frame_error(status) {
+            entry.give_to_dma();
+            return Err(error);
+        }
+
+        let len = ((status >> RDES0_FL_SHIFT) & RDES0_FL_MASK) as usize;
+        Ok(RxPacket {
+            len: len.saturating_sub(CRC_SIZE).min(BUFFER_SIZE),
+            entry,
+        })
+    }
+
+    /// Gives the frames received with an error back to the DMA, until the next descriptor is
+    /// owned by the DMA or holds a valid frame. Returns `true` if any frame was dropped.
+    #[cfg(any(feature = "smoltcp", test))]
+    pub(crate) fn drop_errors(&mut self) -> bool {
+        let mut dropped = false;
+        loop {
+            let entry = &mut self.entries[self.next];
+            if entry.desc.is_owned_by_dma() {
+                break;
+            }
+            // Don't read the status before the ownership
+            fence(Ordering::SeqCst);
+            if frame_error(entry.desc.read(0)).is_none() {
+                break;
+            }
+            entry.give_to_dma();
+            self.next = (self.next + 1) % self.entries.len();
+            dropped = true;
+        }
+        dropped
+    }
+}
+
+fn frame_error(status: u32) -> Option<RxError> {
+    if status & (RDES0_FS | RDES0_LS) != RDES0_FS | RDES0_LS {
+        Some(RxError::Truncated)
+    } else if status & RDES0_ES != 0 {
+        Some(RxError::Frame)
+    } else {
+        None
+    }
+}
+
+/// Received frame, borrowing its descriptor until dropped
+pub struct RxPacket<'a> {
+    entry: &'a mut RxRingEntry,
+    len: usize,
+}
+
+impl Deref for RxPacket<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.entry.buffer[..self.len]
+    }
+}
+
+impl DerefMut for RxPacket<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.entry.buffer[..self.len]
+    }
+}
+
+impl Drop for RxPacket<'_> {
+    fn drop(&mut self) {
+        self.entry.give_to_dma();
+    }
+}
+
+pub(crate) struct TxRing<'a> {
+    entries: &'a mut [TxRingEntry],
+    next: usize,
+}
+
+impl<'a> TxRing<'a> {
+    /// Chains `entries`, all owned by the CPU.
+    ///
+    /// # Panics
+    ///
+    /// When `entries` is empty.
+    pub(crate) fn new(entries: &'a mut [TxRingEntry]) -> Self {
+        assert!(!entries.is_empty());
+        let len = entries.len();
+        for i in 0..len {
+            let next = entries[(i + 1) % len].desc.address();
+            entries[i].setup(next);
+        }
+        Self { entries, next: 0 }
+    }
+
+    /// Address of the first descriptor, for `DMATDLAR`
+    pub(crate) fn address(&self) -> u32 {
+        self.entries[0].desc.address()
+    }
+
+    /// Returns `true` if a descriptor is free to send a frame.
+    pub(crate) fn is_available(&self) -> bool {
+        !self.entries[self.next].desc.is_owned_by_dma()
+    }
+
+    /// Reserves the next descriptor if it is owned by the CPU.
+    pub(crate) fn next_free(&mut self) -> Option<TxSlot<'_>> {
+        let len = self.entries.len();
+        let entry = &mut self.entries[self.next];
+        if entry.desc.is_owned_by_dma() {
+            return None;
+        }
+        Some(TxSlot {
+            entry,
+            next: &mut self.next,
+            len,
+        })
+    }
+
+    /// Lets `f` fill a buffer of `len` bytes and queues it as a single frame.
+    pub(crate) fn send<R>(
+        &mut self,
+        len: usize,
+        f: impl FnOnce(&mut [u8]) -> R,
+    ) -> Result<R, TxError> {
+        if len > BUFFER_SIZE {
+            return Err(TxError::TooLong);
+        }
+        let slot = self.next_free().ok_or(TxError::WouldBlock)?;
+        Ok(slot.send(len, f))
+    }
+}
+
+/// Free transmit descriptor, reserved by [`TxRing::next_free`]
+pub(crate) struct TxSlot<'a> {
+    entry: &'a mut TxRingEntry,
+    next: &'a mut usize,
+    len: usize,
+}
+
+impl TxSlot<'_> {
+    /// Lets `f` fill a buffer of `len` bytes and queues it as a single frame.
+    ///
+    /// # Panics
+    ///
+    /// When `len` is larger than [`BUFFER_SIZE`].
+    pub(crate) fn send<R>(self, len: usize, f: impl FnOnce(&mut [u8]) -> R) -> R {
+        assert!(len <= BUFFER_SIZE);
+        let entry = self.entry;
+        let result = f(&mut entry.buffer[..len]);
+        entry.desc.write(1, len as u32 & TDES1_TBS1_MASK);
+        // The buffer must be written before the descriptor is released
+        fence(Ordering::SeqCst);
+        entry.desc.write(
+            0,
+            DES0_OWN | TDES0_IC | TDES0_LS | TDES0_FS | TDES0_CIC_FULL | TDES0_TCH,
+        );
+        fence(Ordering::SeqCst);
+
+        *self.next = (*self.next + 1) % self.len;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a received frame the way the DMA does and hands the descriptor to the CPU.
+    fn dma_receive(entry: &mut RxRingEntry, frame: &[u8], status: u32) {
+        entry.buffer[..frame.len()].copy_from_slice(frame);
+        let len = (frame.len() + CRC_SIZE) as u32;
+        entry.desc.write(0, (len << RDES0_FL_SHIFT) | status);
+    }
+
+    /// Completes a transmission the way the DMA does.
+    fn dma_transmit(entry: &mut TxRingEntry) {
+        let status = entry.desc.read(0);
+        entry.desc.write(0, status & !DES0_OWN);
+    }
+
+    #[test]
+    fn rx_ring_chained() {
+        let mut entries = [RxRingEntry::INIT, RxRingEntry::INIT, RxRingEntry::INIT];
+        let ring = RxRing::new(&mut entries);
+        assert_eq!(ring.address(), ring.entries[0].desc.address());
+        for (i, entry) in ring.entries.iter().enumerate() {
+            assert!(entry.desc.is_owned_by_dma());
+            assert_eq!(entry.desc.read(1), RDES1_RCH | BUFFER_SIZE as u32);
+            assert_eq!(entry.desc.read(2), dma_address(entry.buffer.as_ptr()));
+            let next = &ring.entries[(i + 1) % 3].desc;
+            assert_eq!(entry.desc.read(3), next.address());
+        }
+        assert!(!ring.is_available());
+    }
+
+    #[test]
+    fn rx_ring_ownership() {
+        let mut entries = [RxRingEntry::INIT, RxRingEntry::INIT];
+        let mut ring = RxRing::new(&mut entries);
+        assert_eq!(ring.recv_next().err(), Some(RxError::WouldBlock));
+
+        dma_receive(&mut ring.entries[0], &[1, 2, 3], RDES0_FS | RDES0_LS);
+        assert!(ring.is_available());
+        {
+            let packet = ring.recv_next().unwrap();
+            assert_eq!(&*packet, &[1, 2, 3]);
+        }
+        // Given back to the DMA when the packet is dropped
+        assert!(ring.entries[0].desc.is_owned_by_dma());
+        assert_eq!(ring.next, 1);
+
+        dma_receive(&mut ring.entries[1], &[4], RDES0_FS | RDES0_LS);
+        assert_eq!(&*ring.recv_next().unwrap(), &[4]);
+        assert_eq!(ring.next, 0);
+        assert_eq!(ring.recv_next().err(), Some(RxError::WouldBlock));
+    }
+
+    #[test]
+    fn rx_ring_errors() {
+        let mut entries = [RxRingEntry::INIT, RxRingEntry::INIT, RxRingEntry::INIT];
+        let mut ring = RxRing::new(&mut entries);
+
+        dma_receive(&mut ring.entries[0], &[1], RDES0_FS);
+        dma_receive(&mut ring.entries[1], &[2], RDES0_FS | RDES0_LS | RDES0_ES);
+        assert_eq!(ring.recv_next().err(), Some(RxError::Truncated));
+        assert_eq!(ring.recv_next().err(), Some(RxError::Frame));
+        assert!(ring.entries[0].desc.is_owned_by_dma());
+        assert!(ring.entries[1].desc.is_owned_by_dma());
+
+        // Errors are skipped up to the next valid frame
+        dma_receive(&mut ring.entries[2], &[3], RDES0_FS | RDES0_LS | RDES0_ES);
+        dma_receive(&mut ring.entries[0], &[4], RDES0_LS);
+        dma_receive(&mut ring.entries[1], &[5], RDES0_FS | RDES0_LS);
+        assert!(ring.drop_errors());
+        assert!(ring.entries[2].desc.is_owned_by_dma());
+        assert!(ring.entries[0].desc.is_owned_by_dma());
+        assert!(!ring.drop_errors());
+        assert_eq!(&*ring.recv_next().unwrap(), &[5]);
+
+        // And stop at a descriptor owned by the DMA
+        dma_receive(&mut ring.entries[2], &[6], RDES0_ES);
+        assert!(ring.drop_errors());
+        assert!(!ring.drop_errors());
+        assert!(!ring.is_available());
+    }
+
+    #[test]
+    fn tx_ring_ownership() {
+        let mut entries = [TxRingEntry::INIT, TxRingEntry::INIT];
+        let mut ring = TxRing::new(&mut entries);
+        assert_eq!(ring.address(), ring.entries[0].desc.address());
+        assert_eq!(ring.entries[0].desc.read(3), ring.entries[1].desc.address());
+        assert_eq!(ring.entries[1].desc.read(3), ring.entries[0].desc.address());
+        assert!(ring.is_available());
+
+        assert_eq!(ring.send(BUFFER_SIZE + 1, |_| ()), Err(TxError::TooLong));
+        assert_eq!(ring.send(3, |buf| buf.copy_from_slice(&[1, 2, 3])), Ok(()));
+        let desc = &ring.entries[0].desc;
+        assert_eq!(
+            desc.read(0),
+            DES0_OWN | TDES0_IC | TDES0_LS | TDES0_FS | TDES0_CIC_FULL | TDES0_TCH
+        );
+        assert_eq!(desc.read(1), 3);
+        assert_eq!(&ring.entries[0].buffer[..3], &[1, 2, 3]);
+
+        ring.next_free().unwrap().send(1, |buf| buf[0] = 4);
+        assert!(ring.next_free().is_none());
+        assert_eq!(ring.send(1, |_| ()), Err(TxError::WouldBlock));
+
+        dma_transmit(&mut ring.entries[0]);
+        assert!(ring.is_available());
+        assert_eq!(ring.send(2, |buf| buf.len()), Ok(2));
+        assert_eq!(ring.next, 1);
+    }
+}
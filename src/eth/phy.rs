@@ -0,0 +1,263 @@
+//! Station management (MDIO) and Ethernet PHYs
+//!
+//! [`Phy`] covers what the MAC driver needs from a PHY: reset, auto-negotiation and the
+//! negotiated link. The default methods only use the IEEE 802.3 basic registers, the included
+//! drivers read the speed and duplex from their vendor status registers.
+
+use super::Error;
+use crate::pac::ETHERNET_MAC;
+
+/// Basic control register
+pub const BMCR: u8 = 0;
+/// Basic status register
+pub const BMSR: u8 = 1;
+/// PHY identifier 1
+pub const PHYIDR1: u8 = 2;
+/// PHY identifier 2
+pub const PHYIDR2: u8 = 3;
+
+const BMCR_RESET: u16 = 1 << 15;
+const BMCR_AN_ENABLE: u16 = 1 << 12;
+const BMCR_AN_RESTART: u16 = 1 << 9;
+const BMSR_AN_COMPLETE: u16 = 1 << 5;
+const BMSR_LINK_UP: u16 = 1 << 2;
+
+/// Reads of `BMCR` while the PHY resets, at least the 0.5 s allowed by IEEE 802.3 as a
+/// management frame takes over 25 µs
+pub const PHY_RESET_TIMEOUT: u32 = 20_000;
+
+/// Access to PHY registers over the management interface
+pub trait Mdio {
+    /// Reads register `reg` of the PHY at address `phy`.
+    fn read(&mut self, phy: u8, reg: u8) -> u16;
+    /// Writes `data` to register `reg` of the PHY at address `phy`.
+    fn write(&mut self, phy: u8, reg: u8, data: u16);
+}
+
+/// Station management interface of the MAC
+pub struct Smi<'a> {
+    mac: &'a ETHERNET_MAC,
+}
+
+impl<'a> Smi<'a> {
+    pub(crate) fn new(mac: &'a ETHERNET_MAC) -> Self {
+        Self { mac }
+    }
+
+    fn wait_ready(&self) {
+        while self.mac.macmiiar.read().mb().bit_is_set() {}
+    }
+}
+
+impl Mdio for Smi<'_> {
+    fn read(&mut self, phy: u8, reg: u8) -> u16 {
+        self.wait_ready();
+        #[allow(unused_unsafe)]
+        self.mac.macmiiar.modify(|_, w| unsafe {
+            w.pa().bits(phy);
+            w.mr().bits(reg);
+            w.mw().clear_bit();
+            w.mb().set_bit()
+        });
+        self.wait_ready();
+        self.mac.macmiidr.read().bits() as u16
+    }
+
+    fn write(&mut self, phy: u8, reg: u8, data: u16) {
+        self.wait_ready();
+        self.mac.macmiidr.write(|w| unsafe { w.bits(data.into()) });
+        #[allow(unused_unsafe)]
+        self.mac.macmiiar.modify(|_, w| unsafe {
+            w.pa().bits(phy);
+            w.mr().bits(reg);
+            w.mw().set_bit();
+            w.mb().set_bit()
+        });
+        self.wait_ready();
+    }
+}
+
+/// Link speed
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Speed {
+    Mbps10,
+    Mbps100,
+}
+
+/// Link duplex mode
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Duplex {
+    Half,
+    Full,
+}
+
+/// Negotiated link
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Link {
+    pub speed: Speed,
+    pub duplex: Duplex,
+}
+
+/// Ethernet PHY
+pub trait Phy {
+    /// Address of the PHY on the management bus
+    fn address(&self) -> u8;
+
+    /// Link parameters once auto-negotiation has completed.
+    fn negotiated(&mut self, mdio: &mut impl Mdio) -> Link;
+
+    /// Resets the PHY and starts auto-negotiation.
+    ///
+    /// Returns [`Error::PhyTimeout`] if the reset bit does not clear within
+    /// [`PHY_RESET_TIMEOUT`] reads, which includes a missing PHY reading as `0xFFFF`.
+    fn init(&mut self, mdio: &mut impl Mdio) -> Result<(), Error> {
+        let phy = self.address();
+        mdio.write(phy, BMCR, BMCR_RESET);
+        (0..PHY_RESET_TIMEOUT)
+            .any(|_| mdio.read(phy, BMCR) & BMCR_RESET == 0)
+            .then(|| ())
+            .ok_or(Error::PhyTimeout)?;
+        mdio.write(phy, BMCR, BMCR_AN_ENABLE | BMCR_AN_RESTART);
+        Ok(())
+    }
+
+    /// Returns the link, or `None` while it is down or still negotiating.
+    fn link(&mut self, mdio: &mut impl Mdio) -> Option<Link> {
+        let phy = self.address();
+        // The link status bit latches low, read twice to get the current state
+        let _ = mdio.read(phy, BMSR);
+        let bmsr = mdio.read(phy, BMSR);
+        if bmsr & BMSR_LINK_UP != 0 && bmsr & BMSR_AN_COMPLETE != 0 {
+            Some(self.negotiated(mdio))
+        } else {
+            None
+        }
+    }
+
+    /// 32-bit PHY identifier (OUI, model and revision).
+    fn id(&mut self, mdio: &mut impl Mdio) -> u32 {
+        let phy = self.address();
+        (u32::from(mdio.read(phy, PHYIDR1)) << 16) | u32::from(mdio.read(phy, PHYIDR2))
+    }
+}
+
+/// Microchip LAN8742A, as found on the Nucleo-144 boards
+pub struct Lan8742 {
+    address: u8,
+}
+
+impl Lan8742 {
+    /// Special control/status register
+    const PSCSR: u8 = 31;
+
+    pub fn new(address: u8) -> Self {
+        Self { address }
+    }
+}
+
+impl Phy for Lan8742 {
+    fn address(&self) -> u8 {
+        self.address
+    }
+
+    fn negotiated(&mut self, mdio: &mut impl Mdio) -> Link {
+        // Speed indication: bit 3 is 100 Mbit/s, bit 4 is full duplex
+        let pscsr = mdio.read(self.address, Self::PSCSR);
+        Link {
+            speed: if pscsr & (1 << 3) != 0 {
+                Speed::Mbps100
+            } else {
+                Speed::Mbps10
+            },
+            duplex: if pscsr & (1 << 4) != 0 {
+                Duplex::Full
+            } else {
+                Duplex::Half
+            },
+        }
+    }
+}
+
+/// Texas Instruments DP83848, as found on the STM3240G-EVAL and several F4 boards
+pub struct Dp83848 {
+    address: u8,
+}
+
+impl Dp83848 {
+    /// PHY status register
+    const PHYSTS: u8 = 0x10;
+
+    pub fn new(address: u8) -> Self {
+        Self { address }
+    }
+}
+
+impl Phy for Dp83848 {
+    fn address(&self) -> u8 {
+        self.address
+    }
+
+    fn negotiated(&mut self, mdio: &mut impl Mdio) -> Link {
+        // Bit 1 is set for 10 Mbit/s, bit 2 for full duplex
+        let physts = mdio.read(self.address, Self::PHYSTS);
+        Link {
+            speed: if physts & (1 << 1) != 0 {
+                Speed::Mbps10
+            } else {
+                Speed::Mbps100
+            },
+            duplex: if physts & (1 << 2) != 0 {
+                Duplex::Full
+            } else {
+                Duplex::Half
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// PHY whose `BMCR` reads `bmcr` after a reset, or `0xFFFF` for none at all
+    struct Bus {
+        bmcr: u16,
+        writes: u32,
+    }
+
+    impl Mdio for Bus {
+        fn read(&mut self, _phy: u8, reg: u8) -> u16 {
+            assert_eq!(reg, BMCR);
+            self.bmcr
+        }
+
+        fn write(&mut self, _phy: u8, reg: u8, _data: u16) {
+            assert_eq!(reg, BMCR);
+            self.writes += 1;
+        }
+    }
+
+    #[test]
+    fn init() {
+        let mut bus = Bus {
+            bmcr: BMCR_AN_ENABLE,
+            writes: 0,
+        };
+        assert_eq!(Lan8742::new(0).init(&mut bus), Ok(()));
+        // Reset, then auto-negotiation
+        assert_eq!(bus.writes, 2);
+    }
+
+    #[test]
+    fn init_without_phy() {
+        let mut bus = Bus {
+            bmcr: 0xFFFF,
+            writes: 0,
+        };
+        assert_eq!(Lan8742::new(0).init(&mut bus), Err(Error::PhyTimeout));
+        assert_eq!(bus.writes, 1);
+    }
+}
@@ -0,0 +1,103 @@
+//! [`smoltcp::phy::Device`] implementation
+
+use smoltcp::phy::{self, ChecksumCapabilities, DeviceCapabilities, Medium};
+use smoltcp::time::Instant;
+
+use core::mem::ManuallyDrop;
+
+use super::{desc::TxSlot, EthernetDma, RxPacket};
+use crate::pac;
+
+/// Largest Ethernet frame without CRC
+const MTU: usize = 1514;
+
+/// Token for the next received frame
+///
+/// The frame's descriptor goes back to the DMA when the token is consumed or dropped.
+pub struct RxToken<'a>(ManuallyDrop<RxPacket<'a>>);
+
+impl phy::RxToken for RxToken<'_> {
+    fn consume<R, F>(mut self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        f(&mut self.0)
+    }
+}
+
+impl Drop for RxToken<'_> {
+    fn drop(&mut self) {
+        // NOTE(unsafe) the packet is dropped only here
+        unsafe { ManuallyDrop::drop(&mut self.0) };
+        // Resume reception if it stopped on a full ring
+        demand_rx_poll();
+    }
+}
+
+/// Token for sending a frame, holding a free descriptor
+pub struct TxToken<'a>(TxSlot<'a>);
+
+impl phy::TxToken for TxToken<'_> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        // `len` is at most the MTU, which fits in a buffer
+        let result = self.0.send(len, f);
+        demand_tx_poll();
+        result
+    }
+}
+
+fn demand_rx_poll() {
+    // NOTE(unsafe) write-only register with no side effect but waking the DMA
+    unsafe {
+        (*pac::ETHERNET_DMA::ptr())
+            .dmarpdr
+            .write(|w| w.rpd().bits(1))
+    };
+}
+
+fn demand_tx_poll() {
+    // NOTE(unsafe) write-only register with no side effect but waking the DMA
+    unsafe {
+        (*pac::ETHERNET_DMA::ptr())
+            .dmatpdr
+            .write(|w| w.tpd().bits(1))
+    };
+}
+
+impl<'rx, 'tx> phy::Device for EthernetDma<'rx, 'tx> {
+    type RxToken<'a>
+        = RxToken<'a>
+    where
+        Self: 'a;
+    type TxToken<'a>
+        = TxToken<'a>
+    where
+        Self: 'a;
+
+    fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let tx = self.tx_ring.next_free()?;
+        // Frames received with an error are dropped here instead of being handed to smoltcp
+        if self.rx_ring.drop_errors() {
+            demand_rx_poll();
+        }
+        let rx = self.rx_ring.recv_next().ok()?;
+        Some((RxToken(ManuallyDrop::new(rx)), TxToken(tx)))
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        self.tx_ring.next_free().map(TxToken)
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.medium = Medium::Ethernet;
+        caps.max_transmission_unit = MTU;
+        caps.max_burst_size = Some(1);
+        // The MAC checks received checksums and inserts them into sent frames
+        caps.checksum = ChecksumCapabilities::ignored();
+        caps
+    }
+}
@@ -14,6 +14,9 @@ pub enum Error {
     ProgrammingAlignment,
     WriteProtection,
     Operation,
+    /// The option byte change needs
+    /// [`UnlockedFlash::write_option_bytes_unchecked`]
+    ReadProtection,
 }
 
 impl Error {
@@ -54,6 +57,8 @@ pub trait FlashExt {
     fn dual_bank(&self) -> bool;
     /// Returns flash memory sector of a given offset. Returns none if offset is out of range.
     fn sector(&self, offset: usize) -> Option<FlashSector>;
+    /// Returns the option bytes loaded at the last reset or written since
+    fn option_bytes(&self) -> OptionBytes;
 }
 
 impl FlashExt for FLASH {
@@ -99,6 +104,14 @@ impl FlashExt for FLASH {
     fn sector(&self, offset: usize) -> Option<FlashSector> {
         flash_sectors(self.len(), self.dual_bank()).find(|s| s.contains(offset))
     }
+
+    fn option_bytes(&self) -> OptionBytes {
+        #[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+        let optcr1 = Some(self.optcr1.read().bits());
+        #[cfg(not(any(feature = "gpio-f427", feature = "gpio-f469")))]
+        let optcr1 = None;
+        OptionBytes::from_optcr(self.optcr.read().bits(), optcr1)
+    }
 }
 
 const PSIZE_X8: u8 = 0b00;
@@ -150,6 +163,10 @@ impl FlashExt for LockedFlash {
     fn sector(&self, offset: usize) -> Option<FlashSector> {
         self.flash.sector(offset)
     }
+
+    fn option_bytes(&self) -> OptionBytes {
+        self.flash.option_bytes()
+    }
 }
 
 /// Result of `FlashExt::unlocked()`
@@ -255,6 +272,47 @@ impl UnlockedFlash<'_> {
         Ok(())
     }
 
+    /// Programs the option bytes, which take effect after the next reset
+    ///
+    /// Read the current values with [`FlashExt::option_bytes`] and change them. Setting read
+    /// protection level 2, going back from level 1 to level 0 or switching on
+    /// [`OptionBytes::pcrop`] returns [`Error::ReadProtection`].
+    pub fn write_option_bytes(&mut self, option_bytes: OptionBytes) -> Result<(), Error> {
+        if self.flash.option_bytes().is_safe_change(&option_bytes) {
+            unsafe { self.write_option_bytes_unchecked(option_bytes) }
+        } else {
+            Err(Error::ReadProtection)
+        }
+    }
+
+    /// Programs the option bytes without checking the read protection transition
+    ///
+    /// # Safety
+    ///
+    /// Read protection level 2 permanently disables debugging and the option bytes can never
+    /// be changed again. Going back from level 1 to level 0 mass erases the flash, including
+    /// the running program. Proprietary code read protection can only be removed by that
+    /// level 1 to level 0 change.
+    pub unsafe fn write_option_bytes_unchecked(
+        &mut self,
+        option_bytes: OptionBytes,
+    ) -> Result<(), Error> {
+        let (optcr, _optcr1) = option_bytes.to_optcr();
+        self.wait_ready();
+        self.flash.optkeyr.write(|w| w.bits(OPT_KEY1));
+        self.flash.optkeyr.write(|w| w.bits(OPT_KEY2));
+        #[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+        self.flash.optcr1.write(|w| w.bits(_optcr1));
+        self.flash.optcr.write(|w| w.bits(optcr));
+        self.flash.optcr.write(|w| w.bits(optcr | OPTCR_OPTSTRT));
+        self.wait_ready();
+        let result = self.ok();
+        self.flash
+            .optcr
+            .modify(|r, w| w.bits(r.bits() | OPTCR_OPTLOCK));
+        result
+    }
+
     fn ok(&self) -> Result<(), Error> {
         Error::read(self.flash).map(Err).unwrap_or(Ok(()))
     }
@@ -264,6 +322,157 @@ impl UnlockedFlash<'_> {
     }
 }
 
+/// Read protection (RDP) level
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadProtection {
+    /// No protection
+    Level0,
+    /// Flash can not be read by the debugger or the bootloader
+    Level1,
+    /// Debugging and option byte changes are disabled for ever
+    Level2,
+}
+
+/// Brown-out reset threshold
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BorLevel {
+    /// 2.70 to 3.60 V
+    Level3 = 0b00,
+    /// 2.40 to 2.70 V
+    Level2 = 0b01,
+    /// 2.10 to 2.40 V
+    Level1 = 0b10,
+    /// Power-on and power-down reset only, 1.80 to 2.10 V
+    Off = 0b11,
+}
+
+/// User option bytes from `FLASH_OPTCR` and `FLASH_OPTCR1`
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OptionBytes {
+    pub rdp: ReadProtection,
+    pub bor_level: BorLevel,
+    /// Independent watchdog started by software, else by hardware at reset
+    pub watchdog_sw: bool,
+    /// Reset instead of entering Stop mode
+    pub reset_on_stop: bool,
+    /// Reset instead of entering Standby mode
+    pub reset_on_standby: bool,
+    /// Write protected sectors, bit `n` for sector `n`, sectors 12 to 23 are only on devices
+    /// with 2 MB of flash
+    pub write_protection: u32,
+    /// The sectors in `write_protection` are protected against data reads and writes instead
+    /// (`SPRMOD`, proprietary code read protection), only instruction fetches are allowed.
+    ///
+    /// Not available on F405, F407, F415 and F417.
+    pub pcrop: bool,
+    /// Other bits of `FLASH_OPTCR` kept unchanged
+    other: u32,
+}
+
+const OPT_KEY1: u32 = 0x0819_2A3B;
+const OPT_KEY2: u32 = 0x4C5D_6E7F;
+
+// FLASH_OPTCR
+const OPTCR_OPTLOCK: u32 = 1 << 0;
+const OPTCR_OPTSTRT: u32 = 1 << 1;
+const OPTCR_BOR_LEV: u32 = 0b11 << 2;
+const OPTCR_WDG_SW: u32 = 1 << 5;
+const OPTCR_NRST_STOP: u32 = 1 << 6;
+const OPTCR_NRST_STDBY: u32 = 1 << 7;
+const OPTCR_RDP: u32 = 0xff << 8;
+const OPTCR_NWRP: u32 = 0xfff << 16;
+const OPTCR_SPRMOD: u32 = 1 << 31;
+
+const RDP_LEVEL0: u32 = 0xAA;
+const RDP_LEVEL1: u32 = 0x55;
+const RDP_LEVEL2: u32 = 0xCC;
+
+impl OptionBytes {
+    /// Decodes `FLASH_OPTCR` and `FLASH_OPTCR1`, `optcr1` is `None` on devices without it.
+    pub const fn from_optcr(optcr: u32, optcr1: Option<u32>) -> Self {
+        let rdp = match (optcr & OPTCR_RDP) >> 8 {
+            RDP_LEVEL0 => ReadProtection::Level0,
+            RDP_LEVEL2 => ReadProtection::Level2,
+            _ => ReadProtection::Level1,
+        };
+        let bor_level = match (optcr & OPTCR_BOR_LEV) >> 2 {
+            0b00 => BorLevel::Level3,
+            0b01 => BorLevel::Level2,
+            0b10 => BorLevel::Level1,
+            _ => BorLevel::Off,
+        };
+        let pcrop = optcr & OPTCR_SPRMOD != 0;
+        let mut nwrp = (optcr & OPTCR_NWRP) >> 16;
+        if let Some(optcr1) = optcr1 {
+            nwrp |= (optcr1 & OPTCR_NWRP) >> 4;
+        } else if !pcrop {
+            nwrp |= 0xfff << 12;
+        }
+        // The nWRP bits are active high with SPRMOD set
+        let protected = if pcrop { nwrp } else { !nwrp };
+        Self {
+            rdp,
+            bor_level,
+            watchdog_sw: optcr & OPTCR_WDG_SW != 0,
+            reset_on_stop: optcr & OPTCR_NRST_STOP == 0,
+            reset_on_standby: optcr & OPTCR_NRST_STDBY == 0,
+            write_protection: protected & 0xff_ffff,
+            pcrop,
+            other: optcr
+                & !(OPTCR_OPTLOCK
+                    | OPTCR_OPTSTRT
+                    | OPTCR_BOR_LEV
+                    | OPTCR_WDG_SW
+                    | OPTCR_NRST_STOP
+                    | OPTCR_NRST_STDBY
+                    | OPTCR_RDP
+                    | OPTCR_NWRP
+                    | OPTCR_SPRMOD),
+        }
+    }
+
+    /// Encodes `FLASH_OPTCR` and `FLASH_OPTCR1`, with `OPTLOCK` and `OPTSTRT` cleared
+    pub const fn to_optcr(&self) -> (u32, u32) {
+        let rdp = match self.rdp {
+            ReadProtection::Level0 => RDP_LEVEL0,
+            ReadProtection::Level1 => RDP_LEVEL1,
+            ReadProtection::Level2 => RDP_LEVEL2,
+        };
+        let nwrp = if self.pcrop {
+            self.write_protection
+        } else {
+            !self.write_protection
+        };
+        let mut optcr = self.other | (rdp << 8) | ((self.bor_level as u32) << 2);
+        optcr |= (nwrp & 0xfff) << 16;
+        if self.pcrop {
+            optcr |= OPTCR_SPRMOD;
+        }
+        if self.watchdog_sw {
+            optcr |= OPTCR_WDG_SW;
+        }
+        if !self.reset_on_stop {
+            optcr |= OPTCR_NRST_STOP;
+        }
+        if !self.reset_on_standby {
+            optcr |= OPTCR_NRST_STDBY;
+        }
+        (optcr, ((nwrp >> 12) & 0xfff) << 16)
+    }
+
+    /// Whether `to` can be programmed over `self` without
+    /// [`UnlockedFlash::write_option_bytes_unchecked`]
+    const fn is_safe_change(&self, to: &Self) -> bool {
+        !matches!(
+            (self.rdp, to.rdp),
+            (_, ReadProtection::Level2) | (ReadProtection::Level1, ReadProtection::Level0)
+        ) && (self.pcrop || !to.pcrop)
+    }
+}
+
 const UNLOCK_KEY1: u32 = 0x45670123;
 const UNLOCK_KEY2: u32 = 0xCDEF89AB;
 
@@ -434,3 +643,94 @@ impl<'a> NorFlash for UnlockedFlash<'a> {
 
 // STM32F4 supports multiple writes
 impl<'a> MultiwriteNorFlash for UnlockedFlash<'a> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn option_bytes_reset_value() {
+        let ob = OptionBytes::from_optcr(0x0FFF_AAED, Some(0x0FFF_0000));
+        assert_eq!(ob.rdp, ReadProtection::Level0);
+        assert_eq!(ob.bor_level, BorLevel::Off);
+        assert!(ob.watchdog_sw);
+        assert!(!ob.reset_on_stop);
+        assert!(!ob.reset_on_standby);
+        assert_eq!(ob.write_protection, 0);
+        assert!(!ob.pcrop);
+        assert_eq!(ob.to_optcr(), (0x0FFF_AAEC, 0x0FFF_0000));
+        assert_eq!(OptionBytes::from_optcr(0x0FFF_AAED, None), ob);
+    }
+
+    #[test]
+    fn option_bytes_round_trip() {
+        // DB1M and BFB2 are kept, sectors 3, 12 and 23 write protected
+        let optcr = 0x4FF7_55F4 | OPTCR_NRST_STOP;
+        let optcr1 = 0x07FE_0000;
+        let ob = OptionBytes::from_optcr(optcr, Some(optcr1));
+        assert_eq!(ob.rdp, ReadProtection::Level1);
+        assert_eq!(ob.bor_level, BorLevel::Level2);
+        assert!(ob.watchdog_sw);
+        assert!(!ob.reset_on_stop);
+        assert!(!ob.reset_on_standby);
+        assert_eq!(ob.write_protection, (1 << 3) | (1 << 12) | (1 << 23));
+        assert_eq!(ob.other, 0x4000_0010);
+        assert_eq!(ob.to_optcr(), (optcr, optcr1));
+
+        let ob = OptionBytes {
+            rdp: ReadProtection::Level0,
+            bor_level: BorLevel::Level3,
+            watchdog_sw: false,
+            reset_on_stop: true,
+            reset_on_standby: true,
+            write_protection: 1 << 13,
+            ..ob
+        };
+        assert_eq!(ob.to_optcr(), (0x4FFF_AA10, 0x0FFD_0000));
+        assert_eq!(OptionBytes::from_optcr(0x4FFF_AA10, Some(0x0FFD_0000)), ob);
+    }
+
+    #[test]
+    fn option_bytes_pcrop() {
+        // With SPRMOD the nWRP bits select the protected sectors
+        let ob = OptionBytes::from_optcr(0x8005_AAED, None);
+        assert!(ob.pcrop);
+        assert_eq!(ob.write_protection, 0b101);
+        assert_eq!(ob.other, 0);
+        assert_eq!(ob.to_optcr(), (0x8005_AAEC, 0));
+
+        let ob = OptionBytes::from_optcr(0x8000_AAED, Some(0x0800_0000));
+        assert_eq!(ob.write_protection, 1 << 23);
+        assert_eq!(ob.to_optcr(), (0x8000_AAEC, 0x0800_0000));
+
+        let ob = OptionBytes { pcrop: false, ..ob };
+        assert_eq!(ob.to_optcr(), (0x0FFF_AAEC, 0x07FF_0000));
+    }
+
+    #[test]
+    fn option_bytes_changes() {
+        let level0 = OptionBytes::from_optcr(0x0FFF_AAED, None);
+        let level1 = OptionBytes {
+            rdp: ReadProtection::Level1,
+            ..level0
+        };
+        let level2 = OptionBytes {
+            rdp: ReadProtection::Level2,
+            ..level0
+        };
+        let pcrop = OptionBytes {
+            pcrop: true,
+            write_protection: 1,
+            ..level0
+        };
+        assert!(level0.is_safe_change(&level1));
+        assert!(level0.is_safe_change(&level0));
+        assert!(level1.is_safe_change(&level1));
+        assert!(!level1.is_safe_change(&level0));
+        assert!(!level0.is_safe_change(&level2));
+        assert!(!level1.is_safe_change(&level2));
+        assert!(!level0.is_safe_change(&pcrop));
+        assert!(pcrop.is_safe_change(&pcrop));
+        assert!(pcrop.is_safe_change(&level0));
+    }
+}
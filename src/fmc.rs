@@ -0,0 +1,5 @@
+//! Flexible Memory Controller
+//!
+//! For LCDs on the NOR/SRAM banks see [`fsmc_lcd`](crate::fsmc_lcd).
+
+pub mod sdram;
@@ -0,0 +1,539 @@
+//! SDRAM controller
+//!
+//! The chip is described by an [`SdramChip`] implementation, [`Is42s16400j`] and
+//! [`Mt48lc4m32b2`] are included. [`Sdram::init`] runs the JEDEC power-up sequence and returns
+//! the whole memory as a slice:
+//!
+//! ```rust,ignore
+//! let clocks = rcc.cfgr.sysclk(180.MHz()).freeze();
+//! let mut delay = cp.SYST.delay(&clocks);
+//!
+//! // STM32F429I-DISCO: IS42S16400J on bank 2
+//! let pins = (
+//!     Bank2(gpiob.pb5, gpiob.pb6),
+//!     (gpiog.pg8, gpiof.pf11, gpiog.pg15, gpioc.pc0, gpiog.pg4, gpiog.pg5, gpioe.pe0, gpioe.pe1),
+//!     (gpiof.pf0, gpiof.pf1, /* ... */ gpiog.pg1),
+//!     (gpiod.pd14, gpiod.pd15, /* ... */ gpiod.pd10),
+//! );
+//! let ram: &'static mut [u32] = Sdram::new(dp.FMC, pins, Is42s16400j, &clocks).init(&mut delay)?;
+//! ```
+//!
+//! The GPIO tables only cover a 16-bit data bus. Boards with a 32-bit bus configure their pins
+//! themselves and use [`Sdram::new_unchecked`].
+
+use core::slice;
+
+use embedded_hal::delay::DelayNs;
+
+use crate::gpio::alt::fsmc as alt;
+use crate::pac::FMC;
+use crate::rcc::{Clocks, Enable, Reset};
+
+/// SDRAM bank of the controller
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bank {
+    /// Selected by `SDNE0`/`SDCKE0`, mapped at `0xC000_0000`
+    One,
+    /// Selected by `SDNE1`/`SDCKE1`, mapped at `0xD000_0000`
+    Two,
+}
+
+impl Bank {
+    pub const fn address(self) -> usize {
+        match self {
+            Self::One => 0xC000_0000,
+            Self::Two => 0xD000_0000,
+        }
+    }
+}
+
+/// SDRAM error
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// HCLK / 3 is above the highest clock of the chip
+    ClockTooFast,
+}
+
+/// Geometry and access settings of an SDRAM
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SdramConfig {
+    /// Column address bits, 8 to 11
+    pub column_bits: u8,
+    /// Row address bits, 11 to 13
+    pub row_bits: u8,
+    /// Data bus width in bits, 8, 16 or 32
+    pub data_width: u8,
+    /// Internal banks, 2 or 4
+    pub internal_banks: u8,
+    /// CAS latency in clock cycles, 1 to 3
+    pub cas_latency: u8,
+    /// Combine reads into bursts
+    pub read_burst: bool,
+    /// Additional HCLK cycles before read data is sampled, 0 to 2
+    pub read_pipe_delay: u8,
+}
+
+impl SdramConfig {
+    /// Size of the memory in bytes
+    pub const fn size(&self) -> usize {
+        (1 << (self.column_bits + self.row_bits))
+            * self.internal_banks as usize
+            * (self.data_width as usize / 8)
+    }
+
+    /// Value of the load mode register command: burst length 1, sequential, single write
+    /// bursts.
+    pub const fn mode_register(&self) -> u16 {
+        ((self.cas_latency as u16) << 4) | (1 << 9)
+    }
+}
+
+/// Timing of an SDRAM, from the datasheet
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SdramTiming {
+    /// Highest clock frequency in Hz
+    pub max_clock: u32,
+    /// Delay between clock start and the first command in µs
+    pub startup_delay_us: u32,
+    /// All rows must be refreshed within this period, in ms
+    pub refresh_period_ms: u32,
+    /// tMRD: load mode register to active, in clock cycles
+    pub mode_register_to_active: u8,
+    /// tWR: write recovery, in clock cycles
+    pub write_recovery: u8,
+    /// tXSR: exit self refresh to active, in ns
+    pub exit_self_refresh: u32,
+    /// tRAS: active to precharge, in ns
+    pub active_to_precharge: u32,
+    /// tRC: active to active, in ns
+    pub row_cycle: u32,
+    /// tRP: precharge to active, in ns
+    pub row_precharge: u32,
+    /// tRCD: active to read/write, in ns
+    pub row_to_column: u32,
+}
+
+/// Description of an SDRAM chip
+pub trait SdramChip {
+    const CONFIG: SdramConfig;
+    const TIMING: SdramTiming;
+}
+
+/// ISSI IS42S16400J-7, 8 MiB with a 16-bit bus, on the STM32F429I-DISCO
+pub struct Is42s16400j;
+
+impl SdramChip for Is42s16400j {
+    const CONFIG: SdramConfig = SdramConfig {
+        column_bits: 8,
+        row_bits: 12,
+        data_width: 16,
+        internal_banks: 4,
+        cas_latency: 3,
+        read_burst: true,
+        read_pipe_delay: 1,
+    };
+    const TIMING: SdramTiming = SdramTiming {
+        max_clock: 143_000_000,
+        startup_delay_us: 100,
+        refresh_period_ms: 64,
+        mode_register_to_active: 2,
+        write_recovery: 2,
+        exit_self_refresh: 70,
+        active_to_precharge: 42,
+        row_cycle: 63,
+        row_precharge: 15,
+        row_to_column: 15,
+    };
+}
+
+/// Micron MT48LC4M32B2-6, 16 MiB with a 32-bit bus, on the STM32F469I-DISCO
+pub struct Mt48lc4m32b2;
+
+impl SdramChip for Mt48lc4m32b2 {
+    const CONFIG: SdramConfig = SdramConfig {
+        column_bits: 8,
+        row_bits: 12,
+        data_width: 32,
+        internal_banks: 4,
+        cas_latency: 3,
+        read_burst: true,
+        read_pipe_delay: 0,
+    };
+    const TIMING: SdramTiming = SdramTiming {
+        max_clock: 167_000_000,
+        startup_delay_us: 100,
+        refresh_period_ms: 64,
+        mode_register_to_active: 2,
+        write_recovery: 2,
+        exit_self_refresh: 70,
+        active_to_precharge: 42,
+        row_cycle: 60,
+        row_precharge: 18,
+        row_to_column: 18,
+    };
+}
+
+/// Number of `sdclk` cycles covering `ns`, limited to the 1 to 16 cycles `SDTR` can hold
+pub const fn cycles(ns: u32, sdclk: u32) -> u32 {
+    let cycles = (ns as u64 * sdclk as u64 + 999_999_999) / 1_000_000_000;
+    if cycles < 1 {
+        1
+    } else if cycles > 16 {
+        16
+    } else {
+        cycles as u32
+    }
+}
+
+/// Value of `SDTRx` for `timing` at `sdclk`.
+pub const fn sdtr(timing: &SdramTiming, sdclk: u32) -> u32 {
+    let tmrd = timing.mode_register_to_active as u32;
+    let txsr = cycles(timing.exit_self_refresh, sdclk);
+    let tras = cycles(timing.active_to_precharge, sdclk);
+    let trc = cycles(timing.row_cycle, sdclk);
+    let trp = cycles(timing.row_precharge, sdclk);
+    let trcd = cycles(timing.row_to_column, sdclk);
+    // tWR must also cover tRAS - tRCD and tRC - tRCD - tRP
+    let mut twr = timing.write_recovery as u32;
+    if tras.saturating_sub(trcd) > twr {
+        twr = tras - trcd;
+    }
+    if trc.saturating_sub(trcd + trp) > twr {
+        twr = trc - trcd - trp;
+    }
+    // The register fields hold the number of cycles minus one
+    (tmrd - 1)
+        | ((txsr - 1) << 4)
+        | ((tras - 1) << 8)
+        | ((trc - 1) << 12)
+        | ((twr - 1) << 16)
+        | ((trp - 1) << 20)
+        | ((trcd - 1) << 24)
+}
+
+/// Value of `SDCRx` for `config`, with the SDRAM clock at HCLK / `clock_divider`.
+pub const fn sdcr(config: &SdramConfig, clock_divider: u32) -> u32 {
+    let nc = (config.column_bits - 8) as u32;
+    let nr = (config.row_bits - 11) as u32;
+    let mwid = match config.data_width {
+        8 => 0,
+        16 => 1,
+        _ => 2,
+    };
+    let nb = (config.internal_banks == 4) as u32;
+    nc | (nr << 2)
+        | (mwid << 4)
+        | (nb << 6)
+        | ((config.cas_latency as u32) << 7)
+        | (clock_divider << 10)
+        | ((config.read_burst as u32) << 12)
+        | ((config.read_pipe_delay as u32) << 13)
+}
+
+/// Refresh timer count for `SDRTR`: the refresh period per row in `sdclk` cycles, minus the
+/// 20 cycle safety margin.
+pub const fn refresh_count(config: &SdramConfig, timing: &SdramTiming, sdclk: u32) -> u32 {
+    let rows = 1u64 << config.row_bits;
+    let per_row = timing.refresh_period_ms as u64 * sdclk as u64 / (rows * 1000);
+    per_row as u32 - 20
+}
+
+/// Smallest HCLK divider (2 or 3) keeping the SDRAM clock within `max_clock`.
+pub const fn clock_divider(hclk: u32, max_clock: u32) -> Option<u32> {
+    if hclk / 2 <= max_clock {
+        Some(2)
+    } else if hclk / 3 <= max_clock {
+        Some(3)
+    } else {
+        None
+    }
+}
+
+/// Bank select and clock enable pins of bank 1: `(SDCKE0, SDNE0)`
+pub struct Bank1<CKE, NE>(pub CKE, pub NE);
+/// Bank select and clock enable pins of bank 2: `(SDCKE1, SDNE1)`
+pub struct Bank2<CKE, NE>(pub CKE, pub NE);
+
+/// Bank select and clock enable pins
+pub trait BankPins {
+    const BANK: Bank;
+    fn convert(self);
+}
+
+impl<CKE: Into<alt::Sdcke0>, NE: Into<alt::Sdne0>> BankPins for Bank1<CKE, NE> {
+    const BANK: Bank = Bank::One;
+    fn convert(self) {
+        let _: (alt::Sdcke0, alt::Sdne0) = (self.0.into(), self.1.into());
+    }
+}
+
+impl<CKE: Into<alt::Sdcke1>, NE: Into<alt::Sdne1>> BankPins for Bank2<CKE, NE> {
+    const BANK: Bank = Bank::Two;
+    fn convert(self) {
+        let _: (alt::Sdcke1, alt::Sdne1) = (self.0.into(), self.1.into());
+    }
+}
+
+/// Address pins, `A0` to `A11` or `A0` to `A12`
+pub trait AddressPins {
+    const LINES: u8;
+    fn convert(self);
+}
+
+/// Data pins, `D0` to `D15`
+pub trait DataPins {
+    const WIDTH: u8;
+    fn convert(self);
+}
+
+macro_rules! pins {
+    ($trait:ident, $const:ident = $n:literal: $($P:ident),+) => {
+        impl<$($P: Into<alt::$P>),+> $trait for ($($P,)+) {
+            const $const: u8 = $n;
+            #[allow(non_snake_case)]
+            fn convert(self) {
+                let ($($P,)+) = self;
+                $(let _: alt::$P = $P.into();)+
+            }
+        }
+    };
+}
+
+pins!(AddressPins, LINES = 12: A0, A1, A2, A3, A4, A5, A6, A7, A8, A9, A10, A11);
+pins!(AddressPins, LINES = 13: A0, A1, A2, A3, A4, A5, A6, A7, A8, A9, A10, A11, A12);
+pins!(DataPins, WIDTH = 16: D0, D1, D2, D3, D4, D5, D6, D7, D8, D9, D10, D11, D12, D13, D14, D15);
+
+/// Command pins: `(SDCLK, SDNRAS, SDNCAS, SDNWE, BA0, BA1, NBL0, NBL1)`
+pub trait ControlPins {
+    fn convert(self);
+}
+
+impl<SDCLK, SDNRAS, SDNCAS, SDNWE, BA0, BA1, NBL0, NBL1> ControlPins
+    for (SDCLK, SDNRAS, SDNCAS, SDNWE, BA0, BA1, NBL0, NBL1)
+where
+    SDCLK: Into<alt::Sdclk>,
+    SDNRAS: Into<alt::Sdnras>,
+    SDNCAS: Into<alt::Sdncas>,
+    SDNWE: Into<alt::Sdnwe>,
+    BA0: Into<alt::Ba0>,
+    BA1: Into<alt::Ba1>,
+    NBL0: Into<alt::Nbl0>,
+    NBL1: Into<alt::Nbl1>,
+{
+    fn convert(self) {
+        let _: (
+            alt::Sdclk,
+            alt::Sdnras,
+            alt::Sdncas,
+            alt::Sdnwe,
+            alt::Ba0,
+            alt::Ba1,
+            alt::Nbl0,
+            alt::Nbl1,
+        ) = (
+            self.0.into(),
+            self.1.into(),
+            self.2.into(),
+            self.3.into(),
+            self.4.into(),
+            self.5.into(),
+            self.6.into(),
+            self.7.into(),
+        );
+    }
+}
+
+/// SDRAM pins: `(bank, control, address, data)`
+pub trait Pins {
+    const BANK: Bank;
+    const ADDRESS_LINES: u8;
+    const DATA_WIDTH: u8;
+    fn convert(self);
+}
+
+impl<BANK, CTRL, ADDR, DATA> Pins for (BANK, CTRL, ADDR, DATA)
+where
+    BANK: BankPins,
+    CTRL: ControlPins,
+    ADDR: AddressPins,
+    DATA: DataPins,
+{
+    const BANK: Bank = BANK::BANK;
+    const ADDRESS_LINES: u8 = ADDR::LINES;
+    const DATA_WIDTH: u8 = DATA::WIDTH;
+    fn convert(self) {
+        self.0.convert();
+        self.1.convert();
+        self.2.convert();
+        self.3.convert();
+    }
+}
+
+// SDCMR
+const CMD_CLOCK_ENABLE: u32 = 0b001;
+const CMD_PRECHARGE_ALL: u32 = 0b010;
+const CMD_AUTO_REFRESH: u32 = 0b011;
+const CMD_LOAD_MODE_REGISTER: u32 = 0b100;
+const CMD_TARGET_BANK1: u32 = 1 << 4;
+const CMD_TARGET_BANK2: u32 = 1 << 3;
+// SDSR
+const SR_BUSY: u32 = 1 << 5;
+// Fields of SDCR and SDTR that only exist for bank 1 and are shared with bank 2
+const SDCR_COMMON: u32 = 0b111_1100_0000_0000;
+const SDTR_COMMON: u32 = (0xf << 12) | (0xf << 20);
+
+/// Auto-refresh cycles during initialization
+const AUTO_REFRESH_CYCLES: u32 = 8;
+
+/// SDRAM controller, not yet initialized
+pub struct Sdram<CHIP> {
+    fmc: FMC,
+    bank: Bank,
+    hclk: u32,
+    _chip: CHIP,
+}
+
+impl<CHIP: SdramChip> Sdram<CHIP> {
+    /// # Panics
+    ///
+    /// When the pins don't match the chip.
+    pub fn new(fmc: FMC, pins: impl Pins, chip: CHIP, clocks: &Clocks) -> Self {
+        fn check<P: Pins, C: SdramChip>(_: &P) -> Bank {
+            assert!(P::ADDRESS_LINES >= C::CONFIG.row_bits.max(C::CONFIG.column_bits));
+            assert_eq!(P::DATA_WIDTH, C::CONFIG.data_width);
+            P::BANK
+        }
+        let bank = check::<_, CHIP>(&pins);
+        pins.convert();
+        // NOTE(unsafe) the pins have been configured
+        unsafe { Self::new_unchecked(fmc, bank, chip, clocks) }
+    }
+
+    /// Creates the controller for `bank` without configuring pins.
+    ///
+    /// # Safety
+    ///
+    /// All pins used by the chip must be in FMC alternate function mode.
+    pub unsafe fn new_unchecked(fmc: FMC, bank: Bank, chip: CHIP, clocks: &Clocks) -> Self {
+        FMC::enable_unchecked();
+        FMC::reset_unchecked();
+        Self {
+            fmc,
+            bank,
+            hclk: clocks.hclk().raw(),
+            _chip: chip,
+        }
+    }
+
+    /// Configures the controller and the chip and returns the memory.
+    ///
+    /// Fails without touching the controller when HCLK / 3 is above the highest clock of the
+    /// chip.
+    pub fn init(self, delay: &mut impl DelayNs) -> Result<&'static mut [u32], Error> {
+        let config = CHIP::CONFIG;
+        let timing = CHIP::TIMING;
+        let divider = clock_divider(self.hclk, timing.max_clock).ok_or(Error::ClockTooFast)?;
+        let sdclk = self.hclk / divider;
+
+        let sdcr = sdcr(&config, divider);
+        let sdtr = sdtr(&timing, sdclk);
+        match self.bank {
+            Bank::One => {
+                self.fmc.sdcr1().write(|w| unsafe { w.bits(sdcr) });
+                self.fmc.sdtr1().write(|w| unsafe { w.bits(sdtr) });
+            }
+            Bank::Two => {
+                self.fmc
+                    .sdcr1()
+                    .write(|w| unsafe { w.bits(sdcr & SDCR_COMMON) });
+                self.fmc
+                    .sdcr2()
+                    .write(|w| unsafe { w.bits(sdcr & !SDCR_COMMON) });
+                self.fmc
+                    .sdtr1()
+                    .write(|w| unsafe { w.bits(sdtr & SDTR_COMMON) });
+                self.fmc
+                    .sdtr2()
+                    .write(|w| unsafe { w.bits(sdtr & !SDTR_COMMON) });
+            }
+        }
+
+        self.command(CMD_CLOCK_ENABLE, 0);
+        delay.delay_us(timing.startup_delay_us);
+        self.command(CMD_PRECHARGE_ALL, 0);
+        self.command(CMD_AUTO_REFRESH | ((AUTO_REFRESH_CYCLES - 1) << 5), 0);
+        self.command(CMD_LOAD_MODE_REGISTER, config.mode_register());
+
+        let count = refresh_count(&config, &timing, sdclk);
+        self.fmc.sdrtr.write(|w| unsafe { w.bits(count << 1) });
+
+        let address = self.bank.address() as *mut u32;
+        // NOTE(unsafe) the memory is mapped now and `self` is consumed, so this is the only
+        // reference to it
+        Ok(unsafe { slice::from_raw_parts_mut(address, config.size() / 4) })
+    }
+
+    fn command(&self, command: u32, mode_register: u16) {
+        let target = match self.bank {
+            Bank::One => CMD_TARGET_BANK1,
+            Bank::Two => CMD_TARGET_BANK2,
+        };
+        while self.fmc.sdsr.read().bits() & SR_BUSY != 0 {}
+        self.fmc
+            .sdcmr
+            .write(|w| unsafe { w.bits(command | target | (u32::from(mode_register) << 9)) });
+        while self.fmc.sdsr.read().bits() & SR_BUSY != 0 {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timing_cycles() {
+        assert_eq!(cycles(0, 90_000_000), 1);
+        assert_eq!(cycles(15, 100_000_000), 2);
+        assert_eq!(cycles(20, 100_000_000), 2);
+        assert_eq!(cycles(1000, 90_000_000), 16);
+    }
+
+    #[test]
+    fn registers() {
+        // 180 MHz HCLK, 90 MHz SDRAM clock
+        assert_eq!(sdtr(&Is42s16400j::TIMING, 90_000_000), 0x0111_5361);
+        assert_eq!(sdcr(&Is42s16400j::CONFIG, 2), 0x39d4);
+        assert_eq!(sdtr(&Mt48lc4m32b2::TIMING, 90_000_000), 0x0111_5361);
+        assert_eq!(sdcr(&Mt48lc4m32b2::CONFIG, 2), 0x19e4);
+        assert_eq!(sdcr(&Mt48lc4m32b2::CONFIG, 3) >> 10 & 0b11, 3);
+
+        // tWR stretched to tRAS - tRCD
+        let sdtr = sdtr(&Is42s16400j::TIMING, 143_000_000);
+        assert_eq!(sdtr >> 8 & 0xf, 7 - 1);
+        assert_eq!(sdtr >> 24 & 0xf, 3 - 1);
+        assert_eq!(sdtr >> 16 & 0xf, 4 - 1);
+    }
+
+    #[test]
+    fn refresh() {
+        let config = Is42s16400j::CONFIG;
+        let timing = Is42s16400j::TIMING;
+        assert_eq!(refresh_count(&config, &timing, 90_000_000), 1386);
+        assert_eq!(refresh_count(&config, &timing, 84_000_000), 1292);
+        assert_eq!(config.size(), 8 << 20);
+        assert_eq!(Mt48lc4m32b2::CONFIG.size(), 16 << 20);
+        assert_eq!(config.mode_register(), 0x230);
+    }
+
+    #[test]
+    fn divider() {
+        assert_eq!(clock_divider(180_000_000, 143_000_000), Some(2));
+        assert_eq!(clock_divider(16_000_000, 143_000_000), Some(2));
+        assert_eq!(clock_divider(180_000_000, 80_000_000), Some(3));
+        assert_eq!(clock_divider(180_000_000, 50_000_000), None);
+    }
+}
@@ -0,0 +1,515 @@
+//! Hash processor
+//!
+//! SHA-1 and MD5, plus SHA-224 and SHA-256 on STM32F437/439/479, as plain hashes or HMAC.
+//!
+//! ```rust,ignore
+//! let mut hash = Hash::new(dp.HASH);
+//!
+//! let mut hasher = hash.start(Algorithm::Sha1);
+//! hasher.update(b"abc");
+//! let digest = hasher.finalize();
+//! assert_eq!(digest.as_bytes()[..4], [0xa9, 0x99, 0x3e, 0x36]);
+//!
+//! // Suspend a hash to compute another one in between
+//! let context = hash.start(Algorithm::Md5).suspend();
+//! let hmac = hash.hmac(Algorithm::Sha1, b"key").finalize();
+//! let md5 = hash.resume(&context).finalize();
+//! ```
+//!
+//! With the `rustcrypto` feature, [`rustcrypto`] provides `digest` trait implementations.
+
+use vcell::VolatileCell;
+
+use crate::dma::traits::{DMASet, PeriAddress};
+use crate::dma::MemoryToPeripheral;
+use crate::pac::HASH;
+use crate::rcc::{Enable, Reset};
+
+#[cfg(feature = "rustcrypto")]
+pub mod rustcrypto;
+
+/// Hash algorithm
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Sha1,
+    Md5,
+    #[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+    Sha224,
+    #[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+    Sha256,
+}
+
+impl Algorithm {
+    /// Digest length in bytes
+    pub const fn output_size(self) -> usize {
+        match self {
+            Self::Sha1 => 20,
+            Self::Md5 => 16,
+            #[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+            Self::Sha224 => 28,
+            #[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+            Self::Sha256 => 32,
+        }
+    }
+
+    /// `ALGO` bits of `HASH_CR`
+    const fn cr_bits(self) -> u32 {
+        match self {
+            Self::Sha1 => 0,
+            Self::Md5 => CR_ALGO0,
+            #[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+            Self::Sha224 => CR_ALGO1,
+            #[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+            Self::Sha256 => CR_ALGO1 | CR_ALGO0,
+        }
+    }
+}
+
+/// Data register value for up to 4 message bytes, with the HASH swapping bytes
+/// (`DATATYPE` = 8 bit).
+pub fn pack_word(bytes: &[u8]) -> u32 {
+    let mut word = [0; 4];
+    word[..bytes.len()].copy_from_slice(bytes);
+    u32::from_le_bytes(word)
+}
+
+/// Value of `NBLW` for a message of `len` bytes: the number of valid bits in the last word.
+pub const fn nblw(len: usize) -> u32 {
+    (len % 4) as u32 * 8
+}
+
+/// Digest bytes from the `HASH_HRx` registers.
+pub fn unpack_digest(words: &[u32], out: &mut [u8]) {
+    for (bytes, word) in out.chunks_mut(4).zip(words) {
+        let len = bytes.len();
+        bytes.copy_from_slice(&word.to_be_bytes()[..len]);
+    }
+}
+
+/// Message digest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Digest {
+    bytes: [u8; 32],
+    len: usize,
+}
+
+impl Digest {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+impl AsRef<[u8]> for Digest {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+// CR
+const CR_INIT: u32 = 1 << 2;
+const CR_DMAE: u32 = 1 << 3;
+const CR_DATATYPE_8: u32 = 0b10 << 4;
+const CR_MODE_HMAC: u32 = 1 << 6;
+const CR_ALGO0: u32 = 1 << 7;
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+const CR_MDMAT: u32 = 1 << 13;
+const CR_LKEY: u32 = 1 << 16;
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+const CR_ALGO1: u32 = 1 << 18;
+// STR
+const STR_DCAL: u32 = 1 << 8;
+// SR
+const SR_DINIS: u32 = 1 << 0;
+const SR_DCIS: u32 = 1 << 1;
+const SR_BUSY: u32 = 1 << 3;
+
+/// Number of context swap registers
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+const CSR_COUNT: usize = 54;
+#[cfg(not(any(feature = "gpio-f427", feature = "gpio-f469")))]
+const CSR_COUNT: usize = 51;
+
+#[repr(C)]
+struct Registers {
+    cr: VolatileCell<u32>,
+    din: VolatileCell<u32>,
+    str: VolatileCell<u32>,
+    hr: [VolatileCell<u32>; 5],
+    imr: VolatileCell<u32>,
+    sr: VolatileCell<u32>,
+    _reserved0: [u32; 52],
+    csr: [VolatileCell<u32>; 54],
+    _reserved1: [u32; 80],
+    /// SHA-2 digest
+    hr_ext: [VolatileCell<u32>; 8],
+}
+
+fn regs() -> &'static Registers {
+    // NOTE(unsafe) only used while the HASH is borrowed
+    unsafe { &*(HASH::ptr() as *const Registers) }
+}
+
+/// Hash processor
+pub struct Hash {
+    hash: HASH,
+}
+
+impl Hash {
+    pub fn new(hash: HASH) -> Self {
+        unsafe {
+            HASH::enable_unchecked();
+            HASH::reset_unchecked();
+        }
+        Self { hash }
+    }
+
+    pub fn release(self) -> HASH {
+        self.hash
+    }
+
+    /// Starts a new hash.
+    pub fn start(&mut self, algorithm: Algorithm) -> Hasher<'_> {
+        init(algorithm.cr_bits());
+        Hasher::new(self, algorithm)
+    }
+
+    /// Hashes `data` at once.
+    pub fn digest(&mut self, algorithm: Algorithm, data: &[u8]) -> Digest {
+        let mut hasher = self.start(algorithm);
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    /// Starts a new HMAC with `key`, which may be longer than a block.
+    pub fn hmac<'k>(&mut self, algorithm: Algorithm, key: &'k [u8]) -> Hmac<'_, 'k> {
+        // Keys longer than a block are hashed first
+        let lkey = if key.len() > 64 { CR_LKEY } else { 0 };
+        init(algorithm.cr_bits() | CR_MODE_HMAC | lkey);
+        write_message(key);
+        let regs = regs();
+        while regs.sr.get() & SR_BUSY != 0 {}
+        Hmac {
+            hasher: Hasher::new(self, algorithm),
+            key,
+        }
+    }
+
+    /// Continues a suspended hash.
+    pub fn resume(&mut self, context: &Context) -> Hasher<'_> {
+        let regs = regs();
+        regs.imr.set(context.imr);
+        regs.str.set(context.str);
+        regs.cr.set(context.cr | CR_INIT);
+        for (reg, word) in regs.csr.iter().zip(&context.csr) {
+            reg.set(*word);
+        }
+        Hasher {
+            _hash: self,
+            algorithm: context.algorithm,
+            pending: context.pending,
+            pending_len: context.pending_len,
+            len: context.len,
+        }
+    }
+
+    /// Starts a hash of `len` bytes fed by DMA.
+    ///
+    /// The DMA transfers whole words, the bytes of the last word past `len` are ignored. On
+    /// STM32F415/417, the message must be sent in a single transfer, later parts allow several.
+    pub fn dma_input(&mut self, algorithm: Algorithm, len: usize) -> DmaInput<'_> {
+        #[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+        let mdmat = CR_MDMAT;
+        #[cfg(not(any(feature = "gpio-f427", feature = "gpio-f469")))]
+        let mdmat = 0;
+        init(algorithm.cr_bits() | CR_DMAE | mdmat);
+        regs().str.set(nblw(len));
+        DmaInput {
+            _hash: self,
+            algorithm,
+        }
+    }
+}
+
+fn init(cr: u32) {
+    let regs = regs();
+    regs.cr.set(cr | CR_DATATYPE_8);
+    regs.cr.set(cr | CR_DATATYPE_8 | CR_INIT);
+}
+
+fn write_block(block: &[u8]) {
+    let regs = regs();
+    for word in block.chunks_exact(4) {
+        regs.din.set(pack_word(word));
+    }
+}
+
+/// Writes a whole message and starts the digest calculation.
+fn write_message(data: &[u8]) {
+    let regs = regs();
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        regs.din.set(pack_word(chunk));
+    }
+    let rest = chunks.remainder();
+    if !rest.is_empty() {
+        regs.din.set(pack_word(rest));
+    }
+    regs.str.set(nblw(data.len()));
+    regs.str.set(nblw(data.len()) | STR_DCAL);
+}
+
+fn read_digest(algorithm: Algorithm) -> Digest {
+    let regs = regs();
+    while regs.sr.get() & SR_DCIS == 0 {}
+    let mut words = [0; 8];
+    let count = (algorithm.output_size() + 3) / 4;
+    let hr = match algorithm {
+        Algorithm::Sha1 | Algorithm::Md5 => &regs.hr[..],
+        #[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+        _ => &regs.hr_ext[..],
+    };
+    for (word, reg) in words[..count].iter_mut().zip(hr) {
+        *word = reg.get();
+    }
+    let mut digest = Digest {
+        bytes: [0; 32],
+        len: algorithm.output_size(),
+    };
+    unpack_digest(&words[..count], &mut digest.bytes[..digest.len]);
+    digest
+}
+
+/// Block size of all algorithms
+const BLOCK_SIZE: usize = 64;
+
+/// Running hash
+///
+/// Only whole blocks are written to the FIFO, so the hash can be suspended between them.
+pub struct Hasher<'h> {
+    _hash: &'h mut Hash,
+    algorithm: Algorithm,
+    /// Bytes of an incomplete block
+    pending: [u8; BLOCK_SIZE],
+    pending_len: usize,
+    len: u64,
+}
+
+impl<'h> Hasher<'h> {
+    fn new(hash: &'h mut Hash, algorithm: Algorithm) -> Self {
+        Self {
+            _hash: hash,
+            algorithm,
+            pending: [0; BLOCK_SIZE],
+            pending_len: 0,
+            len: 0,
+        }
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// Number of bytes hashed so far.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.len += data.len() as u64;
+        if self.pending_len > 0 {
+            let take = (BLOCK_SIZE - self.pending_len).min(data.len());
+            self.pending[self.pending_len..self.pending_len + take].copy_from_slice(&data[..take]);
+            self.pending_len += take;
+            data = &data[take..];
+            if self.pending_len < BLOCK_SIZE {
+                return;
+            }
+            write_block(&self.pending);
+            self.pending_len = 0;
+        }
+        let mut blocks = data.chunks_exact(BLOCK_SIZE);
+        for block in &mut blocks {
+            write_block(block);
+        }
+        let rest = blocks.remainder();
+        self.pending[..rest.len()].copy_from_slice(rest);
+        self.pending_len = rest.len();
+    }
+
+    /// Writes the last bytes and starts the digest calculation.
+    fn finish(&mut self) {
+        write_message(&self.pending[..self.pending_len]);
+    }
+
+    pub fn finalize(mut self) -> Digest {
+        self.finish();
+        read_digest(self.algorithm)
+    }
+
+    /// Saves the state of the hash so the HASH can be used for another one.
+    ///
+    /// Waits until the last block is processed and the FIFO is empty.
+    pub fn suspend(self) -> Context {
+        let regs = regs();
+        while regs.sr.get() & (SR_DINIS | SR_BUSY) != SR_DINIS {}
+        let mut csr = [0; CSR_COUNT];
+        for (word, reg) in csr.iter_mut().zip(&regs.csr) {
+            *word = reg.get();
+        }
+        Context {
+            algorithm: self.algorithm,
+            imr: regs.imr.get(),
+            str: regs.str.get(),
+            cr: regs.cr.get(),
+            csr,
+            pending: self.pending,
+            pending_len: self.pending_len,
+            len: self.len,
+        }
+    }
+}
+
+/// Running HMAC
+pub struct Hmac<'h, 'k> {
+    hasher: Hasher<'h>,
+    key: &'k [u8],
+}
+
+impl Hmac<'_, '_> {
+    pub fn update(&mut self, data: &[u8]) {
+        self.hasher.update(data);
+    }
+
+    pub fn finalize(mut self) -> Digest {
+        self.hasher.finish();
+        // The outer hash uses the key again
+        let regs = regs();
+        while regs.sr.get() & SR_BUSY != 0 {}
+        write_message(self.key);
+        read_digest(self.hasher.algorithm)
+    }
+}
+
+/// State of a suspended hash
+#[derive(Clone)]
+pub struct Context {
+    algorithm: Algorithm,
+    imr: u32,
+    str: u32,
+    cr: u32,
+    csr: [u32; CSR_COUNT],
+    pending: [u8; BLOCK_SIZE],
+    pending_len: usize,
+    len: u64,
+}
+
+/// Input of a hash fed by DMA
+///
+/// Use it with a memory to peripheral [`Transfer`](crate::dma::Transfer) of 32-bit words.
+pub struct DmaInput<'h> {
+    _hash: &'h mut Hash,
+    algorithm: Algorithm,
+}
+
+impl DmaInput<'_> {
+    /// Waits for the digest after the last transfer.
+    pub fn finalize(self) -> Digest {
+        let regs = regs();
+        // With several transfers, the digest calculation is started by software
+        #[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+        {
+            while regs.sr.get() & SR_BUSY != 0 {}
+            regs.str.set(regs.str.get() | STR_DCAL);
+        }
+        let digest = read_digest(self.algorithm);
+        regs.cr.set(regs.cr.get() & !CR_DMAE);
+        digest
+    }
+}
+
+unsafe impl PeriAddress for DmaInput<'_> {
+    #[inline(always)]
+    fn address(&self) -> u32 {
+        regs().din.as_ptr() as u32
+    }
+
+    type MemSize = u32;
+}
+
+unsafe impl<STREAM, const CHANNEL: u8> DMASet<STREAM, CHANNEL, MemoryToPeripheral> for DmaInput<'_> where
+    HASH: DMASet<STREAM, CHANNEL, MemoryToPeripheral>
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_words() {
+        // "abc", swapped back by the HASH
+        assert_eq!(pack_word(b"abcd"), 0x6463_6261);
+        assert_eq!(pack_word(b"abc"), 0x0063_6261);
+        assert_eq!(nblw(3), 24);
+        assert_eq!(nblw(0), 0);
+        assert_eq!(nblw(64), 0);
+        assert_eq!(nblw(65), 8);
+    }
+
+    fn digest(words: &[u32], len: usize) -> [u8; 32] {
+        let mut out = [0; 32];
+        unpack_digest(words, &mut out[..len]);
+        out
+    }
+
+    #[test]
+    fn abc_digests() {
+        // FIPS 180-2 and RFC 1321 digests of "abc"
+        let sha1 = [0xa9993e36, 0x4706816a, 0xba3e2571, 0x7850c26c, 0x9cd0d89d];
+        assert_eq!(
+            digest(&sha1, 20)[..20],
+            [
+                0xa9, 0x99, 0x3e, 0x36, 0x47, 0x06, 0x81, 0x6a, 0xba, 0x3e, 0x25, 0x71, 0x78, 0x50,
+                0xc2, 0x6c, 0x9c, 0xd0, 0xd8, 0x9d
+            ]
+        );
+        let md5 = [0x90015098, 0x3cd24fb0, 0xd6963f7d, 0x28e17f72];
+        assert_eq!(
+            digest(&md5, 16)[..16],
+            [
+                0x90, 0x01, 0x50, 0x98, 0x3c, 0xd2, 0x4f, 0xb0, 0xd6, 0x96, 0x3f, 0x7d, 0x28, 0xe1,
+                0x7f, 0x72
+            ]
+        );
+        let sha224 = [
+            0x23097d22, 0x3405d822, 0x8642a477, 0xbda255b3, 0x2aadbce4, 0xbda0b3f7, 0xe36c9da7,
+        ];
+        assert_eq!(
+            digest(&sha224, 28)[..28],
+            [
+                0x23, 0x09, 0x7d, 0x22, 0x34, 0x05, 0xd8, 0x22, 0x86, 0x42, 0xa4, 0x77, 0xbd, 0xa2,
+                0x55, 0xb3, 0x2a, 0xad, 0xbc, 0xe4, 0xbd, 0xa0, 0xb3, 0xf7, 0xe3, 0x6c, 0x9d, 0xa7
+            ]
+        );
+        let sha256 = [
+            0xba7816bf, 0x8f01cfea, 0x414140de, 0x5dae2223, 0xb00361a3, 0x96177a9c, 0xb410ff61,
+            0xf20015ad,
+        ];
+        assert_eq!(
+            digest(&sha256, 32),
+            [
+                0xba, 0x78, 0x16, 0xbf, 0x8f, 0x01, 0xcf, 0xea, 0x41, 0x41, 0x40, 0xde, 0x5d, 0xae,
+                0x22, 0x23, 0xb0, 0x03, 0x61, 0xa3, 0x96, 0x17, 0x7a, 0x9c, 0xb4, 0x10, 0xff, 0x61,
+                0xf2, 0x00, 0x15, 0xad
+            ]
+        );
+        // Extra words are ignored
+        assert_eq!(digest(&sha256, 20)[..20], digest(&sha256, 32)[..20]);
+        assert_eq!(digest(&sha256, 20)[20..], [0; 12]);
+    }
+}
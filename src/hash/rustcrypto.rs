@@ -0,0 +1,71 @@
+//! [RustCrypto](https://github.com/RustCrypto) trait implementations
+//!
+//! ```rust,ignore
+//! use digest::{FixedOutput, Update};
+//!
+//! let mut sha = Sha1::new(&mut hash);
+//! sha.update(image);
+//! let digest = sha.finalize_fixed();
+//! ```
+
+use digest::consts::{U16, U20};
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+use digest::consts::{U28, U32};
+use digest::{FixedOutput, HashMarker, Output, OutputSizeUser, Update};
+
+use super::{Algorithm, Hash, Hasher};
+
+macro_rules! hasher {
+    ($(#[$attr:meta])* $Name:ident: $algorithm:ident, $OutputSize:ty) => {
+        $(#[$attr])*
+        pub struct $Name<'h>(Hasher<'h>);
+
+        $(#[$attr])*
+        impl<'h> $Name<'h> {
+            pub fn new(hash: &'h mut Hash) -> Self {
+                Self(hash.start(Algorithm::$algorithm))
+            }
+        }
+
+        $(#[$attr])*
+        impl OutputSizeUser for $Name<'_> {
+            type OutputSize = $OutputSize;
+        }
+
+        $(#[$attr])*
+        impl Update for $Name<'_> {
+            fn update(&mut self, data: &[u8]) {
+                self.0.update(data);
+            }
+        }
+
+        $(#[$attr])*
+        impl FixedOutput for $Name<'_> {
+            fn finalize_into(self, out: &mut Output<Self>) {
+                out.copy_from_slice(self.0.finalize().as_bytes());
+            }
+        }
+
+        $(#[$attr])*
+        impl HashMarker for $Name<'_> {}
+    };
+}
+
+hasher!(
+    /// SHA-1 on the HASH
+    Sha1: Sha1, U20
+);
+hasher!(
+    /// MD5 on the HASH
+    Md5: Md5, U16
+);
+hasher!(
+    /// SHA-224 on the HASH
+    #[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+    Sha224: Sha224, U28
+);
+hasher!(
+    /// SHA-256 on the HASH
+    #[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+    Sha256: Sha256, U32
+);
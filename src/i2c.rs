@@ -13,13 +13,18 @@ mod hal_1;
 
 pub mod dma;
 
-#[derive(Debug, Eq, PartialEq)]
+// The async feature requires Rust 1.75
+#[cfg(feature = "async")]
+#[allow(clippy::incompatible_msrv)]
+pub mod asynch;
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
 pub enum DutyCycle {
     Ratio2to1,
     Ratio16to9,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Mode {
     Standard {
         frequency: Hertz,
@@ -68,6 +73,7 @@ impl From<Hertz> for Mode {
 pub struct I2c<I2C: Instance> {
     i2c: I2C,
     pins: (I2C::Scl, I2C::Sda),
+    mode: Mode,
 }
 
 pub use embedded_hal::i2c::NoAcknowledgeSource;
@@ -78,7 +84,8 @@ pub enum Error {
     Overrun,
     NoAcknowledge(NoAcknowledgeSource),
     Timeout,
-    // Note: The Bus error type is not currently returned, but is maintained for compatibility.
+    // Note: The Bus error type is only returned by the async driver when a DMA transfer fails,
+    // bus errors detected by the peripheral are ignored (see the errata).
     Bus,
     Crc,
     ArbitrationLoss,
@@ -108,6 +115,9 @@ pub trait Instance:
 {
     #[doc(hidden)]
     fn ptr() -> *const i2c1::RegisterBlock;
+    #[cfg(feature = "async")]
+    #[doc(hidden)]
+    fn waker() -> &'static crate::waker::WakerCell;
 }
 
 // Implemented by all I2C instances
@@ -119,6 +129,12 @@ macro_rules! i2c {
             fn ptr() -> *const i2c1::RegisterBlock {
                 <$I2C>::ptr() as *const _
             }
+
+            #[cfg(feature = "async")]
+            fn waker() -> &'static crate::waker::WakerCell {
+                static WAKER: crate::waker::WakerCell = crate::waker::WakerCell::new();
+                &WAKER
+            }
         }
     };
 }
@@ -166,12 +182,21 @@ where
         }
 
         let pins = (pins.0.into(), pins.1.into());
+        let mode = mode.into();
 
-        let i2c = I2c { i2c, pins };
+        let i2c = I2c { i2c, pins, mode };
         i2c.i2c_init(mode, clocks.pclk1());
         i2c
     }
 
+    /// Recalculates the bus timing for new `Clocks`, e.g. after
+    /// [`CFGR::reconfigure`](crate::rcc::CFGR::reconfigure).
+    ///
+    /// Must not be called during a transfer.
+    pub fn configure(&mut self, clocks: &Clocks) {
+        self.i2c_init(self.mode, clocks.pclk1());
+    }
+
     pub fn release(self) -> (I2C, (I2C::Scl, I2C::Sda)) {
         (self.i2c, self.pins)
     }
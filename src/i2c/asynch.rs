@@ -0,0 +1,465 @@
+//! Async I2C master using DMA.
+//!
+//! [`I2c::into_async`] takes a transmit and a receive stream and returns an [`AsyncI2c`]
+//! implementing [`embedded_hal_async::i2c::I2c`]. Both stream interrupts must call
+//! `on_interrupt` (see [`crate::dma::future`]), and the I2C event and error interrupts must be
+//! unmasked in the NVIC with handlers calling [`on_interrupt`]:
+//!
+//! ```rust,ignore
+//! #[interrupt]
+//! fn I2C1_EV() {
+//!     i2c::asynch::on_interrupt::<pac::I2C1>();
+//! }
+//!
+//! #[interrupt]
+//! fn I2C1_ER() {
+//!     i2c::asynch::on_interrupt::<pac::I2C1>();
+//! }
+//! ```
+//!
+//! Consecutive operations of the same direction are sent with separate DMA transfers while the
+//! clock is stretched in between.
+
+use core::{future::poll_fn, future::Future, marker::PhantomData, pin::Pin, task::Poll};
+
+use embedded_hal::i2c::Operation;
+
+use super::dma::{Rx, Tx};
+use super::{Error, I2c, Instance};
+use crate::dma::{
+    future::{start_transfer, AsyncStream, TransferFuture},
+    traits::{Channel, DMASet},
+    ChannelX, DmaDirection, MemoryToPeripheral, PeripheralToMemory,
+};
+use crate::pac::i2c1;
+
+/// Handles the I2C event and error interrupts for the async driver.
+///
+/// Masks the I2C interrupts and wakes the waiting task.
+pub fn on_interrupt<I2C: Instance>() {
+    let i2c = unsafe { &*I2C::ptr() };
+    i2c.cr2.modify(|_, w| {
+        w.itevten().clear_bit();
+        w.itbufen().clear_bit();
+        w.iterren().clear_bit()
+    });
+    I2C::waker().wake();
+}
+
+/// Async I2C master
+pub struct AsyncI2c<I2C: Instance, TXS, const TXC: u8, RXS, const RXC: u8> {
+    i2c: I2c<I2C>,
+    tx_stream: TXS,
+    rx_stream: RXS,
+}
+
+impl<I2C: Instance> I2c<I2C> {
+    /// Converts the bus to an async one transmitting with `tx_stream` and receiving with
+    /// `rx_stream`.
+    pub fn into_async<TXS, const TXC: u8, RXS, const RXC: u8>(
+        self,
+        tx_stream: TXS,
+        rx_stream: RXS,
+    ) -> AsyncI2c<I2C, TXS, TXC, RXS, RXC>
+    where
+        TXS: AsyncStream,
+        RXS: AsyncStream,
+        ChannelX<TXC>: Channel,
+        ChannelX<RXC>: Channel,
+        Tx<I2C>: DMASet<TXS, TXC, MemoryToPeripheral>,
+        Rx<I2C>: DMASet<RXS, RXC, PeripheralToMemory>,
+    {
+        AsyncI2c {
+            i2c: self,
+            tx_stream,
+            rx_stream,
+        }
+    }
+}
+
+impl<I2C: Instance, TXS, const TXC: u8, RXS, const RXC: u8> AsyncI2c<I2C, TXS, TXC, RXS, RXC>
+where
+    TXS: AsyncStream,
+    RXS: AsyncStream,
+    ChannelX<TXC>: Channel,
+    ChannelX<RXC>: Channel,
+{
+    /// Returns the bus and both streams.
+    pub fn release(self) -> (I2c<I2C>, TXS, RXS) {
+        (self.i2c, self.tx_stream, self.rx_stream)
+    }
+
+    /// Executes `operations` in a single transaction with the device at `addr`.
+    ///
+    /// A repeated START is sent whenever the direction changes, and STOP after the last
+    /// operation or on error. Empty reads are skipped, as the bus can not read zero bytes.
+    pub async fn transaction(
+        &mut self,
+        addr: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Error> {
+        if next_group(operations).is_none() {
+            return Ok(());
+        }
+        let mut cleanup = Cleanup::<I2C> {
+            stop: true,
+            _i2c: PhantomData,
+        };
+        let result = self.transaction_inner(addr, operations).await;
+        cleanup.stop = result.is_err();
+        result
+    }
+
+    pub async fn read(&mut self, addr: u8, buffer: &mut [u8]) -> Result<(), Error> {
+        self.transaction(addr, &mut [Operation::Read(buffer)]).await
+    }
+
+    pub async fn write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Error> {
+        self.transaction(addr, &mut [Operation::Write(bytes)]).await
+    }
+
+    pub async fn write_read(
+        &mut self,
+        addr: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Error> {
+        self.transaction(
+            addr,
+            &mut [Operation::Write(bytes), Operation::Read(buffer)],
+        )
+        .await
+    }
+
+    async fn transaction_inner(
+        &mut self,
+        addr: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Error> {
+        let i2c = &self.i2c;
+
+        // Wait until a previous STOP condition finishes
+        while i2c.i2c.cr1.read().stop().bit_is_set() {}
+        i2c.i2c.cr1.modify(|_, w| w.start().set_bit());
+
+        let mut rest = operations;
+        while let Some((read, len)) = next_group(rest) {
+            let (group, tail) = core::mem::take(&mut rest).split_at_mut(len);
+            rest = tail;
+            let last = next_group(rest).is_none();
+
+            if read {
+                read_group::<_, _, RXC>(i2c, &mut self.rx_stream, addr, group, last).await?;
+            } else {
+                write_group::<_, _, TXC>(i2c, &mut self.tx_stream, addr, group, last).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Leaves the peripheral idle when a transaction ends or is cancelled.
+struct Cleanup<I2C: Instance> {
+    stop: bool,
+    _i2c: PhantomData<I2C>,
+}
+
+impl<I2C: Instance> Drop for Cleanup<I2C> {
+    fn drop(&mut self) {
+        let i2c = unsafe { &*I2C::ptr() };
+        i2c.cr2.modify(|_, w| {
+            w.dmaen().clear_bit();
+            w.last().clear_bit();
+            w.itevten().clear_bit();
+            w.itbufen().clear_bit();
+            w.iterren().clear_bit()
+        });
+        if self.stop {
+            i2c.cr1.modify(|_, w| w.stop().set_bit());
+        }
+    }
+}
+
+fn is_empty_read(op: &Operation<'_>) -> bool {
+    matches!(op, Operation::Read(buffer) if buffer.is_empty())
+}
+
+/// Direction (`true` for reading) and number of the leading operations sent without a repeated
+/// START, or `None` if nothing is left to transfer.
+///
+/// Empty reads join whichever group they are in, a read group always has at least one byte.
+fn next_group(ops: &[Operation<'_>]) -> Option<(bool, usize)> {
+    let read = matches!(
+        ops.iter().find(|op| !is_empty_read(op))?,
+        Operation::Read(_)
+    );
+    let len = ops
+        .iter()
+        .take_while(|op| is_empty_read(op) || matches!(op, Operation::Read(_)) == read)
+        .count();
+    Some((read, len))
+}
+
+/// Bytes to read in a group.
+fn read_len(ops: &[Operation<'_>]) -> usize {
+    ops.iter()
+        .map(|op| match op {
+            Operation::Read(buffer) => buffer.len(),
+            Operation::Write(_) => 0,
+        })
+        .sum()
+}
+
+/// Generates STOP after the last group, a repeated START otherwise.
+fn end_group(i2c: &i2c1::RegisterBlock, last: bool) {
+    if last {
+        i2c.cr1.modify(|_, w| w.stop().set_bit());
+    } else {
+        i2c.cr1.modify(|_, w| w.start().set_bit());
+    }
+}
+
+/// Waits until `done` returns `true` for the status register.
+///
+/// `buffer` also enables the TXE/RXNE interrupts, which is only needed without DMA.
+async fn wait_for<I2C: Instance>(
+    i2c: &I2c<I2C>,
+    buffer: bool,
+    nack: fn(Error) -> Error,
+    done: impl Fn(&i2c1::sr1::R) -> bool,
+) -> Result<(), Error> {
+    poll_fn(|cx| {
+        I2C::waker().register(cx.waker());
+        match i2c.check_and_clear_error_flags() {
+            Err(e) => Poll::Ready(Err(nack(e))),
+            Ok(sr1) if done(&sr1) => Poll::Ready(Ok(())),
+            Ok(_) => {
+                // A flag set after the check above fires the interrupt as soon as it is unmasked
+                i2c.i2c.cr2.modify(|_, w| {
+                    w.itevten().set_bit();
+                    w.itbufen().bit(buffer);
+                    w.iterren().set_bit()
+                });
+                Poll::Pending
+            }
+        }
+    })
+    .await
+}
+
+/// Waits for a DMA transfer while watching for bus errors.
+async fn dma_transfer<I2C: Instance, STREAM: AsyncStream>(
+    i2c: &I2c<I2C>,
+    mut transfer: TransferFuture<'_, STREAM>,
+) -> Result<(), Error> {
+    poll_fn(|cx| {
+        I2C::waker().register(cx.waker());
+        if let Poll::Ready(result) = Pin::new(&mut transfer).poll(cx) {
+            return Poll::Ready(result.map_err(|_| Error::Bus));
+        }
+        if let Err(e) = i2c.check_and_clear_error_flags() {
+            return Poll::Ready(Err(e.nack_data()));
+        }
+        i2c.i2c.cr2.modify(|_, w| w.iterren().set_bit());
+        Poll::Pending
+    })
+    .await
+}
+
+/// Waits for the START condition, then sends the address byte.
+async fn address<I2C: Instance>(i2c: &I2c<I2C>, byte: u8) -> Result<(), Error> {
+    wait_for(i2c, false, |e| e, |sr1| sr1.sb().bit_is_set()).await?;
+    i2c.i2c.dr.write(|w| unsafe { w.bits(u32::from(byte)) });
+    wait_for(i2c, false, Error::nack_addr, |sr1| sr1.addr().bit_is_set()).await
+}
+
+async fn read_group<I2C: Instance, STREAM: AsyncStream, const CHANNEL: u8>(
+    i2c: &I2c<I2C>,
+    stream: &mut STREAM,
+    addr: u8,
+    ops: &mut [Operation<'_>],
+    last: bool,
+) -> Result<(), Error>
+where
+    ChannelX<CHANNEL>: Channel,
+{
+    let regs = &i2c.i2c;
+    let total = read_len(ops);
+
+    regs.cr1.modify(|_, w| w.ack().bit(total > 1));
+    address(i2c, (addr << 1) | 1).await?;
+
+    if total == 1 {
+        // A single byte can't be received with DMA: NACK has to be prepared before ADDR is
+        // cleared
+        let _ = regs.sr2.read();
+        end_group(regs, last);
+        wait_for(i2c, true, Error::nack_data, |sr1| sr1.rx_ne().bit_is_set()).await?;
+        let byte = regs.dr.read().bits() as u8;
+        for op in ops {
+            if let Operation::Read(buffer) = op {
+                if let Some(b) = buffer.first_mut() {
+                    *b = byte;
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    regs.cr2
+        .modify(|_, w| w.dmaen().enabled().last().clear_bit());
+    let mut remaining = total;
+    let mut addr_cleared = false;
+    for op in ops {
+        if let Operation::Read(buffer) = op {
+            for chunk in buffer.chunks_mut(u16::MAX as usize) {
+                remaining -= chunk.len();
+                if remaining == 0 {
+                    // NACK the last byte when the stream reaches the end of the buffer
+                    regs.cr2.modify(|_, w| w.last().set_bit());
+                }
+                // NOTE(unsafe) `chunk` outlives the transfer
+                let transfer = unsafe {
+                    start_transfer::<_, CHANNEL, u8>(
+                        stream,
+                        DmaDirection::PeripheralToMemory,
+                        regs.dr.as_ptr() as u32,
+                        chunk.as_mut_ptr(),
+                        chunk.len(),
+                        true,
+                    )
+                };
+                if !addr_cleared {
+                    // Clear condition by reading SR2
+                    let _ = regs.sr2.read();
+                    addr_cleared = true;
+                }
+                dma_transfer(i2c, transfer).await?;
+            }
+        }
+    }
+    regs.cr2
+        .modify(|_, w| w.dmaen().disabled().last().clear_bit());
+    end_group(regs, last);
+
+    Ok(())
+}
+
+async fn write_group<I2C: Instance, STREAM: AsyncStream, const CHANNEL: u8>(
+    i2c: &I2c<I2C>,
+    stream: &mut STREAM,
+    addr: u8,
+    ops: &mut [Operation<'_>],
+    last: bool,
+) -> Result<(), Error>
+where
+    ChannelX<CHANNEL>: Channel,
+{
+    let regs = &i2c.i2c;
+    address(i2c, addr << 1).await?;
+
+    regs.cr2.modify(|_, w| w.dmaen().enabled());
+    let mut addr_cleared = false;
+    for op in ops.iter() {
+        if let Operation::Write(bytes) = op {
+            for chunk in bytes.chunks(u16::MAX as usize) {
+                // NOTE(unsafe) `chunk` outlives the transfer
+                let transfer = unsafe {
+                    start_transfer::<_, CHANNEL, u8>(
+                        stream,
+                        DmaDirection::MemoryToPeripheral,
+                        regs.dr.as_ptr() as u32,
+                        chunk.as_ptr(),
+                        chunk.len(),
+                        true,
+                    )
+                };
+                if !addr_cleared {
+                    // Clear condition by reading SR2
+                    let _ = regs.sr2.read();
+                    addr_cleared = true;
+                }
+                dma_transfer(i2c, transfer).await?;
+            }
+        }
+    }
+    regs.cr2.modify(|_, w| w.dmaen().disabled());
+
+    if addr_cleared {
+        // Wait until the last byte is on the bus
+        wait_for(i2c, false, Error::nack_data, |sr1| sr1.btf().bit_is_set()).await?;
+    } else {
+        // Nothing to send, e.g. when probing for a device
+        let _ = regs.sr2.read();
+    }
+    end_group(regs, last);
+
+    Ok(())
+}
+
+impl<I2C: Instance, TXS, const TXC: u8, RXS, const RXC: u8> embedded_hal::i2c::ErrorType
+    for AsyncI2c<I2C, TXS, TXC, RXS, RXC>
+{
+    type Error = Error;
+}
+
+impl<I2C: Instance, TXS, const TXC: u8, RXS, const RXC: u8> embedded_hal_async::i2c::I2c
+    for AsyncI2c<I2C, TXS, TXC, RXS, RXC>
+where
+    TXS: AsyncStream,
+    RXS: AsyncStream,
+    ChannelX<TXC>: Channel,
+    ChannelX<RXC>: Channel,
+{
+    async fn transaction(
+        &mut self,
+        addr: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.transaction(addr, operations).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups() {
+        let (mut a, mut b) = ([0; 2], [0; 3]);
+        let mut ops = [
+            Operation::Write(&[1]),
+            Operation::Write(&[2, 3]),
+            Operation::Read(&mut a),
+            Operation::Read(&mut b),
+            Operation::Write(&[]),
+        ];
+        assert_eq!(next_group(&ops), Some((false, 2)));
+        assert_eq!(next_group(&ops[2..]), Some((true, 2)));
+        assert_eq!(read_len(&ops[2..4]), 5);
+        // A zero-length write still addresses the device
+        assert_eq!(next_group(&ops[4..]), Some((false, 1)));
+        assert_eq!(next_group(&ops[5..]), None);
+        ops[4] = Operation::Read(&mut []);
+        assert_eq!(next_group(&ops[4..]), None);
+    }
+
+    #[test]
+    fn empty_reads() {
+        let mut a = [0; 1];
+        assert_eq!(next_group(&[Operation::Read(&mut [])]), None);
+        // Empty reads don't split a group or start one
+        let ops = [
+            Operation::Write(&[1]),
+            Operation::Read(&mut []),
+            Operation::Write(&[2]),
+            Operation::Read(&mut a),
+        ];
+        assert_eq!(next_group(&ops), Some((false, 3)));
+        assert_eq!(next_group(&ops[3..]), Some((true, 1)));
+        let ops = [Operation::Read(&mut []), Operation::Write(&[1])];
+        assert_eq!(next_group(&ops), Some((false, 2)));
+        assert_eq!(read_len(&ops), 0);
+    }
+}
@@ -85,15 +85,24 @@ pub use stm32f4::stm32f469 as pac;
 pub use crate::pac::interrupt;
 
 pub mod adc;
+#[cfg(feature = "aes")]
+pub mod aes;
+pub mod backup;
 pub mod bb;
 #[cfg(all(feature = "can", any(feature = "can1", feature = "can2",)))]
 pub mod can;
 pub mod crc32;
+#[cfg(feature = "cryp")]
+pub mod cryp;
 #[cfg(feature = "dac")]
 pub mod dac;
+#[cfg(feature = "dfsdm")]
+pub mod dfsdm;
 #[cfg(feature = "fmpi2c1")]
 pub mod fmpi2c;
 pub mod gpio;
+#[cfg(feature = "hash")]
+pub mod hash;
 pub mod i2c;
 pub mod i2s;
 #[cfg(all(feature = "usb_fs", feature = "otg-fs"))]
@@ -105,16 +114,27 @@ pub mod otg_hs;
 pub mod rng;
 
 pub mod dma;
+#[cfg(feature = "dma2d")]
+pub mod dma2d;
 pub mod dwt;
+#[cfg(feature = "eth")]
+pub mod eth;
 pub mod flash;
+#[cfg(feature = "fmc")]
+pub mod fmc;
 #[cfg(all(feature = "fsmc_lcd", any(feature = "fmc", feature = "fsmc")))]
 pub mod fsmc_lcd;
+#[cfg(feature = "ltdc")]
+pub mod ltdc;
 pub mod prelude;
+pub mod pwr;
 pub mod qei;
 #[cfg(feature = "quadspi")]
 pub mod qspi;
 pub mod rcc;
 pub mod rtc;
+#[cfg(feature = "sai")]
+pub mod sai;
 #[cfg(all(feature = "sdio-host", feature = "sdio"))]
 pub mod sdio;
 pub mod serial;
@@ -127,6 +147,9 @@ pub mod timer;
 pub mod uart;
 pub mod watchdog;
 
+#[cfg(feature = "async")]
+pub mod waker;
+
 mod sealed {
     pub trait Sealed {}
 }
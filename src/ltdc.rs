@@ -0,0 +1,719 @@
+//! LCD-TFT display controller
+//!
+//! The pixel clock comes from the "R" output of PLLSAI and is configured with
+//! [`CFGR::lcd_clk`](crate::rcc::CFGR::lcd_clk). Up to two layers are blended on top of a
+//! background color, each reading its frame buffer from memory.
+//!
+//! ```rust,ignore
+//! static mut FRONT: [u8; 480 * 272 * 2] = [0; 480 * 272 * 2];
+//! static mut BACK: [u8; 480 * 272 * 2] = [0; 480 * 272 * 2];
+//!
+//! let timing = DisplayTiming::RK043FN48H;
+//! let clocks = rcc.cfgr.sysclk(168.MHz()).lcd_clk(timing.pixel_clock).freeze();
+//!
+//! let mut ltdc = Ltdc::new(dp.LTDC, pins, timing, &clocks).unwrap();
+//! ltdc.enable_layer(Layer::L1, LayerConfig::full_screen(&timing, PixelFormat::Rgb565), unsafe { &mut FRONT });
+//!
+//! let mut back = unsafe { &mut BACK[..] };
+//! loop {
+//!     // draw into `back`...
+//!     back = ltdc.swap_buffer(Layer::L1, back);
+//! }
+//! ```
+//!
+//! With the `embedded-graphics` feature, [`FrameBuffer`] implements `DrawTarget` over a layer
+//! buffer.
+
+use enumflags2::BitFlags;
+use vcell::VolatileCell;
+
+use crate::gpio::alt::ltdc as alt;
+use crate::pac::LTDC;
+use crate::rcc::{Clocks, Enable, Reset};
+use crate::time::Hertz;
+
+#[cfg(feature = "embedded-graphics")]
+mod graphics;
+#[cfg(feature = "embedded-graphics")]
+pub use graphics::{FrameBuffer, FrameBufferColor};
+
+/// Parallel RGB interface pins
+pub trait Pins {
+    type LtdcPins;
+    fn convert(self) -> Self::LtdcPins;
+}
+
+/// No pins, the F469 DSI host takes the LTDC output internally
+impl Pins for () {
+    type LtdcPins = ();
+    fn convert(self) -> Self::LtdcPins {}
+}
+
+macro_rules! rgb_pins {
+    ($doc:literal, [$($R:ident),+], [$($G:ident),+], [$($B:ident),+]) => {
+        #[doc = $doc]
+        impl<CLK, HSYNC, VSYNC, DE, $($R,)+ $($G,)+ $($B,)+> Pins
+            for (CLK, HSYNC, VSYNC, DE, ($($R,)+), ($($G,)+), ($($B,)+))
+        where
+            CLK: Into<alt::Clk>,
+            HSYNC: Into<alt::Hsync>,
+            VSYNC: Into<alt::Vsync>,
+            DE: Into<alt::De>,
+            $($R: Into<alt::$R>,)+
+            $($G: Into<alt::$G>,)+
+            $($B: Into<alt::$B>,)+
+        {
+            type LtdcPins = (
+                alt::Clk,
+                alt::Hsync,
+                alt::Vsync,
+                alt::De,
+                ($(alt::$R,)+),
+                ($(alt::$G,)+),
+                ($(alt::$B,)+),
+            );
+            #[allow(non_snake_case)]
+            fn convert(self) -> Self::LtdcPins {
+                let (clk, hsync, vsync, de, ($($R,)+), ($($G,)+), ($($B,)+)) = self;
+                (
+                    clk.into(),
+                    hsync.into(),
+                    vsync.into(),
+                    de.into(),
+                    ($($R.into(),)+),
+                    ($($G.into(),)+),
+                    ($($B.into(),)+),
+                )
+            }
+        }
+    };
+}
+
+rgb_pins!(
+    "RGB565 pins: `(CLK, HSYNC, VSYNC, DE, (R3..R7), (G2..G7), (B3..B7))`",
+    [R3, R4, R5, R6, R7],
+    [G2, G3, G4, G5, G6, G7],
+    [B3, B4, B5, B6, B7]
+);
+rgb_pins!(
+    "RGB666 pins: `(CLK, HSYNC, VSYNC, DE, (R2..R7), (G2..G7), (B2..B7))`",
+    [R2, R3, R4, R5, R6, R7],
+    [G2, G3, G4, G5, G6, G7],
+    [B2, B3, B4, B5, B6, B7]
+);
+rgb_pins!(
+    "RGB888 pins: `(CLK, HSYNC, VSYNC, DE, (R0..R7), (G0..G7), (B0..B7))`",
+    [R0, R1, R2, R3, R4, R5, R6, R7],
+    [G0, G1, G2, G3, G4, G5, G6, G7],
+    [B0, B1, B2, B3, B4, B5, B6, B7]
+);
+
+/// Signal polarity
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Polarity {
+    ActiveLow,
+    ActiveHigh,
+}
+
+/// Edge of the pixel clock the panel samples on
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockPolarity {
+    /// Data changes on the falling edge
+    Normal,
+    /// Data changes on the rising edge
+    Inverted,
+}
+
+/// Panel timing, in pixel clocks horizontally and lines vertically
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayTiming {
+    pub width: u16,
+    pub height: u16,
+    pub h_sync: u16,
+    pub h_back_porch: u16,
+    pub h_front_porch: u16,
+    pub v_sync: u16,
+    pub v_back_porch: u16,
+    pub v_front_porch: u16,
+    pub h_sync_polarity: Polarity,
+    pub v_sync_polarity: Polarity,
+    pub de_polarity: Polarity,
+    pub pclk_polarity: ClockPolarity,
+    /// Pixel clock to request from [`CFGR::lcd_clk`](crate::rcc::CFGR::lcd_clk)
+    pub pixel_clock: Hertz,
+}
+
+/// Values of the `SSCR`, `BPCR`, `AWCR` and `TWCR` registers
+///
+/// Each pair is `(horizontal, vertical)`.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimingRegisters {
+    /// Sync width minus one
+    pub sync: (u16, u16),
+    /// Accumulated back porch
+    pub back_porch: (u16, u16),
+    /// Accumulated active width and height
+    pub active: (u16, u16),
+    /// Total width and height
+    pub total: (u16, u16),
+}
+
+impl TimingRegisters {
+    const fn pack(value: (u16, u16)) -> u32 {
+        ((value.0 as u32) << 16) | value.1 as u32
+    }
+}
+
+impl DisplayTiming {
+    /// Rocktech RK043FN48H, the 480x272 panel of the STM32F746G-DISCO and several F4 boards
+    pub const RK043FN48H: Self = Self {
+        width: 480,
+        height: 272,
+        h_sync: 41,
+        h_back_porch: 13,
+        h_front_porch: 32,
+        v_sync: 10,
+        v_back_porch: 2,
+        v_front_porch: 2,
+        h_sync_polarity: Polarity::ActiveLow,
+        v_sync_polarity: Polarity::ActiveLow,
+        de_polarity: Polarity::ActiveLow,
+        pclk_polarity: ClockPolarity::Normal,
+        pixel_clock: Hertz::MHz(9),
+    };
+
+    /// ILI9341 in RGB mode, the 240x320 panel of the STM32F429I-DISCO
+    pub const ILI9341: Self = Self {
+        width: 240,
+        height: 320,
+        h_sync: 10,
+        h_back_porch: 20,
+        h_front_porch: 10,
+        v_sync: 2,
+        v_back_porch: 2,
+        v_front_porch: 4,
+        h_sync_polarity: Polarity::ActiveLow,
+        v_sync_polarity: Polarity::ActiveLow,
+        de_polarity: Polarity::ActiveLow,
+        pclk_polarity: ClockPolarity::Normal,
+        pixel_clock: Hertz::MHz(6),
+    };
+
+    /// Accumulated values as programmed into the timing registers.
+    pub const fn registers(&self) -> TimingRegisters {
+        let hsw = self.h_sync - 1;
+        let vsh = self.v_sync - 1;
+        let ahbp = hsw + self.h_back_porch;
+        let avbp = vsh + self.v_back_porch;
+        let aaw = ahbp + self.width;
+        let aah = avbp + self.height;
+        TimingRegisters {
+            sync: (hsw, vsh),
+            back_porch: (ahbp, avbp),
+            active: (aaw, aah),
+            total: (aaw + self.h_front_porch, aah + self.v_front_porch),
+        }
+    }
+
+    /// Pixel clocks per line, including blanking
+    pub const fn total_width(&self) -> u32 {
+        self.h_sync as u32
+            + self.h_back_porch as u32
+            + self.width as u32
+            + self.h_front_porch as u32
+    }
+
+    /// Lines per frame, including blanking
+    pub const fn total_height(&self) -> u32 {
+        self.v_sync as u32
+            + self.v_back_porch as u32
+            + self.height as u32
+            + self.v_front_porch as u32
+    }
+
+    /// Frames per second at `pixel_clock`.
+    pub const fn refresh_rate(&self, pixel_clock: Hertz) -> Hertz {
+        Hertz::from_raw(pixel_clock.raw() / (self.total_width() * self.total_height()))
+    }
+}
+
+/// Pixel format of a layer frame buffer
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    Argb8888 = 0,
+    Rgb888 = 1,
+    Rgb565 = 2,
+    Argb1555 = 3,
+    Argb4444 = 4,
+    /// 8-bit index into the CLUT
+    L8 = 5,
+    /// 4-bit alpha, 4-bit CLUT index
+    Al44 = 6,
+    /// 8-bit alpha, 8-bit CLUT index
+    Al88 = 7,
+}
+
+impl PixelFormat {
+    pub const fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::Argb8888 => 4,
+            Self::Rgb888 => 3,
+            Self::Rgb565 | Self::Argb1555 | Self::Argb4444 | Self::Al88 => 2,
+            Self::L8 | Self::Al44 => 1,
+        }
+    }
+
+    /// Returns `true` for the formats that look up their color in the CLUT.
+    pub const fn uses_clut(self) -> bool {
+        matches!(self, Self::L8 | Self::Al44 | Self::Al88)
+    }
+}
+
+/// How a layer is blended with the layers and background below it
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Blending {
+    /// Only the constant alpha of the layer is used
+    Constant,
+    /// The pixel alpha is multiplied with the constant alpha
+    PixelAlpha,
+}
+
+impl Blending {
+    const fn factors(self) -> u32 {
+        match self {
+            Self::Constant => (0b100 << 8) | 0b101,
+            Self::PixelAlpha => (0b110 << 8) | 0b111,
+        }
+    }
+}
+
+/// LTDC layer
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layer {
+    L1 = 0,
+    L2 = 1,
+}
+
+/// Window and format of a layer
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayerConfig {
+    /// Position of the window within the active area
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+    pub format: PixelFormat,
+    pub blending: Blending,
+    /// Constant alpha
+    pub alpha: u8,
+    /// ARGB8888 color outside of the window
+    pub default_color: u32,
+}
+
+impl LayerConfig {
+    /// Opaque layer covering the whole display.
+    pub const fn full_screen(timing: &DisplayTiming, format: PixelFormat) -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            width: timing.width,
+            height: timing.height,
+            format,
+            blending: Blending::PixelAlpha,
+            alpha: 0xff,
+            default_color: 0,
+        }
+    }
+
+    /// Size in bytes of one line of the frame buffer
+    pub const fn pitch(&self) -> usize {
+        self.width as usize * self.format.bytes_per_pixel()
+    }
+
+    /// Size in bytes of the frame buffer
+    pub const fn buffer_size(&self) -> usize {
+        self.pitch() * self.height as usize
+    }
+
+    /// Values of `LxWHPCR` and `LxWVPCR`: the window in timing coordinates.
+    pub const fn window(&self, timing: &DisplayTiming) -> (u32, u32) {
+        let (ahbp, avbp) = timing.registers().back_porch;
+        let h_start = ahbp as u32 + self.x as u32 + 1;
+        let v_start = avbp as u32 + self.y as u32 + 1;
+        (
+            ((h_start + self.width as u32 - 1) << 16) | h_start,
+            ((v_start + self.height as u32 - 1) << 16) | v_start,
+        )
+    }
+}
+
+/// LTDC error
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The LCD clock has not been configured with `CFGR::lcd_clk`
+    NoClock,
+}
+
+/// LTDC interrupt events
+#[enumflags2::bitflags]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Event {
+    /// The line set with [`Ltdc::set_interrupt_line`] has been reached
+    Line = 1 << 0,
+    /// A layer FIFO ran empty while pixels were requested
+    FifoUnderrun = 1 << 1,
+    /// Bus error while reading a frame buffer
+    TransferError = 1 << 2,
+    /// The shadow registers have been reloaded
+    RegisterReload = 1 << 3,
+}
+
+// LxCR
+const CR_LEN: u32 = 1 << 0;
+const CR_COLKEN: u32 = 1 << 1;
+const CR_CLUTEN: u32 = 1 << 4;
+
+/// Registers of one layer, identical for both layers
+#[repr(C)]
+struct LayerRegisters {
+    cr: VolatileCell<u32>,
+    whpcr: VolatileCell<u32>,
+    wvpcr: VolatileCell<u32>,
+    ckcr: VolatileCell<u32>,
+    pfcr: VolatileCell<u32>,
+    cacr: VolatileCell<u32>,
+    dccr: VolatileCell<u32>,
+    bfcr: VolatileCell<u32>,
+    _reserved0: [u32; 2],
+    cfbar: VolatileCell<u32>,
+    cfblr: VolatileCell<u32>,
+    cfblnr: VolatileCell<u32>,
+    _reserved1: [u32; 3],
+    clutwr: VolatileCell<u32>,
+}
+
+impl LayerRegisters {
+    fn modify_cr(&self, f: impl FnOnce(u32) -> u32) {
+        self.cr.set(f(self.cr.get()));
+    }
+}
+
+/// LCD-TFT display controller
+pub struct Ltdc {
+    ltdc: LTDC,
+    timing: DisplayTiming,
+    pixel_clock: Hertz,
+    buffers: [Option<&'static mut [u8]>; 2],
+}
+
+impl Ltdc {
+    /// Configures the timing and enables the controller with both layers disabled.
+    pub fn new(
+        ltdc: LTDC,
+        pins: impl Pins,
+        timing: DisplayTiming,
+        clocks: &Clocks,
+    ) -> Result<Self, Error> {
+        let pixel_clock = clocks.lcd_clk().ok_or(Error::NoClock)?;
+        let _pins = pins.convert();
+
+        unsafe {
+            LTDC::enable_unchecked();
+            LTDC::reset_unchecked();
+        }
+
+        let regs = timing.registers();
+        ltdc.sscr
+            .write(|w| unsafe { w.bits(TimingRegisters::pack(regs.sync)) });
+        ltdc.bpcr
+            .write(|w| unsafe { w.bits(TimingRegisters::pack(regs.back_porch)) });
+        ltdc.awcr
+            .write(|w| unsafe { w.bits(TimingRegisters::pack(regs.active)) });
+        ltdc.twcr
+            .write(|w| unsafe { w.bits(TimingRegisters::pack(regs.total)) });
+        ltdc.gcr.modify(|_, w| {
+            w.hspol()
+                .bit(timing.h_sync_polarity == Polarity::ActiveHigh);
+            w.vspol()
+                .bit(timing.v_sync_polarity == Polarity::ActiveHigh);
+            w.depol().bit(timing.de_polarity == Polarity::ActiveHigh);
+            w.pcpol()
+                .bit(timing.pclk_polarity == ClockPolarity::Inverted)
+        });
+        ltdc.bccr.write(|w| unsafe { w.bits(0) });
+
+        let mut ltdc = Self {
+            ltdc,
+            timing,
+            pixel_clock,
+            buffers: [None, None],
+        };
+        ltdc.reload();
+        ltdc.ltdc.gcr.modify(|_, w| w.ltdcen().set_bit());
+        Ok(ltdc)
+    }
+
+    /// Display timing
+    pub fn timing(&self) -> &DisplayTiming {
+        &self.timing
+    }
+
+    /// Actual pixel clock
+    pub fn pixel_clock(&self) -> Hertz {
+        self.pixel_clock
+    }
+
+    /// Sets the RGB888 color shown where no layer is enabled.
+    pub fn set_background(&mut self, rgb: u32) {
+        self.ltdc.bccr.write(|w| unsafe { w.bits(rgb & 0xff_ffff) });
+    }
+
+    /// Sets the line at which [`Event::Line`] fires, counted from the start of the frame
+    /// including sync and back porch.
+    pub fn set_interrupt_line(&mut self, line: u16) {
+        self.ltdc
+            .lipcr
+            .write(|w| unsafe { w.bits(u32::from(line) & 0x7ff) });
+    }
+
+    /// Returns `true` during the vertical sync pulse.
+    pub fn is_vsync(&self) -> bool {
+        self.ltdc.cdsr.read().vsyncs().bit_is_set()
+    }
+
+    fn layer(&self, layer: Layer) -> &LayerRegisters {
+        let offset = match layer {
+            Layer::L1 => 0x84,
+            Layer::L2 => 0x104,
+        };
+        // NOTE(unsafe) both layer register blocks lie within the LTDC we own
+        unsafe { &*((LTDC::ptr() as *const u8).add(offset) as *const LayerRegisters) }
+    }
+
+    /// Loads the shadow registers immediately.
+    fn reload(&mut self) {
+        self.ltdc.srcr.write(|w| w.imr().set_bit());
+        while self.ltdc.srcr.read().imr().bit_is_set() {}
+    }
+
+    /// Shows `buffer` on `layer` with the window and format of `config`.
+    ///
+    /// Returns the buffer the layer showed before, if any.
+    ///
+    /// # Panics
+    ///
+    /// When `buffer` is smaller than [`LayerConfig::buffer_size`].
+    pub fn enable_layer(
+        &mut self,
+        layer: Layer,
+        config: LayerConfig,
+        buffer: &'static mut [u8],
+    ) -> Option<&'static mut [u8]> {
+        assert!(buffer.len() >= config.buffer_size());
+        let (whpcr, wvpcr) = config.window(&self.timing);
+        let pitch = config.pitch() as u32;
+        let regs = self.layer(layer);
+        regs.whpcr.set(whpcr);
+        regs.wvpcr.set(wvpcr);
+        regs.pfcr.set(config.format as u32);
+        regs.cacr.set(u32::from(config.alpha));
+        regs.dccr.set(config.default_color);
+        regs.bfcr.set(config.blending.factors());
+        regs.cfbar.set(buffer.as_ptr() as u32);
+        // The line length includes 3 bytes of bus latency
+        regs.cfblr.set((pitch << 16) | (pitch + 3));
+        regs.cfblnr.set(u32::from(config.height));
+        regs.modify_cr(|cr| {
+            if config.format.uses_clut() {
+                cr | CR_LEN | CR_CLUTEN
+            } else {
+                (cr | CR_LEN) & !CR_CLUTEN
+            }
+        });
+        self.reload();
+        self.buffers[layer as usize].replace(buffer)
+    }
+
+    /// Disables `layer` and returns its buffer.
+    pub fn disable_layer(&mut self, layer: Layer) -> Option<&'static mut [u8]> {
+        self.layer(layer).modify_cr(|cr| cr & !CR_LEN);
+        self.reload();
+        self.buffers[layer as usize].take()
+    }
+
+    /// Changes the constant alpha of `layer`, effective immediately.
+    pub fn set_alpha(&mut self, layer: Layer, alpha: u8) {
+        self.layer(layer).cacr.set(u32::from(alpha));
+        self.reload();
+    }
+
+    /// Makes the pixels of `layer` that match the RGB888 color `key` transparent, or disables
+    /// color keying for `None`.
+    pub fn set_color_key(&mut self, layer: Layer, key: Option<u32>) {
+        let regs = self.layer(layer);
+        match key {
+            Some(rgb) => {
+                regs.ckcr.set(rgb & 0xff_ffff);
+                regs.modify_cr(|cr| cr | CR_COLKEN);
+            }
+            None => regs.modify_cr(|cr| cr & !CR_COLKEN),
+        }
+        self.reload();
+    }
+
+    /// Loads the color lookup table of `layer` with RGB888 colors, starting at index 0.
+    ///
+    /// The table is used by the `L8`, `AL44` and `AL88` formats. It should only be written while
+    /// the layer is disabled or during vertical blanking.
+    ///
+    /// # Panics
+    ///
+    /// When `clut` has more than 256 entries.
+    pub fn set_clut(&mut self, layer: Layer, clut: &[u32]) {
+        assert!(clut.len() <= 256);
+        let regs = self.layer(layer);
+        for (address, rgb) in clut.iter().enumerate() {
+            regs.clutwr
+                .set(((address as u32) << 24) | (rgb & 0xff_ffff));
+        }
+        self.reload();
+    }
+
+    /// Shows `buffer` on `layer` from the next vertical blanking on.
+    ///
+    /// Blocks until the switch has happened and returns the previous buffer, which the LTDC no
+    /// longer reads from and can be drawn into without tearing.
+    ///
+    /// # Panics
+    ///
+    /// When `layer` is not enabled, or `buffer` is smaller than the current one.
+    pub fn swap_buffer(&mut self, layer: Layer, buffer: &'static mut [u8]) -> &'static mut [u8] {
+        let previous = self.buffers[layer as usize]
+            .take()
+            .expect("layer is not enabled");
+        assert!(buffer.len() >= previous.len());
+        self.layer(layer).cfbar.set(buffer.as_ptr() as u32);
+        self.ltdc.srcr.write(|w| w.vbr().set_bit());
+        while self.ltdc.srcr.read().vbr().bit_is_set() {}
+        self.buffers[layer as usize] = Some(buffer);
+        previous
+    }
+
+    /// Disables the controller and returns the peripheral.
+    pub fn release(self) -> LTDC {
+        self.ltdc.gcr.modify(|_, w| w.ltdcen().clear_bit());
+        self.ltdc
+    }
+
+    fn listen_event(&mut self, disable: Option<BitFlags<Event>>, enable: Option<BitFlags<Event>>) {
+        self.ltdc.ier.modify(|r, w| unsafe {
+            w.bits({
+                let mut bits = r.bits();
+                if let Some(d) = disable {
+                    bits &= !d.bits();
+                }
+                if let Some(e) = enable {
+                    bits |= e.bits();
+                }
+                bits
+            })
+        });
+    }
+}
+
+impl crate::Listen for Ltdc {
+    type Event = Event;
+
+    fn listen(&mut self, event: impl Into<BitFlags<Self::Event>>) {
+        self.listen_event(None, Some(event.into()));
+    }
+
+    fn listen_only(&mut self, event: impl Into<BitFlags<Self::Event>>) {
+        self.listen_event(Some(BitFlags::ALL), Some(event.into()));
+    }
+
+    fn unlisten(&mut self, event: impl Into<BitFlags<Self::Event>>) {
+        self.listen_event(Some(event.into()), None);
+    }
+}
+
+impl crate::ReadFlags for Ltdc {
+    type Flag = Event;
+
+    fn flags(&self) -> BitFlags<Self::Flag> {
+        BitFlags::from_bits_truncate(self.ltdc.isr.read().bits())
+    }
+}
+
+impl crate::ClearFlags for Ltdc {
+    type Flag = Event;
+
+    fn clear_flags(&mut self, flags: impl Into<BitFlags<Self::Flag>>) {
+        self.ltdc
+            .icr
+            .write(|w| unsafe { w.bits(flags.into().bits()) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timing_registers() {
+        // Values of the ST BSP for both panels
+        assert_eq!(
+            DisplayTiming::RK043FN48H.registers(),
+            TimingRegisters {
+                sync: (40, 9),
+                back_porch: (53, 11),
+                active: (533, 283),
+                total: (565, 285),
+            }
+        );
+        assert_eq!(
+            DisplayTiming::ILI9341.registers(),
+            TimingRegisters {
+                sync: (9, 1),
+                back_porch: (29, 3),
+                active: (269, 323),
+                total: (279, 327),
+            }
+        );
+        assert_eq!(TimingRegisters::pack((565, 285)), (565 << 16) | 285);
+        assert_eq!(
+            DisplayTiming::RK043FN48H.refresh_rate(Hertz::MHz(9)),
+            Hertz::from_raw(55)
+        );
+    }
+
+    #[test]
+    fn layer_window() {
+        let timing = DisplayTiming::RK043FN48H;
+        let layer = LayerConfig::full_screen(&timing, PixelFormat::Rgb565);
+        assert_eq!(layer.window(&timing), ((533 << 16) | 54, (283 << 16) | 12));
+        assert_eq!(layer.pitch(), 960);
+        assert_eq!(layer.buffer_size(), 960 * 272);
+
+        let layer = LayerConfig {
+            x: 10,
+            y: 20,
+            width: 100,
+            height: 50,
+            ..LayerConfig::full_screen(&timing, PixelFormat::Argb8888)
+        };
+        assert_eq!(layer.window(&timing), ((163 << 16) | 64, (81 << 16) | 32));
+        assert_eq!(layer.pitch(), 400);
+    }
+}
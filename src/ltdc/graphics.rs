@@ -0,0 +1,109 @@
+//! [`embedded_graphics_core::draw_target::DrawTarget`] over a layer frame buffer
+
+use core::convert::Infallible;
+use core::marker::PhantomData;
+
+use embedded_graphics_core::draw_target::DrawTarget;
+use embedded_graphics_core::geometry::{OriginDimensions, Size};
+use embedded_graphics_core::pixelcolor::{Gray8, GrayColor, IntoStorage, Rgb565, Rgb888, RgbColor};
+use embedded_graphics_core::Pixel;
+
+use super::{LayerConfig, PixelFormat};
+
+/// Color with a matching LTDC pixel format
+pub trait FrameBufferColor: embedded_graphics_core::pixelcolor::PixelColor {
+    const FORMAT: PixelFormat;
+
+    /// Stores the color in `dst`, which is `FORMAT.bytes_per_pixel()` long.
+    fn store(self, dst: &mut [u8]);
+}
+
+impl FrameBufferColor for Rgb565 {
+    const FORMAT: PixelFormat = PixelFormat::Rgb565;
+
+    fn store(self, dst: &mut [u8]) {
+        dst.copy_from_slice(&self.into_storage().to_le_bytes());
+    }
+}
+
+impl FrameBufferColor for Rgb888 {
+    const FORMAT: PixelFormat = PixelFormat::Rgb888;
+
+    fn store(self, dst: &mut [u8]) {
+        // Little endian, blue first
+        dst.copy_from_slice(&[self.b(), self.g(), self.r()]);
+    }
+}
+
+impl FrameBufferColor for Gray8 {
+    const FORMAT: PixelFormat = PixelFormat::L8;
+
+    fn store(self, dst: &mut [u8]) {
+        dst[0] = self.luma();
+    }
+}
+
+/// Frame buffer of a layer, drawn to with `embedded-graphics`
+pub struct FrameBuffer<'a, C> {
+    buffer: &'a mut [u8],
+    width: u16,
+    height: u16,
+    _color: PhantomData<C>,
+}
+
+impl<'a, C: FrameBufferColor> FrameBuffer<'a, C> {
+    /// # Panics
+    ///
+    /// When `buffer` is too small for `width` x `height` pixels.
+    pub fn new(buffer: &'a mut [u8], width: u16, height: u16) -> Self {
+        assert!(buffer.len() >= width as usize * height as usize * C::FORMAT.bytes_per_pixel());
+        Self {
+            buffer,
+            width,
+            height,
+            _color: PhantomData,
+        }
+    }
+
+    /// Frame buffer with the window size of `config`.
+    ///
+    /// # Panics
+    ///
+    /// When the format of `config` does not match `C`, or `buffer` is too small.
+    pub fn for_layer(buffer: &'a mut [u8], config: &LayerConfig) -> Self {
+        assert_eq!(config.format, C::FORMAT);
+        Self::new(buffer, config.width, config.height)
+    }
+
+    /// Releases the buffer.
+    pub fn release(self) -> &'a mut [u8] {
+        self.buffer
+    }
+}
+
+impl<C: FrameBufferColor> OriginDimensions for FrameBuffer<'_, C> {
+    fn size(&self) -> Size {
+        Size::new(self.width.into(), self.height.into())
+    }
+}
+
+impl<C: FrameBufferColor> DrawTarget for FrameBuffer<'_, C> {
+    type Color = C;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let bpp = C::FORMAT.bytes_per_pixel();
+        for Pixel(point, color) in pixels {
+            if let (Ok(x), Ok(y)) = (u16::try_from(point.x), u16::try_from(point.y)) {
+                if x < self.width && y < self.height {
+                    let offset = (y as usize * self.width as usize + x as usize) * bpp;
+                    color.store(&mut self.buffer[offset..offset + bpp]);
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -51,6 +51,8 @@ pub use fugit::RateExtU32 as _fugit_RateExtU32;
 pub use crate::can::CanExt as _stm32f4xx_hal_can_CanExt;
 #[cfg(feature = "dac")]
 pub use crate::dac::DacExt as _stm32f4xx_hal_dac_DacExt;
+#[cfg(feature = "dfsdm")]
+pub use crate::dfsdm::DfsdmExt as _stm32f4xx_hal_dfsdm_DfsdmExt;
 pub use crate::dma::traits::DmaEventExt as _;
 pub use crate::dma::traits::DmaFlagExt as _;
 pub use crate::dma::traits::Stream as _;
@@ -64,10 +66,13 @@ pub use crate::i2c::dma::I2CMasterWriteDMA as _stm32f4xx_hal_i2c_dma_I2CMasterWr
 pub use crate::i2c::dma::I2CMasterWriteReadDMA as _stm32f4xx_hal_i2c_dma_I2CMasterWriteReadDMA;
 pub use crate::i2c::I2cExt as _stm32f4xx_hal_i2c_I2cExt;
 pub use crate::i2s::I2sExt as _stm32f4xx_hal_i2s_I2sExt;
+pub use crate::pwr::PwrExt as _stm32f4xx_hal_pwr_PwrExt;
 pub use crate::qei::QeiExt as _stm32f4xx_hal_QeiExt;
 pub use crate::rcc::RccExt as _stm32f4xx_hal_rcc_RccExt;
 #[cfg(feature = "rng")]
 pub use crate::rng::RngExt as _stm32f4xx_hal_rng_RngExt;
+#[cfg(feature = "sai")]
+pub use crate::sai::SaiExt as _stm32f4xx_hal_sai_SaiExt;
 pub use crate::serial::RxISR as _stm32f4xx_hal_serial_RxISR;
 pub use crate::serial::RxListen as _stm32f4xx_hal_serial_RxListen;
 pub use crate::serial::SerialExt as _stm32f4xx_hal_serial_SerialExt;
@@ -80,6 +85,7 @@ pub use crate::time::U32Ext as _stm32f4xx_hal_time_U32Ext;
 pub use crate::timer::MonoTimer64Ext as _;
 #[cfg(feature = "rtic1")]
 pub use crate::timer::MonoTimerExt as _;
+pub use crate::timer::OutputCompareExt as _stm32f4xx_hal_timer_OutputCompareExt;
 pub use crate::timer::PwmExt as _stm32f4xx_hal_timer_PwmExt;
 #[cfg(feature = "rtic1")]
 pub use crate::timer::SysMonoTimerExt as _stm32f4xx_hal_timer_SysMonoTimerExt;
@@ -0,0 +1,406 @@
+//! Power controller and low-power modes
+//!
+//! ```rust,ignore
+//! let mut pwr = dp.PWR.constrain();
+//! let clocks = rcc.cfgr.use_hse(8.MHz()).sysclk(168.MHz()).freeze();
+//!
+//! // Wait for an EXTI interrupt with the regulator in low-power mode, the PLL runs again after
+//! pwr.stop(&mut cp.SCB, StopConfig::default().regulator(Regulator::LowPower));
+//!
+//! match pwr.wakeup_reason() {
+//!     WakeupReason::Standby => {}
+//!     _ => {}
+//! }
+//!
+//! // Leave Standby on the RTC alarm A or a rising edge on WKUP
+//! rtc.set_alarm(Alarm::AlarmA, AlarmDay::EveryDay, time)?;
+//! rtc.enable_standby_wakeup(rtc::Event::AlarmA);
+//! pwr.standby(&mut cp.SCB, true);
+//! ```
+//!
+//! [`Pwr`] dereferences to [`PWR`] so it can be passed to [`Rtc`](crate::rtc::Rtc).
+
+use core::ops::{Deref, DerefMut};
+
+use cortex_m::peripheral::SCB;
+use enumflags2::BitFlags;
+
+use crate::pac::{PWR, RCC};
+use crate::rcc::{Enable, ResetReason};
+
+/// Extension trait that constrains the `PWR` peripheral
+pub trait PwrExt {
+    fn constrain(self) -> Pwr;
+}
+
+impl PwrExt for PWR {
+    fn constrain(self) -> Pwr {
+        unsafe {
+            PWR::enable_unchecked();
+        }
+        Pwr { pwr: self }
+    }
+}
+
+/// Voltage regulator state in Stop mode
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Regulator {
+    /// Faster wakeup
+    #[default]
+    Main,
+    /// Lower consumption
+    LowPower,
+}
+
+/// Stop mode configuration
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StopConfig {
+    pub regulator: Regulator,
+    /// Power down the flash, which saves power at the cost of a longer wakeup
+    pub flash_power_down: bool,
+}
+
+impl StopConfig {
+    pub fn regulator(mut self, regulator: Regulator) -> Self {
+        self.regulator = regulator;
+        self
+    }
+
+    pub fn flash_power_down(mut self, flash_power_down: bool) -> Self {
+        self.flash_power_down = flash_power_down;
+        self
+    }
+}
+
+/// Why the device is running
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WakeupReason {
+    /// Left Standby on a WKUP pin or RTC event
+    Standby,
+    /// A WKUP pin or RTC event set the wakeup flag outside Standby, e.g. to leave Stop
+    WakeupEvent,
+    /// Power-on or power-down reset
+    PowerOn,
+    /// Brown-out reset without a power-on reset
+    BrownOut,
+    /// Reset on the NRST pin
+    Pin,
+    /// Independent or window watchdog reset
+    Watchdog,
+    /// Software reset
+    Software,
+    /// Entering Standby or Stop with the `nRST_STDBY`/`nRST_STOP` option bits cleared
+    LowPower,
+    Unknown,
+}
+
+impl WakeupReason {
+    /// Decodes `PWR_CSR` and the reset flags.
+    ///
+    /// Standby left through NRST or the independent watchdog only sets the Standby flag, so it
+    /// is reported as that reset. As a power-on reset also sets `Pin` and `BrownOut` and a
+    /// watchdog or software reset drives NRST, the reset with the most specific flag is reported.
+    pub fn from_bits(pwr_csr: u32, reset: BitFlags<ResetReason>) -> Self {
+        let wakeup = pwr_csr & CSR_WUF != 0;
+        if wakeup && pwr_csr & CSR_SBF != 0 {
+            Self::Standby
+        } else if wakeup {
+            Self::WakeupEvent
+        } else if reset.contains(ResetReason::LowPower) {
+            Self::LowPower
+        } else if reset.intersects(ResetReason::IndependentWatchdog | ResetReason::WindowWatchdog) {
+            Self::Watchdog
+        } else if reset.contains(ResetReason::Software) {
+            Self::Software
+        } else if reset.contains(ResetReason::PowerOn) {
+            Self::PowerOn
+        } else if reset.contains(ResetReason::BrownOut) {
+            Self::BrownOut
+        } else if reset.contains(ResetReason::Pin) {
+            Self::Pin
+        } else {
+            Self::Unknown
+        }
+    }
+}
+
+// CR
+const CR_LPDS: u32 = 1 << 0;
+const CR_PDDS: u32 = 1 << 1;
+const CR_CWUF: u32 = 1 << 2;
+const CR_CSBF: u32 = 1 << 3;
+const CR_FPDS: u32 = 1 << 9;
+// CSR
+const CSR_WUF: u32 = 1 << 0;
+const CSR_SBF: u32 = 1 << 1;
+const CSR_EWUP: u32 = 1 << 8;
+
+/// Power controller
+pub struct Pwr {
+    pwr: PWR,
+}
+
+impl Deref for Pwr {
+    type Target = PWR;
+
+    fn deref(&self) -> &PWR {
+        &self.pwr
+    }
+}
+
+impl DerefMut for Pwr {
+    fn deref_mut(&mut self) -> &mut PWR {
+        &mut self.pwr
+    }
+}
+
+impl Pwr {
+    pub fn release(self) -> PWR {
+        self.pwr
+    }
+
+    /// Goes back to Sleep mode after handling an interrupt instead of returning to thread mode.
+    pub fn sleep_on_exit(&mut self, scb: &mut SCB, enable: bool) {
+        if enable {
+            scb.set_sleeponexit();
+        } else {
+            scb.clear_sleeponexit();
+        }
+    }
+
+    /// Waits for an interrupt in Sleep mode.
+    pub fn sleep(&mut self, scb: &mut SCB) {
+        scb.clear_sleepdeep();
+        cortex_m::asm::wfi();
+    }
+
+    /// Waits for an EXTI interrupt in Stop mode.
+    ///
+    /// Stop mode runs on the HSI afterwards, so the oscillators, PLLs and system clock selected
+    /// by [`CFGR::freeze`](crate::rcc::CFGR::freeze) are restored before returning and the
+    /// `Clocks` stay valid.
+    pub fn stop(&mut self, scb: &mut SCB, config: StopConfig) {
+        let rcc = unsafe { &*RCC::ptr() };
+        let cr = rcc.cr.read().bits();
+        let sw = rcc.cfgr.read().bits() & 0b11;
+        let overdrive = self.overdrive_enabled();
+
+        let mut bits = 0;
+        if config.regulator == Regulator::LowPower {
+            bits |= CR_LPDS;
+        }
+        if config.flash_power_down {
+            bits |= CR_FPDS;
+        }
+        self.pwr.cr.modify(|r, w| unsafe {
+            w.bits((r.bits() & !(CR_PDDS | CR_LPDS | CR_FPDS)) | bits | CR_CWUF)
+        });
+
+        scb.set_sleepdeep();
+        cortex_m::asm::dsb();
+        cortex_m::asm::wfi();
+        scb.clear_sleepdeep();
+
+        restore_clocks(cr, sw, overdrive);
+    }
+
+    /// Enters Standby mode, which only ends with a reset.
+    ///
+    /// Besides NRST and IWDG resets, Standby is left on a rising edge on WKUP (PA0) if
+    /// `wakeup_pin` is set, and on the RTC events armed with
+    /// [`Rtc::enable_standby_wakeup`](crate::rtc::Rtc::enable_standby_wakeup).
+    pub fn standby(&mut self, scb: &mut SCB, wakeup_pin: bool) -> ! {
+        self.pwr.csr.modify(|r, w| unsafe {
+            w.bits(if wakeup_pin {
+                r.bits() | CSR_EWUP
+            } else {
+                r.bits() & !CSR_EWUP
+            })
+        });
+        self.pwr
+            .cr
+            .modify(|r, w| unsafe { w.bits(r.bits() | CR_PDDS | CR_CWUF) });
+
+        scb.set_sleepdeep();
+        cortex_m::asm::dsb();
+        loop {
+            cortex_m::asm::wfi();
+        }
+    }
+
+    /// Reads and clears the Standby and wakeup flags.
+    ///
+    /// The reset flags are only read, they are cleared by
+    /// [`ResetReason::read_and_clear`](crate::rcc::ResetReason::read_and_clear).
+    pub fn wakeup_reason(&mut self) -> WakeupReason {
+        let reason = WakeupReason::from_bits(self.pwr.csr.read().bits(), ResetReason::read());
+        self.pwr
+            .cr
+            .modify(|r, w| unsafe { w.bits(r.bits() | CR_CSBF | CR_CWUF) });
+        reason
+    }
+
+    #[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469"))]
+    fn overdrive_enabled(&self) -> bool {
+        self.pwr.csr.read().odswrdy().bit_is_set()
+    }
+
+    #[cfg(not(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469")))]
+    fn overdrive_enabled(&self) -> bool {
+        false
+    }
+}
+
+// RCC_CR
+const RCC_CR_HSEON: u32 = 1 << 16;
+const RCC_CR_HSEBYP: u32 = 1 << 18;
+// PLLON, PLLI2SON and PLLSAION, each followed by its ready flag
+#[cfg(feature = "gpio-f410")]
+const RCC_CR_PLLS: u32 = 1 << 24;
+#[cfg(not(any(
+    feature = "gpio-f410",
+    feature = "gpio-f427",
+    feature = "gpio-f446",
+    feature = "gpio-f469"
+)))]
+const RCC_CR_PLLS: u32 = (1 << 24) | (1 << 26);
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469"))]
+const RCC_CR_PLLS: u32 = (1 << 24) | (1 << 26) | (1 << 28);
+
+/// Turns on the oscillators and PLLs in `cr`, the over-drive and selects `sw` again.
+fn restore_clocks(cr: u32, sw: u32, overdrive: bool) {
+    let rcc = unsafe { &*RCC::ptr() };
+    if cr & RCC_CR_HSEON != 0 {
+        rcc.cr
+            .modify(|r, w| unsafe { w.bits(r.bits() | (cr & (RCC_CR_HSEON | RCC_CR_HSEBYP))) });
+        while rcc.cr.read().hserdy().bit_is_clear() {}
+    }
+    let plls = cr & RCC_CR_PLLS;
+    if plls != 0 {
+        rcc.cr.modify(|r, w| unsafe { w.bits(r.bits() | plls) });
+        if overdrive {
+            enable_overdrive();
+        }
+        while rcc.cr.read().bits() & (plls << 1) != plls << 1 {}
+    }
+    rcc.cfgr
+        .modify(|r, w| unsafe { w.bits((r.bits() & !0b11) | sw) });
+    while (rcc.cfgr.read().bits() >> 2) & 0b11 != sw {}
+}
+
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469"))]
+fn enable_overdrive() {
+    let pwr = unsafe { &*PWR::ptr() };
+    pwr.cr.modify(|_, w| w.oden().set_bit());
+    while pwr.csr.read().odrdy().bit_is_clear() {}
+    pwr.cr.modify(|_, w| w.odswen().set_bit());
+    while pwr.csr.read().odswrdy().bit_is_clear() {}
+}
+
+#[cfg(not(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469")))]
+fn enable_overdrive() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wakeup_unknown() {
+        assert_eq!(
+            WakeupReason::from_bits(0, BitFlags::empty()),
+            WakeupReason::Unknown
+        );
+    }
+
+    #[test]
+    fn wakeup_standby() {
+        // Standby wins over the reset flags left from before
+        assert_eq!(
+            WakeupReason::from_bits(CSR_SBF | CSR_WUF, ResetReason::PowerOn | ResetReason::Pin),
+            WakeupReason::Standby
+        );
+    }
+
+    #[test]
+    fn wakeup_standby_reset() {
+        assert_eq!(
+            WakeupReason::from_bits(CSR_SBF, ResetReason::Pin.into()),
+            WakeupReason::Pin
+        );
+        assert_eq!(
+            WakeupReason::from_bits(CSR_SBF, ResetReason::IndependentWatchdog | ResetReason::Pin),
+            WakeupReason::Watchdog
+        );
+    }
+
+    #[test]
+    fn wakeup_event() {
+        assert_eq!(
+            WakeupReason::from_bits(CSR_WUF, ResetReason::PowerOn | ResetReason::Pin),
+            WakeupReason::WakeupEvent
+        );
+    }
+
+    #[test]
+    fn wakeup_power_on() {
+        assert_eq!(
+            WakeupReason::from_bits(
+                0,
+                ResetReason::PowerOn | ResetReason::BrownOut | ResetReason::Pin
+            ),
+            WakeupReason::PowerOn
+        );
+    }
+
+    #[test]
+    fn wakeup_brown_out() {
+        assert_eq!(
+            WakeupReason::from_bits(0, ResetReason::BrownOut | ResetReason::Pin),
+            WakeupReason::BrownOut
+        );
+    }
+
+    #[test]
+    fn wakeup_pin() {
+        assert_eq!(
+            WakeupReason::from_bits(0, ResetReason::Pin.into()),
+            WakeupReason::Pin
+        );
+        // Other PWR_CSR flags are ignored
+        assert_eq!(
+            WakeupReason::from_bits(!(CSR_SBF | CSR_WUF), ResetReason::Pin.into()),
+            WakeupReason::Pin
+        );
+    }
+
+    #[test]
+    fn wakeup_watchdog() {
+        assert_eq!(
+            WakeupReason::from_bits(0, ResetReason::IndependentWatchdog | ResetReason::Pin),
+            WakeupReason::Watchdog
+        );
+        assert_eq!(
+            WakeupReason::from_bits(0, ResetReason::WindowWatchdog.into()),
+            WakeupReason::Watchdog
+        );
+    }
+
+    #[test]
+    fn wakeup_software() {
+        assert_eq!(
+            WakeupReason::from_bits(0, ResetReason::Software | ResetReason::Pin),
+            WakeupReason::Software
+        );
+    }
+
+    #[test]
+    fn wakeup_low_power() {
+        assert_eq!(
+            WakeupReason::from_bits(0, ResetReason::LowPower | ResetReason::Pin),
+            WakeupReason::LowPower
+        );
+    }
+}
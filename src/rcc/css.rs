@@ -0,0 +1,126 @@
+//! Clock security system
+//!
+//! When the HSE fails while CSS is enabled, the hardware stops the HSE and the PLLs, switches the
+//! system clock to the HSI and raises an NMI. The bus prescalers are kept.
+//!
+//! ```rust,ignore
+//! let clocks = rcc.cfgr.use_hse(8.MHz()).sysclk(168.MHz()).freeze();
+//! css::enable();
+//!
+//! #[exception]
+//! fn NonMaskableInt() {
+//!     if css::on_nmi() {
+//!         HSE_FAILED.store(true, Ordering::Relaxed);
+//!     }
+//! }
+//!
+//! // In thread mode
+//! if HSE_FAILED.load(Ordering::Relaxed) {
+//!     let clocks = clocks.without_hse();
+//!     timer.configure(&clocks);
+//! }
+//! ```
+
+use fugit::HertzU32 as Hertz;
+use fugit::RateExtU32;
+
+use super::{Clocks, HSI};
+use crate::bb;
+use crate::pac::RCC;
+
+// RCC_CR
+const CSSON: u8 = 19;
+// RCC_CIR
+const CSSF: u32 = 1 << 7;
+const CSSC: u8 = 23;
+
+/// Enables the clock security system, which only runs while the HSE is ready.
+pub fn enable() {
+    let rcc = unsafe { &*RCC::ptr() };
+    unsafe { bb::set(&rcc.cr, CSSON) };
+}
+
+pub fn disable() {
+    let rcc = unsafe { &*RCC::ptr() };
+    unsafe { bb::clear(&rcc.cr, CSSON) };
+}
+
+/// Returns `true` if an HSE failure was detected and not cleared yet
+pub fn is_failed() -> bool {
+    let rcc = unsafe { &*RCC::ptr() };
+    rcc.cir.read().bits() & CSSF != 0
+}
+
+/// Handles an HSE failure from the `NonMaskableInt` exception.
+///
+/// Clears the CSS flag and selects the HSI as system clock source, so later clock changes start
+/// from the state the hardware switched to. Only bit-band accesses are used, so it is safe to
+/// preempt any register update. Returns `false` if the NMI has another cause.
+pub fn on_nmi() -> bool {
+    if !is_failed() {
+        return false;
+    }
+    let rcc = unsafe { &*RCC::ptr() };
+    unsafe {
+        bb::set(&rcc.cir, CSSC);
+        // SW = 0b00, never passing through another source
+        bb::clear(&rcc.cfgr, 1);
+        bb::clear(&rcc.cfgr, 0);
+    }
+    true
+}
+
+impl Clocks {
+    /// Returns the clocks after an HSE failure.
+    ///
+    /// The system clock runs on the HSI with the same prescalers and all clocks generated by the
+    /// PLLs are stopped. I2S and SAI clocks taken from I2S_CKIN keep running, but `Clocks` does
+    /// not record their source, so they are `None` here as well: keep the previous frequency for
+    /// those. Without HSE, this is a copy.
+    pub fn without_hse(&self) -> Clocks {
+        if self.hse.is_none() {
+            return *self;
+        }
+        let div = |a: Hertz, b: Hertz| (a.raw() + b.raw() / 2) / b.raw();
+        let hpre = div(self.sysclk, self.hclk);
+        let ppre1 = div(self.hclk, self.pclk1);
+        let ppre2 = div(self.hclk, self.pclk2);
+
+        let sysclk = HSI;
+        let hclk = sysclk / hpre;
+        let pclk1 = hclk / ppre1;
+        let pclk2 = hclk / ppre2;
+        Clocks {
+            hclk: hclk.Hz(),
+            pclk1: pclk1.Hz(),
+            pclk2: pclk2.Hz(),
+            timclk1: (pclk1 * if ppre1 == 1 { 1 } else { 2 }).Hz(),
+            timclk2: (pclk2 * if ppre2 == 1 { 1 } else { 2 }).Hz(),
+            sysclk: sysclk.Hz(),
+            pll48clk: None,
+            hse: None,
+            pllclk: None,
+            #[cfg(not(feature = "gpio-f410"))]
+            plli2sclk: None,
+
+            #[cfg(not(any(feature = "gpio-f412", feature = "gpio-f413", feature = "gpio-f446")))]
+            i2s_clk: None,
+            #[cfg(any(feature = "gpio-f412", feature = "gpio-f413", feature = "gpio-f446"))]
+            i2s_apb1_clk: None,
+            #[cfg(any(feature = "gpio-f412", feature = "gpio-f413", feature = "gpio-f446"))]
+            i2s_apb2_clk: None,
+
+            #[cfg(any(feature = "gpio-f413", feature = "gpio-f427", feature = "gpio-f469"))]
+            saia_clk: None,
+            #[cfg(any(feature = "gpio-f413", feature = "gpio-f427", feature = "gpio-f469"))]
+            saib_clk: None,
+            #[cfg(feature = "gpio-f446")]
+            sai1_clk: None,
+            #[cfg(feature = "gpio-f446")]
+            sai2_clk: None,
+
+            #[cfg(feature = "ltdc")]
+            lcd_clk: None,
+        }
+    }
+}
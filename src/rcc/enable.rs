@@ -126,6 +126,21 @@ bus! {
     GPIOK => (AHB1, 10),
 }
 
+#[cfg(feature = "cryp")]
+bus! {
+    CRYP => (AHB2, 4),
+}
+
+#[cfg(feature = "aes")]
+bus! {
+    AES => (AHB2, 4),
+}
+
+#[cfg(feature = "hash")]
+bus! {
+    HASH => (AHB2, 5),
+}
+
 #[cfg(feature = "rng")]
 bus! {
     RNG => (AHB2, 6),
@@ -146,6 +161,16 @@ bus! {
     FMC => (AHB3, 0),
 }
 
+#[cfg(feature = "eth")]
+bus! {
+    ETHERNET_MAC => (AHB1, 25),
+}
+
+#[cfg(feature = "dma2d")]
+bus! {
+    DMA2D => (AHB1, 23),
+}
+
 // TODO: fix absent ahb3lpenr
 #[cfg(feature = "fsmc")]
 impl crate::Sealed for crate::pac::FSMC {}
@@ -160,6 +185,7 @@ bus_reset!(FSMC => 0);
 
 bus! {
     PWR => (APB1, 28),
+    WWDG => (APB1, 11),
 }
 
 bus! {
@@ -281,6 +307,54 @@ bus! {
     SDIO => (APB2, 11),
 }
 
+#[cfg(feature = "ltdc")]
+bus! {
+    LTDC => (APB2, 26),
+}
+
+#[cfg(all(feature = "dfsdm1", feature = "gpio-f412"))]
+bus! {
+    DFSDM => (APB2, 24),
+}
+#[cfg(all(feature = "dfsdm1", feature = "gpio-f413"))]
+bus! {
+    DFSDM1 => (APB2, 24),
+}
+#[cfg(feature = "dfsdm2")]
+bus! {
+    DFSDM2 => (APB2, 25),
+}
+
+#[cfg(all(
+    feature = "sai1",
+    not(any(
+        feature = "gpio-f446",
+        feature = "stm32f417",
+        feature = "stm32f427",
+        feature = "stm32f437"
+    ))
+))]
+bus! {
+    SAI => (APB2, 22),
+}
+#[cfg(all(
+    feature = "sai1",
+    any(
+        feature = "gpio-f446",
+        feature = "stm32f417",
+        feature = "stm32f427",
+        feature = "stm32f437"
+    )
+))]
+bus! {
+    SAI1 => (APB2, 22),
+}
+
+#[cfg(feature = "sai2")]
+bus! {
+    SAI2 => (APB2, 23),
+}
+
 bus! {
     TIM1 => (APB2, 0),
     TIM5 => (APB1, 3),
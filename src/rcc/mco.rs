@@ -0,0 +1,247 @@
+//! Microcontroller clock outputs
+//!
+//! MCO1 on PA8 and MCO2 on PC9 output an internal clock divided by 1 to 5.
+//!
+//! ```rust,ignore
+//! let clocks = rcc.cfgr.use_hse(8.MHz()).sysclk(168.MHz()).freeze();
+//! let mco1 = Mco1::new(gpioa.pa8, Mco1Source::Hse, Prescaler::Div1, Speed::Medium, &clocks)?;
+//! assert_eq!(mco1.frequency(), 8.MHz::<1, 1>());
+//! ```
+
+use fugit::HertzU32 as Hertz;
+use fugit::RateExtU32;
+
+use super::{Clocks, HSI};
+use crate::gpio::{alt::rcc as alt, PinSpeed, Speed};
+use crate::pac::RCC;
+
+/// LSE crystal frequency
+pub const LSE: u32 = 32_768;
+
+/// MCO1 clock source
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Mco1Source {
+    Hsi = 0b00,
+    Lse = 0b01,
+    Hse = 0b10,
+    Pll = 0b11,
+}
+
+/// MCO2 clock source
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Mco2Source {
+    Sysclk = 0b00,
+    #[cfg(not(feature = "gpio-f410"))]
+    PllI2s = 0b01,
+    Hse = 0b10,
+    Pll = 0b11,
+}
+
+/// Clock output divider
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Prescaler {
+    Div1 = 0b000,
+    Div2 = 0b100,
+    Div3 = 0b101,
+    Div4 = 0b110,
+    Div5 = 0b111,
+}
+
+impl Prescaler {
+    pub const fn divider(self) -> u32 {
+        match self {
+            Self::Div1 => 1,
+            Self::Div2 => 2,
+            Self::Div3 => 3,
+            Self::Div4 => 4,
+            Self::Div5 => 5,
+        }
+    }
+}
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// The source clock is not enabled by [`CFGR::freeze`](super::CFGR::freeze)
+    SourceDisabled,
+    /// The output frequency is above the limit of the I/O speed
+    Speed(Hertz),
+}
+
+/// Returns the highest frequency a pin can output at `speed` with VDD >= 2.7 V.
+pub const fn max_frequency(speed: Speed) -> u32 {
+    match speed {
+        Speed::Low => 2_000_000,
+        Speed::Medium => 25_000_000,
+        Speed::High => 50_000_000,
+        Speed::VeryHigh => 100_000_000,
+    }
+}
+
+/// Returns the output frequency if it is within the limit of `speed`.
+pub fn check(source: Option<Hertz>, prescaler: Prescaler, speed: Speed) -> Result<Hertz, Error> {
+    let frequency = source.ok_or(Error::SourceDisabled)? / prescaler.divider();
+    if frequency.raw() > max_frequency(speed) {
+        return Err(Error::Speed(frequency));
+    }
+    Ok(frequency)
+}
+
+impl Mco1Source {
+    /// Returns the source frequency, if it is running.
+    pub fn frequency(self, clocks: &Clocks) -> Option<Hertz> {
+        match self {
+            Self::Hsi => Some(HSI.Hz()),
+            Self::Lse => Some(LSE.Hz()),
+            Self::Hse => clocks.hse(),
+            Self::Pll => clocks.pllclk(),
+        }
+    }
+}
+
+impl Mco2Source {
+    /// Returns the source frequency, if it is running.
+    pub fn frequency(self, clocks: &Clocks) -> Option<Hertz> {
+        match self {
+            Self::Sysclk => Some(clocks.sysclk()),
+            #[cfg(not(feature = "gpio-f410"))]
+            Self::PllI2s => clocks.plli2sclk(),
+            Self::Hse => clocks.hse(),
+            Self::Pll => clocks.pllclk(),
+        }
+    }
+}
+
+/// Clock output on PA8
+pub struct Mco1 {
+    pin: alt::Mco1,
+    frequency: Hertz,
+}
+
+impl Mco1 {
+    /// Selects the source and divider and sets the pin speed.
+    ///
+    /// The LSE must be started with [`Rtc`](crate::rtc::Rtc) before, which is not checked.
+    pub fn new(
+        pin: impl Into<alt::Mco1>,
+        source: Mco1Source,
+        prescaler: Prescaler,
+        speed: Speed,
+        clocks: &Clocks,
+    ) -> Result<Self, Error> {
+        let frequency = check(source.frequency(clocks), prescaler, speed)?;
+        let rcc = unsafe { &*RCC::ptr() };
+        rcc.cfgr.modify(|r, w| unsafe {
+            w.bits(
+                (r.bits() & !((0b11 << 21) | (0b111 << 24)))
+                    | ((source as u32) << 21)
+                    | ((prescaler as u32) << 24),
+            )
+        });
+        let mut pin = pin.into();
+        pin.set_speed(speed);
+        Ok(Self { pin, frequency })
+    }
+
+    /// Returns the real output frequency
+    pub fn frequency(&self) -> Hertz {
+        self.frequency
+    }
+
+    /// Releases the pin, the clock keeps running on MCO1.
+    pub fn release(self) -> alt::Mco1 {
+        self.pin
+    }
+}
+
+/// Clock output on PC9
+pub struct Mco2 {
+    pin: alt::Mco2,
+    frequency: Hertz,
+}
+
+impl Mco2 {
+    /// Selects the source and divider and sets the pin speed.
+    pub fn new(
+        pin: impl Into<alt::Mco2>,
+        source: Mco2Source,
+        prescaler: Prescaler,
+        speed: Speed,
+        clocks: &Clocks,
+    ) -> Result<Self, Error> {
+        let frequency = check(source.frequency(clocks), prescaler, speed)?;
+        let rcc = unsafe { &*RCC::ptr() };
+        rcc.cfgr.modify(|r, w| unsafe {
+            w.bits(
+                (r.bits() & !((0b11 << 30) | (0b111 << 27)))
+                    | ((source as u32) << 30)
+                    | ((prescaler as u32) << 27),
+            )
+        });
+        let mut pin = pin.into();
+        pin.set_speed(speed);
+        Ok(Self { pin, frequency })
+    }
+
+    /// Returns the real output frequency
+    pub fn frequency(&self) -> Hertz {
+        self.frequency
+    }
+
+    /// Releases the pin, the clock keeps running on MCO2.
+    pub fn release(self) -> alt::Mco2 {
+        self.pin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prescalers() {
+        let hse = Some(Hertz::MHz(25));
+        assert_eq!(
+            check(hse, Prescaler::Div1, Speed::Medium),
+            Ok(Hertz::MHz(25))
+        );
+        assert_eq!(
+            check(hse, Prescaler::Div2, Speed::Medium),
+            Ok(Hertz::from_raw(12_500_000))
+        );
+        assert_eq!(
+            check(hse, Prescaler::Div5, Speed::Low),
+            Err(Error::Speed(Hertz::MHz(5)))
+        );
+        // 168 MHz needs a prescaler even at very high speed
+        let pll = Some(Hertz::MHz(168));
+        assert_eq!(
+            check(pll, Prescaler::Div1, Speed::VeryHigh),
+            Err(Error::Speed(Hertz::MHz(168)))
+        );
+        assert_eq!(
+            check(pll, Prescaler::Div2, Speed::VeryHigh),
+            Ok(Hertz::MHz(84))
+        );
+        assert_eq!(
+            check(pll, Prescaler::Div3, Speed::High),
+            Err(Error::Speed(Hertz::MHz(56)))
+        );
+        assert_eq!(check(pll, Prescaler::Div4, Speed::High), Ok(Hertz::MHz(42)));
+        assert_eq!(
+            check(pll, Prescaler::Div5, Speed::Medium),
+            Err(Error::Speed(Hertz::from_raw(33_600_000)))
+        );
+    }
+
+    #[test]
+    fn disabled_source() {
+        assert_eq!(
+            check(None, Prescaler::Div1, Speed::VeryHigh),
+            Err(Error::SourceDisabled)
+        );
+    }
+}
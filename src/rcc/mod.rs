@@ -19,6 +19,15 @@
 //!     assert!(clocks.i2s_clk().unwrap() == 48.MHz().into());
 //! ```
 //!
+//! [`CFGR::plan`] computes the main clock tree without touching the hardware, so a configuration
+//! can be checked on the host. [`CFGR::try_freeze`] applies it and reports a [`ClockError`]
+//! instead of panicking or waiting forever for the HSE and PLLs.
+//!
+//! ```
+//! let plan = rcc.cfgr.use_hse(8.MHz()).sysclk(168.MHz()).plan()?;
+//! assert_eq!(plan.pll.map(|pll| (pll.m, pll.n, pll.p)), Some((4, 168, 2)));
+//! ```
+//!
 //! # Limitations
 //!
 //! Unlike the clock configuration tool provided by ST, the code does not extensively search all
@@ -40,20 +49,26 @@
 //! and on the STM32F413/423 SAI clocks are generated by the I2S PLL. On these MCUs, the actual
 //! frequencies may substantially deviate from the requested frequencies.
 
+use crate::bb;
 use crate::pac::rcc::cfgr::{HPRE_A, SW_A};
 use crate::pac::{self, rcc, RCC};
 
+use enumflags2::BitFlags;
 use fugit::HertzU32 as Hertz;
 use fugit::RateExtU32;
 
 #[cfg(not(feature = "gpio-f410"))]
 use pll::I2sPll;
 use pll::MainPll;
+pub use pll::PllConfig;
 #[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469"))]
 use pll::SaiPll;
 
 mod pll;
 
+pub mod css;
+pub mod mco;
+
 mod enable;
 use crate::pac::rcc::RegisterBlock as RccRB;
 
@@ -292,35 +307,51 @@ impl BusTimerClock for APB2 {
 
 impl RccExt for RCC {
     fn constrain(self) -> Rcc {
-        Rcc {
-            cfgr: CFGR {
-                hse: None,
-                hse_bypass: false,
-                hclk: None,
-                pclk1: None,
-                pclk2: None,
-                sysclk: None,
-                pll48clk: false,
-                i2s_ckin: None,
-                #[cfg(any(
-                    feature = "gpio-f401",
-                    feature = "gpio-f410",
-                    feature = "gpio-f411",
-                    feature = "gpio-f417",
-                    feature = "gpio-f427",
-                    feature = "gpio-f469",
-                ))]
-                i2s_clk: None,
-                #[cfg(any(feature = "gpio-f412", feature = "gpio-f413", feature = "gpio-f446"))]
-                i2s_apb1_clk: None,
-                #[cfg(any(feature = "gpio-f412", feature = "gpio-f413", feature = "gpio-f446"))]
-                i2s_apb2_clk: None,
-                #[cfg(feature = "sai1")]
-                sai1_clk: None,
-                #[cfg(feature = "sai1")]
-                sai2_clk: None,
-            },
-        }
+        Rcc { cfgr: CFGR::new() }
+    }
+}
+
+/// Reset flags of `RCC_CSR`
+///
+/// Several flags can be set at once, a power-on reset also sets `Pin` and `BrownOut`.
+#[enumflags2::bitflags]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[repr(u32)]
+pub enum ResetReason {
+    /// Brown-out reset, also set on power-on
+    BrownOut = 1 << 25,
+    /// Reset on the NRST pin
+    Pin = 1 << 26,
+    /// Power-on or power-down reset
+    PowerOn = 1 << 27,
+    /// Software reset
+    Software = 1 << 28,
+    /// Independent watchdog reset
+    IndependentWatchdog = 1 << 29,
+    /// Window watchdog reset
+    WindowWatchdog = 1 << 30,
+    /// Entering Standby or Stop with the `nRST_STDBY`/`nRST_STOP` option bits cleared
+    LowPower = 1 << 31,
+}
+
+impl ResetReason {
+    /// Reads the reset flags without clearing them
+    pub fn read() -> BitFlags<Self> {
+        let rcc = unsafe { &*RCC::ptr() };
+        BitFlags::from_bits_truncate(rcc.csr.read().bits())
+    }
+
+    /// Reads the reset flags and clears them, so the next reset reports only its own cause.
+    ///
+    /// Call it after [`Pwr::wakeup_reason`](crate::pwr::Pwr::wakeup_reason), which reads the
+    /// same flags.
+    pub fn read_and_clear() -> BitFlags<Self> {
+        let rcc = unsafe { &*RCC::ptr() };
+        let reason = BitFlags::from_bits_truncate(rcc.csr.read().bits());
+        // RMVF
+        unsafe { bb::set(&rcc.csr, 24) };
+        reason
     }
 }
 
@@ -393,6 +424,18 @@ pub const PCLK2_MAX: u32 = SYSCLK_MAX / 2;
 /// Maximum APB1 peripheral clock frequency
 pub const PCLK1_MAX: u32 = PCLK2_MAX / 2;
 
+#[cfg(feature = "gpio-f401")]
+/// Minimum PLL VCO output frequency
+pub const VCO_MIN: u32 = 192_000_000;
+
+#[cfg(not(feature = "gpio-f401"))]
+/// Minimum PLL VCO output frequency
+pub const VCO_MIN: u32 = 100_000_000;
+
+/// Maximum PLL VCO output frequency
+pub const VCO_MAX: u32 = 432_000_000;
+
+#[derive(Clone)]
 pub struct CFGR {
     hse: Option<u32>,
     hse_bypass: bool,
@@ -420,11 +463,46 @@ pub struct CFGR {
     sai1_clk: Option<u32>,
     #[cfg(feature = "sai1")]
     sai2_clk: Option<u32>,
+    #[cfg(feature = "ltdc")]
+    lcd_clk: Option<u32>,
 }
 
 impl CFGR {
+    const fn new() -> Self {
+        CFGR {
+            hse: None,
+            hse_bypass: false,
+            hclk: None,
+            pclk1: None,
+            pclk2: None,
+            sysclk: None,
+            pll48clk: false,
+            i2s_ckin: None,
+            #[cfg(any(
+                feature = "gpio-f401",
+                feature = "gpio-f410",
+                feature = "gpio-f411",
+                feature = "gpio-f417",
+                feature = "gpio-f427",
+                feature = "gpio-f469",
+            ))]
+            i2s_clk: None,
+            #[cfg(any(feature = "gpio-f412", feature = "gpio-f413", feature = "gpio-f446"))]
+            i2s_apb1_clk: None,
+            #[cfg(any(feature = "gpio-f412", feature = "gpio-f413", feature = "gpio-f446"))]
+            i2s_apb2_clk: None,
+            #[cfg(feature = "sai1")]
+            sai1_clk: None,
+            #[cfg(feature = "sai1")]
+            sai2_clk: None,
+            #[cfg(feature = "ltdc")]
+            lcd_clk: None,
+        }
+    }
+
     /// Uses HSE (external oscillator) instead of HSI (internal RC oscillator) as the clock source.
-    /// Will result in a hang if an external oscillator is not connected or it fails to start.
+    /// Will result in a hang if an external oscillator is not connected or it fails to start,
+    /// unless [`CFGR::try_freeze`] is used.
     pub fn use_hse(mut self, freq: Hertz) -> Self {
         self.hse = Some(freq.raw());
         self
@@ -533,112 +611,114 @@ impl CFGR {
         self.sai2_clk = Some(freq.raw());
         self
     }
-    #[cfg(feature = "gpio-f410")]
-    #[inline(always)]
-    fn pll_setup(&self, pllsrcclk: u32, pllsysclk: Option<u32>) -> PllSetup {
-        let i2s_clocks = self.i2s_clocks();
 
-        let main_pll = if let Some(i2s_clk) = i2s_clocks.pll_i2s_clk {
+    /// Selects the LCD-TFT (pixel) clock frequency and enables it.
+    #[cfg(feature = "ltdc")]
+    pub fn lcd_clk(mut self, freq: Hertz) -> Self {
+        self.lcd_clk = Some(freq.raw());
+        self
+    }
+    /// Chooses the main PLL dividers without touching the hardware.
+    fn main_pll(
+        &self,
+        pllsrcclk: u32,
+        pllsysclk: Option<u32>,
+    ) -> Result<Option<PllConfig>, ClockError> {
+        #[cfg(feature = "gpio-f410")]
+        if let Some(i2s_clk) = self.i2s_clocks()?.pll_i2s_clk {
             // The I2S frequency is generated by the main PLL. The frequency needs to be accurate,
             // so we need an expensive full PLL configuration search.
-            MainPll::setup_with_i2s(
-                pllsrcclk,
-                self.hse.is_some(),
-                pllsysclk,
-                self.pll48clk,
-                i2s_clk,
-            )
-        } else {
-            MainPll::fast_setup(pllsrcclk, self.hse.is_some(), pllsysclk, self.pll48clk)
-        };
-
-        PllSetup {
-            use_pll: main_pll.use_pll,
-            pllsysclk: main_pll.pllsysclk,
-            pll48clk: main_pll.pll48clk,
-            i2s: i2s_clocks.real(main_pll.plli2sclk, self.i2s_ckin),
+            return MainPll::plan_with_i2s(pllsrcclk, pllsysclk, self.pll48clk, i2s_clk);
         }
+        MainPll::fast_plan(pllsrcclk, pllsysclk, self.pll48clk)
     }
 
-    #[cfg(feature = "gpio-f413")]
+    #[cfg(feature = "gpio-f410")]
     #[inline(always)]
-    fn pll_setup(&self, pllsrcclk: u32, pllsysclk: Option<u32>) -> PllSetup {
-        let rcc = unsafe { &*RCC::ptr() };
+    fn pll_setup(
+        &self,
+        pllsrcclk: u32,
+        main_pll: Option<&PllConfig>,
+    ) -> Result<PllSetup, ClockError> {
+        let i2s_clocks = self.i2s_clocks()?;
+        let plli2sclk = main_pll.and_then(|pll| pll.plli2sclk(pllsrcclk));
 
-        let i2s_clocks = self.i2s_clocks();
-        let sai_clocks = self.sai_clocks();
-
-        let main_pll = MainPll::fast_setup(pllsrcclk, self.hse.is_some(), pllsysclk, self.pll48clk);
+        Ok(PllSetup {
+            i2s: i2s_clocks.real(plli2sclk, self.i2s_ckin),
+        })
+    }
 
-        let (i2s_pll, real_sai_clk) = if let Some(i2s_clk) = i2s_clocks.pll_i2s_clk {
+    #[cfg(feature = "gpio-f413")]
+    #[inline(always)]
+    fn pll_setup(
+        &self,
+        pllsrcclk: u32,
+        _main_pll: Option<&PllConfig>,
+    ) -> Result<PllSetup, ClockError> {
+        let i2s_clocks = self.i2s_clocks()?;
+        let sai_clocks = self.sai_clocks()?;
+
+        let (i2s_pll, real_sai_clk, plli2sdivr) = if let Some(i2s_clk) = i2s_clocks.pll_i2s_clk {
             // Currently, we only support generating SAI/PLL clocks with the I2S PLL. This is only
             // really usable when the frequencies are identical or the I2S frequency is a multiple of
             // the SAI frequency. Therefore, we just optimize the PLL for the I2S frequency and then
             // derive the SAI frequency from the I2S frequency.
-            let i2s_pll = I2sPll::setup(pllsrcclk, Some(i2s_clk));
-
-            if let Some(sai_clk) = sai_clocks.pll_sai_clk {
-                let div = u32::min(
-                    u32::max((i2s_pll.plli2sclk.unwrap() + (sai_clk >> 1)) / sai_clk, 1),
-                    31,
-                );
-                rcc.dckcfgr.modify(|_, w| w.plli2sdivr().bits(div as u8));
-                let real_sai_clk = sai_clk / div;
-                (i2s_pll, Some(real_sai_clk))
+            let i2s_pll = I2sPll::plan(pllsrcclk, Some(i2s_clk))?;
+
+            if let (Some(sai_clk), Some(plli2sclk)) = (sai_clocks.pll_sai_clk, i2s_pll.plli2sclk) {
+                let div = u32::min(u32::max((plli2sclk + (sai_clk >> 1)) / sai_clk, 1), 31);
+                (i2s_pll, Some(plli2sclk / div), Some(div as u8))
             } else {
-                (i2s_pll, None)
+                (i2s_pll, None, None)
             }
         } else if let Some(pll_sai_clk) = sai_clocks.pll_sai_clk {
             // We try all divider values to get the best approximation of the requested frequency.
             // NOTE: STM32F413/423 have a different divider range than other models!
             let (i2s_pll, real_sai_clk, div) = (1..31)
-                .map(|div| {
-                    let i2s_pll = I2sPll::setup(pllsrcclk, Some(pll_sai_clk * div));
-                    let real_clk = i2s_pll.plli2sclk.unwrap() / div;
-                    (i2s_pll, real_clk, div)
+                .filter_map(|div| {
+                    let i2s_pll = I2sPll::plan(pllsrcclk, Some(pll_sai_clk * div)).ok()?;
+                    let real_clk = i2s_pll.plli2sclk? / div;
+                    Some((i2s_pll, real_clk, div))
                 })
-                .min_by_key(|(_, real_clk, _)| (*real_clk as i32 - pll_sai_clk as i32).abs())
-                .unwrap();
-            rcc.dckcfgr.modify(|_, w| w.plli2sdivr().bits(div as u8));
-            (i2s_pll, Some(real_sai_clk))
+                .min_by_key(|(_, real_clk, _)| real_clk.abs_diff(pll_sai_clk))
+                .ok_or(ClockError::PeripheralClock)?;
+            (i2s_pll, Some(real_sai_clk), Some(div as u8))
         } else {
-            (I2sPll::unused(), None)
+            (I2sPll::unused(), None, None)
         };
 
-        PllSetup {
-            use_pll: main_pll.use_pll,
-            use_i2spll: i2s_pll.use_pll,
-            pllsysclk: main_pll.pllsysclk,
-            pll48clk: main_pll.pll48clk,
+        Ok(PllSetup {
             i2s: i2s_clocks.real(i2s_pll.plli2sclk, self.i2s_ckin),
             sai: sai_clocks.real(real_sai_clk, self.i2s_ckin),
-        }
+            i2s_pll,
+            plli2sdivr,
+        })
     }
 
     #[cfg(any(feature = "gpio-f411", feature = "gpio-f412", feature = "gpio-f446"))]
     #[inline(always)]
-    fn pll_setup(&self, pllsrcclk: u32, pllsysclk: Option<u32>) -> PllSetup {
-        let i2s_clocks = self.i2s_clocks();
+    fn pll_setup(
+        &self,
+        pllsrcclk: u32,
+        _main_pll: Option<&PllConfig>,
+    ) -> Result<PllSetup, ClockError> {
+        let i2s_clocks = self.i2s_clocks()?;
         #[cfg(feature = "gpio-f446")]
-        let sai_clocks = self.sai_clocks();
+        let sai_clocks = self.sai_clocks()?;
 
         // All PLLs are completely independent.
-        let main_pll = MainPll::fast_setup(pllsrcclk, self.hse.is_some(), pllsysclk, self.pll48clk);
-        let i2s_pll = I2sPll::setup(pllsrcclk, i2s_clocks.pll_i2s_clk);
+        let i2s_pll = I2sPll::plan(pllsrcclk, i2s_clocks.pll_i2s_clk)?;
         #[cfg(feature = "gpio-f446")]
-        let sai_pll = SaiPll::setup(pllsrcclk, sai_clocks.pll_sai_clk);
+        let sai_pll = SaiPll::plan(pllsrcclk, sai_clocks.pll_sai_clk)?;
 
-        PllSetup {
-            use_pll: main_pll.use_pll,
-            use_i2spll: i2s_pll.use_pll,
-            #[cfg(feature = "gpio-f446")]
-            use_saipll: sai_pll.use_pll,
-            pllsysclk: main_pll.pllsysclk,
-            pll48clk: main_pll.pll48clk,
+        Ok(PllSetup {
             i2s: i2s_clocks.real(i2s_pll.plli2sclk, self.i2s_ckin),
             #[cfg(feature = "gpio-f446")]
             sai: sai_clocks.real(sai_pll.sai_clk, self.i2s_ckin),
-        }
+            i2s_pll,
+            #[cfg(feature = "gpio-f446")]
+            sai_pll,
+        })
     }
 
     #[cfg(any(
@@ -648,33 +728,37 @@ impl CFGR {
         feature = "gpio-f469",
     ))]
     #[inline(always)]
-    fn pll_setup(&self, pllsrcclk: u32, pllsysclk: Option<u32>) -> PllSetup {
-        let i2s_clocks = self.i2s_clocks();
+    fn pll_setup(
+        &self,
+        pllsrcclk: u32,
+        main_pll: Option<&PllConfig>,
+    ) -> Result<PllSetup, ClockError> {
+        let i2s_clocks = self.i2s_clocks()?;
         #[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
-        let sai_clocks = self.sai_clocks();
+        let sai_clocks = self.sai_clocks()?;
 
         // We have separate PLLs, but they share the "M" divider.
-        let main_pll = MainPll::fast_setup(pllsrcclk, self.hse.is_some(), pllsysclk, self.pll48clk);
-        let i2s_pll = I2sPll::setup_shared_m(pllsrcclk, main_pll.m, i2s_clocks.pll_i2s_clk);
+        let main_m = main_pll.map(|pll| u32::from(pll.m));
+        let i2s_pll = I2sPll::plan_shared_m(pllsrcclk, main_m, i2s_clocks.pll_i2s_clk)?;
         #[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
-        let sai_pll =
-            SaiPll::setup_shared_m(pllsrcclk, main_pll.m.or(i2s_pll.m), sai_clocks.pll_sai_clk);
+        #[allow(unused_mut)]
+        let mut sai_pll =
+            SaiPll::plan_shared_m(pllsrcclk, main_m.or(i2s_pll.m), sai_clocks.pll_sai_clk)?;
+        #[cfg(feature = "ltdc")]
+        sai_pll.plan_lcd(pllsrcclk, main_m.or(i2s_pll.m), self.lcd_clk)?;
 
-        PllSetup {
-            use_pll: main_pll.use_pll,
-            use_i2spll: i2s_pll.use_pll,
-            #[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
-            use_saipll: sai_pll.use_pll,
-            pllsysclk: main_pll.pllsysclk,
-            pll48clk: main_pll.pll48clk,
+        Ok(PllSetup {
             i2s: i2s_clocks.real(i2s_pll.plli2sclk, self.i2s_ckin),
+            i2s_pll,
             #[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
             sai: sai_clocks.real(sai_pll.sai_clk, self.i2s_ckin),
-        }
+            #[cfg(any(feature = "gpio-f427", feature = "gpio-f469"))]
+            sai_pll,
+        })
     }
 
     #[cfg(feature = "sai1")]
-    fn sai_clocks(&self) -> SaiClocks {
+    fn sai_clocks(&self) -> Result<SaiClocks, ClockError> {
         let sai1_ext = self.sai1_clk.is_some() && self.sai1_clk == self.i2s_ckin;
         #[cfg(not(feature = "gpio-f446"))]
         let sai2_ext = self.sai2_clk.is_some() && self.sai2_clk == self.i2s_ckin;
@@ -685,19 +769,20 @@ impl CFGR {
         let pll_sai_clk2 = self.sai2_clk;
         #[cfg(not(feature = "gpio-f446"))]
         let pll_sai_clk2 = if sai2_ext { None } else { self.sai2_clk };
+        // Only one SAI PLL frequency is implemented
         if pll_sai_clk.is_some() && pll_sai_clk2.is_some() && pll_sai_clk != pll_sai_clk2 {
-            panic!("only one SAI PLL frequency implemented");
+            return Err(ClockError::PeripheralClock);
         }
-        SaiClocks {
+        Ok(SaiClocks {
             sai1_ext,
             #[cfg(not(feature = "gpio-f446"))]
             sai2_ext,
             pll_sai_clk,
-        }
+        })
     }
 
     #[cfg(any(feature = "gpio-f412", feature = "gpio-f413", feature = "gpio-f446"))]
-    fn i2s_clocks(&self) -> I2sClocks {
+    fn i2s_clocks(&self) -> Result<I2sClocks, ClockError> {
         let i2s_apb1_ext = self.i2s_apb1_clk.is_some() && self.i2s_apb1_clk == self.i2s_ckin;
         let i2s_apb2_ext = self.i2s_apb2_clk.is_some() && self.i2s_apb2_clk == self.i2s_ckin;
         let pll_i2s_clk = if i2s_apb1_ext {
@@ -710,49 +795,35 @@ impl CFGR {
         } else {
             self.i2s_apb2_clk
         };
+        // Only one I2S PLL frequency is implemented
         if pll_i2s_clk.is_some() && pll_i2s_clk2.is_some() && pll_i2s_clk != pll_i2s_clk2 {
-            panic!("only one I2S PLL frequency implemented");
+            return Err(ClockError::PeripheralClock);
         }
-        I2sClocks {
+        Ok(I2sClocks {
             i2s_apb1_ext,
             i2s_apb2_ext,
             pll_i2s_clk,
-        }
+        })
     }
 
     #[cfg(not(any(feature = "gpio-f412", feature = "gpio-f413", feature = "gpio-f446")))]
-    fn i2s_clocks(&self) -> I2sClocks {
+    fn i2s_clocks(&self) -> Result<I2sClocks, ClockError> {
         let i2s_ext = self.i2s_clk.is_some() && self.i2s_clk == self.i2s_ckin;
         let pll_i2s_clk = if i2s_ext { None } else { self.i2s_clk };
-        I2sClocks {
+        Ok(I2sClocks {
             i2s_ext,
             pll_i2s_clk,
-        }
+        })
     }
 
-    fn flash_setup(sysclk: u32) {
+    fn flash_setup(latency: u8) {
         use crate::pac::FLASH;
 
-        #[cfg(any(
-            feature = "gpio-f401",
-            feature = "gpio-f417",
-            feature = "gpio-f410",
-            feature = "gpio-f411",
-            feature = "gpio-f412",
-            feature = "gpio-f427",
-            feature = "gpio-f446",
-            feature = "gpio-f469",
-        ))]
-        let flash_latency_step = 30_000_000;
-
-        #[cfg(feature = "gpio-f413")]
-        let flash_latency_step = 25_000_000;
-
         unsafe {
             let flash = &(*FLASH::ptr());
             // Adjust flash wait states
             flash.acr.modify(|_, w| {
-                w.latency().bits(((sysclk - 1) / flash_latency_step) as u8);
+                w.latency().bits(latency);
                 w.prften().set_bit();
                 w.icen().set_bit();
                 w.dcen().set_bit()
@@ -760,92 +831,220 @@ impl CFGR {
         }
     }
 
-    /// Initialises the hardware according to CFGR state returning a Clocks instance.
-    /// Panics if overclocking is attempted.
-    pub fn freeze(self) -> Clocks {
-        self.freeze_internal(false)
-    }
-
-    /// Initialises the hardware according to CFGR state returning a Clocks instance.
-    /// Allows overclocking.
+    /// Computes the PLL dividers, bus prescalers, flash wait states, voltage scale and over-drive
+    /// for the requested clocks without touching the hardware.
     ///
-    /// # Safety
-    ///
-    /// This method does not check if the clocks are bigger or smaller than the officially
-    /// recommended.
-    pub unsafe fn freeze_unchecked(self) -> Clocks {
-        self.freeze_internal(true)
+    /// The I2S, SAI and LCD-TFT clocks are chosen later by [`CFGR::try_freeze`].
+    pub fn plan(&self) -> Result<ClockPlan, ClockError> {
+        self.plan_internal(false)
     }
 
-    fn freeze_internal(self, unchecked: bool) -> Clocks {
-        let rcc = unsafe { &*RCC::ptr() };
-
-        //let (use_pll, sysclk_on_pll, sysclk, pll48clk) = self.pll_setup();
+    fn plan_internal(&self, unchecked: bool) -> Result<ClockPlan, ClockError> {
         let pllsrcclk = self.hse.unwrap_or(HSI);
         let sysclk = self.sysclk.unwrap_or(pllsrcclk);
         let sysclk_on_pll = sysclk != pllsrcclk;
 
-        let plls = self.pll_setup(pllsrcclk, sysclk_on_pll.then_some(sysclk));
-        let sysclk = if sysclk_on_pll {
-            plls.pllsysclk.unwrap()
-        } else {
-            sysclk
+        let pll = self.main_pll(pllsrcclk, sysclk_on_pll.then(|| sysclk))?;
+        if let Some(pll) = &pll {
+            if !unchecked && !(VCO_MIN..=VCO_MAX).contains(&pll.vco_out(pllsrcclk)) {
+                return Err(ClockError::VcoRange);
+            }
+        }
+        let sysclk = match &pll {
+            Some(pll) if sysclk_on_pll => pll.pllsysclk(pllsrcclk),
+            _ => sysclk,
         };
 
-        assert!(unchecked || !sysclk_on_pll || (SYSCLK_MIN..=SYSCLK_MAX).contains(&sysclk));
+        if !unchecked && sysclk_on_pll && !(SYSCLK_MIN..=SYSCLK_MAX).contains(&sysclk) {
+            return Err(ClockError::SysclkRange(sysclk.Hz()));
+        }
 
         let hclk = self.hclk.unwrap_or(sysclk);
-        let (hpre_bits, hpre_div) = match (sysclk + hclk - 1) / hclk {
+        let hpre = match (sysclk + hclk - 1) / hclk {
             0 => unreachable!(),
-            1 => (HPRE_A::Div1, 1),
-            2 => (HPRE_A::Div2, 2),
-            3..=5 => (HPRE_A::Div4, 4),
-            6..=11 => (HPRE_A::Div8, 8),
-            12..=39 => (HPRE_A::Div16, 16),
-            40..=95 => (HPRE_A::Div64, 64),
-            96..=191 => (HPRE_A::Div128, 128),
-            192..=383 => (HPRE_A::Div256, 256),
-            _ => (HPRE_A::Div512, 512),
+            1 => 1,
+            2 => 2,
+            3..=5 => 4,
+            6..=11 => 8,
+            12..=39 => 16,
+            40..=95 => 64,
+            96..=191 => 128,
+            192..=383 => 256,
+            _ => 512,
         };
 
         // Calculate real AHB clock
-        let hclk = sysclk / hpre_div;
+        let hclk = sysclk / u32::from(hpre);
 
         let pclk1 = self
             .pclk1
             .unwrap_or_else(|| core::cmp::min(PCLK1_MAX, hclk));
-        let (ppre1_bits, ppre1) = match (hclk + pclk1 - 1) / pclk1 {
-            0 => unreachable!(),
-            1 => (0b000, 1u8),
-            2 => (0b100, 2),
-            3..=5 => (0b101, 4),
-            6..=11 => (0b110, 8),
-            _ => (0b111, 16),
-        };
+        let ppre1 = apb_prescaler(hclk, pclk1);
 
         // Calculate real APB1 clock
         let pclk1 = hclk / u32::from(ppre1);
 
-        assert!(unchecked || pclk1 <= PCLK1_MAX);
+        if !unchecked && pclk1 > PCLK1_MAX {
+            return Err(ClockError::Pclk1Limit(pclk1.Hz()));
+        }
 
         let pclk2 = self
             .pclk2
             .unwrap_or_else(|| core::cmp::min(PCLK2_MAX, hclk));
-        let (ppre2_bits, ppre2) = match (hclk + pclk2 - 1) / pclk2 {
-            0 => unreachable!(),
-            1 => (0b000, 1u8),
-            2 => (0b100, 2),
-            3..=5 => (0b101, 4),
-            6..=11 => (0b110, 8),
-            _ => (0b111, 16),
-        };
+        let ppre2 = apb_prescaler(hclk, pclk2);
 
         // Calculate real APB2 clock
         let pclk2 = hclk / u32::from(ppre2);
 
-        assert!(unchecked || pclk2 <= PCLK2_MAX);
+        if !unchecked && pclk2 > PCLK2_MAX {
+            return Err(ClockError::Pclk2Limit(pclk2.Hz()));
+        }
 
-        Self::flash_setup(sysclk);
+        let pll48clk = self
+            .pll48clk
+            .then(|| pll.map(|pll| pll.pll48clk(pllsrcclk)))
+            .flatten();
+        if self.pll48clk {
+            let pll48clk = pll48clk.unwrap_or_default();
+            // USB specification allows +-0.25%
+            if 48_000_000_u32.abs_diff(pll48clk) > 120_000 {
+                return Err(ClockError::Pll48clkAccuracy(pll48clk.Hz()));
+            }
+        }
+
+        #[cfg(feature = "gpio-f413")]
+        let flash_latency_step = 25_000_000;
+        #[cfg(not(feature = "gpio-f413"))]
+        let flash_latency_step = 30_000_000;
+
+        Ok(ClockPlan {
+            sysclk_source: if sysclk_on_pll {
+                SysclkSource::Pll
+            } else if self.hse.is_some() {
+                SysclkSource::Hse
+            } else {
+                SysclkSource::Hsi
+            },
+            pll,
+            sysclk: sysclk.Hz(),
+            hclk: hclk.Hz(),
+            pclk1: pclk1.Hz(),
+            pclk2: pclk2.Hz(),
+            pll48clk: pll48clk.map(Hertz::from_raw),
+            hpre,
+            ppre1,
+            ppre2,
+            flash_latency: ((sysclk - 1) / flash_latency_step) as u8,
+            voltage_scale: VoltageScale::for_hclk(hclk),
+            // Enable voltage regulator overdrive if HCLK is above the limit
+            overdrive: cfg!(any(
+                feature = "gpio-f427",
+                feature = "gpio-f446",
+                feature = "gpio-f469"
+            )) && pll.is_some()
+                && hclk > 168_000_000,
+        })
+    }
+
+    /// Initialises the hardware according to CFGR state returning a Clocks instance.
+    /// Panics if overclocking is attempted.
+    pub fn freeze(self) -> Clocks {
+        let plan = self
+            .plan_internal(false)
+            .expect("invalid clock configuration");
+        self.apply(&plan, None)
+            .expect("invalid clock configuration")
+    }
+
+    /// Initialises the hardware according to CFGR state returning a Clocks instance.
+    /// Allows overclocking.
+    ///
+    /// # Safety
+    ///
+    /// This method does not check if the clocks are bigger or smaller than the officially
+    /// recommended.
+    pub unsafe fn freeze_unchecked(self) -> Clocks {
+        let plan = self
+            .plan_internal(true)
+            .expect("invalid clock configuration");
+        self.apply(&plan, None)
+            .expect("invalid clock configuration")
+    }
+
+    /// Initialises the hardware according to [`CFGR::plan`] returning a Clocks instance.
+    ///
+    /// Unlike [`CFGR::freeze`], this returns an error instead of panicking for unreachable clocks
+    /// and gives up if the HSE does not start or a PLL does not lock within
+    /// [`STARTUP_TIMEOUT`] polls. The previous clock configuration is restored in that case.
+    pub fn try_freeze(self) -> Result<Clocks, ClockError> {
+        let plan = self.plan()?;
+        let saved = SavedClocks::save();
+        self.apply(&plan, Some(STARTUP_TIMEOUT)).map_err(|e| {
+            saved.restore();
+            e
+        })
+    }
+
+    /// Changes the system clock and bus prescalers at runtime, returning the new `Clocks`.
+    ///
+    /// The system clock runs from the HSI while the PLLs are stopped and reprogrammed, so the
+    /// flash wait states always fit and I2S, SAI and LCD-TFT clocks pause. If an oscillator,
+    /// PLL or the over-drive does not become ready, the previous configuration is restored.
+    /// Drivers created with the old `Clocks` must be updated with their `configure` methods
+    /// afterwards.
+    ///
+    /// ```rust,ignore
+    /// let rcc = dp.RCC.constrain();
+    /// let slow = rcc.cfgr.clone();
+    /// let fast = rcc.cfgr.use_hse(8.MHz()).sysclk(168.MHz());
+    /// let clocks = fast.clone().freeze();
+    ///
+    /// // 16 MHz from the HSI
+    /// let clocks = slow.reconfigure()?;
+    /// serial.configure(&clocks)?;
+    ///
+    /// let clocks = fast.reconfigure()?;
+    /// serial.configure(&clocks)?;
+    /// ```
+    pub fn reconfigure(self) -> Result<Clocks, ClockError> {
+        let plan = self.plan()?;
+        let saved = SavedClocks::save();
+        let rcc = unsafe { &*RCC::ptr() };
+
+        rcc.cr.modify(|_, w| w.hsion().set_bit());
+        while rcc.cr.read().hsirdy().bit_is_clear() {}
+        rcc.cfgr.modify(|_, w| w.sw().variant(SW_A::Hsi));
+        while rcc.cfgr.read().sws().bits() != 0 {}
+
+        stop_plls();
+
+        #[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469"))]
+        if !plan.overdrive {
+            overdrive_off();
+        }
+
+        // HSEBYP can only be changed while the HSE is off
+        if self.hse.is_none() || rcc.cr.read().hsebyp().bit() != self.hse_bypass {
+            rcc.cr.modify(|_, w| w.hseon().clear_bit());
+            while rcc.cr.read().hserdy().bit_is_set() {}
+            rcc.cr.modify(|_, w| w.hsebyp().clear_bit());
+        }
+
+        self.apply(&plan, Some(STARTUP_TIMEOUT)).map_err(|e| {
+            saved.restore();
+            e
+        })
+    }
+
+    fn apply(self, plan: &ClockPlan, timeout: Option<u32>) -> Result<Clocks, ClockError> {
+        let rcc = unsafe { &*RCC::ptr() };
+
+        let pllsrcclk = self.hse.unwrap_or(HSI);
+        let plls = self.pll_setup(pllsrcclk, plan.pll.as_ref())?;
+        PllConfig::apply(plan.pll.as_ref(), self.hse.is_some());
+        plls.apply();
+
+        Self::flash_setup(plan.flash_latency);
 
         if self.hse.is_some() {
             // enable HSE and wait for it to be ready
@@ -855,49 +1054,60 @@ impl CFGR {
                 }
                 w.hseon().set_bit()
             });
-            while rcc.cr.read().hserdy().bit_is_clear() {}
+            wait(timeout, ClockError::HseTimeout, || {
+                rcc.cr.read().hserdy().bit_is_set()
+            })?;
         }
 
-        if plls.use_pll {
+        if plan.pll.is_some() {
+            // Enable clock for PWR peripheral
+            rcc.apb1enr.modify(|_, w| w.pwren().set_bit());
+            // Stall the pipeline to work around erratum 2.1.13 (DM00037591)
+            cortex_m::asm::dsb();
+            plan.voltage_scale.apply();
+
             // Enable PLL
             rcc.cr.modify(|_, w| w.pllon().set_bit());
 
-            // Enable voltage regulator overdrive if HCLK is above the limit
             #[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469"))]
-            if hclk > 168_000_000 {
-                // Enable clock for PWR peripheral
-                rcc.apb1enr.modify(|_, w| w.pwren().set_bit());
-
-                // Stall the pipeline to work around erratum 2.1.13 (DM00037591)
-                cortex_m::asm::dsb();
-
+            if plan.overdrive {
                 let pwr = unsafe { &*crate::pac::PWR::ptr() };
                 pwr.cr.modify(|_, w| w.oden().set_bit());
-                while pwr.csr.read().odrdy().bit_is_clear() {}
+                wait(timeout, ClockError::OverdriveTimeout, || {
+                    pwr.csr.read().odrdy().bit_is_set()
+                })?;
                 pwr.cr.modify(|_, w| w.odswen().set_bit());
-                while pwr.csr.read().odswrdy().bit_is_clear() {}
+                wait(timeout, ClockError::OverdriveTimeout, || {
+                    pwr.csr.read().odswrdy().bit_is_set()
+                })?;
             }
 
             // Wait for PLL to stabilise
-            while rcc.cr.read().pllrdy().bit_is_clear() {}
+            wait(timeout, ClockError::PllTimeout, || {
+                rcc.cr.read().pllrdy().bit_is_set()
+            })?;
         }
 
         #[cfg(not(feature = "gpio-f410"))]
-        if plls.use_i2spll {
+        if plls.i2s_pll.use_pll {
             // Enable PLL.
             rcc.cr.modify(|_, w| w.plli2son().set_bit());
 
             // Wait for PLL to stabilise
-            while rcc.cr.read().plli2srdy().bit_is_clear() {}
+            wait(timeout, ClockError::PllTimeout, || {
+                rcc.cr.read().plli2srdy().bit_is_set()
+            })?;
         }
 
         #[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469"))]
-        if plls.use_saipll {
+        if plls.sai_pll.use_pll {
             // Enable PLL.
             rcc.cr.modify(|_, w| w.pllsaion().set_bit());
 
             // Wait for PLL to stabilise
-            while rcc.cr.read().pllsairdy().bit_is_clear() {}
+            wait(timeout, ClockError::PllTimeout, || {
+                rcc.cr.read().pllsairdy().bit_is_set()
+            })?;
         }
 
         // Select I2S and SAI clocks
@@ -905,10 +1115,22 @@ impl CFGR {
         #[cfg(feature = "sai1")]
         plls.sai.config_clocksel();
 
+        let hpre_bits = match plan.hpre {
+            1 => HPRE_A::Div1,
+            2 => HPRE_A::Div2,
+            4 => HPRE_A::Div4,
+            8 => HPRE_A::Div8,
+            16 => HPRE_A::Div16,
+            64 => HPRE_A::Div64,
+            128 => HPRE_A::Div128,
+            256 => HPRE_A::Div256,
+            _ => HPRE_A::Div512,
+        };
+
         // Set scaling factors
         rcc.cfgr.modify(|_, w| unsafe {
-            w.ppre2().bits(ppre2_bits);
-            w.ppre1().bits(ppre1_bits);
+            w.ppre2().bits(ppre_bits(plan.ppre2));
+            w.ppre1().bits(ppre_bits(plan.ppre1));
             w.hpre().variant(hpre_bits)
         });
 
@@ -918,29 +1140,25 @@ impl CFGR {
 
         // Select system clock source
         rcc.cfgr.modify(|_, w| {
-            w.sw().variant(if sysclk_on_pll {
-                SW_A::Pll
-            } else if self.hse.is_some() {
-                SW_A::Hse
-            } else {
-                SW_A::Hsi
+            w.sw().variant(match plan.sysclk_source {
+                SysclkSource::Pll => SW_A::Pll,
+                SysclkSource::Hse => SW_A::Hse,
+                SysclkSource::Hsi => SW_A::Hsi,
             })
         });
 
-        let pclk_mul = if ppre1 == 1 { 1 } else { 2 };
-        let timclk1 = Hertz::from_raw(pclk1 * pclk_mul);
-
-        let pclk_mul = if ppre2 == 1 { 1 } else { 2 };
-        let timclk2 = Hertz::from_raw(pclk2 * pclk_mul);
-
-        let clocks = Clocks {
-            hclk: hclk.Hz(),
-            pclk1: pclk1.Hz(),
-            pclk2: pclk2.Hz(),
-            timclk1,
-            timclk2,
-            sysclk: sysclk.Hz(),
-            pll48clk: plls.pll48clk.map(Hertz::from_raw),
+        Ok(Clocks {
+            hclk: plan.hclk,
+            pclk1: plan.pclk1,
+            pclk2: plan.pclk2,
+            timclk1: plan.timclk1(),
+            timclk2: plan.timclk2(),
+            sysclk: plan.sysclk,
+            pll48clk: plan.pll48clk,
+            hse: self.hse.map(Hertz::from_raw),
+            pllclk: plan.pll.map(|pll| pll.pllsysclk(pllsrcclk).Hz()),
+            #[cfg(not(feature = "gpio-f410"))]
+            plli2sclk: plls.i2s_pll.plli2sclk.map(Hertz::from_raw),
 
             #[cfg(not(any(feature = "gpio-f412", feature = "gpio-f413", feature = "gpio-f446")))]
             i2s_clk: plls.i2s.i2s_clk.map(Hertz::from_raw),
@@ -957,27 +1175,390 @@ impl CFGR {
             sai1_clk: plls.sai.sai1_clk.map(Hertz::from_raw),
             #[cfg(feature = "sai2")]
             sai2_clk: plls.sai.sai2_clk.map(Hertz::from_raw),
-        };
 
-        if self.pll48clk {
-            assert!(clocks.is_pll48clk_valid());
+            #[cfg(feature = "ltdc")]
+            lcd_clk: plls.sai_pll.lcd_clk.map(Hertz::from_raw),
+        })
+    }
+}
+
+/// Polls `ready` forever, or at most `timeout` times.
+fn wait(
+    timeout: Option<u32>,
+    error: ClockError,
+    mut ready: impl FnMut() -> bool,
+) -> Result<(), ClockError> {
+    match timeout {
+        None => {
+            while !ready() {}
+            Ok(())
+        }
+        Some(timeout) => (0..timeout).any(|_| ready()).then(|| ()).ok_or(error),
+    }
+}
+
+/// Stops the main, I2S and SAI PLLs.
+fn stop_plls() {
+    let rcc = unsafe { &*RCC::ptr() };
+    rcc.cr.modify(|_, w| {
+        #[cfg(not(feature = "gpio-f410"))]
+        w.plli2son().clear_bit();
+        #[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469"))]
+        w.pllsaion().clear_bit();
+        w.pllon().clear_bit()
+    });
+}
+
+/// Leaves over-drive mode, the system clock must not come from the PLL.
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469"))]
+fn overdrive_off() {
+    let pwr = unsafe { &*crate::pac::PWR::ptr() };
+    pwr.cr.modify(|_, w| w.odswen().clear_bit());
+    pwr.cr.modify(|_, w| w.oden().clear_bit());
+}
+
+/// Clock registers saved before a change, to go back to when it fails halfway
+struct SavedClocks {
+    cr: rcc::cr::R,
+    cfgr: u32,
+    pllcfgr: u32,
+    #[cfg(not(feature = "gpio-f410"))]
+    plli2scfgr: u32,
+    #[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469"))]
+    pllsaicfgr: u32,
+    #[cfg(not(feature = "gpio-f417"))]
+    dckcfgr: u32,
+    #[cfg(any(
+        feature = "gpio-f410",
+        feature = "gpio-f412",
+        feature = "gpio-f413",
+        feature = "gpio-f446"
+    ))]
+    dckcfgr2: u32,
+    pwr_cr: pac::pwr::cr::R,
+    acr: u32,
+}
+
+impl SavedClocks {
+    fn save() -> Self {
+        let rcc = unsafe { &*RCC::ptr() };
+        let pwr = unsafe { &*pac::PWR::ptr() };
+        let flash = unsafe { &*pac::FLASH::ptr() };
+
+        // PWR reads as zero while its clock is off
+        rcc.apb1enr.modify(|_, w| w.pwren().set_bit());
+        cortex_m::asm::dsb();
+
+        Self {
+            cr: rcc.cr.read(),
+            cfgr: rcc.cfgr.read().bits(),
+            pllcfgr: rcc.pllcfgr.read().bits(),
+            #[cfg(not(feature = "gpio-f410"))]
+            plli2scfgr: rcc.plli2scfgr.read().bits(),
+            #[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469"))]
+            pllsaicfgr: rcc.pllsaicfgr.read().bits(),
+            #[cfg(not(feature = "gpio-f417"))]
+            dckcfgr: rcc.dckcfgr.read().bits(),
+            #[cfg(any(
+                feature = "gpio-f410",
+                feature = "gpio-f412",
+                feature = "gpio-f413",
+                feature = "gpio-f446"
+            ))]
+            dckcfgr2: rcc.dckcfgr2.read().bits(),
+            pwr_cr: pwr.cr.read(),
+            acr: flash.acr.read().bits(),
+        }
+    }
+
+    /// Goes back to the saved clocks. If an oscillator or PLL does not start again within
+    /// [`STARTUP_TIMEOUT`], the system clock stays on the HSI.
+    fn restore(&self) {
+        let rcc = unsafe { &*RCC::ptr() };
+        let pwr = unsafe { &*pac::PWR::ptr() };
+        let flash = unsafe { &*pac::FLASH::ptr() };
+        let timeout = Some(STARTUP_TIMEOUT);
+
+        rcc.cr.modify(|_, w| w.hsion().set_bit());
+        while rcc.cr.read().hsirdy().bit_is_clear() {}
+        rcc.cfgr.modify(|_, w| w.sw().variant(SW_A::Hsi));
+        while rcc.cfgr.read().sws().bits() != 0 {}
+
+        stop_plls();
+        #[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469"))]
+        overdrive_off();
+
+        unsafe {
+            rcc.pllcfgr.write(|w| w.bits(self.pllcfgr));
+            #[cfg(not(feature = "gpio-f410"))]
+            rcc.plli2scfgr.write(|w| w.bits(self.plli2scfgr));
+            #[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469"))]
+            rcc.pllsaicfgr.write(|w| w.bits(self.pllsaicfgr));
+            #[cfg(not(feature = "gpio-f417"))]
+            rcc.dckcfgr.write(|w| w.bits(self.dckcfgr));
+            #[cfg(any(
+                feature = "gpio-f410",
+                feature = "gpio-f412",
+                feature = "gpio-f413",
+                feature = "gpio-f446"
+            ))]
+            rcc.dckcfgr2.write(|w| w.bits(self.dckcfgr2));
+        }
+
+        // HSEBYP can only be changed while the HSE is off
+        if !self.cr.hseon().bit() || rcc.cr.read().hsebyp().bit() != self.cr.hsebyp().bit() {
+            rcc.cr.modify(|_, w| w.hseon().clear_bit());
+            while rcc.cr.read().hserdy().bit_is_set() {}
+            rcc.cr.modify(|_, w| w.hsebyp().bit(self.cr.hsebyp().bit()));
+        }
+        if self.cr.hseon().bit() {
+            rcc.cr.modify(|_, w| w.hseon().set_bit());
+            if wait(timeout, ClockError::HseTimeout, || {
+                rcc.cr.read().hserdy().bit_is_set()
+            })
+            .is_err()
+            {
+                return;
+            }
+        }
+
+        // Voltage scale, over-drive stays off until the PLL runs
+        const VOS: u32 = 0b11 << 14;
+        pwr.cr
+            .modify(|r, w| unsafe { w.bits((r.bits() & !VOS) | (self.pwr_cr.bits() & VOS)) });
+
+        if self.cr.pllon().bit() {
+            rcc.cr.modify(|_, w| w.pllon().set_bit());
+            #[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469"))]
+            if self.pwr_cr.oden().bit() {
+                pwr.cr.modify(|_, w| w.oden().set_bit());
+                if wait(timeout, ClockError::OverdriveTimeout, || {
+                    pwr.csr.read().odrdy().bit_is_set()
+                })
+                .is_err()
+                {
+                    return;
+                }
+                pwr.cr
+                    .modify(|_, w| w.odswen().bit(self.pwr_cr.odswen().bit()));
+                if wait(timeout, ClockError::OverdriveTimeout, || {
+                    !self.pwr_cr.odswen().bit() || pwr.csr.read().odswrdy().bit_is_set()
+                })
+                .is_err()
+                {
+                    return;
+                }
+            }
+            if wait(timeout, ClockError::PllTimeout, || {
+                rcc.cr.read().pllrdy().bit_is_set()
+            })
+            .is_err()
+            {
+                return;
+            }
         }
 
-        clocks
+        #[cfg(not(feature = "gpio-f410"))]
+        if self.cr.plli2son().bit() {
+            rcc.cr.modify(|_, w| w.plli2son().set_bit());
+            if wait(timeout, ClockError::PllTimeout, || {
+                rcc.cr.read().plli2srdy().bit_is_set()
+            })
+            .is_err()
+            {
+                return;
+            }
+        }
+
+        #[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469"))]
+        if self.cr.pllsaion().bit() {
+            rcc.cr.modify(|_, w| w.pllsaion().set_bit());
+            if wait(timeout, ClockError::PllTimeout, || {
+                rcc.cr.read().pllsairdy().bit_is_set()
+            })
+            .is_err()
+            {
+                return;
+            }
+        }
+
+        // More wait states before the clock gets faster, fewer only after it got slower
+        let latency = |acr: u32| acr & 0xf;
+        if latency(self.acr) > latency(flash.acr.read().bits()) {
+            flash.acr.write(|w| unsafe { w.bits(self.acr) });
+        }
+
+        // Prescalers first, then the system clock source
+        const SW: u32 = 0b11;
+        rcc.cfgr.write(|w| unsafe { w.bits(self.cfgr & !SW) });
+        cortex_m::asm::delay(16);
+        rcc.cfgr.write(|w| unsafe { w.bits(self.cfgr) });
+        while rcc.cfgr.read().sws().bits() as u32 != self.cfgr & SW {}
+
+        flash.acr.write(|w| unsafe { w.bits(self.acr) });
+    }
+}
+
+/// Returns the smallest APB prescaler giving at most `pclk`.
+const fn apb_prescaler(hclk: u32, pclk: u32) -> u8 {
+    match (hclk + pclk - 1) / pclk {
+        0 => unreachable!(),
+        1 => 1,
+        2 => 2,
+        3..=5 => 4,
+        6..=11 => 8,
+        _ => 16,
     }
 }
 
+const fn ppre_bits(ppre: u8) -> u8 {
+    match ppre {
+        1 => 0b000,
+        2 => 0b100,
+        4 => 0b101,
+        8 => 0b110,
+        _ => 0b111,
+    }
+}
+
+/// Polls of a ready flag before [`CFGR::try_freeze`] gives up
+pub const STARTUP_TIMEOUT: u32 = 0x5000;
+
+/// Reason why the requested clocks can not be generated or enabled
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ClockError {
+    /// The PLL source can not be divided to 1..2 MHz or the VCO output is not within
+    /// [`VCO_MIN`] and [`VCO_MAX`]
+    VcoRange,
+    /// The system clock is not within [`SYSCLK_MIN`] and [`SYSCLK_MAX`]
+    SysclkRange(Hertz),
+    /// APB1 clock above [`PCLK1_MAX`]
+    Pclk1Limit(Hertz),
+    /// APB2 clock above [`PCLK2_MAX`]
+    Pclk2Limit(Hertz),
+    /// The 48 MHz clock deviates by more than the 0.25% allowed for USB
+    Pll48clkAccuracy(Hertz),
+    /// The HSE did not become ready
+    HseTimeout,
+    /// A PLL did not become ready
+    PllTimeout,
+    /// The over-drive did not become ready
+    OverdriveTimeout,
+    /// The I2S, SAI or LCD-TFT clock can not be generated by its PLL, or peripherals sharing a
+    /// PLL output request different frequencies
+    PeripheralClock,
+}
+
+/// System clock source
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SysclkSource {
+    Hsi,
+    Hse,
+    Pll,
+}
+
+/// Regulator voltage scaling, lower scales limit HCLK but save power
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum VoltageScale {
+    Scale1,
+    Scale2,
+    Scale3,
+}
+
+impl VoltageScale {
+    /// Returns the lowest scale supporting `hclk`.
+    pub const fn for_hclk(hclk: u32) -> Self {
+        #[cfg(feature = "gpio-f401")]
+        let (scale3_max, scale2_max) = (60_000_000, u32::MAX);
+        #[cfg(any(
+            feature = "gpio-f410",
+            feature = "gpio-f411",
+            feature = "gpio-f412",
+            feature = "gpio-f413",
+        ))]
+        let (scale3_max, scale2_max) = (64_000_000, 84_000_000);
+        // Scale 3 does not exist
+        #[cfg(feature = "gpio-f417")]
+        let (scale3_max, scale2_max) = (0, 144_000_000);
+        #[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469"))]
+        let (scale3_max, scale2_max) = (120_000_000, 144_000_000);
+
+        if hclk <= scale3_max {
+            Self::Scale3
+        } else if hclk <= scale2_max {
+            Self::Scale2
+        } else {
+            Self::Scale1
+        }
+    }
+
+    /// Writes `PWR_CR.VOS`, which takes effect when the main PLL is enabled.
+    fn apply(self) {
+        #[cfg(feature = "gpio-f417")]
+        let (mask, bits) = (1, matches!(self, Self::Scale1) as u32);
+        #[cfg(not(feature = "gpio-f417"))]
+        let (mask, bits) = (
+            0b11,
+            match self {
+                Self::Scale1 => 0b11,
+                Self::Scale2 => 0b10,
+                Self::Scale3 => 0b01,
+            },
+        );
+        let pwr = unsafe { &*crate::pac::PWR::ptr() };
+        pwr.cr
+            .modify(|r, w| unsafe { w.bits((r.bits() & !(mask << 14)) | (bits << 14)) });
+    }
+}
+
+/// Clock tree computed by [`CFGR::plan`]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ClockPlan {
+    pub sysclk_source: SysclkSource,
+    /// Main PLL dividers, `None` if the main PLL stays off
+    pub pll: Option<PllConfig>,
+    pub sysclk: Hertz,
+    pub hclk: Hertz,
+    pub pclk1: Hertz,
+    pub pclk2: Hertz,
+    pub pll48clk: Option<Hertz>,
+    /// AHB prescaler
+    pub hpre: u16,
+    /// APB1 prescaler
+    pub ppre1: u8,
+    /// APB2 prescaler
+    pub ppre2: u8,
+    /// Flash wait states for 2.7 to 3.6 V
+    pub flash_latency: u8,
+    pub voltage_scale: VoltageScale,
+    pub overdrive: bool,
+}
+
+impl ClockPlan {
+    /// Returns the frequency for timers on APB1
+    pub fn timclk1(&self) -> Hertz {
+        self.pclk1 * if self.ppre1 == 1 { 1 } else { 2 }
+    }
+
+    /// Returns the frequency for timers on APB2
+    pub fn timclk2(&self) -> Hertz {
+        self.pclk2 * if self.ppre2 == 1 { 1 } else { 2 }
+    }
+}
+
+/// I2S, SAI and LCD-TFT clocks and PLLs chosen by [`CFGR::pll_setup`]
 struct PllSetup {
-    use_pll: bool,
     #[cfg(not(feature = "gpio-f410"))]
-    use_i2spll: bool,
+    i2s_pll: I2sPll,
     #[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469"))]
-    use_saipll: bool,
-
-    pllsysclk: Option<u32>,
-    pll48clk: Option<u32>,
+    sai_pll: SaiPll,
+    /// I2S PLL divider for the SAI clock
+    #[cfg(feature = "gpio-f413")]
+    plli2sdivr: Option<u8>,
 
     i2s: RealI2sClocks,
 
@@ -985,6 +1566,21 @@ struct PllSetup {
     sai: RealSaiClocks,
 }
 
+impl PllSetup {
+    /// Writes the PLL dividers, after the main PLL configuration which sets the PLL source.
+    fn apply(&self) {
+        #[cfg(not(feature = "gpio-f410"))]
+        self.i2s_pll.apply();
+        #[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469"))]
+        self.sai_pll.apply();
+        #[cfg(feature = "gpio-f413")]
+        if let Some(div) = self.plli2sdivr {
+            let rcc = unsafe { &*RCC::ptr() };
+            rcc.dckcfgr.modify(|_, w| w.plli2sdivr().bits(div));
+        }
+    }
+}
+
 #[cfg(any(feature = "gpio-f412", feature = "gpio-f413", feature = "gpio-f446"))]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
@@ -1178,6 +1774,10 @@ pub struct Clocks {
     timclk2: Hertz,
     sysclk: Hertz,
     pll48clk: Option<Hertz>,
+    hse: Option<Hertz>,
+    pllclk: Option<Hertz>,
+    #[cfg(not(feature = "gpio-f410"))]
+    plli2sclk: Option<Hertz>,
 
     #[cfg(not(any(feature = "gpio-f412", feature = "gpio-f413", feature = "gpio-f446")))]
     i2s_clk: Option<Hertz>,
@@ -1194,6 +1794,9 @@ pub struct Clocks {
     sai1_clk: Option<Hertz>,
     #[cfg(feature = "gpio-f446")]
     sai2_clk: Option<Hertz>,
+
+    #[cfg(feature = "ltdc")]
+    lcd_clk: Option<Hertz>,
 }
 
 impl Clocks {
@@ -1232,6 +1835,22 @@ impl Clocks {
         self.pll48clk
     }
 
+    /// Returns the frequency of the HSE, if enabled
+    pub fn hse(&self) -> Option<Hertz> {
+        self.hse
+    }
+
+    /// Returns the frequency of the main PLL system clock output, if enabled
+    pub fn pllclk(&self) -> Option<Hertz> {
+        self.pllclk
+    }
+
+    /// Returns the frequency of the I2S PLL output, if enabled
+    #[cfg(not(feature = "gpio-f410"))]
+    pub fn plli2sclk(&self) -> Option<Hertz> {
+        self.plli2sclk
+    }
+
     /// Returns true if the PLL48 clock is within USB
     /// specifications. It is required to use the USB functionality.
     pub fn is_pll48clk_valid(&self) -> bool {
@@ -1277,4 +1896,317 @@ impl Clocks {
     pub fn sai2_clk(&self) -> Option<Hertz> {
         self.sai2_clk
     }
+
+    /// Returns the frequency of the LCD-TFT clock.
+    #[cfg(feature = "ltdc")]
+    pub fn lcd_clk(&self) -> Option<Hertz> {
+        self.lcd_clk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HSE: u32 = 8_000_000;
+
+    #[test]
+    fn plan_hsi() {
+        let plan = CFGR::new().plan().unwrap();
+        assert_eq!(plan.sysclk_source, SysclkSource::Hsi);
+        assert_eq!(plan.pll, None);
+        assert_eq!(plan.sysclk, Hertz::from_raw(HSI));
+        assert_eq!(plan.pclk1, Hertz::from_raw(HSI));
+        assert_eq!(plan.pclk2, Hertz::from_raw(HSI));
+        assert_eq!(plan.flash_latency, 0);
+        assert!(!plan.overdrive);
+    }
+
+    #[test]
+    fn plan_sysclk_max() {
+        let plan = CFGR::new()
+            .use_hse(Hertz::from_raw(HSE))
+            .sysclk(Hertz::from_raw(SYSCLK_MAX))
+            .plan()
+            .unwrap();
+        assert_eq!(plan.sysclk_source, SysclkSource::Pll);
+        assert_eq!(plan.sysclk, Hertz::from_raw(SYSCLK_MAX));
+        assert_eq!(plan.hclk, Hertz::from_raw(SYSCLK_MAX));
+        assert_eq!(plan.hpre, 1);
+        assert_eq!(plan.pclk1, Hertz::from_raw(PCLK1_MAX));
+        assert_eq!(plan.pclk2, Hertz::from_raw(PCLK2_MAX));
+        assert_eq!(plan.voltage_scale, VoltageScale::for_hclk(SYSCLK_MAX));
+        assert_eq!(plan.overdrive, SYSCLK_MAX > 168_000_000);
+        let pll = plan.pll.unwrap();
+        assert!((VCO_MIN..=VCO_MAX).contains(&pll.vco_out(HSE)));
+    }
+
+    #[cfg(feature = "gpio-f401")]
+    #[test]
+    fn plan_f401() {
+        let plan = CFGR::new()
+            .use_hse(Hertz::MHz(25))
+            .sysclk(Hertz::MHz(84))
+            .require_pll48clk()
+            .plan()
+            .unwrap();
+        assert_eq!(plan.sysclk, Hertz::MHz(84));
+        assert_eq!(plan.pll48clk, Some(Hertz::MHz(48)));
+        assert_eq!((plan.ppre1, plan.ppre2), (2, 1));
+        assert_eq!(plan.flash_latency, 2);
+        assert_eq!(plan.voltage_scale, VoltageScale::Scale2);
+    }
+
+    #[cfg(any(
+        feature = "gpio-f410",
+        feature = "gpio-f411",
+        feature = "gpio-f412",
+        feature = "gpio-f413",
+    ))]
+    #[test]
+    fn plan_f41x() {
+        let plan = CFGR::new()
+            .use_hse(Hertz::from_raw(HSE))
+            .sysclk(Hertz::MHz(100))
+            .plan()
+            .unwrap();
+        assert_eq!(plan.sysclk, Hertz::MHz(100));
+        assert_eq!((plan.ppre1, plan.ppre2), (2, 1));
+        assert_eq!(plan.timclk1(), Hertz::MHz(100));
+        assert_eq!(plan.flash_latency, 3);
+        assert_eq!(plan.voltage_scale, VoltageScale::Scale1);
+    }
+
+    #[cfg(feature = "gpio-f417")]
+    #[test]
+    fn plan_f417() {
+        let plan = CFGR::new()
+            .use_hse(Hertz::from_raw(HSE))
+            .sysclk(Hertz::MHz(168))
+            .require_pll48clk()
+            .plan()
+            .unwrap();
+        assert_eq!(plan.sysclk, Hertz::MHz(168));
+        assert_eq!(plan.pll48clk, Some(Hertz::MHz(48)));
+        assert_eq!((plan.ppre1, plan.ppre2), (4, 2));
+        assert_eq!(
+            (plan.timclk1(), plan.timclk2()),
+            (Hertz::MHz(84), Hertz::MHz(168))
+        );
+        assert_eq!(plan.flash_latency, 5);
+        assert_eq!(plan.voltage_scale, VoltageScale::Scale1);
+        assert!(!plan.overdrive);
+    }
+
+    #[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469"))]
+    #[test]
+    fn plan_overdrive() {
+        let plan = CFGR::new()
+            .use_hse(Hertz::from_raw(HSE))
+            .sysclk(Hertz::MHz(180))
+            .plan()
+            .unwrap();
+        assert_eq!(plan.sysclk, Hertz::MHz(180));
+        assert_eq!((plan.ppre1, plan.ppre2), (4, 2));
+        assert_eq!(plan.flash_latency, 5);
+        assert!(plan.overdrive);
+
+        let plan = CFGR::new()
+            .use_hse(Hertz::from_raw(HSE))
+            .sysclk(Hertz::MHz(168))
+            .plan()
+            .unwrap();
+        assert!(!plan.overdrive);
+    }
+
+    #[test]
+    fn plan_errors() {
+        assert!(matches!(
+            CFGR::new()
+                .use_hse(Hertz::from_raw(HSE))
+                .sysclk(Hertz::from_raw(SYSCLK_MAX + 8_000_000))
+                .plan(),
+            Err(ClockError::SysclkRange(_))
+        ));
+        assert_eq!(
+            CFGR::new()
+                .use_hse(Hertz::from_raw(HSE))
+                .sysclk(Hertz::from_raw(SYSCLK_MAX))
+                .pclk1(Hertz::from_raw(SYSCLK_MAX))
+                .plan(),
+            Err(ClockError::Pclk1Limit(Hertz::from_raw(SYSCLK_MAX)))
+        );
+        if PCLK2_MAX < SYSCLK_MAX {
+            assert_eq!(
+                CFGR::new()
+                    .use_hse(Hertz::from_raw(HSE))
+                    .sysclk(Hertz::from_raw(SYSCLK_MAX))
+                    .pclk2(Hertz::from_raw(SYSCLK_MAX))
+                    .plan(),
+                Err(ClockError::Pclk2Limit(Hertz::from_raw(SYSCLK_MAX)))
+            );
+        }
+        // The VCO would have to run above 432 MHz
+        assert_eq!(
+            CFGR::new()
+                .use_hse(Hertz::from_raw(HSE))
+                .sysclk(Hertz::MHz(300))
+                .plan(),
+            Err(ClockError::VcoRange)
+        );
+    }
+
+    #[test]
+    fn fast_plan() {
+        assert_eq!(MainPll::fast_plan(HSE, None, false), Ok(None));
+
+        let pll = MainPll::fast_plan(HSE, Some(SYSCLK_MAX), false)
+            .unwrap()
+            .unwrap();
+        assert!((1_000_000..=2_000_000).contains(&(HSE / u32::from(pll.m))));
+        assert!((VCO_MIN..=VCO_MAX).contains(&pll.vco_out(HSE)));
+        assert_eq!(pll.pllsysclk(HSE), SYSCLK_MAX);
+
+        let pll = MainPll::fast_plan(HSE, None, true).unwrap().unwrap();
+        assert_eq!(pll.pll48clk(HSE), 48_000_000);
+
+        assert_eq!(
+            MainPll::fast_plan(HSE, Some(300_000_000), false),
+            Err(ClockError::VcoRange)
+        );
+    }
+
+    #[cfg(not(feature = "gpio-f401"))]
+    #[test]
+    fn fast_plan_pll48clk() {
+        let pll = MainPll::fast_plan(HSE, Some(96_000_000), true)
+            .unwrap()
+            .unwrap();
+        assert_eq!(pll.pllsysclk(HSE), 96_000_000);
+        assert_eq!(pll.pll48clk(HSE), 48_000_000);
+    }
+
+    #[cfg(feature = "gpio-f410")]
+    #[test]
+    fn plan_with_i2s() {
+        // All outputs exact with a 384 MHz VCO
+        let pll = MainPll::plan_with_i2s(HSE, Some(96_000_000), true, 96_000_000)
+            .unwrap()
+            .unwrap();
+        assert_eq!(pll.pllsysclk(HSE), 96_000_000);
+        assert_eq!(pll.pll48clk(HSE), 48_000_000);
+        assert_eq!(pll.plli2sclk(HSE), Some(96_000_000));
+        assert!((VCO_MIN..=VCO_MAX).contains(&pll.vco_out(HSE)));
+
+        let pll = MainPll::plan_with_i2s(HSE, Some(100_000_000), false, 50_000_000)
+            .unwrap()
+            .unwrap();
+        assert_eq!(pll.pllsysclk(HSE), 100_000_000);
+        assert_eq!(pll.plli2sclk(HSE), Some(50_000_000));
+
+        // Only the I2S clock, the system clock stays on the PLL source
+        let pll = MainPll::plan_with_i2s(HSE, None, false, 86_000_000)
+            .unwrap()
+            .unwrap();
+        assert_eq!(pll.plli2sclk(HSE), Some(86_000_000));
+    }
+
+    #[cfg(not(feature = "gpio-f410"))]
+    #[test]
+    fn i2s_pll() {
+        let pll = I2sPll::plan(HSE, Some(86_000_000)).unwrap();
+        assert!(pll.use_pll);
+        assert_eq!(pll.plli2sclk, Some(86_000_000));
+        assert!(!I2sPll::plan(HSE, None).unwrap().use_pll);
+    }
+
+    #[cfg(feature = "gpio-f446")]
+    #[test]
+    fn i2s_clocks_conflict() {
+        let cfgr = CFGR::new()
+            .use_hse(Hertz::from_raw(HSE))
+            .i2s_apb1_clk(Hertz::MHz(86))
+            .i2s_apb2_clk(Hertz::MHz(96));
+        assert!(matches!(
+            cfgr.pll_setup(HSE, None),
+            Err(ClockError::PeripheralClock)
+        ));
+    }
+
+    #[cfg(feature = "ltdc")]
+    #[test]
+    fn lcd_pll() {
+        // 2 MHz VCO input, exact with a 144 MHz VCO
+        assert_eq!(
+            SaiPll::optimize_lcd(2_000_000, 50..=432, 9_000_000),
+            Some((72, 2, 8, 9_000_000))
+        );
+        // "N" fixed by the SAI clock
+        assert_eq!(
+            SaiPll::optimize_lcd(2_000_000, 192..=192, 9_000_000),
+            Some((192, 5, 8, 9_600_000))
+        );
+        // The VCO can not run fast enough
+        assert_eq!(SaiPll::optimize_lcd(2_000_000, 50..=432, 500_000_000), None);
+
+        let mut pll = SaiPll::unused();
+        pll.plan_lcd(HSE, None, Some(9_000_000)).unwrap();
+        assert!(pll.use_pll);
+        assert_eq!(pll.lcd_clk, Some(9_000_000));
+        assert_eq!(
+            SaiPll::unused().plan_lcd(HSE, None, Some(500_000_000)),
+            Err(ClockError::PeripheralClock)
+        );
+    }
+
+    #[test]
+    fn apb_prescalers() {
+        assert_eq!(apb_prescaler(16_000_000, 16_000_000), 1);
+        assert_eq!(apb_prescaler(84_000_000, 42_000_000), 2);
+        assert_eq!(apb_prescaler(100_000_000, 50_000_000), 2);
+        // Rounded up to the next prescaler
+        assert_eq!(apb_prescaler(100_000_000, 42_000_000), 4);
+        assert_eq!(apb_prescaler(168_000_000, 42_000_000), 4);
+        assert_eq!(apb_prescaler(168_000_000, 30_000_000), 8);
+        assert_eq!(apb_prescaler(180_000_000, 1_000_000), 16);
+    }
+
+    #[cfg(feature = "gpio-f401")]
+    #[test]
+    fn voltage_scale() {
+        assert_eq!(VoltageScale::for_hclk(60_000_000), VoltageScale::Scale3);
+        assert_eq!(VoltageScale::for_hclk(60_000_001), VoltageScale::Scale2);
+        assert_eq!(VoltageScale::for_hclk(84_000_000), VoltageScale::Scale2);
+    }
+
+    #[cfg(any(
+        feature = "gpio-f410",
+        feature = "gpio-f411",
+        feature = "gpio-f412",
+        feature = "gpio-f413",
+    ))]
+    #[test]
+    fn voltage_scale() {
+        assert_eq!(VoltageScale::for_hclk(64_000_000), VoltageScale::Scale3);
+        assert_eq!(VoltageScale::for_hclk(64_000_001), VoltageScale::Scale2);
+        assert_eq!(VoltageScale::for_hclk(84_000_000), VoltageScale::Scale2);
+        assert_eq!(VoltageScale::for_hclk(84_000_001), VoltageScale::Scale1);
+    }
+
+    #[cfg(feature = "gpio-f417")]
+    #[test]
+    fn voltage_scale() {
+        assert_eq!(VoltageScale::for_hclk(16_000_000), VoltageScale::Scale2);
+        assert_eq!(VoltageScale::for_hclk(144_000_000), VoltageScale::Scale2);
+        assert_eq!(VoltageScale::for_hclk(144_000_001), VoltageScale::Scale1);
+    }
+
+    #[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469"))]
+    #[test]
+    fn voltage_scale() {
+        assert_eq!(VoltageScale::for_hclk(120_000_000), VoltageScale::Scale3);
+        assert_eq!(VoltageScale::for_hclk(120_000_001), VoltageScale::Scale2);
+        assert_eq!(VoltageScale::for_hclk(144_000_000), VoltageScale::Scale2);
+        assert_eq!(VoltageScale::for_hclk(144_000_001), VoltageScale::Scale1);
+    }
 }
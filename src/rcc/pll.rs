@@ -1,37 +1,74 @@
+use super::{ClockError, VCO_MAX, VCO_MIN};
 use crate::pac::RCC;
 
-pub struct MainPll {
-    pub use_pll: bool,
-    pub pllsysclk: Option<u32>,
-    pub pll48clk: Option<u32>,
-    /// "M" divisor, required for the other PLLs on some MCUs.
-    pub m: Option<u32>,
-    /// "R" output, required for I2S on STM32F410.
-    pub plli2sclk: Option<u32>,
+/// Main PLL dividers
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct PllConfig {
+    /// Input divider, the VCO input must be within 1 and 2 MHz
+    pub m: u8,
+    /// VCO multiplier
+    pub n: u16,
+    /// System clock divider (2, 4, 6 or 8)
+    pub p: u8,
+    /// 48 MHz clock divider
+    pub q: u8,
+    /// I2S clock divider on STM32F410
+    pub r: Option<u8>,
 }
 
+impl PllConfig {
+    /// Returns the VCO output frequency.
+    pub const fn vco_out(&self, pllsrcclk: u32) -> u32 {
+        pllsrcclk / self.m as u32 * self.n as u32
+    }
+
+    /// Returns the frequency of the system clock output.
+    pub const fn pllsysclk(&self, pllsrcclk: u32) -> u32 {
+        self.vco_out(pllsrcclk) / self.p as u32
+    }
+
+    /// Returns the frequency of the 48 MHz clock output.
+    pub const fn pll48clk(&self, pllsrcclk: u32) -> u32 {
+        self.vco_out(pllsrcclk) / self.q as u32
+    }
+
+    /// Returns the frequency of the I2S clock output.
+    pub fn plli2sclk(&self, pllsrcclk: u32) -> Option<u32> {
+        self.r.map(|r| self.vco_out(pllsrcclk) / u32::from(r))
+    }
+
+    /// Writes the dividers, or only the PLL source if the main PLL is unused.
+    pub(crate) fn apply(pll: Option<&Self>, use_hse: bool) {
+        // Even if we do not use the main PLL, we still need to set the PLL source as that setting
+        // applies to the I2S and SAI PLLs as well.
+        unsafe { &*RCC::ptr() }.pllcfgr.write(|w| unsafe {
+            if let Some(pll) = pll {
+                w.pllm().bits(pll.m);
+                w.plln().bits(pll.n);
+                w.pllp().bits(pll.p / 2 - 1);
+                w.pllq().bits(pll.q);
+                #[cfg(feature = "gpio-f410")]
+                if let Some(pllr) = pll.r {
+                    w.pllr().bits(pllr);
+                }
+            }
+            w.pllsrc().bit(use_hse)
+        });
+    }
+}
+
+pub struct MainPll;
+
 impl MainPll {
-    pub fn fast_setup(
+    pub fn fast_plan(
         pllsrcclk: u32,
-        use_hse: bool,
         pllsysclk: Option<u32>,
         pll48clk: bool,
-    ) -> MainPll {
+    ) -> Result<Option<PllConfig>, ClockError> {
         let sysclk = pllsysclk.unwrap_or(pllsrcclk);
         if pllsysclk.is_none() && !pll48clk {
-            // Even if we do not use the main PLL, we still need to set the PLL source as that setting
-            // applies to the I2S and SAI PLLs as well.
-            unsafe { &*RCC::ptr() }
-                .pllcfgr
-                .write(|w| w.pllsrc().bit(use_hse));
-
-            return MainPll {
-                use_pll: false,
-                pllsysclk: None,
-                pll48clk: None,
-                m: None,
-                plli2sclk: None,
-            };
+            return Ok(None);
         }
         // Input divisor from PLL source clock, must result to frequency in
         // the range from 1 to 2 MHz
@@ -39,7 +76,10 @@ impl MainPll {
         let pllm_max = pllsrcclk / 1_000_000;
 
         // Sysclk output divisor must be one of 2, 4, 6 or 8
-        let sysclk_div = core::cmp::min(8, (432_000_000 / sysclk) & !1);
+        let sysclk_div = core::cmp::min(8, (VCO_MAX / sysclk) & !1);
+        if sysclk_div == 0 {
+            return Err(ClockError::VcoRange);
+        }
 
         let target_freq = if pll48clk {
             48_000_000
@@ -55,10 +95,9 @@ impl MainPll {
                 let plln = target_freq / vco_in;
                 target_freq - vco_in * plln
             })
-            .unwrap();
+            .ok_or(ClockError::VcoRange)?;
 
         let vco_in = pllsrcclk / pllm;
-        assert!((1_000_000..=2_000_000).contains(&vco_in));
 
         // Main scaler, must result in >= 100MHz (>= 192MHz for F401)
         // and <= 432MHz, min 50, max 432
@@ -77,38 +116,25 @@ impl MainPll {
         } else {
             sysclk * sysclk_div / vco_in
         };
-        let pllp = (sysclk_div / 2) - 1;
 
         let pllq = (vco_in * plln + 47_999_999) / 48_000_000;
-        let real_pll48clk = vco_in * plln / pllq;
-
-        unsafe { &*RCC::ptr() }.pllcfgr.write(|w| unsafe {
-            w.pllm().bits(pllm as u8);
-            w.plln().bits(plln as u16);
-            w.pllp().bits(pllp as u8);
-            w.pllq().bits(pllq as u8);
-            w.pllsrc().bit(use_hse)
-        });
-
-        let real_pllsysclk = vco_in * plln / sysclk_div;
 
-        MainPll {
-            use_pll: true,
-            pllsysclk: Some(real_pllsysclk),
-            pll48clk: if pll48clk { Some(real_pll48clk) } else { None },
-            m: Some(pllm),
-            plli2sclk: None,
-        }
+        Ok(Some(PllConfig {
+            m: pllm as u8,
+            n: plln as u16,
+            p: sysclk_div as u8,
+            q: pllq as u8,
+            r: None,
+        }))
     }
 
     #[cfg(feature = "gpio-f410")]
-    pub fn setup_with_i2s(
+    pub fn plan_with_i2s(
         pllsrcclk: u32,
-        use_hse: bool,
         pllsysclk: Option<u32>,
         pll48clk: bool,
         plli2sclk: u32,
-    ) -> MainPll {
+    ) -> Result<Option<PllConfig>, ClockError> {
         use super::{SYSCLK_MAX, SYSCLK_MIN};
 
         // Input divisor from PLL source clock, must result to frequency in
@@ -121,8 +147,8 @@ impl MainPll {
                 let vco_in = pllsrcclk / m;
 
                 // The VCO output must be within 100 and 432 MHz.
-                let plln_min = (100_000_000 + vco_in - 1) / vco_in;
-                let plln_max = 432_000_000 / vco_in;
+                let plln_min = (VCO_MIN + vco_in - 1) / vco_in;
+                let plln_max = VCO_MAX / vco_in;
 
                 (plln_min..=plln_max)
                     .filter_map(|n| {
@@ -144,9 +170,13 @@ impl MainPll {
                         };
 
                         // The 48 MHz clock must be accurate within 0.25% for USB.
-                        let q = pll48clk.then_some(Self::best_divider(
-                            vco_out, 47_880_000, 48_000_000, 48_120_000, 2, 15,
-                        )?);
+                        let q = if pll48clk {
+                            Some(Self::best_divider(
+                                vco_out, 47_880_000, 48_000_000, 48_120_000, 2, 15,
+                            )?)
+                        } else {
+                            None
+                        };
 
                         // We do not set any accuracy requirements for I2S, as on F410 this frequency is
                         // provided on a best-effort basis.
@@ -154,7 +184,7 @@ impl MainPll {
                         let r = Self::best_divider(vco_out, 0, plli2sclk, u32::MAX, 2, 15)?;
 
                         let error = p.map(|(_, _, error)| error).unwrap_or(0)
-                            + p.map(|(_, _, error)| error).unwrap_or(0)
+                            + q.map(|(_, _, error)| error).unwrap_or(0)
                             + r.2;
 
                         Some((m, n, p.map(|p| p.0), q.map(|q| q.0), r.0, error))
@@ -162,31 +192,16 @@ impl MainPll {
                     .min_by_key(|(_, _, _, _, _, error)| *error)
             })
             .min_by_key(|(_, _, _, _, _, error)| *error)
-            .expect("could not find a valid main PLL configuration");
-
-        unsafe { &*RCC::ptr() }.pllcfgr.write(|w| unsafe {
-            w.pllm().bits(pllm as u8);
-            w.plln().bits(plln as u16);
-            if let Some(pllp) = pllp {
-                w.pllp().bits(pllp as u8 / 2 - 1);
-            }
-            if let Some(pllq) = pllq {
-                w.pllq().bits(pllq as u8);
-            }
-            w.pllr().bits(pllr as u8);
-            w.pllsrc().bit(use_hse)
-        });
-
-        let real_pllsysclk = pllp.map(|pllp| pllsrcclk / pllm * plln / pllp);
-        let real_pll48clk = pllq.map(|pllq| pllsrcclk / pllm * plln / pllq);
-
-        MainPll {
-            use_pll: true,
-            pllsysclk: real_pllsysclk,
-            pll48clk: real_pll48clk,
-            m: Some(pllm),
-            plli2sclk: None,
-        }
+            .ok_or(ClockError::VcoRange)?;
+
+        // Unused outputs keep their reset dividers
+        Ok(Some(PllConfig {
+            m: pllm as u8,
+            n: plln as u16,
+            p: pllp.unwrap_or(2) as u8,
+            q: pllq.unwrap_or(4) as u8,
+            r: Some(pllr as u8),
+        }))
     }
 
     #[cfg(feature = "gpio-f410")]
@@ -199,22 +214,14 @@ impl MainPll {
         max_div: u32,
     ) -> Option<(u32, u32, u32)> {
         let div = (vco_out + target / 2) / target;
-        let min_div = u32::max(
-            min_div,
-            if max != 0 {
-                (vco_out + max - 1) / max
-            } else {
-                0
-            },
-        );
-        let max_div = u32::min(max_div, if min != 0 { vco_out / min } else { u32::MAX });
+        let min_div = u32::max(min_div, (vco_out - 1).checked_div(max).map_or(0, |d| d + 1));
+        let max_div = u32::min(max_div, vco_out.checked_div(min).unwrap_or(u32::MAX));
         if min_div > max_div {
             return None;
         }
         let div = u32::min(u32::max(div, min_div), max_div);
         let output = vco_out / div;
-        let error = (output as i32 - target as i32).abs() as u32;
-        Some((div, output, error))
+        Some((div, output, output.abs_diff(target)))
     }
 }
 
@@ -225,6 +232,8 @@ pub struct I2sPll {
     pub m: Option<u32>,
     /// PLL I2S clock output.
     pub plli2sclk: Option<u32>,
+    /// Dividers written by [`Self::apply`]
+    config: Option<SingleOutputPll>,
 }
 
 #[cfg(not(feature = "gpio-f410"))]
@@ -234,23 +243,24 @@ impl I2sPll {
             use_pll: false,
             m: None,
             plli2sclk: None,
+            config: None,
         }
     }
 
-    pub fn setup(pllsrcclk: u32, plli2sclk: Option<u32>) -> I2sPll {
+    /// Chooses the dividers closest to `plli2sclk` without touching the hardware.
+    pub fn plan(pllsrcclk: u32, plli2sclk: Option<u32>) -> Result<I2sPll, ClockError> {
         let Some(target) = plli2sclk else {
-            return Self::unused();
+            return Ok(Self::unused());
         };
         // Input divisor from PLL source clock, must result to frequency in
         // the range from 1 to 2 MHz
         let pllm_min = (pllsrcclk + 1_999_999) / 2_000_000;
         let pllm_max = pllsrcclk / 1_000_000;
-        let (pll, config, _) = (pllm_min..=pllm_max)
-            .map(|m| Self::optimize_fixed_m(pllsrcclk, m, target))
-            .min_by_key(|(_, _, error)| *error)
-            .expect("no suitable I2S PLL configuration found");
-        Self::apply_config(config);
-        pll
+        (pllm_min..=pllm_max)
+            .filter_map(|m| Self::optimize_fixed_m(pllsrcclk, m, target))
+            .min_by_key(|(_, error)| *error)
+            .map(|(pll, _)| pll)
+            .ok_or(ClockError::PeripheralClock)
     }
 
     #[cfg(any(
@@ -259,32 +269,42 @@ impl I2sPll {
         feature = "gpio-f427",
         feature = "gpio-f469",
     ))]
-    pub fn setup_shared_m(pllsrcclk: u32, m: Option<u32>, plli2sclk: Option<u32>) -> I2sPll {
+    pub fn plan_shared_m(
+        pllsrcclk: u32,
+        m: Option<u32>,
+        plli2sclk: Option<u32>,
+    ) -> Result<I2sPll, ClockError> {
         // "m" is None if the main PLL is not in use.
         let Some(m) = m else {
-            return Self::setup(pllsrcclk, plli2sclk);
+            return Self::plan(pllsrcclk, plli2sclk);
         };
         let Some(target) = plli2sclk else {
-            return Self::unused();
+            return Ok(Self::unused());
         };
-        let (pll, config, _) = Self::optimize_fixed_m(pllsrcclk, m, target);
-        Self::apply_config(config);
-        pll
+        Self::optimize_fixed_m(pllsrcclk, m, target)
+            .map(|(pll, _)| pll)
+            .ok_or(ClockError::PeripheralClock)
     }
 
-    fn optimize_fixed_m(pllsrcclk: u32, m: u32, plli2sclk: u32) -> (I2sPll, SingleOutputPll, u32) {
+    fn optimize_fixed_m(pllsrcclk: u32, m: u32, plli2sclk: u32) -> Option<(I2sPll, u32)> {
         let (config, real_plli2sclk, error) =
-            SingleOutputPll::optimize(pllsrcclk, m, plli2sclk, 2, 7)
-                .expect("did not find any valid I2S PLL config");
-        (
+            SingleOutputPll::optimize(pllsrcclk, m, plli2sclk, 2, 7)?;
+        Some((
             I2sPll {
                 use_pll: true,
                 m: Some(config.m as u32),
                 plli2sclk: Some(real_plli2sclk),
+                config: Some(config),
             },
-            config,
             error,
-        )
+        ))
+    }
+
+    /// Writes the planned dividers, if the PLL is used.
+    pub fn apply(&self) {
+        if let Some(config) = &self.config {
+            Self::apply_config(config);
+        }
     }
 
     #[cfg(not(any(
@@ -293,7 +313,7 @@ impl I2sPll {
         feature = "gpio-f413",
         feature = "gpio-f446",
     )))]
-    fn apply_config(config: SingleOutputPll) {
+    fn apply_config(config: &SingleOutputPll) {
         let rcc = unsafe { &*RCC::ptr() };
         // "M" may have been written before, but the value is identical.
         rcc.pllcfgr
@@ -307,7 +327,7 @@ impl I2sPll {
         feature = "gpio-f413",
         feature = "gpio-f446",
     ))]
-    fn apply_config(config: SingleOutputPll) {
+    fn apply_config(config: &SingleOutputPll) {
         let rcc = unsafe { &*RCC::ptr() };
         rcc.plli2scfgr.modify(|_, w| unsafe {
             w.plli2sm().bits(config.m);
@@ -322,6 +342,23 @@ pub struct SaiPll {
     pub use_pll: bool,
     /// SAI clock (PLL output divided by the SAI clock divider).
     pub sai_clk: Option<u32>,
+    /// LCD-TFT clock (PLL "R" output divided by the LCD clock divider).
+    #[cfg(feature = "ltdc")]
+    pub lcd_clk: Option<u32>,
+    /// Dividers written by [`Self::apply`]
+    config: Option<SaiPllConfig>,
+}
+
+#[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469",))]
+#[derive(Clone, Copy)]
+struct SaiPllConfig {
+    m: u8,
+    n: u16,
+    /// "Q" and the SAI clock divider
+    q: Option<(u8, u8)>,
+    /// "R" and the LCD clock divider
+    #[cfg(feature = "ltdc")]
+    r: Option<(u8, u8)>,
 }
 
 #[cfg(any(feature = "gpio-f427", feature = "gpio-f446", feature = "gpio-f469",))]
@@ -330,91 +367,192 @@ impl SaiPll {
         SaiPll {
             use_pll: false,
             sai_clk: None,
+            #[cfg(feature = "ltdc")]
+            lcd_clk: None,
+            config: None,
         }
     }
 
-    pub fn setup(pllsrcclk: u32, sai_clk: Option<u32>) -> SaiPll {
+    /// Chooses the dividers closest to `sai_clk` without touching the hardware.
+    pub fn plan(pllsrcclk: u32, sai_clk: Option<u32>) -> Result<SaiPll, ClockError> {
         let Some(target) = sai_clk else {
-            return Self::unused();
+            return Ok(Self::unused());
         };
         // Input divisor from PLL source clock, must result to frequency in
         // the range from 1 to 2 MHz
         let pllm_min = (pllsrcclk + 1_999_999) / 2_000_000;
         let pllm_max = pllsrcclk / 1_000_000;
-        let (pll, config, saidiv, _) = (pllm_min..=pllm_max)
-            .map(|m| Self::optimize_fixed_m(pllsrcclk, m, target))
-            .min_by_key(|(_, _, _, error)| *error)
-            .expect("no suitable SAI PLL configuration found");
-        Self::apply_config(config, saidiv);
-        pll
+        (pllm_min..=pllm_max)
+            .filter_map(|m| Self::optimize_fixed_m(pllsrcclk, m, target))
+            .min_by_key(|(_, error)| *error)
+            .map(|(pll, _)| pll)
+            .ok_or(ClockError::PeripheralClock)
     }
 
     #[cfg(any(feature = "gpio-f427", feature = "gpio-f469",))]
-    pub fn setup_shared_m(pllsrcclk: u32, m: Option<u32>, sai_clk: Option<u32>) -> SaiPll {
+    pub fn plan_shared_m(
+        pllsrcclk: u32,
+        m: Option<u32>,
+        sai_clk: Option<u32>,
+    ) -> Result<SaiPll, ClockError> {
         // "m" is None if both other PLLs are not in use.
         let Some(m) = m else {
-            return Self::setup(pllsrcclk, sai_clk);
+            return Self::plan(pllsrcclk, sai_clk);
         };
         let Some(target) = sai_clk else {
-            return Self::unused();
+            return Ok(Self::unused());
         };
-        let (pll, config, saidiv, _) = Self::optimize_fixed_m(pllsrcclk, m, target);
-        Self::apply_config(config, saidiv);
-        pll
+        Self::optimize_fixed_m(pllsrcclk, m, target)
+            .map(|(pll, _)| pll)
+            .ok_or(ClockError::PeripheralClock)
     }
 
-    fn optimize_fixed_m(
-        pllsrcclk: u32,
-        m: u32,
-        sai_clk: u32,
-    ) -> (SaiPll, SingleOutputPll, u32, u32) {
+    fn optimize_fixed_m(pllsrcclk: u32, m: u32, sai_clk: u32) -> Option<(SaiPll, u32)> {
         // NOTE: This code tests lots of configurations due to the nested loops for the two
         // dividers. A smarter approach can probably speed up the search.
         let (config, saidiv, real_sai_clk, error) = (1..=32)
             .filter_map(|saidiv| {
                 let target = sai_clk * saidiv;
-                let (config, real_sai_clk, error) =
-                    SingleOutputPll::optimize(pllsrcclk, m, target, 2, 15)?;
-                Some((config, saidiv, real_sai_clk, error))
+                let (config, output, _) = SingleOutputPll::optimize(pllsrcclk, m, target, 2, 15)?;
+                let real_sai_clk = output / saidiv;
+                Some((config, saidiv, real_sai_clk, real_sai_clk.abs_diff(sai_clk)))
             })
-            .min_by_key(|(_, _, _, error)| *error)
-            .expect("no suitable I2S PLL configuration found");
-        (
+            .min_by_key(|(_, _, _, error)| *error)?;
+        Some((
             SaiPll {
                 use_pll: true,
                 sai_clk: Some(real_sai_clk),
+                #[cfg(feature = "ltdc")]
+                lcd_clk: None,
+                config: Some(SaiPllConfig {
+                    m: config.m,
+                    n: config.n,
+                    q: Some((config.outdiv, saidiv as u8)),
+                    #[cfg(feature = "ltdc")]
+                    r: None,
+                }),
             },
-            config,
-            saidiv,
             error,
-        )
+        ))
+    }
+
+    /// Adds the LCD-TFT clock to the PLL.
+    ///
+    /// If the PLL already generates the SAI clock, "N" is kept and only the "R" output and the
+    /// LCD clock divider are chosen.
+    #[cfg(feature = "ltdc")]
+    pub fn plan_lcd(
+        &mut self,
+        pllsrcclk: u32,
+        m: Option<u32>,
+        lcd_clk: Option<u32>,
+    ) -> Result<(), ClockError> {
+        let Some(target) = lcd_clk else {
+            return Ok(());
+        };
+        let (m, n_range) = match &self.config {
+            Some(config) => (u32::from(config.m), config.n.into()..=config.n.into()),
+            // "m" is None if the other PLLs are not in use. The input of the PLL must be in the
+            // range from 1 to 2 MHz.
+            None => (m.unwrap_or((pllsrcclk + 1_999_999) / 2_000_000), 50..=432),
+        };
+        let (n, r, divr, real_lcd_clk) = Self::optimize_lcd(pllsrcclk / m, n_range, target)
+            .ok_or(ClockError::PeripheralClock)?;
+
+        let config = self.config.get_or_insert(SaiPllConfig {
+            m: m as u8,
+            n,
+            q: None,
+            r: None,
+        });
+        config.n = n;
+        config.r = Some((r, divr as u8));
+        self.use_pll = true;
+        self.lcd_clk = Some(real_lcd_clk);
+        Ok(())
     }
 
+    /// Finds "N" within `n_range`, "R" and the LCD clock divider closest to `target`.
+    ///
+    /// Returns `(n, r, divr, lcd_clk)`.
+    #[cfg(feature = "ltdc")]
+    pub(crate) fn optimize_lcd(
+        vco_in: u32,
+        n_range: core::ops::RangeInclusive<u32>,
+        target: u32,
+    ) -> Option<(u16, u8, u32, u32)> {
+        (2..=7)
+            .flat_map(|r| [2, 4, 8, 16].into_iter().map(move |divr| (r, divr)))
+            .filter_map(|(r, divr)| {
+                let target_vco_out = target.checked_mul(r * divr)?;
+                let n = ((target_vco_out + (vco_in >> 1)) / vco_in)
+                    .clamp(*n_range.start(), *n_range.end());
+                let vco_out = vco_in * n;
+                if !(VCO_MIN..=VCO_MAX).contains(&vco_out) {
+                    return None;
+                }
+                let output = vco_out / r / divr;
+                let error = output.abs_diff(target);
+                Some((n as u16, r as u8, divr, output, error))
+            })
+            .min_by_key(|(_, _, _, _, error)| *error)
+            .map(|(n, r, divr, output, _)| (n, r, divr, output))
+    }
+
+    /// Writes the planned dividers, if the PLL is used.
     #[cfg(not(feature = "gpio-f446"))]
-    fn apply_config(config: SingleOutputPll, saidiv: u32) {
+    pub fn apply(&self) {
+        let Some(config) = &self.config else {
+            return;
+        };
         let rcc = unsafe { &*RCC::ptr() };
-        rcc.dckcfgr
-            .modify(|_, w| w.pllsaidivq().bits(saidiv as u8 - 1));
         // "M" may have been written before, but the value is identical.
         rcc.pllcfgr
             .modify(|_, w| unsafe { w.pllm().bits(config.m) });
-        rcc.pllsaicfgr
-            .modify(|_, w| unsafe { w.pllsain().bits(config.n).pllsaiq().bits(config.outdiv) });
+        rcc.pllsaicfgr.modify(|_, w| unsafe {
+            if let Some((q, _)) = config.q {
+                w.pllsaiq().bits(q);
+            }
+            #[cfg(feature = "ltdc")]
+            if let Some((r, _)) = config.r {
+                w.pllsair().bits(r);
+            }
+            w.pllsain().bits(config.n)
+        });
+        rcc.dckcfgr.modify(|_, w| {
+            if let Some((_, saidiv)) = config.q {
+                w.pllsaidivq().bits(saidiv - 1);
+            }
+            // The divider is encoded as log2(divr) - 1
+            #[cfg(feature = "ltdc")]
+            if let Some((_, divr)) = config.r {
+                w.pllsaidivr().bits(divr.trailing_zeros() as u8 - 1);
+            }
+            w
+        });
     }
+    /// Writes the planned dividers, if the PLL is used.
     #[cfg(feature = "gpio-f446")]
-    fn apply_config(config: SingleOutputPll, saidiv: u32) {
+    pub fn apply(&self) {
+        let Some(config) = &self.config else {
+            return;
+        };
         let rcc = unsafe { &*RCC::ptr() };
-        rcc.dckcfgr
-            .modify(|_, w| w.pllsaidivq().bits(saidiv as u8 - 1));
         rcc.pllsaicfgr.modify(|_, w| unsafe {
+            if let Some((q, _)) = config.q {
+                w.pllsaiq().bits(q);
+            }
             w.pllsaim().bits(config.m);
-            w.pllsain().bits(config.n);
-            w.pllsaiq().bits(config.outdiv)
+            w.pllsain().bits(config.n)
         });
+        if let Some((_, saidiv)) = config.q {
+            rcc.dckcfgr.modify(|_, w| w.pllsaidivq().bits(saidiv - 1));
+        }
     }
 }
 
 #[cfg(not(feature = "gpio-f410"))]
+#[derive(Clone, Copy)]
 struct SingleOutputPll {
     m: u8,
     n: u16,
@@ -442,7 +580,7 @@ impl SingleOutputPll {
                 };
                 let n = (target_vco_out + (vco_in >> 1)) / vco_in;
                 let vco_out = vco_in * n;
-                if !(100_000_000..=432_000_000).contains(&vco_out) {
+                if !(VCO_MIN..=VCO_MAX).contains(&vco_out) {
                     return None;
                 }
                 let output = vco_out / outdiv;
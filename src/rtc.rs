@@ -25,6 +25,8 @@ pub enum Event {
     AlarmB,
     Wakeup,
     Timestamp,
+    /// Any tamper input
+    Tamper,
 }
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -62,6 +64,278 @@ impl From<Weekday> for AlarmDay {
     }
 }
 
+/// Synchronization shift of the calendar
+///
+/// The clock is delayed by `subfs / (PREDIV_S + 1)` seconds and, with `add1s`, advanced by
+/// one second.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct Shift {
+    pub add1s: bool,
+    pub subfs: u16,
+}
+
+impl Shift {
+    /// Computes the shift that moves the clock by `nano` nanoseconds, forward if positive
+    ///
+    /// The offset must be less than one second, it is rounded to the nearest sub-second tick.
+    pub fn from_nanos(nano: i32, prediv_s: u16) -> Result<Self, Error> {
+        if !(-999_999_999..=999_999_999).contains(&nano) {
+            return Err(Error::InvalidInputData);
+        }
+        let period = prediv_s as i64 + 1;
+        let ticks = (nano.unsigned_abs() as i64 * period + 500_000_000) / 1_000_000_000;
+        Ok(match (nano < 0, ticks) {
+            (_, 0) => Self {
+                add1s: false,
+                subfs: 0,
+            },
+            (true, ticks) => Self {
+                add1s: false,
+                subfs: ticks.min(period - 1) as u16,
+            },
+            (false, ticks) if ticks >= period => Self {
+                add1s: true,
+                subfs: 0,
+            },
+            (false, ticks) => Self {
+                add1s: true,
+                subfs: (period - ticks) as u16,
+            },
+        })
+    }
+}
+
+/// RTC tamper input
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Tamper {
+    /// RTC_TAMP1 on PC13
+    Tamper1 = 0,
+    /// RTC_TAMP2
+    #[cfg(not(any(
+        feature = "gpio-f401",
+        feature = "gpio-f410",
+        feature = "gpio-f411",
+        feature = "gpio-f417"
+    )))]
+    Tamper2 = 1,
+}
+
+/// Tamper input trigger
+///
+/// Edges can only be used with [`TamperFilter::Edge`], levels only with a filter.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum TamperTrigger {
+    RisingEdge,
+    FallingEdge,
+    LowLevel,
+    HighLevel,
+}
+
+/// Number of consecutive samples at the active level that trigger a tamper event
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum TamperFilter {
+    /// Edge detection without sampling
+    Edge = 0b00,
+    Samples2 = 0b01,
+    Samples4 = 0b10,
+    Samples8 = 0b11,
+}
+
+/// Tamper input sampling frequency, as a divider of RTCCLK
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum TamperFrequency {
+    Div32768 = 0b000,
+    Div16384 = 0b001,
+    Div8192 = 0b010,
+    Div4096 = 0b011,
+    Div2048 = 0b100,
+    Div1024 = 0b101,
+    Div512 = 0b110,
+    Div256 = 0b111,
+}
+
+/// Duration of the pull-up precharge before each sample, in RTCCLK cycles
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum TamperPrecharge {
+    Cycles1 = 0b00,
+    Cycles2 = 0b01,
+    Cycles4 = 0b10,
+    Cycles8 = 0b11,
+}
+
+/// Settings shared by all tamper inputs
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct TamperConfig {
+    pub filter: TamperFilter,
+    pub frequency: TamperFrequency,
+    /// Precharge of the inputs with the internal pull-up, `None` disables the pull-up
+    pub precharge: Option<TamperPrecharge>,
+    /// Saves a timestamp on tamper events
+    pub timestamp: bool,
+}
+
+impl Default for TamperConfig {
+    fn default() -> Self {
+        Self {
+            filter: TamperFilter::Edge,
+            frequency: TamperFrequency::Div32768,
+            precharge: Some(TamperPrecharge::Cycles1),
+            timestamp: false,
+        }
+    }
+}
+
+impl TamperConfig {
+    pub fn filter(mut self, filter: TamperFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn frequency(mut self, frequency: TamperFrequency) -> Self {
+        self.frequency = frequency;
+        self
+    }
+
+    pub fn precharge(mut self, precharge: Option<TamperPrecharge>) -> Self {
+        self.precharge = precharge;
+        self
+    }
+
+    pub fn timestamp(mut self, timestamp: bool) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+}
+
+/// Frequency of the calibration output on AFO_CALIB (PC13)
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum CalibrationOutput {
+    /// RTCCLK / (PREDIV_A + 1), 512 Hz with LSE and the default prescalers
+    Hz512 = 0,
+    /// RTCCLK / ((PREDIV_A + 1) * (PREDIV_S + 1)), 1 Hz with the default prescalers
+    Hz1 = 1,
+}
+
+/// Smooth calibration cycle length
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum CalibrationPeriod {
+    /// 2²⁰ RTCCLK cycles, full resolution of 0.954 ppm
+    Seconds32,
+    /// 2¹⁹ RTCCLK cycles, the lowest bit of CALM is ignored
+    Seconds16,
+    /// 2¹⁸ RTCCLK cycles, the two lowest bits of CALM are ignored
+    Seconds8,
+}
+
+/// Smooth calibration register values
+///
+/// Every calibration cycle of 2²⁰ RTCCLK cycles, `calm` pulses are masked and, with `calp`,
+/// 512 pulses are inserted.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct SmoothCalibration {
+    pub calp: bool,
+    pub calm: u16,
+}
+
+impl SmoothCalibration {
+    /// Largest error that can be corrected on a clock running fast, in ppm
+    pub const MAX_FAST_PPM: f32 = 511. * 1_000_000. / (1 << 20) as f32;
+    /// Largest error that can be corrected on a clock running slow, in ppm
+    pub const MAX_SLOW_PPM: f32 = 512. * 1_000_000. / (1 << 20) as f32;
+
+    /// Computes the values that compensate a measured frequency error.
+    ///
+    /// `ppm` is positive if the RTC clock runs fast, and negative if it runs slow. It is rounded
+    /// to the nearest step of 0.954 ppm.
+    pub fn from_ppm(ppm: f32) -> Result<Self, Error> {
+        if !(-Self::MAX_SLOW_PPM..=Self::MAX_FAST_PPM).contains(&ppm) {
+            return Err(Error::InvalidInputData);
+        }
+        // Number of pulses to remove per 2^20 cycles, negative to add
+        let pulses = ppm * (1 << 20) as f32 / 1_000_000.;
+        let pulses = if pulses < 0. {
+            (pulses - 0.5) as i32
+        } else {
+            (pulses + 0.5) as i32
+        };
+        Ok(if pulses < 0 {
+            Self {
+                calp: true,
+                calm: (512 + pulses) as u16,
+            }
+        } else {
+            Self {
+                calp: false,
+                calm: (pulses as u16).min(511),
+            }
+        })
+    }
+
+    /// Returns the error compensated by these values, in ppm.
+    pub fn ppm(&self) -> f32 {
+        let pulses = self.calm as i32 - if self.calp { 512 } else { 0 };
+        pulses as f32 * 1_000_000. / (1 << 20) as f32
+    }
+}
+
+/// Coarse digital calibration register values
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct CoarseCalibration {
+    /// Negative calibration, slows the calendar down
+    pub dcs: bool,
+    pub dc: u8,
+}
+
+impl CoarseCalibration {
+    /// Computes the values that compensate a measured frequency error.
+    ///
+    /// `ppm` is positive if the RTC clock runs fast and is corrected in steps of about 2 ppm up
+    /// to 63 ppm. A clock running slow is corrected in steps of about 4 ppm up to 126 ppm.
+    /// `ppm` is rounded to the nearest step.
+    pub fn from_ppm(ppm: i32) -> Result<Self, Error> {
+        // DC = 31 corrects 63 ppm with DCS and 126 ppm without
+        if ppm >= 0 {
+            let dc = (i64::from(ppm) * 62 + 63) / 126;
+            if dc > 31 {
+                return Err(Error::InvalidInputData);
+            }
+            Ok(Self {
+                dcs: true,
+                dc: dc as u8,
+            })
+        } else {
+            let dc = (i64::from(ppm) * -62 + 126) / 252;
+            if dc > 31 {
+                return Err(Error::InvalidInputData);
+            }
+            Ok(Self {
+                dcs: false,
+                dc: dc as u8,
+            })
+        }
+    }
+}
+
+// RTC_CR
+const CR_DCE: u32 = 1 << 7;
+// RTC_ISR, TAMP1F and TAMP2F
+const ISR_TAMPF: u32 = 0b11 << 13;
+// RTC_TAFCR, TAMP1E and TAMP2E
+const TAFCR_TAMPE: u32 = (1 << 3) | 1;
+// RTC_TAFCR, TAMPPUDIS, TAMPPRCH, TAMPFLT, TAMPFREQ and TAMPTS
+const TAFCR_CONFIG: u32 = 0xff80;
+
 /// RTC clock source LSE oscillator clock (type state)
 pub struct Lse;
 /// RTC clock source LSI oscillator clock (type state)
@@ -497,6 +771,10 @@ impl<CS> Rtc<CS> {
         Ok(())
     }
 
+    /// Reads the date and time, with a resolution of 1 / (PREDIV_S + 1) second
+    ///
+    /// RTC_SSR, RTC_TR and RTC_DR are read in this order after a shadow register update, so they
+    /// come from the same instant.
     pub fn get_datetime(&mut self) -> PrimitiveDateTime {
         // Wait for Registers synchronization flag,  to ensure consistency between the RTC_SSR, RTC_TR and RTC_DR shadow registers.
         while self.regs.isr.read().rsf().bit_is_clear() {}
@@ -519,10 +797,16 @@ impl<CS> Rtc<CS> {
         let prediv_s = self.regs.prer.read().prediv_s().bits();
         let nano = ss_to_nano(ss, prediv_s);
 
-        PrimitiveDateTime::new(
+        let datetime = PrimitiveDateTime::new(
             Date::from_calendar_date(year.into(), month.try_into().unwrap(), day).unwrap(),
             Time::from_hms_nano(hours, minutes, seconds, nano).unwrap(),
-        )
+        );
+        // After a shift that delays the clock, SS is above PREDIV_S and the seconds are one ahead
+        if ss > prediv_s {
+            datetime - time::Duration::SECOND
+        } else {
+            datetime
+        }
     }
 
     /// Configures the wakeup timer to trigger periodically every `interval` seconds
@@ -602,10 +886,204 @@ impl<CS> Rtc<CS> {
         let prediv_s = self.regs.prer.read().prediv_s().bits();
         let nano = ss_to_nano(ss, prediv_s);
 
-        PrimitiveDateTime::new(
+        let datetime = PrimitiveDateTime::new(
             Date::from_calendar_date(year.into(), month.try_into().unwrap(), day).unwrap(),
             Time::from_hms_nano(hours, minutes, seconds, nano).unwrap(),
-        )
+        );
+        // After a shift that delays the clock, SS is above PREDIV_S and the seconds are one ahead
+        if ss > prediv_s {
+            datetime - time::Duration::SECOND
+        } else {
+            datetime
+        }
+    }
+
+    /// Shifts the calendar by a fraction of a second, to synchronize it to a reference
+    ///
+    /// Waits for the shift to be done. Fails if the reference clock detection is on or if
+    /// `subfs` is above PREDIV_S.
+    pub fn shift(&mut self, shift: Shift) -> Result<(), Error> {
+        // REFCKON
+        if self.regs.cr.read().bits() & (1 << 4) != 0 {
+            return Err(Error::InvalidInputData);
+        }
+        let prediv_s = self.regs.prer.read().prediv_s().bits();
+        // SS[15] set means a previous shift could overflow
+        if shift.subfs > prediv_s || self.regs.ssr.read().ss().bits() & 0x8000 != 0 {
+            return Err(Error::InvalidInputData);
+        }
+        self.modify(false, |regs| {
+            // SHPF, a previous shift is pending
+            while regs.isr.read().bits() & (1 << 3) != 0 {}
+            regs.shiftr
+                .write(|w| unsafe { w.bits((u32::from(shift.add1s) << 31) | shift.subfs as u32) });
+        });
+        while self.regs.isr.read().bits() & (1 << 3) != 0 {}
+        Ok(())
+    }
+
+    /// Sets the sub-second part of an alarm
+    ///
+    /// The alarm matches when the `bits` lowest bits of RTC_SSR equal those of `ss`. With
+    /// `bits == 0` the sub-seconds are ignored, which is the reset state; at most 15 bits can be
+    /// compared. Use [`nano_to_ss`] to convert from nanoseconds. An enabled alarm is kept enabled.
+    pub fn set_alarm_subseconds(&mut self, alarm: Alarm, ss: u16, bits: u8) -> Result<(), Error> {
+        if bits > 15 || ss > 0x7fff {
+            return Err(Error::InvalidInputData);
+        }
+        self.modify(false, |rtc| {
+            let enabled = rtc.cr.read().bits() & (1 << (8 + alarm as u32)) != 0;
+            unsafe {
+                bb::clear(&rtc.cr, 8 + (alarm as u8));
+            }
+            while rtc.isr.read().bits() & (1 << (alarm as u32)) == 0 {}
+            rtc.alrmssr[alarm as usize]
+                .write(|w| unsafe { w.bits(((bits as u32) << 24) | ss as u32) });
+            if enabled {
+                unsafe {
+                    bb::set(&rtc.cr, 8 + (alarm as u8));
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Configures the filter, sampling and precharge shared by the tamper inputs
+    ///
+    /// Disables all tamper inputs.
+    pub fn configure_tamper(&mut self, config: TamperConfig) {
+        let (puds, prch) = match config.precharge {
+            Some(prch) => (0, prch as u32),
+            None => (1, 0),
+        };
+        self.modify(false, |regs| {
+            regs.tafcr.modify(|r, w| unsafe {
+                w.bits(
+                    (r.bits() & !(TAFCR_TAMPE | TAFCR_CONFIG))
+                        | (puds << 15)
+                        | (prch << 13)
+                        | ((config.filter as u32) << 11)
+                        | ((config.frequency as u32) << 8)
+                        | (u32::from(config.timestamp) << 7),
+                )
+            });
+        });
+    }
+
+    /// Enables the tamper input with `trigger`
+    ///
+    /// A tamper event resets the backup registers. Edge triggers require
+    /// [`TamperFilter::Edge`], level triggers require a filter.
+    pub fn enable_tamper(&mut self, tamper: Tamper, trigger: TamperTrigger) -> Result<(), Error> {
+        let edge = self.regs.tafcr.read().bits() & (0b11 << 11) == 0;
+        let trg = match (trigger, edge) {
+            (TamperTrigger::RisingEdge, true) | (TamperTrigger::LowLevel, false) => false,
+            (TamperTrigger::FallingEdge, true) | (TamperTrigger::HighLevel, false) => true,
+            _ => return Err(Error::InvalidInputData),
+        };
+        let shift = 3 * (tamper as u8);
+        self.modify(false, |regs| unsafe {
+            bb::clear(&regs.tafcr, shift);
+            if trg {
+                bb::set(&regs.tafcr, shift + 1);
+            } else {
+                bb::clear(&regs.tafcr, shift + 1);
+            }
+            bb::clear(&regs.isr, 13 + (tamper as u8));
+            bb::set(&regs.tafcr, shift);
+        });
+        Ok(())
+    }
+
+    /// Disables the tamper input
+    pub fn disable_tamper(&mut self, tamper: Tamper) {
+        self.modify(false, |regs| unsafe {
+            bb::clear(&regs.tafcr, 3 * (tamper as u8));
+            bb::clear(&regs.isr, 13 + (tamper as u8));
+        });
+    }
+
+    /// Returns `true` if a tamper event was detected on the input
+    pub fn is_tampered(&self, tamper: Tamper) -> bool {
+        self.regs.isr.read().bits() & (1 << (13 + tamper as u32)) != 0
+    }
+
+    /// Clears the tamper flag of the input
+    pub fn clear_tamper(&mut self, tamper: Tamper) {
+        unsafe { bb::clear(&self.regs.isr, 13 + (tamper as u8)) };
+    }
+
+    /// Outputs the calibration clock on AFO_CALIB (PC13)
+    ///
+    /// The output has priority over the GPIO configuration, but not over the alarm output.
+    pub fn enable_calibration_output(&mut self, output: CalibrationOutput) {
+        self.modify(false, |regs| unsafe {
+            match output {
+                CalibrationOutput::Hz512 => bb::clear(&regs.cr, 19),
+                CalibrationOutput::Hz1 => bb::set(&regs.cr, 19),
+            }
+            bb::set(&regs.cr, 23);
+        });
+    }
+
+    /// Disables the calibration output
+    pub fn disable_calibration_output(&mut self) {
+        self.modify(false, |regs| unsafe { bb::clear(&regs.cr, 23) });
+    }
+
+    /// Sets the smooth digital calibration
+    ///
+    /// Disables coarse calibration, which cannot be used at the same time.
+    pub fn set_smooth_calibration(
+        &mut self,
+        calibration: SmoothCalibration,
+        period: CalibrationPeriod,
+    ) {
+        let (calw, mask) = match period {
+            CalibrationPeriod::Seconds32 => (0b00, 0x1ff),
+            CalibrationPeriod::Seconds16 => (0b01, 0x1fe),
+            CalibrationPeriod::Seconds8 => (0b10, 0x1fc),
+        };
+        if self.regs.cr.read().bits() & CR_DCE != 0 {
+            self.modify(true, |regs| unsafe { bb::clear(&regs.cr, 7) });
+        }
+        self.modify(false, |regs| {
+            // Wait until a previous recalibration is done (RECALPF)
+            while regs.isr.read().bits() & (1 << 16) != 0 {}
+            regs.calr.write(|w| unsafe {
+                w.bits(
+                    (u32::from(calibration.calp) << 15)
+                        | (calw << 13)
+                        | (calibration.calm & mask) as u32,
+                )
+            });
+        });
+    }
+
+    /// Sets the coarse digital calibration
+    ///
+    /// Clears the smooth calibration, which cannot be used at the same time. The asynchronous
+    /// prescaler must be at least 6.
+    pub fn set_coarse_calibration(&mut self, calibration: CoarseCalibration) -> Result<(), Error> {
+        if self.regs.prer.read().prediv_a().bits() < 6 {
+            return Err(Error::InvalidInputData);
+        }
+        self.modify(false, |regs| {
+            while regs.isr.read().bits() & (1 << 16) != 0 {}
+            regs.calr.write(|w| unsafe { w.bits(0) });
+        });
+        self.modify(true, |regs| {
+            regs.calibr.write(|w| unsafe {
+                w.bits((u32::from(calibration.dcs) << 7) | calibration.dc as u32)
+            });
+            regs.cr.modify(|r, w| unsafe { w.bits(r.bits() | CR_DCE) });
+        });
+        Ok(())
+    }
+
+    /// Disables the coarse digital calibration
+    pub fn disable_coarse_calibration(&mut self) {
+        self.modify(true, |regs| unsafe { bb::clear(&regs.cr, 7) });
     }
 
     /// Sets the time at which an alarm will be triggered
@@ -646,9 +1124,7 @@ impl<CS> Rtc<CS> {
                 w.wdsel().bit(wdsel);
                 w.msk4().bit(daymask)
             });
-            // subsecond alarm not implemented
-            // would need a conversion method between `time.micros` and RTC ticks
-            // write the SS value and mask to `rtc.alrmssr[alarm]`
+            // The sub-second part set by `set_alarm_subseconds` is kept
 
             // enable alarm and reenable interrupt if it was enabled
             unsafe {
@@ -685,6 +1161,11 @@ impl<CS> Rtc<CS> {
                 exti.imr.modify(|_, w| w.mr21().set_bit());
                 regs.cr.modify(|_, w| w.tsie().set_bit());
             }
+            Event::Tamper => {
+                exti.rtsr.modify(|_, w| w.tr21().enabled());
+                exti.imr.modify(|_, w| w.mr21().set_bit());
+                unsafe { bb::set(&regs.tafcr, 2) };
+            }
         });
     }
 
@@ -712,6 +1193,42 @@ impl<CS> Rtc<CS> {
                 exti.imr.modify(|_, w| w.mr21().clear_bit());
                 exti.rtsr.modify(|_, w| w.tr21().disabled());
             }
+            Event::Tamper => {
+                unsafe { bb::clear(&regs.tafcr, 2) };
+                exti.imr.modify(|_, w| w.mr21().clear_bit());
+                exti.rtsr.modify(|_, w| w.tr21().disabled());
+            }
+        });
+    }
+
+    /// Arms `event` to leave Standby mode: enables its interrupt and clears its flag.
+    ///
+    /// Unlike [`Self::listen`] the EXTI line is left alone, as it does not run in Standby. Call
+    /// it after setting the alarm or wakeup timer and before
+    /// [`Pwr::standby`](crate::pwr::Pwr::standby).
+    pub fn enable_standby_wakeup(&mut self, event: Event) {
+        self.modify(false, |regs| match event {
+            Event::AlarmA => {
+                regs.cr.modify(|_, w| w.alraie().set_bit());
+                regs.isr.modify(|_, w| w.alraf().clear_bit());
+            }
+            Event::AlarmB => {
+                regs.cr.modify(|_, w| w.alrbie().set_bit());
+                regs.isr.modify(|_, w| w.alrbf().clear_bit());
+            }
+            Event::Wakeup => {
+                regs.cr.modify(|_, w| w.wutie().set_bit());
+                regs.isr.modify(|_, w| w.wutf().clear_bit());
+            }
+            Event::Timestamp => {
+                regs.cr.modify(|_, w| w.tsie().set_bit());
+                regs.isr.modify(|_, w| w.tsf().clear_bit());
+            }
+            Event::Tamper => {
+                unsafe { bb::set(&regs.tafcr, 2) };
+                regs.isr
+                    .modify(|r, w| unsafe { w.bits(r.bits() & !ISR_TAMPF) });
+            }
         });
     }
 
@@ -722,6 +1239,7 @@ impl<CS> Rtc<CS> {
             Event::AlarmB => self.regs.isr.read().alrbf().bit_is_set(),
             Event::Wakeup => self.regs.isr.read().wutf().bit_is_set(),
             Event::Timestamp => self.regs.isr.read().tsf().bit_is_set(),
+            Event::Tamper => self.regs.isr.read().bits() & ISR_TAMPF != 0,
         }
     }
 
@@ -744,6 +1262,12 @@ impl<CS> Rtc<CS> {
                 self.regs.isr.modify(|_, w| w.tsf().clear_bit());
                 unsafe { (*pac::EXTI::ptr()).pr.write(|w| w.pr21().set_bit()) };
             }
+            Event::Tamper => {
+                self.regs
+                    .isr
+                    .modify(|r, w| unsafe { w.bits(r.bits() & !ISR_TAMPF) });
+                unsafe { (*pac::EXTI::ptr()).pr.write(|w| w.pr21().set_bit()) };
+            }
         }
     }
 }
@@ -807,10 +1331,130 @@ fn decode_year(dr: &dr::R) -> u16 {
     year as u16
 }
 
-const fn ss_to_nano(ss: u16, prediv_s: u16) -> u32 {
-    let ss = ss as u32;
-    let prediv_s = prediv_s as u32;
-    assert!(ss <= prediv_s);
+/// Converts a sub-second value from RTC_SSR into nanoseconds of the current second.
+///
+/// Values above `prediv_s` are valid after a shift and belong to the previous second.
+pub const fn ss_to_nano(ss: u16, prediv_s: u16) -> u32 {
+    let ss = ss as u64;
+    let period = prediv_s as u64 + 1;
+    let ticks = if ss < period {
+        period - 1 - ss
+    } else {
+        2 * period - 1 - ss % (2 * period)
+    };
+    (ticks * 1_000_000_000 / period) as u32
+}
 
-    (((prediv_s - ss) * 100_000) / (prediv_s + 1)) * 10_000
+/// Converts nanoseconds of a second into the RTC_SSR value at that instant, rounding down.
+///
+/// Returns `None` if `nano` is a second or more.
+pub const fn nano_to_ss(nano: u32, prediv_s: u16) -> Option<u16> {
+    if nano >= 1_000_000_000 {
+        return None;
+    }
+    let period = prediv_s as u64 + 1;
+    let ticks = nano as u64 * period / 1_000_000_000;
+    Some((period - 1 - ticks) as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_ppm(calibration: SmoothCalibration, ppm: f32) {
+        assert!(
+            (calibration.ppm() - ppm).abs() < 0.001,
+            "{}",
+            calibration.ppm()
+        );
+    }
+
+    #[test]
+    fn smooth_calibration() {
+        let cal = |calp, calm| SmoothCalibration { calp, calm };
+        assert_eq!(SmoothCalibration::from_ppm(0.), Ok(cal(false, 0)));
+        // 0.954 ppm steps, rounded to the nearest
+        assert_eq!(SmoothCalibration::from_ppm(0.4), Ok(cal(false, 0)));
+        assert_eq!(SmoothCalibration::from_ppm(0.5), Ok(cal(false, 1)));
+        assert_eq!(SmoothCalibration::from_ppm(10.), Ok(cal(false, 10)));
+        assert_eq!(SmoothCalibration::from_ppm(-0.4), Ok(cal(false, 0)));
+        // Slow clocks insert 512 pulses with CALP and mask fewer
+        assert_eq!(SmoothCalibration::from_ppm(-0.5), Ok(cal(true, 511)));
+        assert_eq!(SmoothCalibration::from_ppm(-10.), Ok(cal(true, 502)));
+        assert_ppm(cal(true, 502), -9.537);
+        assert_ppm(cal(false, 100), 95.367);
+
+        let fast = SmoothCalibration::from_ppm(SmoothCalibration::MAX_FAST_PPM).unwrap();
+        assert_eq!(fast, cal(false, 511));
+        assert_ppm(fast, SmoothCalibration::MAX_FAST_PPM);
+        let slow = SmoothCalibration::from_ppm(-SmoothCalibration::MAX_SLOW_PPM).unwrap();
+        assert_eq!(slow, cal(true, 0));
+        assert_ppm(slow, -SmoothCalibration::MAX_SLOW_PPM);
+
+        for ppm in [
+            SmoothCalibration::MAX_FAST_PPM + 0.01,
+            -SmoothCalibration::MAX_SLOW_PPM - 0.01,
+            f32::NAN,
+        ] {
+            assert_eq!(
+                SmoothCalibration::from_ppm(ppm),
+                Err(Error::InvalidInputData)
+            );
+        }
+    }
+
+    #[test]
+    fn subseconds() {
+        // 32.768 kHz LSE with the default prescalers
+        assert_eq!(nano_to_ss(0, 255), Some(255));
+        assert_eq!(nano_to_ss(500_000_000, 255), Some(127));
+        assert_eq!(nano_to_ss(999_999_999, 255), Some(0));
+        assert_eq!(nano_to_ss(1_000_000_000, 255), None);
+        assert_eq!(nano_to_ss(u32::MAX, 0x7fff), None);
+        assert_eq!(nano_to_ss(999_999_999, 0x7fff), Some(0));
+        assert_eq!(nano_to_ss(3_906_249, 255), Some(255));
+
+        assert_eq!(ss_to_nano(255, 255), 0);
+        assert_eq!(ss_to_nano(0, 255), 996_093_750);
+        // After a shift, belongs to the previous second
+        assert_eq!(ss_to_nano(256, 255), 996_093_750);
+        assert_eq!(ss_to_nano(511, 255), 0);
+        for ss in 0..=255 {
+            assert_eq!(nano_to_ss(ss_to_nano(ss, 255), 255), Some(ss));
+        }
+    }
+
+    #[test]
+    fn coarse_calibration() {
+        let cal = |dcs, dc| CoarseCalibration { dcs, dc };
+        assert_eq!(CoarseCalibration::from_ppm(0), Ok(cal(true, 0)));
+        // Fast clocks, 2.03 ppm steps
+        assert_eq!(CoarseCalibration::from_ppm(1), Ok(cal(true, 0)));
+        assert_eq!(CoarseCalibration::from_ppm(2), Ok(cal(true, 1)));
+        assert_eq!(CoarseCalibration::from_ppm(21), Ok(cal(true, 10)));
+        assert_eq!(CoarseCalibration::from_ppm(63), Ok(cal(true, 31)));
+        assert_eq!(CoarseCalibration::from_ppm(64), Ok(cal(true, 31)));
+        assert_eq!(
+            CoarseCalibration::from_ppm(65),
+            Err(Error::InvalidInputData)
+        );
+        // Slow clocks, 4.06 ppm steps
+        assert_eq!(CoarseCalibration::from_ppm(-2), Ok(cal(false, 0)));
+        assert_eq!(CoarseCalibration::from_ppm(-3), Ok(cal(false, 1)));
+        assert_eq!(CoarseCalibration::from_ppm(-41), Ok(cal(false, 10)));
+        assert_eq!(CoarseCalibration::from_ppm(-126), Ok(cal(false, 31)));
+        assert_eq!(CoarseCalibration::from_ppm(-128), Ok(cal(false, 31)));
+        assert_eq!(
+            CoarseCalibration::from_ppm(-129),
+            Err(Error::InvalidInputData)
+        );
+        assert_eq!(
+            CoarseCalibration::from_ppm(i32::MIN),
+            Err(Error::InvalidInputData)
+        );
+        assert_eq!(
+            CoarseCalibration::from_ppm(i32::MAX),
+            Err(Error::InvalidInputData)
+        );
+    }
 }
@@ -0,0 +1,874 @@
+//! Serial audio interface
+//!
+//! Each SAI has two independent sub-blocks, A and B. A sub-block is either asynchronous with its
+//! own clock and frame sync pins, or synchronous to the other sub-block of the same SAI and only
+//! uses its data pin.
+//!
+//! ```rust,ignore
+//! let clocks = rcc.cfgr.saia_clk(12288.kHz()).saib_clk(12288.kHz()).freeze();
+//! let (block_a, block_b) = dp.SAI.split();
+//!
+//! let config = Config::default().sample_rate(48.kHz()).data_size(DataSize::Bits24);
+//! let mut tx = block_a
+//!     .master_tx((gpioe.pe2, gpioe.pe5, gpioe.pe4, gpioe.pe6), config, &clocks)
+//!     .unwrap();
+//! let mut rx = block_b.synchronous_rx(gpioe.pe3, config).unwrap();
+//!
+//! // The synchronous sub-block must be enabled first
+//! rx.enable();
+//! tx.enable();
+//! ```
+//!
+//! Sub-blocks implement `PeriAddress` and `DMASet`, so they can be used with
+//! [`Transfer`](crate::dma::Transfer) after calling [`Sai::set_dma`].
+
+use core::marker::PhantomData;
+
+use enumflags2::BitFlags;
+
+use crate::dma::traits::{DMASet, PeriAddress, SAICH};
+use crate::dma::{MemoryToPeripheral, PeripheralToMemory};
+use crate::gpio::alt;
+use crate::pac;
+use crate::rcc::{Clocks, Enable, Reset};
+use crate::time::Hertz;
+
+#[cfg(not(any(
+    feature = "gpio-f446",
+    feature = "stm32f417",
+    feature = "stm32f427",
+    feature = "stm32f437"
+)))]
+use pac::{sai as regs, SAI as SAI1};
+#[cfg(any(
+    feature = "gpio-f446",
+    feature = "stm32f417",
+    feature = "stm32f427",
+    feature = "stm32f437"
+))]
+use pac::{sai1 as regs, SAI1};
+
+/// SAI peripheral
+pub trait Instance: crate::Sealed + Enable + Reset {
+    #[doc(hidden)]
+    fn ptr() -> *const regs::RegisterBlock;
+    /// Kernel clock of sub-block `block`
+    #[doc(hidden)]
+    fn clock(clocks: &Clocks, block: u8) -> Option<Hertz>;
+}
+
+impl Instance for SAI1 {
+    fn ptr() -> *const regs::RegisterBlock {
+        SAI1::ptr() as *const _
+    }
+
+    #[cfg(not(feature = "gpio-f446"))]
+    fn clock(clocks: &Clocks, block: u8) -> Option<Hertz> {
+        match block {
+            0 => clocks.saia_clk(),
+            _ => clocks.saib_clk(),
+        }
+    }
+
+    #[cfg(feature = "gpio-f446")]
+    fn clock(clocks: &Clocks, _block: u8) -> Option<Hertz> {
+        clocks.sai1_clk()
+    }
+}
+
+#[cfg(feature = "sai2")]
+impl Instance for pac::SAI2 {
+    fn ptr() -> *const regs::RegisterBlock {
+        pac::SAI2::ptr() as *const _
+    }
+
+    fn clock(clocks: &Clocks, _block: u8) -> Option<Hertz> {
+        clocks.sai2_clk()
+    }
+}
+
+/// Pin types of a sub-block
+pub trait BlockPins {
+    type Mclk;
+    type Sck;
+    type Fs;
+    type Sd;
+}
+
+macro_rules! block_pins {
+    ($SAI:ty, $C:literal: $sai:ident, $Mclk:ident, $Sck:ident, $Fs:ident, $Sd:ident) => {
+        impl BlockPins for SubBlock<$SAI, $C> {
+            type Mclk = alt::$sai::$Mclk;
+            type Sck = alt::$sai::$Sck;
+            type Fs = alt::$sai::$Fs;
+            type Sd = alt::$sai::$Sd;
+        }
+    };
+}
+
+block_pins!(SAI1, 0: sai1, MclkA, SckA, FsA, SdA);
+block_pins!(SAI1, 1: sai1, MclkB, SckB, FsB, SdB);
+#[cfg(feature = "sai2")]
+block_pins!(pac::SAI2, 0: sai2, MclkA, SckA, FsA, SdA);
+#[cfg(feature = "sai2")]
+block_pins!(pac::SAI2, 1: sai2, MclkB, SckB, FsB, SdB);
+
+/// Pins of an asynchronous sub-block: `(SCK, FS, SD)` or `(MCLK, SCK, FS, SD)`
+pub trait AsyncPins<B: BlockPins> {
+    fn convert(self);
+}
+
+impl<B, SCK, FS, SD> AsyncPins<B> for (SCK, FS, SD)
+where
+    B: BlockPins,
+    SCK: Into<B::Sck>,
+    FS: Into<B::Fs>,
+    SD: Into<B::Sd>,
+{
+    fn convert(self) {
+        let _: (B::Sck, B::Fs, B::Sd) = (self.0.into(), self.1.into(), self.2.into());
+    }
+}
+
+impl<B, MCLK, SCK, FS, SD> AsyncPins<B> for (MCLK, SCK, FS, SD)
+where
+    B: BlockPins,
+    MCLK: Into<B::Mclk>,
+    SCK: Into<B::Sck>,
+    FS: Into<B::Fs>,
+    SD: Into<B::Sd>,
+{
+    fn convert(self) {
+        let _: (B::Mclk, B::Sck, B::Fs, B::Sd) =
+            (self.0.into(), self.1.into(), self.2.into(), self.3.into());
+    }
+}
+
+/// Audio frame format
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// Philips I2S: frame sync low for the left channel, data one bit after the frame sync edge
+    I2s,
+    /// MSB justified: frame sync high for the left channel, data aligned with the frame sync
+    LeftJustified,
+    /// TDM/DSP: one bit clock frame sync pulse one bit before the first slot
+    Tdm,
+    /// S/PDIF output, transmitter only
+    Spdif,
+}
+
+/// Bits per sample
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataSize {
+    Bits8 = 0b010,
+    Bits10 = 0b011,
+    Bits16 = 0b100,
+    Bits20 = 0b101,
+    Bits24 = 0b110,
+    Bits32 = 0b111,
+}
+
+impl DataSize {
+    pub const fn bits(self) -> u32 {
+        match self {
+            Self::Bits8 => 8,
+            Self::Bits10 => 10,
+            Self::Bits16 => 16,
+            Self::Bits20 => 20,
+            Self::Bits24 => 24,
+            Self::Bits32 => 32,
+        }
+    }
+}
+
+/// Bits per slot
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlotSize {
+    /// Same as the data size
+    DataSize = 0b00,
+    Bits16 = 0b01,
+    Bits32 = 0b10,
+}
+
+/// Companding of 8-bit samples
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Companding {
+    None = 0b00,
+    MuLaw = 0b10,
+    ALaw = 0b11,
+}
+
+/// Sub-block configuration
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    pub protocol: Protocol,
+    pub data_size: DataSize,
+    pub slot_size: SlotSize,
+    /// Slots per frame, 1 to 16
+    pub slots: u8,
+    /// Bit mask of the slots in use
+    pub slot_mask: u16,
+    /// Send the same sample in both slots (transmitter) or only store the first slot
+    /// (receiver), for two slots only
+    pub mono: bool,
+    pub companding: Companding,
+    pub sample_rate: Hertz,
+    /// Generate a master clock of 256 times the sample rate
+    pub master_clock: bool,
+}
+
+impl Default for Config {
+    /// 48 kHz stereo I2S with 16-bit samples
+    fn default() -> Self {
+        Self {
+            protocol: Protocol::I2s,
+            data_size: DataSize::Bits16,
+            slot_size: SlotSize::DataSize,
+            slots: 2,
+            slot_mask: 0b11,
+            mono: false,
+            companding: Companding::None,
+            sample_rate: Hertz::kHz(48),
+            master_clock: true,
+        }
+    }
+}
+
+impl Config {
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    pub fn data_size(mut self, data_size: DataSize) -> Self {
+        self.data_size = data_size;
+        self
+    }
+
+    pub fn slot_size(mut self, slot_size: SlotSize) -> Self {
+        self.slot_size = slot_size;
+        self
+    }
+
+    /// Sets the slots per frame and the active ones.
+    pub fn slots(mut self, slots: u8, mask: u16) -> Self {
+        self.slots = slots;
+        self.slot_mask = mask;
+        self
+    }
+
+    pub fn mono(mut self, mono: bool) -> Self {
+        self.mono = mono;
+        self
+    }
+
+    pub fn companding(mut self, companding: Companding) -> Self {
+        self.companding = companding;
+        self
+    }
+
+    pub fn sample_rate(mut self, sample_rate: Hertz) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    pub fn master_clock(mut self, master_clock: bool) -> Self {
+        self.master_clock = master_clock;
+        self
+    }
+
+    /// Bits per slot
+    pub const fn slot_bits(&self) -> u32 {
+        match self.slot_size {
+            SlotSize::DataSize => self.data_size.bits(),
+            SlotSize::Bits16 => 16,
+            SlotSize::Bits32 => 32,
+        }
+    }
+
+    /// Bit clocks per frame
+    pub const fn frame_length(&self) -> u32 {
+        match self.protocol {
+            Protocol::Spdif => 64,
+            _ => self.slots as u32 * self.slot_bits(),
+        }
+    }
+
+    /// Value of `xFRCR`.
+    pub const fn frame_register(&self) -> u32 {
+        let frl = self.frame_length() - 1;
+        // (active length, FSDEF, FSPOL, FSOFF)
+        let (active, fsdef, fspol, fsoff) = match self.protocol {
+            Protocol::I2s => (self.frame_length() / 2, 1, 0, 1),
+            Protocol::LeftJustified => (self.frame_length() / 2, 1, 1, 0),
+            Protocol::Tdm | Protocol::Spdif => (1, 0, 1, 1),
+        };
+        frl | ((active - 1) << 8) | (fsdef << 16) | (fspol << 17) | (fsoff << 18)
+    }
+
+    /// Value of `xSLOTR`.
+    pub const fn slot_register(&self) -> u32 {
+        let mask = self.slot_mask as u32 & ((1 << self.slots) - 1);
+        ((self.slot_size as u32) << 6) | ((self.slots as u32 - 1) << 8) | (mask << 16)
+    }
+
+    /// `MCKDIV` and `NODIV` bits of `xCR1` for a master clocked by `sai_clk`.
+    ///
+    /// Returns [`Error::SampleRate`] unless the sample rate can be reached exactly.
+    pub fn divider_bits(&self, sai_clk: Hertz) -> Result<u32, Error> {
+        if self.protocol != Protocol::Spdif && !self.is_valid() {
+            return Err(Error::InvalidConfig);
+        }
+        match clock_divider(
+            sai_clk.raw(),
+            self.sample_rate.raw(),
+            self.frame_length(),
+            self.master_clock,
+        ) {
+            Some((mckdiv, rate)) if rate == self.sample_rate.raw() => {
+                let nodiv = if self.master_clock { 0 } else { CR1_NODIV };
+                Ok(nodiv | (u32::from(mckdiv) << 20))
+            }
+            _ => Err(Error::SampleRate),
+        }
+    }
+
+    /// Checks the frame against the limits of the SAI.
+    pub const fn is_valid(&self) -> bool {
+        let frame = self.frame_length();
+        self.slots >= 1
+            && self.slots <= 16
+            && self.slot_bits() >= self.data_size.bits()
+            && frame >= 8
+            && frame <= 256
+            // With a master clock, the frame length must divide 256
+            && (!self.master_clock || frame.is_power_of_two())
+            && (!self.mono || self.slots == 2)
+            && (matches!(self.companding, Companding::None)
+                || matches!(self.data_size, DataSize::Bits8))
+    }
+}
+
+/// Master clock divider (`MCKDIV`) and the resulting sample rate.
+///
+/// With `master_clock`, the master clock runs at 256 times the sample rate, otherwise the
+/// bit clock is derived directly from `sai_clk`.
+pub const fn clock_divider(
+    sai_clk: u32,
+    sample_rate: u32,
+    frame_length: u32,
+    master_clock: bool,
+) -> Option<(u8, u32)> {
+    let ratio = if master_clock { 256 } else { frame_length };
+    let target = sample_rate as u64 * ratio as u64;
+    if target == 0 {
+        return None;
+    }
+    // The divider is 1 for MCKDIV = 0 and 2 * MCKDIV otherwise
+    let div = (sai_clk as u64 + target / 2) / target;
+    let mckdiv = if div <= 1 { 0 } else { (div + 1) / 2 };
+    if mckdiv > 15 {
+        return None;
+    }
+    let real_div = if mckdiv == 0 { 1 } else { 2 * mckdiv };
+    Some((
+        mckdiv as u8,
+        (sai_clk as u64 / (real_div * ratio as u64)) as u32,
+    ))
+}
+
+/// SAI error
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The SAI clock is not enabled in `rcc::CFGR`
+    NoClock,
+    /// The frame does not fit the SAI, see [`Config::is_valid`]
+    InvalidConfig,
+    /// The sample rate cannot be derived exactly from the SAI clock
+    SampleRate,
+    /// Receive FIFO overrun or transmit FIFO underrun
+    Overrun,
+    /// A frame sync came earlier than expected (slave)
+    AnticipatedFrameSync,
+    /// A frame sync was missing (slave)
+    LateFrameSync,
+}
+
+/// SAI interrupt events and status flags
+#[enumflags2::bitflags]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Event {
+    Overrun = 1 << 0,
+    MuteDetected = 1 << 1,
+    WrongClockConfig = 1 << 2,
+    /// The FIFO needs data (transmitter) or holds data (receiver)
+    FifoRequest = 1 << 3,
+    CodecNotReady = 1 << 4,
+    AnticipatedFrameSync = 1 << 5,
+    LateFrameSync = 1 << 6,
+}
+
+/// Unconfigured sub-block; `C` is 0 for A and 1 for B
+pub struct SubBlock<SAI, const C: u8> {
+    _sai: PhantomData<SAI>,
+}
+
+pub type SubBlockA<SAI> = SubBlock<SAI, 0>;
+pub type SubBlockB<SAI> = SubBlock<SAI, 1>;
+
+/// Transmitting sub-block
+pub struct Transmit;
+/// Receiving sub-block
+pub struct Receive;
+
+/// Splits an SAI into its sub-blocks.
+pub trait SaiExt: Sized {
+    fn split(self) -> (SubBlockA<Self>, SubBlockB<Self>);
+}
+
+impl<SAI: Instance> SaiExt for SAI {
+    fn split(self) -> (SubBlockA<Self>, SubBlockB<Self>) {
+        unsafe {
+            SAI::enable_unchecked();
+            SAI::reset_unchecked();
+        }
+        (
+            SubBlock { _sai: PhantomData },
+            SubBlock { _sai: PhantomData },
+        )
+    }
+}
+
+// CR1
+const CR1_MODE_MASTER_TX: u32 = 0b00;
+const CR1_MODE_MASTER_RX: u32 = 0b01;
+const CR1_MODE_SLAVE_TX: u32 = 0b10;
+const CR1_MODE_SLAVE_RX: u32 = 0b11;
+const CR1_PRTCFG_SPDIF: u32 = 0b01 << 2;
+const CR1_CKSTR: u32 = 1 << 9;
+const CR1_SYNCEN_INTERNAL: u32 = 0b01 << 10;
+const CR1_MONO: u32 = 1 << 12;
+const CR1_SAIEN: u32 = 1 << 16;
+const CR1_DMAEN: u32 = 1 << 17;
+const CR1_NODIV: u32 = 1 << 19;
+// SR
+const SR_FLVL_SHIFT: u32 = 16;
+const FLVL_EMPTY: u32 = 0b000;
+const FLVL_FULL: u32 = 0b101;
+
+impl<SAI: Instance, const C: u8> SubBlock<SAI, C>
+where
+    Self: BlockPins,
+{
+    /// Transmitter generating the clock and frame sync.
+    pub fn master_tx(
+        self,
+        pins: impl AsyncPins<Self>,
+        config: Config,
+        clocks: &Clocks,
+    ) -> Result<Sai<SAI, C, Transmit>, Error> {
+        pins.convert();
+        let div = Self::divider(&config, clocks)?;
+        Ok(Sai::configure(CR1_MODE_MASTER_TX | div, config))
+    }
+
+    /// Receiver generating the clock and frame sync.
+    pub fn master_rx(
+        self,
+        pins: impl AsyncPins<Self>,
+        config: Config,
+        clocks: &Clocks,
+    ) -> Result<Sai<SAI, C, Receive>, Error> {
+        pins.convert();
+        let div = Self::divider(&config, clocks)?;
+        Ok(Sai::configure(CR1_MODE_MASTER_RX | div, config))
+    }
+
+    /// Transmitter clocked by an external master.
+    pub fn slave_tx(
+        self,
+        pins: (
+            impl Into<<Self as BlockPins>::Sck>,
+            impl Into<<Self as BlockPins>::Fs>,
+            impl Into<<Self as BlockPins>::Sd>,
+        ),
+        config: Config,
+    ) -> Result<Sai<SAI, C, Transmit>, Error> {
+        AsyncPins::<Self>::convert(pins);
+        Self::check(&config)?;
+        Ok(Sai::configure(CR1_MODE_SLAVE_TX, config))
+    }
+
+    /// Receiver clocked by an external master.
+    pub fn slave_rx(
+        self,
+        pins: (
+            impl Into<<Self as BlockPins>::Sck>,
+            impl Into<<Self as BlockPins>::Fs>,
+            impl Into<<Self as BlockPins>::Sd>,
+        ),
+        config: Config,
+    ) -> Result<Sai<SAI, C, Receive>, Error> {
+        AsyncPins::<Self>::convert(pins);
+        Self::check(&config)?;
+        Ok(Sai::configure(CR1_MODE_SLAVE_RX, config))
+    }
+
+    /// Transmitter using the clock and frame sync of the other sub-block.
+    ///
+    /// `config` must describe the same frame as the other sub-block.
+    pub fn synchronous_tx(
+        self,
+        sd: impl Into<<Self as BlockPins>::Sd>,
+        config: Config,
+    ) -> Result<Sai<SAI, C, Transmit>, Error> {
+        let _: <Self as BlockPins>::Sd = sd.into();
+        Self::check(&config)?;
+        Ok(Sai::configure(
+            CR1_MODE_SLAVE_TX | CR1_SYNCEN_INTERNAL,
+            config,
+        ))
+    }
+
+    /// Receiver using the clock and frame sync of the other sub-block.
+    ///
+    /// `config` must describe the same frame as the other sub-block.
+    pub fn synchronous_rx(
+        self,
+        sd: impl Into<<Self as BlockPins>::Sd>,
+        config: Config,
+    ) -> Result<Sai<SAI, C, Receive>, Error> {
+        let _: <Self as BlockPins>::Sd = sd.into();
+        Self::check(&config)?;
+        Ok(Sai::configure(
+            CR1_MODE_SLAVE_RX | CR1_SYNCEN_INTERNAL,
+            config,
+        ))
+    }
+
+    /// S/PDIF transmitter at `sample_rate`, sending 24-bit samples.
+    pub fn spdif_tx(
+        self,
+        sd: impl Into<<Self as BlockPins>::Sd>,
+        sample_rate: Hertz,
+        clocks: &Clocks,
+    ) -> Result<Sai<SAI, C, Transmit>, Error> {
+        let _: <Self as BlockPins>::Sd = sd.into();
+        let config = Config::default()
+            .protocol(Protocol::Spdif)
+            .data_size(DataSize::Bits24)
+            .sample_rate(sample_rate)
+            .master_clock(false);
+        let div = Self::divider(&config, clocks)?;
+        Ok(Sai::configure(
+            CR1_MODE_MASTER_TX | CR1_PRTCFG_SPDIF | div,
+            config,
+        ))
+    }
+
+    fn check(config: &Config) -> Result<(), Error> {
+        // Slaves do not generate a master clock
+        let config = config.master_clock(false);
+        if config.protocol == Protocol::Spdif || !config.is_valid() {
+            Err(Error::InvalidConfig)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// `MCKDIV` and `NODIV` bits of `CR1`
+    fn divider(config: &Config, clocks: &Clocks) -> Result<u32, Error> {
+        let sai_clk = SAI::clock(clocks, C).ok_or(Error::NoClock)?;
+        config.divider_bits(sai_clk)
+    }
+}
+
+/// Configured sub-block
+pub struct Sai<SAI, const C: u8, DIR> {
+    _sai: PhantomData<(SAI, DIR)>,
+}
+
+impl<SAI: Instance, const C: u8, DIR> Sai<SAI, C, DIR> {
+    fn block() -> &'static regs::CH {
+        // NOTE(unsafe) sub-blocks only access their own registers
+        unsafe { &(*SAI::ptr()).ch[C as usize] }
+    }
+
+    fn configure(cr1: u32, config: Config) -> Self {
+        let ch = Self::block();
+        // Transmitters change data on the falling edge, receivers sample on the rising edge
+        let ckstr = if config.protocol != Protocol::Spdif {
+            CR1_CKSTR
+        } else {
+            0
+        };
+        let mono = if config.mono { CR1_MONO } else { 0 };
+        ch.cr1
+            .write(|w| unsafe { w.bits(cr1 | ckstr | mono | ((config.data_size as u32) << 5)) });
+        ch.cr2
+            .write(|w| unsafe { w.bits((config.companding as u32) << 14) });
+        ch.frcr
+            .write(|w| unsafe { w.bits(config.frame_register()) });
+        ch.slotr
+            .write(|w| unsafe { w.bits(config.slot_register()) });
+        Self { _sai: PhantomData }
+    }
+
+    /// Starts the sub-block. A synchronous sub-block must be enabled before its master.
+    pub fn enable(&mut self) {
+        Self::block()
+            .cr1
+            .modify(|r, w| unsafe { w.bits(r.bits() | CR1_SAIEN) });
+    }
+
+    /// Stops the sub-block at the end of the current frame.
+    pub fn disable(&mut self) {
+        let ch = Self::block();
+        ch.cr1
+            .modify(|r, w| unsafe { w.bits(r.bits() & !CR1_SAIEN) });
+        while ch.cr1.read().bits() & CR1_SAIEN != 0 {}
+    }
+
+    /// Enables or disables DMA requests.
+    pub fn set_dma(&mut self, enable: bool) {
+        Self::block().cr1.modify(|r, w| unsafe {
+            w.bits(if enable {
+                r.bits() | CR1_DMAEN
+            } else {
+                r.bits() & !CR1_DMAEN
+            })
+        });
+    }
+
+    fn fifo_level() -> u32 {
+        (Self::block().sr.read().bits() >> SR_FLVL_SHIFT) & 0b111
+    }
+
+    fn check_errors() -> Result<(), Error> {
+        let ch = Self::block();
+        let sr = ch.sr.read().bits();
+        let error = if sr & Event::Overrun as u32 != 0 {
+            Error::Overrun
+        } else if sr & Event::AnticipatedFrameSync as u32 != 0 {
+            Error::AnticipatedFrameSync
+        } else if sr & Event::LateFrameSync as u32 != 0 {
+            Error::LateFrameSync
+        } else {
+            return Ok(());
+        };
+        ch.clrfr.write(|w| unsafe {
+            w.bits((Event::Overrun | Event::AnticipatedFrameSync | Event::LateFrameSync).bits())
+        });
+        Err(error)
+    }
+
+    /// Disables the sub-block and returns it unconfigured.
+    pub fn release(mut self) -> SubBlock<SAI, C> {
+        self.disable();
+        SubBlock { _sai: PhantomData }
+    }
+
+    fn listen_event(&mut self, disable: Option<BitFlags<Event>>, enable: Option<BitFlags<Event>>) {
+        Self::block().im.modify(|r, w| unsafe {
+            w.bits({
+                let mut bits = r.bits();
+                if let Some(d) = disable {
+                    bits &= !d.bits();
+                }
+                if let Some(e) = enable {
+                    bits |= e.bits();
+                }
+                bits
+            })
+        });
+    }
+}
+
+impl<SAI: Instance, const C: u8> Sai<SAI, C, Transmit> {
+    /// Queues a sample, right aligned.
+    pub fn write(&mut self, sample: u32) -> nb::Result<(), Error> {
+        Self::check_errors()?;
+        if Self::fifo_level() == FLVL_FULL {
+            return Err(nb::Error::WouldBlock);
+        }
+        Self::block().dr.write(|w| unsafe { w.bits(sample) });
+        Ok(())
+    }
+}
+
+impl<SAI: Instance, const C: u8> Sai<SAI, C, Receive> {
+    /// Takes the next received sample, right aligned.
+    pub fn read(&mut self) -> nb::Result<u32, Error> {
+        Self::check_errors()?;
+        if Self::fifo_level() == FLVL_EMPTY {
+            return Err(nb::Error::WouldBlock);
+        }
+        Ok(Self::block().dr.read().bits())
+    }
+}
+
+impl<SAI: Instance, const C: u8, DIR> crate::Listen for Sai<SAI, C, DIR> {
+    type Event = Event;
+
+    fn listen(&mut self, event: impl Into<BitFlags<Self::Event>>) {
+        self.listen_event(None, Some(event.into()));
+    }
+
+    fn listen_only(&mut self, event: impl Into<BitFlags<Self::Event>>) {
+        self.listen_event(Some(BitFlags::ALL), Some(event.into()));
+    }
+
+    fn unlisten(&mut self, event: impl Into<BitFlags<Self::Event>>) {
+        self.listen_event(Some(event.into()), None);
+    }
+}
+
+impl<SAI: Instance, const C: u8, DIR> crate::ReadFlags for Sai<SAI, C, DIR> {
+    type Flag = Event;
+
+    fn flags(&self) -> BitFlags<Self::Flag> {
+        BitFlags::from_bits_truncate(Self::block().sr.read().bits())
+    }
+}
+
+impl<SAI: Instance, const C: u8, DIR> crate::ClearFlags for Sai<SAI, C, DIR> {
+    type Flag = Event;
+
+    fn clear_flags(&mut self, flags: impl Into<BitFlags<Self::Flag>>) {
+        Self::block()
+            .clrfr
+            .write(|w| unsafe { w.bits(flags.into().bits()) });
+    }
+}
+
+unsafe impl<SAI: Instance, const C: u8, DIR> PeriAddress for Sai<SAI, C, DIR> {
+    #[inline(always)]
+    fn address(&self) -> u32 {
+        Self::block().dr.as_ptr() as u32
+    }
+
+    type MemSize = u32;
+}
+
+unsafe impl<SAI, const C: u8, STREAM, const CHANNEL: u8> DMASet<STREAM, CHANNEL, MemoryToPeripheral>
+    for Sai<SAI, C, Transmit>
+where
+    SAICH<SAI, C>: DMASet<STREAM, CHANNEL, MemoryToPeripheral>,
+{
+}
+
+unsafe impl<SAI, const C: u8, STREAM, const CHANNEL: u8> DMASet<STREAM, CHANNEL, PeripheralToMemory>
+    for Sai<SAI, C, Receive>
+where
+    SAICH<SAI, C>: DMASet<STREAM, CHANNEL, PeripheralToMemory>,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_register() {
+        // 32-bit I2S frame, frame sync low for half of it, one bit before the first slot
+        assert_eq!(Config::default().frame_register(), 0x0005_0F1F);
+        let left = Config::default()
+            .protocol(Protocol::LeftJustified)
+            .data_size(DataSize::Bits24)
+            .slot_size(SlotSize::Bits32);
+        assert_eq!(left.frame_register(), 0x0003_1F3F);
+        let tdm = Config::default().protocol(Protocol::Tdm).slots(8, 0xff);
+        assert_eq!(tdm.frame_register(), 0x0006_007F);
+        let spdif = Config::default().protocol(Protocol::Spdif);
+        assert_eq!(spdif.frame_register(), 0x0006_003F);
+    }
+
+    #[test]
+    fn slot_register() {
+        assert_eq!(Config::default().slot_register(), 0x0003_0100);
+        // Slots beyond the frame are masked
+        let tdm = Config::default()
+            .protocol(Protocol::Tdm)
+            .slot_size(SlotSize::Bits32)
+            .slots(8, 0xffff);
+        assert_eq!(tdm.slot_register(), 0x00FF_0780);
+    }
+
+    #[test]
+    fn validity() {
+        assert!(Config::default().is_valid());
+        assert!(!Config::default().slots(0, 0).is_valid());
+        assert!(!Config::default().slots(17, 0xffff).is_valid());
+        // 3 slots of 16 bits do not divide the master clock
+        assert!(!Config::default().slots(3, 0b111).is_valid());
+        assert!(Config::default()
+            .slots(3, 0b111)
+            .master_clock(false)
+            .is_valid());
+        assert!(!Config::default()
+            .slot_size(SlotSize::Bits16)
+            .data_size(DataSize::Bits24)
+            .is_valid());
+        assert!(!Config::default().companding(Companding::MuLaw).is_valid());
+    }
+
+    #[test]
+    fn divider_bits() {
+        let clk = Hertz::from_raw(12_288_000);
+        assert_eq!(Config::default().divider_bits(clk), Ok(0));
+        assert_eq!(
+            Config::default().divider_bits(Hertz::from_raw(49_152_000)),
+            Ok(2 << 20)
+        );
+        // Bit clock at 32 fs
+        assert_eq!(
+            Config::default().master_clock(false).divider_bits(clk),
+            Ok(CR1_NODIV | (4 << 20))
+        );
+        // 12 kHz is the closest to 16 kHz
+        assert_eq!(
+            Config::default()
+                .sample_rate(Hertz::kHz(16))
+                .divider_bits(clk),
+            Err(Error::SampleRate)
+        );
+        assert_eq!(
+            Config::default().slots(0, 0).divider_bits(clk),
+            Err(Error::InvalidConfig)
+        );
+    }
+
+    #[test]
+    fn clock_divider() {
+        // MCLK at 256 fs
+        assert_eq!(
+            super::clock_divider(12_288_000, 48_000, 32, true),
+            Some((0, 48_000))
+        );
+        assert_eq!(
+            super::clock_divider(49_152_000, 48_000, 32, true),
+            Some((2, 48_000))
+        );
+        // Bit clock at 64 fs
+        assert_eq!(
+            super::clock_divider(12_288_000, 48_000, 64, false),
+            Some((2, 48_000))
+        );
+        // Odd ratios round to the next even divider
+        assert_eq!(
+            super::clock_divider(12_288_000, 16_000, 32, true),
+            Some((2, 12_000))
+        );
+        // MCKDIV is limited to 15
+        assert_eq!(super::clock_divider(196_608_000, 8_000, 32, true), None);
+        assert_eq!(super::clock_divider(12_288_000, 0, 32, true), None);
+    }
+}
@@ -19,6 +19,11 @@ use core::marker::PhantomData;
 mod hal_02;
 mod hal_1;
 
+// The async feature requires Rust 1.75
+#[cfg(feature = "async")]
+#[allow(clippy::incompatible_msrv)]
+pub mod asynch;
+
 pub(crate) mod uart_impls;
 pub use uart_impls::Instance;
 use uart_impls::RegisterBlockImpl;
@@ -190,6 +195,7 @@ pub struct Tx<USART: CommonPins, WORD = u8> {
     _word: PhantomData<WORD>,
     usart: USART,
     pin: USART::Tx<PushPull>,
+    baudrate: u32,
 }
 
 pub trait SerialExt: Sized + Instance {
@@ -273,6 +279,18 @@ macro_rules! halUsart {
                     })
                 });
             }
+
+            #[cfg(feature = "async")]
+            fn tx_waker() -> &'static crate::waker::WakerCell {
+                static WAKER: crate::waker::WakerCell = crate::waker::WakerCell::new();
+                &WAKER
+            }
+
+            #[cfg(feature = "async")]
+            fn rx_waker() -> &'static crate::waker::WakerCell {
+                static WAKER: crate::waker::WakerCell = crate::waker::WakerCell::new();
+                &WAKER
+            }
         }
     };
 }
@@ -299,13 +317,13 @@ impl<UART: CommonPins> Rx<UART, u16> {
 
 impl<UART: CommonPins> Tx<UART, u8> {
     pub(crate) fn with_u16_data(self) -> Tx<UART, u16> {
-        Tx::new(self.usart, self.pin)
+        Tx::new(self.usart, self.pin, self.baudrate)
     }
 }
 
 impl<UART: CommonPins> Tx<UART, u16> {
     pub(crate) fn with_u8_data(self) -> Tx<UART, u8> {
-        Tx::new(self.usart, self.pin)
+        Tx::new(self.usart, self.pin, self.baudrate)
     }
 }
 
@@ -323,11 +341,12 @@ impl<UART: CommonPins, WORD> Rx<UART, WORD> {
 }
 
 impl<UART: CommonPins, WORD> Tx<UART, WORD> {
-    pub(crate) fn new(usart: UART, pin: UART::Tx<PushPull>) -> Self {
+    pub(crate) fn new(usart: UART, pin: UART::Tx<PushPull>, baudrate: u32) -> Self {
         Self {
             _word: PhantomData,
             usart,
             pin,
+            baudrate,
         }
     }
 
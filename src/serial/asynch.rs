@@ -0,0 +1,348 @@
+//! Async serial transmitter and receiver using DMA.
+//!
+//! [`Tx::into_async`] and [`Rx::into_async`] take a DMA stream and return halves implementing
+//! [`embedded_io_async::Write`] and [`embedded_io_async::Read`]. Besides the stream interrupt
+//! (see [`crate::dma::future`]), the UART interrupt must be unmasked in the NVIC and its handler
+//! must call [`on_interrupt`]:
+//!
+//! ```rust,ignore
+//! #[interrupt]
+//! fn USART1() {
+//!     serial::asynch::on_interrupt::<pac::USART1>();
+//! }
+//! ```
+
+use core::{future::poll_fn, future::Future, pin::Pin, task::Poll};
+
+use enumflags2::BitFlags;
+
+use super::{CFlag, Error, Event, Flag, Instance, RegisterBlockImpl, Rx, Tx};
+use crate::dma::{
+    future::{start_transfer, AsyncStream},
+    traits::{Channel, DMASet},
+    ChannelX, DmaDirection, MemoryToPeripheral, PeripheralToMemory,
+};
+
+/// Handles the UART interrupt for the async drivers.
+///
+/// Transmission complete wakes the transmitter and an idle line wakes the receiver, each
+/// interrupt is masked until the woken task listens to it again.
+pub fn on_interrupt<UART: Instance>() {
+    let uart = unsafe { &*UART::ptr() };
+    let (tx, rx) = woken(uart.flags());
+    if tx {
+        uart.listen_event(Some(Event::TransmissionComplete.into()), None);
+        UART::tx_waker().wake();
+    }
+    if rx {
+        uart.listen_event(Some(Event::Idle.into()), None);
+        UART::rx_waker().wake();
+    }
+}
+
+/// Whether the flags wake the transmitter and the receiver.
+fn woken(flags: BitFlags<Flag>) -> (bool, bool) {
+    (
+        flags.contains(Flag::TransmissionComplete),
+        flags.contains(Flag::Idle),
+    )
+}
+
+/// Receive error reported by the status flags, if any.
+pub(crate) fn line_error(flags: BitFlags<Flag>) -> Option<Error> {
+    if flags.contains(Flag::ParityError) {
+        Some(Error::Parity)
+    } else if flags.contains(Flag::FramingError) {
+        Some(Error::FrameFormat)
+    } else if flags.contains(Flag::Noise) {
+        Some(Error::Noise)
+    } else if flags.contains(Flag::Overrun) {
+        Some(Error::Overrun)
+    } else {
+        None
+    }
+}
+
+/// State of a pending read after `received` bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RxState {
+    Receiving,
+    /// The line went idle after at least one byte
+    Idle,
+    Error(Error),
+}
+
+fn rx_state(flags: BitFlags<Flag>, received: usize) -> RxState {
+    if let Some(error) = line_error(flags) {
+        RxState::Error(error)
+    } else if flags.contains(Flag::Idle) && received != 0 {
+        RxState::Idle
+    } else {
+        RxState::Receiving
+    }
+}
+
+/// Whether the idle flag, and the errors, must be cleared by reading SR and DR.
+///
+/// A byte waiting in DR would be lost, it is left for the DMA, whose read completes the
+/// clearing sequence.
+fn must_clear_idle(flags: BitFlags<Flag>) -> bool {
+    flags.intersects(Flag::Idle | Flag::ParityError | Flag::FramingError | Flag::Noise)
+        && !flags.contains(Flag::RxNotEmpty)
+}
+
+fn clear_idle<UART: Instance>(uart: &UART::RegisterBlock) {
+    if must_clear_idle(uart.flags()) {
+        uart.clear_idle_interrupt();
+    }
+}
+
+/// Async serial transmitter
+pub struct AsyncTx<UART: Instance, STREAM, const CHANNEL: u8> {
+    tx: Tx<UART>,
+    stream: STREAM,
+}
+
+/// Async serial receiver
+pub struct AsyncRx<UART: Instance, STREAM, const CHANNEL: u8> {
+    rx: Rx<UART>,
+    stream: STREAM,
+}
+
+impl<UART: Instance> Tx<UART> {
+    /// Converts the transmitter to an async one that sends data with `stream`.
+    pub fn into_async<STREAM, const CHANNEL: u8>(
+        self,
+        stream: STREAM,
+    ) -> AsyncTx<UART, STREAM, CHANNEL>
+    where
+        STREAM: AsyncStream,
+        ChannelX<CHANNEL>: Channel,
+        Self: DMASet<STREAM, CHANNEL, MemoryToPeripheral>,
+    {
+        unsafe { (*UART::ptr()).set_dma_tx(true) };
+        AsyncTx { tx: self, stream }
+    }
+}
+
+impl<UART: Instance> Rx<UART> {
+    /// Converts the receiver to an async one that receives data with `stream`.
+    pub fn into_async<STREAM, const CHANNEL: u8>(
+        self,
+        stream: STREAM,
+    ) -> AsyncRx<UART, STREAM, CHANNEL>
+    where
+        STREAM: AsyncStream,
+        ChannelX<CHANNEL>: Channel,
+        Self: DMASet<STREAM, CHANNEL, PeripheralToMemory>,
+    {
+        unsafe { (*UART::ptr()).set_dma_rx(true) };
+        AsyncRx { rx: self, stream }
+    }
+}
+
+impl<UART: Instance, STREAM, const CHANNEL: u8> AsyncTx<UART, STREAM, CHANNEL>
+where
+    STREAM: AsyncStream,
+    ChannelX<CHANNEL>: Channel,
+{
+    /// Sends up to 65535 bytes of `buf` and returns how many were sent.
+    ///
+    /// Completes once the DMA has handed the data to the UART, use [`flush`](Self::flush)
+    /// to wait until the last byte has left the shift register.
+    pub async fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let len = buf.len().min(u16::MAX as usize);
+        let uart = unsafe { &*UART::ptr() };
+        uart.clear_flags(CFlag::TransmissionComplete.into());
+
+        // NOTE(unsafe) `buf` is borrowed until the transfer completes or is aborted on drop
+        let transfer = unsafe {
+            start_transfer::<_, CHANNEL, u8>(
+                &mut self.stream,
+                DmaDirection::MemoryToPeripheral,
+                uart.peri_address(),
+                buf.as_ptr(),
+                len,
+                true,
+            )
+        };
+        transfer.await.map_err(|_| Error::Other)?;
+        Ok(len)
+    }
+
+    /// Waits until the transmission is complete.
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        let uart = unsafe { &*UART::ptr() };
+        poll_fn(|cx| {
+            UART::tx_waker().register(cx.waker());
+            if uart.flags().contains(Flag::TransmissionComplete) {
+                Poll::Ready(Ok(()))
+            } else {
+                uart.listen_event(None, Some(Event::TransmissionComplete.into()));
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Disables DMA requests and returns the transmitter and the stream.
+    pub fn release(self) -> (Tx<UART>, STREAM) {
+        unsafe { (*UART::ptr()).set_dma_tx(false) };
+        (self.tx, self.stream)
+    }
+}
+
+impl<UART: Instance, STREAM, const CHANNEL: u8> AsyncRx<UART, STREAM, CHANNEL>
+where
+    STREAM: AsyncStream,
+    ChannelX<CHANNEL>: Channel,
+{
+    /// Receives into `buf` and returns the number of bytes read.
+    ///
+    /// Completes when `buf` (at most 65535 bytes of it) is full, or when the line goes idle
+    /// after at least one byte was received.
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let len = buf.len().min(u16::MAX as usize);
+        let uart = unsafe { &*UART::ptr() };
+        // Drop a stale idle flag and pending errors from before the transfer
+        clear_idle::<UART>(uart);
+
+        // NOTE(unsafe) `buf` is borrowed until the transfer completes or is aborted on drop
+        let mut transfer = unsafe {
+            start_transfer::<_, CHANNEL, u8>(
+                &mut self.stream,
+                DmaDirection::PeripheralToMemory,
+                uart.peri_address(),
+                buf.as_mut_ptr(),
+                len,
+                true,
+            )
+        };
+
+        poll_fn(|cx| {
+            UART::rx_waker().register(cx.waker());
+            if let Poll::Ready(result) = Pin::new(&mut transfer).poll(cx) {
+                return Poll::Ready(result.map(|_| len).map_err(|_| Error::Other));
+            }
+            match rx_state(uart.flags(), len - transfer.remaining() as usize) {
+                RxState::Error(error) => {
+                    transfer.abort();
+                    clear_idle::<UART>(uart);
+                    Poll::Ready(Err(error))
+                }
+                RxState::Idle => {
+                    let received = len - transfer.abort() as usize;
+                    clear_idle::<UART>(uart);
+                    Poll::Ready(Ok(received))
+                }
+                RxState::Receiving => {
+                    uart.listen_event(None, Some(Event::Idle.into()));
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    /// Disables DMA requests and returns the receiver and the stream.
+    pub fn release(self) -> (Rx<UART>, STREAM) {
+        unsafe { (*UART::ptr()).set_dma_rx(false) };
+        (self.rx, self.stream)
+    }
+}
+
+impl embedded_io::Error for Error {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        match self {
+            Error::FrameFormat | Error::Parity | Error::Noise => {
+                embedded_io::ErrorKind::InvalidData
+            }
+            Error::Overrun | Error::Other => embedded_io::ErrorKind::Other,
+        }
+    }
+}
+
+impl<UART: Instance, STREAM, const CHANNEL: u8> embedded_io::ErrorType
+    for AsyncTx<UART, STREAM, CHANNEL>
+{
+    type Error = Error;
+}
+
+impl<UART: Instance, STREAM, const CHANNEL: u8> embedded_io::ErrorType
+    for AsyncRx<UART, STREAM, CHANNEL>
+{
+    type Error = Error;
+}
+
+impl<UART: Instance, STREAM, const CHANNEL: u8> embedded_io_async::Write
+    for AsyncTx<UART, STREAM, CHANNEL>
+where
+    STREAM: AsyncStream,
+    ChannelX<CHANNEL>: Channel,
+{
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.write(buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.flush().await
+    }
+}
+
+impl<UART: Instance, STREAM, const CHANNEL: u8> embedded_io_async::Read
+    for AsyncRx<UART, STREAM, CHANNEL>
+where
+    STREAM: AsyncStream,
+    ChannelX<CHANNEL>: Channel,
+{
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.read(buf).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wakes_each_half_from_its_flag() {
+        assert_eq!(woken(BitFlags::empty()), (false, false));
+        assert_eq!(
+            woken(Flag::TransmissionComplete | Flag::TxEmpty),
+            (true, false)
+        );
+        assert_eq!(woken(Flag::Idle.into()), (false, true));
+        assert_eq!(woken(Flag::Idle | Flag::TransmissionComplete), (true, true));
+    }
+
+    #[test]
+    fn idle_ends_a_read_after_data() {
+        assert_eq!(rx_state(Flag::Idle.into(), 0), RxState::Receiving);
+        assert_eq!(rx_state(Flag::Idle.into(), 3), RxState::Idle);
+        assert_eq!(rx_state(BitFlags::empty(), 3), RxState::Receiving);
+        assert_eq!(
+            rx_state(Flag::Idle | Flag::Overrun | Flag::RxNotEmpty, 3),
+            RxState::Error(Error::Overrun)
+        );
+        assert_eq!(
+            rx_state(Flag::FramingError | Flag::Noise, 0),
+            RxState::Error(Error::FrameFormat)
+        );
+    }
+
+    #[test]
+    fn keeps_a_waiting_byte() {
+        assert!(!must_clear_idle(BitFlags::empty()));
+        assert!(!must_clear_idle(Flag::TransmissionComplete.into()));
+        assert!(must_clear_idle(Flag::Idle.into()));
+        assert!(must_clear_idle(Flag::Noise.into()));
+        assert!(!must_clear_idle(Flag::Idle | Flag::RxNotEmpty));
+        assert!(!must_clear_idle(Flag::Overrun | Flag::RxNotEmpty));
+    }
+}
@@ -29,6 +29,12 @@ pub trait Instance: crate::Sealed + rcc::Enable + rcc::Reset + rcc::BusClock + C
     fn ptr() -> *const Self::RegisterBlock;
     #[doc(hidden)]
     fn set_stopbits(&self, bits: config::StopBits);
+    #[cfg(feature = "async")]
+    #[doc(hidden)]
+    fn tx_waker() -> &'static crate::waker::WakerCell;
+    #[cfg(feature = "async")]
+    #[doc(hidden)]
+    fn rx_waker() -> &'static crate::waker::WakerCell;
 }
 
 pub trait RegisterBlockImpl: crate::Sealed {
@@ -40,6 +46,9 @@ pub trait RegisterBlockImpl: crate::Sealed {
         clocks: &Clocks,
     ) -> Result<Serial<UART, WORD>, config::InvalidConfig>;
 
+    /// Writes the baud rate divider, the USART is disabled meanwhile.
+    fn set_baudrate(&self, pclk_freq: u32, baud: u32) -> Result<(), config::InvalidConfig>;
+
     fn read_u16(&self) -> nb::Result<u16, Error>;
     fn write_u16(&self, word: u16) -> nb::Result<(), Error>;
 
@@ -116,10 +125,66 @@ pub trait RegisterBlockImpl: crate::Sealed {
         self.listen_event(Some(Event::TxEmpty.into()), None)
     }
 
+    // DMA
+    fn set_dma_tx(&self, enable: bool);
+    fn set_dma_rx(&self, enable: bool);
+
     // PeriAddress
     fn peri_address(&self) -> u32;
 }
 
+/// Returns OVER8 and the BRR value for `baud`.
+fn baud_divider(pclk_freq: u32, baud: u32) -> Option<(bool, u32)> {
+    // The frequency to calculate USARTDIV is this:
+    //
+    // (Taken from STM32F411xC/E Reference Manual,
+    // Section 19.3.4, Equation 1)
+    //
+    // 16 bit oversample: OVER8 = 0
+    // 8 bit oversample:  OVER8 = 1
+    //
+    // USARTDIV =          (pclk)
+    //            ------------------------
+    //            8 x (2 - OVER8) x (baud)
+    //
+    // BUT, the USARTDIV has 4 "fractional" bits, which effectively
+    // means that we need to "correct" the equation as follows:
+    //
+    // USARTDIV =      (pclk) * 16
+    //            ------------------------
+    //            8 x (2 - OVER8) x (baud)
+    //
+    // When OVER8 is enabled, we can only use the lowest three
+    // fractional bits, so we'll need to shift those last four bits
+    // right one bit
+
+    // Calculate correct baudrate divisor on the fly
+    if (pclk_freq / 16) >= baud {
+        // We have the ability to oversample to 16 bits, take
+        // advantage of it.
+        //
+        // We also add `baud / 2` to the `pclk_freq` to ensure
+        // rounding of values to the closest scale, rather than the
+        // floored behavior of normal integer division.
+        let div = (pclk_freq + (baud / 2)) / baud;
+        Some((false, div))
+    } else if (pclk_freq / 8) >= baud {
+        // We are close enough to pclk where we can only
+        // oversample 8.
+        //
+        // See note above regarding `baud` and rounding.
+        let div = ((pclk_freq * 2) + (baud / 2)) / baud;
+
+        // Ensure the the fractional bits (only 3) are
+        // right-aligned.
+        let frac = div & 0xF;
+        let div = (div & !0xF) | (frac >> 1);
+        Some((true, div))
+    } else {
+        None
+    }
+}
+
 macro_rules! uartCommon {
     ($RegisterBlock:ty) => {
         impl RegisterBlockImpl for $RegisterBlock {
@@ -141,55 +206,7 @@ macro_rules! uartCommon {
 
                 let pclk_freq = UART::clock(clocks).raw();
                 let baud = config.baudrate.0;
-
-                // The frequency to calculate USARTDIV is this:
-                //
-                // (Taken from STM32F411xC/E Reference Manual,
-                // Section 19.3.4, Equation 1)
-                //
-                // 16 bit oversample: OVER8 = 0
-                // 8 bit oversample:  OVER8 = 1
-                //
-                // USARTDIV =          (pclk)
-                //            ------------------------
-                //            8 x (2 - OVER8) x (baud)
-                //
-                // BUT, the USARTDIV has 4 "fractional" bits, which effectively
-                // means that we need to "correct" the equation as follows:
-                //
-                // USARTDIV =      (pclk) * 16
-                //            ------------------------
-                //            8 x (2 - OVER8) x (baud)
-                //
-                // When OVER8 is enabled, we can only use the lowest three
-                // fractional bits, so we'll need to shift those last four bits
-                // right one bit
-
-                // Calculate correct baudrate divisor on the fly
-                let (over8, div) = if (pclk_freq / 16) >= baud {
-                    // We have the ability to oversample to 16 bits, take
-                    // advantage of it.
-                    //
-                    // We also add `baud / 2` to the `pclk_freq` to ensure
-                    // rounding of values to the closest scale, rather than the
-                    // floored behavior of normal integer division.
-                    let div = (pclk_freq + (baud / 2)) / baud;
-                    (false, div)
-                } else if (pclk_freq / 8) >= baud {
-                    // We are close enough to pclk where we can only
-                    // oversample 8.
-                    //
-                    // See note above regarding `baud` and rounding.
-                    let div = ((pclk_freq * 2) + (baud / 2)) / baud;
-
-                    // Ensure the the fractional bits (only 3) are
-                    // right-aligned.
-                    let frac = div & 0xF;
-                    let div = (div & !0xF) | (frac >> 1);
-                    (true, div)
-                } else {
-                    return Err(config::InvalidConfig);
-                };
+                let (over8, div) = baud_divider(pclk_freq, baud).ok_or(config::InvalidConfig)?;
 
                 let register_block = unsafe { &*UART::ptr() };
                 register_block.brr.write(|w| unsafe { w.bits(div) });
@@ -221,13 +238,23 @@ macro_rules! uartCommon {
                 }
 
                 let serial = Serial {
-                    tx: Tx::new(uart, pins.0.into()),
+                    tx: Tx::new(uart, pins.0.into(), baud),
                     rx: Rx::new(pins.1.into()),
                 };
                 serial.tx.usart.set_stopbits(config.stopbits);
                 Ok(serial)
             }
 
+            fn set_baudrate(&self, pclk_freq: u32, baud: u32) -> Result<(), config::InvalidConfig> {
+                let (over8, div) = baud_divider(pclk_freq, baud).ok_or(config::InvalidConfig)?;
+                let enabled = self.cr1.read().ue().bit_is_set();
+                self.cr1.modify(|_, w| w.ue().clear_bit());
+                self.brr.write(|w| unsafe { w.bits(div) });
+                self.cr1
+                    .modify(|_, w| w.over8().bit(over8).ue().bit(enabled));
+                Ok(())
+            }
+
             fn read_u16(&self) -> nb::Result<u16, Error> {
                 // NOTE(unsafe) atomic read with no side effects
                 let sr = self.sr.read();
@@ -313,6 +340,14 @@ macro_rules! uartCommon {
                 });
             }
 
+            fn set_dma_tx(&self, enable: bool) {
+                self.cr3.modify(|_, w| w.dmat().bit(enable));
+            }
+
+            fn set_dma_rx(&self, enable: bool) {
+                self.cr3.modify(|_, w| w.dmar().bit(enable));
+            }
+
             fn peri_address(&self) -> u32 {
                 self.dr.as_ptr() as u32
             }
@@ -538,6 +573,21 @@ impl<UART: Instance, WORD> Serial<UART, WORD> {
     }
 }
 
+impl<UART: Instance, WORD> Serial<UART, WORD> {
+    /// Recalculates the baud rate divider for new `Clocks`, e.g. after
+    /// [`CFGR::reconfigure`](crate::rcc::CFGR::reconfigure).
+    pub fn configure(&mut self, clocks: &Clocks) -> Result<(), config::InvalidConfig> {
+        self.tx.configure(clocks)
+    }
+}
+
+impl<UART: Instance, WORD> Tx<UART, WORD> {
+    /// Recalculates the baud rate divider for new `Clocks`, which also applies to the `Rx` half.
+    pub fn configure(&mut self, clocks: &Clocks) -> Result<(), config::InvalidConfig> {
+        unsafe { (*UART::ptr()).set_baudrate(UART::clock(clocks).raw(), self.baudrate) }
+    }
+}
+
 unsafe impl<UART: Instance> PeriAddress for Rx<UART, u8> {
     #[inline(always)]
     fn address(&self) -> u32 {
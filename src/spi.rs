@@ -36,6 +36,11 @@ pub struct Mode {
 mod hal_02;
 mod hal_1;
 
+// The async feature requires Rust 1.75
+#[cfg(feature = "async")]
+#[allow(clippy::incompatible_msrv)]
+pub mod asynch;
+
 use crate::pac::spi1;
 use crate::rcc;
 
@@ -54,6 +59,8 @@ pub enum Error {
     ModeFault,
     /// CRC error
     Crc,
+    /// DMA transfer error
+    Dma,
 }
 
 /// A filler type for when the SCK pin is unnecessary
@@ -156,6 +163,7 @@ pub struct Inner<SPI: Instance> {
 pub struct Spi<SPI: Instance, const BIDI: bool = false, W = u8> {
     inner: Inner<SPI>,
     pins: (SPI::Sck, SPI::Miso, SPI::Mosi),
+    freq: Hertz,
     _operation: PhantomData<W>,
 }
 
@@ -476,8 +484,8 @@ impl<SPI: Instance> Spi<SPI, false, u8> {
 
         let pins = (pins.0.into(), pins.1.into(), pins.2.into());
 
-        Self::_new(spi, pins)
-            .pre_init(mode.into(), freq, SPI::clock(clocks))
+        Self::_new(spi, pins, freq)
+            .pre_init(mode.into(), SPI::clock(clocks))
             .init()
     }
 }
@@ -505,8 +513,8 @@ impl<SPI: Instance> Spi<SPI, true, u8> {
 
         let pins = (pins.0.into(), NoPin::new().into(), pins.1.into());
 
-        Self::_new(spi, pins)
-            .pre_init(mode.into(), freq, SPI::clock(clocks))
+        Self::_new(spi, pins, freq)
+            .pre_init(mode.into(), SPI::clock(clocks))
             .init()
     }
 }
@@ -578,17 +586,18 @@ impl<SPI: Instance, const BIDI: bool, W> SpiSlave<SPI, BIDI, W> {
 }
 
 impl<SPI: Instance, const BIDI: bool, W> Spi<SPI, BIDI, W> {
-    fn _new(spi: SPI, pins: (SPI::Sck, SPI::Miso, SPI::Mosi)) -> Self {
+    fn _new(spi: SPI, pins: (SPI::Sck, SPI::Miso, SPI::Mosi), freq: Hertz) -> Self {
         Self {
             inner: Inner::new(spi),
             pins,
+            freq,
             _operation: PhantomData,
         }
     }
 
     /// Convert the spi to another mode.
     fn into_mode<const BIDI2: bool, W2: FrameSize>(self) -> Spi<SPI, BIDI2, W2> {
-        let mut spi = Spi::_new(self.inner.spi, self.pins);
+        let mut spi = Spi::_new(self.inner.spi, self.pins, self.freq);
         spi.enable(false);
         spi.init()
     }
@@ -613,21 +622,11 @@ impl<SPI: Instance, const BIDI: bool, W> SpiSlave<SPI, BIDI, W> {
 
 impl<SPI: Instance, const BIDI: bool, W> Spi<SPI, BIDI, W> {
     /// Pre initializing the SPI bus.
-    fn pre_init(self, mode: Mode, freq: Hertz, clock: Hertz) -> Self {
+    fn pre_init(self, mode: Mode, clock: Hertz) -> Self {
         // disable SS output
         self.spi.cr2.write(|w| w.ssoe().clear_bit());
 
-        let br = match clock.raw() / freq.raw() {
-            0 => unreachable!(),
-            1..=2 => 0b000,
-            3..=5 => 0b001,
-            6..=11 => 0b010,
-            12..=23 => 0b011,
-            24..=47 => 0b100,
-            48..=95 => 0b101,
-            96..=191 => 0b110,
-            _ => 0b111,
-        };
+        let br = baud_rate_divider(clock, self.freq);
 
         self.spi.cr1.write(|w| {
             w.cpha().bit(mode.phase == Phase::CaptureOnSecondTransition);
@@ -650,6 +649,35 @@ impl<SPI: Instance, const BIDI: bool, W> Spi<SPI, BIDI, W> {
     }
 }
 
+impl<SPI: Instance, const BIDI: bool, W> Spi<SPI, BIDI, W> {
+    /// Recalculates the baud rate prescaler for new `Clocks`, e.g. after
+    /// [`CFGR::reconfigure`](crate::rcc::CFGR::reconfigure).
+    ///
+    /// Must not be called during a transfer.
+    pub fn configure(&mut self, clocks: &Clocks) {
+        let br = baud_rate_divider(SPI::clock(clocks), self.freq);
+        let enabled = self.spi.cr1.read().spe().bit_is_set();
+        self.enable(false);
+        self.spi.cr1.modify(|_, w| w.br().bits(br));
+        self.enable(enabled);
+    }
+}
+
+/// Returns the `BR` bits for an SCK frequency close to `freq`.
+fn baud_rate_divider(clock: Hertz, freq: Hertz) -> u8 {
+    match clock.raw() / freq.raw() {
+        0 => unreachable!(),
+        1..=2 => 0b000,
+        3..=5 => 0b001,
+        6..=11 => 0b010,
+        12..=23 => 0b011,
+        24..=47 => 0b100,
+        48..=95 => 0b101,
+        96..=191 => 0b110,
+        _ => 0b111,
+    }
+}
+
 impl<SPI: Instance, const BIDI: bool, W> SpiSlave<SPI, BIDI, W> {
     /// Pre initializing the SPI bus.
     fn pre_init(self, mode: Mode) -> Self {
@@ -0,0 +1,271 @@
+//! Async SPI master using DMA.
+//!
+//! [`Spi::into_async`] takes a transmit and a receive stream and returns an [`AsyncSpi`]
+//! implementing [`embedded_hal_async::spi::SpiBus`]. Both stream interrupts must call
+//! `on_interrupt` (see [`crate::dma::future`]).
+
+use core::{future::poll_fn, future::Future, pin::Pin, task::Poll};
+
+use super::{Error, Instance, Rx, Spi, Tx};
+use crate::dma::{
+    future::{start_transfer, AsyncStream},
+    traits::{Channel, DMASet},
+    ChannelX, DmaDirection, MemoryToPeripheral, PeripheralToMemory,
+};
+
+/// Word clocked out while only reading
+static DUMMY_TX: u8 = 0;
+
+/// Words a stream can move at once
+const MAX_CHUNK: usize = u16::MAX as usize;
+
+/// Offset and length of the chunks of a `len` word transfer.
+fn chunks(len: usize) -> impl Iterator<Item = (usize, usize)> {
+    (0..len)
+        .step_by(MAX_CHUNK)
+        .map(move |offset| (offset, (len - offset).min(MAX_CHUNK)))
+}
+
+/// Async SPI master, full duplex with 8-bit frames
+pub struct AsyncSpi<SPI: Instance, TXS, const TXC: u8, RXS, const RXC: u8> {
+    spi: Spi<SPI, false, u8>,
+    tx_stream: TXS,
+    rx_stream: RXS,
+}
+
+impl<SPI: Instance> Spi<SPI, false, u8> {
+    /// Converts the bus to an async one transmitting with `tx_stream` and receiving with
+    /// `rx_stream`.
+    pub fn into_async<TXS, const TXC: u8, RXS, const RXC: u8>(
+        self,
+        tx_stream: TXS,
+        rx_stream: RXS,
+    ) -> AsyncSpi<SPI, TXS, TXC, RXS, RXC>
+    where
+        TXS: AsyncStream,
+        RXS: AsyncStream,
+        ChannelX<TXC>: Channel,
+        ChannelX<RXC>: Channel,
+        Tx<SPI>: DMASet<TXS, TXC, MemoryToPeripheral>,
+        Rx<SPI>: DMASet<RXS, RXC, PeripheralToMemory>,
+    {
+        self.inner.spi.cr2.modify(|_, w| {
+            w.txdmaen().enabled();
+            w.rxdmaen().enabled()
+        });
+        AsyncSpi {
+            spi: self,
+            tx_stream,
+            rx_stream,
+        }
+    }
+}
+
+impl<SPI: Instance, TXS, const TXC: u8, RXS, const RXC: u8> AsyncSpi<SPI, TXS, TXC, RXS, RXC>
+where
+    TXS: AsyncStream,
+    RXS: AsyncStream,
+    ChannelX<TXC>: Channel,
+    ChannelX<RXC>: Channel,
+{
+    /// Disables DMA requests and returns the bus and both streams.
+    pub fn release(self) -> (Spi<SPI, false, u8>, TXS, RXS) {
+        self.spi.inner.spi.cr2.modify(|_, w| {
+            w.txdmaen().disabled();
+            w.rxdmaen().disabled()
+        });
+        (self.spi, self.tx_stream, self.rx_stream)
+    }
+
+    /// Clocks `len` words, reading into `rx` and writing from `tx`, in chunks the streams can
+    /// handle.
+    ///
+    /// # Safety
+    ///
+    /// `rx` and `tx` must be valid for `len` words if they are incremented, for one word
+    /// otherwise.
+    async unsafe fn run(
+        &mut self,
+        rx: *mut u8,
+        rx_increment: bool,
+        tx: *const u8,
+        tx_increment: bool,
+        len: usize,
+    ) -> Result<(), Error> {
+        for (offset, chunk) in chunks(len) {
+            let rx = if rx_increment { rx.add(offset) } else { rx };
+            let tx = if tx_increment { tx.add(offset) } else { tx };
+            self.run_chunk(rx, rx_increment, tx, tx_increment, chunk)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async unsafe fn run_chunk(
+        &mut self,
+        rx: *mut u8,
+        rx_increment: bool,
+        tx: *const u8,
+        tx_increment: bool,
+        len: usize,
+    ) -> Result<(), Error> {
+        let spi = &self.spi.inner.spi;
+        // Drop stale data and clear OVR before the receive stream starts
+        let _ = spi.dr.read();
+        let _ = spi.sr.read();
+
+        let address = spi.dr.as_ptr() as u32;
+        // The receive stream must be ready before the first word is clocked
+        let mut rx_transfer = start_transfer::<_, RXC, u8>(
+            &mut self.rx_stream,
+            DmaDirection::PeripheralToMemory,
+            address,
+            rx,
+            len,
+            rx_increment,
+        );
+        let mut tx_transfer = start_transfer::<_, TXC, u8>(
+            &mut self.tx_stream,
+            DmaDirection::MemoryToPeripheral,
+            address,
+            tx,
+            len,
+            tx_increment,
+        );
+
+        let (mut rx_done, mut tx_done) = (false, false);
+        poll_fn(|cx| {
+            if !rx_done {
+                match Pin::new(&mut rx_transfer).poll(cx) {
+                    Poll::Ready(Ok(())) => rx_done = true,
+                    Poll::Ready(Err(_)) => return Poll::Ready(Err(Error::Dma)),
+                    Poll::Pending => {}
+                }
+            }
+            if !tx_done {
+                match Pin::new(&mut tx_transfer).poll(cx) {
+                    Poll::Ready(Ok(())) => tx_done = true,
+                    Poll::Ready(Err(_)) => return Poll::Ready(Err(Error::Dma)),
+                    Poll::Pending => {}
+                }
+            }
+            if rx_done && tx_done {
+                Poll::Ready(Ok(()))
+            } else {
+                Poll::Pending
+            }
+        })
+        .await?;
+
+        if self.spi.is_overrun() {
+            let _ = spi.dr.read();
+            let _ = spi.sr.read();
+            return Err(Error::Overrun);
+        }
+        while self.spi.is_busy() {}
+        Ok(())
+    }
+
+    /// Reads `words`, sending zeros.
+    pub async fn read(&mut self, words: &mut [u8]) -> Result<(), Error> {
+        // NOTE(unsafe) `words` and `DUMMY_TX` outlive the transfer
+        unsafe {
+            self.run(words.as_mut_ptr(), true, &DUMMY_TX, false, words.len())
+                .await
+        }
+    }
+
+    /// Writes `words`, discarding the received data.
+    pub async fn write(&mut self, words: &[u8]) -> Result<(), Error> {
+        let mut dummy = 0u8;
+        // NOTE(unsafe) `words` and `dummy` outlive the transfer
+        unsafe {
+            self.run(&mut dummy, false, words.as_ptr(), true, words.len())
+                .await
+        }
+    }
+
+    /// Writes `write` while reading into `read`.
+    ///
+    /// When the lengths differ, the shorter buffer is padded with zeros on the write side or
+    /// discarded data on the read side.
+    pub async fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Error> {
+        let common = read.len().min(write.len());
+        // NOTE(unsafe) both buffers outlive the transfer
+        unsafe {
+            self.run(read.as_mut_ptr(), true, write.as_ptr(), true, common)
+                .await?;
+        }
+        if read.len() > common {
+            self.read(&mut read[common..]).await
+        } else {
+            self.write(&write[common..]).await
+        }
+    }
+
+    /// Writes `words` and replaces them with the received data.
+    pub async fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Error> {
+        let rx = words.as_mut_ptr();
+        // The transmit stream always runs ahead of the receive stream, so each word is sent
+        // before it is overwritten
+        // NOTE(unsafe) `words` outlives the transfer
+        unsafe { self.run(rx, true, rx as *const u8, true, words.len()).await }
+    }
+}
+
+impl<SPI: Instance, TXS, const TXC: u8, RXS, const RXC: u8> embedded_hal::spi::ErrorType
+    for AsyncSpi<SPI, TXS, TXC, RXS, RXC>
+{
+    type Error = Error;
+}
+
+impl<SPI: Instance, TXS, const TXC: u8, RXS, const RXC: u8> embedded_hal_async::spi::SpiBus<u8>
+    for AsyncSpi<SPI, TXS, TXC, RXS, RXC>
+where
+    TXS: AsyncStream,
+    RXS: AsyncStream,
+    ChannelX<TXC>: Channel,
+    ChannelX<RXC>: Channel,
+{
+    async fn read(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        self.read(words).await
+    }
+
+    async fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        self.write(words).await
+    }
+
+    async fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
+        self.transfer(read, write).await
+    }
+
+    async fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        self.transfer_in_place(words).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        // Every transfer waits for the bus to go idle
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transfer_chunks() {
+        assert_eq!(chunks(0).next(), None);
+        let mut one = chunks(10);
+        assert_eq!(one.next(), Some((0, 10)));
+        assert_eq!(one.next(), None);
+        let mut full = chunks(MAX_CHUNK);
+        assert_eq!(full.next(), Some((0, MAX_CHUNK)));
+        assert_eq!(full.next(), None);
+        let mut three = chunks(2 * MAX_CHUNK + 5);
+        assert_eq!(three.next(), Some((0, MAX_CHUNK)));
+        assert_eq!(three.next(), Some((MAX_CHUNK, MAX_CHUNK)));
+        assert_eq!(three.next(), Some((2 * MAX_CHUNK, 5)));
+        assert_eq!(three.next(), None);
+    }
+}
@@ -5,6 +5,7 @@
 #![allow(non_upper_case_globals)]
 
 use core::convert::TryFrom;
+use core::marker::PhantomData;
 use cortex_m::peripheral::syst::SystClkSource;
 use cortex_m::peripheral::SYST;
 use enumflags2::BitFlags;
@@ -22,6 +23,18 @@ pub mod delay;
 pub use delay::*;
 pub mod pwm;
 pub use pwm::*;
+pub mod capture;
+pub use capture::Capture;
+pub mod compare;
+pub use compare::{OutputCompare, OutputCompareExt};
+pub mod advanced;
+pub use advanced::{Alignment, BreakConfig, CommutationTrigger, LockLevel};
+pub mod pwm_dma;
+pub use pwm_dma::{BurstChannels, PwmDmaStream};
+pub mod slave;
+pub use slave::{
+    Etr, EtrConfig, EtrPrescaler, InternalTrigger, Itr, SlaveMode, Trigger, TriggerSource,
+};
 #[cfg(not(feature = "gpio-f410"))]
 pub mod pwm_input;
 #[cfg(not(feature = "gpio-f410"))]
@@ -150,6 +163,10 @@ pub enum Error {
     /// Timer is disabled
     Disabled,
     WrongAutoReload,
+    /// The registers are write-protected by the BDTR lock level
+    Locked,
+    /// The frequency can not be reached with the 16-bit prescaler
+    WrongPrescaler,
 }
 
 pub trait TimerExt: Sized {
@@ -290,8 +307,8 @@ pub enum Ocm {
     PwmMode2 = 7,
 }
 
-/// Wrapper type that indicates which register of the contained timer to use for DMA.
-pub struct CCR<T, const C: u8>(T);
+/// Capture/compare register `C` of timer `T` as DMA source or destination.
+pub struct CCR<T, const C: u8>(PhantomData<T>);
 pub type CCR1<T> = CCR<T, 0>;
 pub type CCR2<T> = CCR<T, 1>;
 pub type CCR3<T> = CCR<T, 2>;
@@ -300,6 +317,13 @@ pub type CCR4<T> = CCR<T, 3>;
 /// Wrapper type that indicates which register of the contained timer to use for DMA.
 pub struct DMAR<T>(T);
 
+impl<T> DMAR<T> {
+    /// Returns the timer
+    pub fn release(self) -> T {
+        self.0
+    }
+}
+
 mod sealed {
     use super::{BitFlags, Channel, Event, Flag, IdleState, Ocm, Polarity};
     pub trait General {
@@ -325,6 +349,7 @@ mod sealed {
         fn read_count(&self) -> Self::Width;
         fn write_count(&mut self, value: Self::Width);
         fn start_one_pulse(&mut self);
+        fn enable_one_pulse(&mut self, b: bool);
         fn start_free(&mut self, update: bool);
         fn cr1_reset(&mut self);
         fn cnt_reset(&mut self);
@@ -338,6 +363,10 @@ mod sealed {
         fn enable_channel(channel: u8, b: bool);
         fn set_channel_polarity(channel: u8, p: Polarity);
         fn set_nchannel_polarity(channel: u8, p: Polarity);
+        fn set_input_capture(channel: u8, ccs: u8, psc: u8, filter: u8);
+        fn set_output_mode(channel: u8, mode: Ocm, preload: bool);
+        fn set_capture_edge(channel: u8, falling: bool, both: bool);
+        fn enable_dma_request(channel: u8, b: bool);
     }
 
     pub trait Advanced: WithPwmCommon {
@@ -345,6 +374,11 @@ mod sealed {
         fn set_dtg_value(value: u8);
         fn read_dtg_value() -> u8;
         fn idle_state(channel: u8, comp: bool, s: IdleState);
+        fn read_bdtr() -> u32;
+        fn modify_bdtr(mask: u32, bits: u32);
+        fn set_repetition_counter(value: u8);
+        fn enable_com_preload(b: bool, on_trigger: bool);
+        fn trigger_com();
     }
 
     pub trait WithPwm: WithPwmCommon {
@@ -356,8 +390,29 @@ mod sealed {
         type Mms;
         fn master_mode(&mut self, mode: Self::Mms);
     }
+
+    pub trait SlaveTimer: General {
+        fn slave_mode(&mut self, sms: u8, ts: u8);
+    }
+
+    pub trait WithEtr: SlaveTimer {
+        fn set_etr(&mut self, etp: bool, etps: u8, etf: u8);
+        fn enable_external_clock2(&mut self, b: bool);
+    }
+
+    pub trait CenterAligned: General {
+        fn set_cms(&mut self, cms: u8);
+    }
+
+    pub trait WithDmar: General {
+        fn set_dma_burst(&mut self, dba: u8, dbl: u8);
+        fn enable_update_dma(&mut self, b: bool);
+    }
 }
-pub(crate) use sealed::{Advanced, General, MasterTimer, WithPwm, WithPwmCommon};
+pub(crate) use sealed::{
+    Advanced, CenterAligned, General, MasterTimer, SlaveTimer, WithDmar, WithEtr, WithPwm,
+    WithPwmCommon,
+};
 
 pub trait Instance:
     crate::Sealed + rcc::Enable + rcc::Reset + rcc::BusTimerClock + General
@@ -467,6 +522,12 @@ macro_rules! hal {
                 self.cr1.modify(|_, w| unsafe { w.bits(1 << 3) }.cen().set_bit());
             }
             #[inline(always)]
+            fn enable_one_pulse(&mut self, b: bool) {
+                // OPM, not in the PAC of every 1-channel timer
+                self.cr1
+                    .modify(|r, w| unsafe { w.bits((r.bits() & !(1 << 3)) | (u32::from(b) << 3)) });
+            }
+            #[inline(always)]
             fn start_free(&mut self, update: bool) {
                 self.cr1.modify(|_, w| w.cen().set_bit().udis().bit(!update));
             }
@@ -529,6 +590,53 @@ macro_rules! hal {
                         unsafe { bb::write(&tim.ccer, c*4 + 3, p == Polarity::ActiveLow); }
                     }
                 }
+
+                #[inline(always)]
+                fn set_input_capture(c: u8, ccs: u8, psc: u8, filter: u8) {
+                    if c < Self::CH_NUMBER {
+                        // CCMR1 at 0x18 and CCMR2 at 0x1C, not all timers have both
+                        let ccmr = unsafe { (<$TIM>::ptr() as *mut u32).add(6 + c as usize / 2) };
+                        let shift = 8 * (c % 2);
+                        let bits = ((u32::from(filter & 0xf) << 4) | (u32::from(psc & 0b11) << 2) | u32::from(ccs & 0b11)) << shift;
+                        unsafe {
+                            let r = ccmr.read_volatile();
+                            ccmr.write_volatile((r & !(0xff << shift)) | bits);
+                        }
+                    }
+                }
+
+                #[inline(always)]
+                fn set_output_mode(c: u8, mode: Ocm, preload: bool) {
+                    if c < Self::CH_NUMBER {
+                        let ccmr = unsafe { (<$TIM>::ptr() as *mut u32).add(6 + c as usize / 2) };
+                        let shift = 8 * (c % 2);
+                        // OCxM and OCxPE, CCxS = 0b00 selects output
+                        let bits = ((mode as u32) << 4 | u32::from(preload) << 3) << shift;
+                        unsafe {
+                            let r = ccmr.read_volatile();
+                            ccmr.write_volatile((r & !(0b0111_1011 << shift)) | bits);
+                        }
+                    }
+                }
+
+                #[inline(always)]
+                fn set_capture_edge(c: u8, falling: bool, both: bool) {
+                    let tim = unsafe { &*<$TIM>::ptr() };
+                    if c < Self::CH_NUMBER {
+                        unsafe {
+                            bb::write(&tim.ccer, c*4 + 1, falling);
+                            bb::write(&tim.ccer, c*4 + 3, both);
+                        }
+                    }
+                }
+
+                #[inline(always)]
+                fn enable_dma_request(c: u8, b: bool) {
+                    let tim = unsafe { &*<$TIM>::ptr() };
+                    if c < Self::CH_NUMBER {
+                        unsafe { bb::write(&tim.dier, c + 9, b); }
+                    }
+                }
             }
 
             $(
@@ -560,6 +668,30 @@ macro_rules! hal {
                             }
                         }
                     }
+                    fn read_bdtr() -> u32 {
+                        let tim = unsafe { &*<$TIM>::ptr() };
+                        tim.bdtr.read().bits()
+                    }
+                    fn modify_bdtr(mask: u32, bits: u32) {
+                        let tim = unsafe { &*<$TIM>::ptr() };
+                        tim.bdtr.modify(|r, w| unsafe { w.bits((r.bits() & !mask) | (bits & mask)) });
+                    }
+                    fn set_repetition_counter(value: u8) {
+                        let tim = unsafe { &*<$TIM>::ptr() };
+                        tim.rcr.write(|w| unsafe { w.bits(value.into()) });
+                    }
+                    fn enable_com_preload(b: bool, on_trigger: bool) {
+                        let tim = unsafe { &*<$TIM>::ptr() };
+                        // CCUS, then CCPC
+                        unsafe {
+                            bb::write(&tim.cr2, 2, on_trigger);
+                            bb::write(&tim.cr2, 0, b);
+                        }
+                    }
+                    fn trigger_com() {
+                        let tim = unsafe { &*<$TIM>::ptr() };
+                        tim.egr.write(|w| unsafe { w.bits(1 << 5) });
+                    }
                 }
             )?
 
@@ -567,7 +699,8 @@ macro_rules! hal {
             unsafe impl<const C: u8> PeriAddress for CCR<$TIM, C> {
                 #[inline(always)]
                 fn address(&self) -> u32 {
-                    self.0.ccr[C as usize].as_ptr() as u32
+                    // NOTE(unsafe) only the address is taken
+                    unsafe { core::ptr::addr_of!((*<$TIM>::ptr()).ccr[C as usize]) as u32 }
                 }
 
                 type MemSize = $bits;
@@ -594,6 +727,19 @@ macro_rules! with_dmar {
 
             type MemSize = $memsize;
         }
+
+        impl WithDmar for $TIM {
+            #[inline(always)]
+            fn set_dma_burst(&mut self, dba: u8, dbl: u8) {
+                self.dcr.write(|w| unsafe {
+                    w.bits(u32::from(dbl & 0b1_1111) << 8 | u32::from(dba & 0b1_1111))
+                });
+            }
+            #[inline(always)]
+            fn enable_update_dma(&mut self, b: bool) {
+                unsafe { bb::write(&self.dier, 8, b) };
+            }
+        }
     };
 }
 
@@ -820,6 +966,86 @@ hal!(pac::TIM1: [Timer1, u16, dmar: u16, c: (4, _aoe), m: tim1,]);
 #[cfg(feature = "tim5")]
 hal!(pac::TIM5: [Timer5, u16, dmar: u16, c: (4), m: tim5,]);
 
+macro_rules! with_slave {
+    ($TIM:ty $(, $etr:ident)?) => {
+        impl SlaveTimer for $TIM {
+            #[inline(always)]
+            fn slave_mode(&mut self, sms: u8, ts: u8) {
+                self.smcr.modify(|r, w| unsafe {
+                    w.bits(
+                        (r.bits() & !0x77) | (u32::from(ts & 0b111) << 4) | u32::from(sms & 0b111),
+                    )
+                });
+            }
+        }
+
+        $(with_slave!(@$etr $TIM);)?
+    };
+    (@etr $TIM:ty) => {
+        impl WithEtr for $TIM {
+            #[inline(always)]
+            fn set_etr(&mut self, etp: bool, etps: u8, etf: u8) {
+                // ETP, ETPS and ETF, ECE is kept
+                self.smcr.modify(|r, w| unsafe {
+                    w.bits(
+                        (r.bits() & !0xBF00)
+                            | (u32::from(etp) << 15)
+                            | (u32::from(etps & 0b11) << 12)
+                            | (u32::from(etf & 0b1111) << 8),
+                    )
+                });
+            }
+            #[inline(always)]
+            fn enable_external_clock2(&mut self, b: bool) {
+                self.smcr.modify(|r, w| unsafe { w.bits((r.bits() & !(1 << 14)) | (u32::from(b) << 14)) });
+            }
+        }
+    };
+}
+
+macro_rules! with_cms {
+    ($TIM:ty) => {
+        impl CenterAligned for $TIM {
+            #[inline(always)]
+            fn set_cms(&mut self, cms: u8) {
+                self.cr1.modify(|r, w| unsafe {
+                    w.bits((r.bits() & !(0b11 << 5)) | (u32::from(cms & 0b11) << 5))
+                });
+            }
+        }
+    };
+}
+
+#[cfg(feature = "tim1")]
+with_cms!(pac::TIM1);
+#[cfg(feature = "tim2")]
+with_cms!(pac::TIM2);
+#[cfg(feature = "tim3")]
+with_cms!(pac::TIM3);
+#[cfg(feature = "tim4")]
+with_cms!(pac::TIM4);
+#[cfg(feature = "tim5")]
+with_cms!(pac::TIM5);
+#[cfg(feature = "tim8")]
+with_cms!(pac::TIM8);
+
+#[cfg(feature = "tim1")]
+with_slave!(pac::TIM1, etr);
+#[cfg(feature = "tim2")]
+with_slave!(pac::TIM2, etr);
+#[cfg(feature = "tim3")]
+with_slave!(pac::TIM3, etr);
+#[cfg(feature = "tim4")]
+with_slave!(pac::TIM4, etr);
+#[cfg(feature = "tim5")]
+with_slave!(pac::TIM5, etr);
+#[cfg(feature = "tim8")]
+with_slave!(pac::TIM8, etr);
+#[cfg(feature = "tim9")]
+with_slave!(pac::TIM9);
+#[cfg(feature = "tim12")]
+with_slave!(pac::TIM12);
+
 #[cfg(feature = "tim6")]
 hal!(pac::TIM6: [Timer6, u16, m: tim6,]);
 #[cfg(feature = "tim7")]
@@ -0,0 +1,260 @@
+//! Break input, lock, repetition counter and commutation of advanced-control timers
+//!
+//! These are the TIM1 and TIM8 features for inverters and motor drives, next to the dead time
+//! and idle states of the complementary [`Pwm`](super::Pwm) outputs. Center-aligned counting
+//! is also available on TIM2 to TIM5.
+//!
+//! ```rust,ignore
+//! // The lock level is frozen by the first write of BDTR, so lock before starting the outputs
+//! let mut timer = FTimer::<_, 1_000_000>::new(dp.TIM1, &clocks);
+//! let config = BreakConfig::default().input(Polarity::ActiveLow);
+//! timer.configure_break_and_lock(config, 100, LockLevel::Level1).unwrap();
+//! let mut pwm = timer.pwm(channels, 50.micros());
+//! pwm.set_alignment(Alignment::Center1);
+//! pwm.listen(Event::Break);
+//!
+//! // Six-step drive, the next step is applied on `commutate`
+//! pwm.enable_commutation_preload(CommutationTrigger::Software);
+//! pwm.set_commutation_state(Channel::C1, Ocm::PwmMode1, true, true);
+//! pwm.set_commutation_state(Channel::C2, Ocm::ForceInactive, false, true);
+//! pwm.set_commutation_state(Channel::C3, Ocm::Frozen, false, false);
+//! pwm.commutate();
+//! ```
+//!
+//! A break clears MOE, which drives all outputs to their idle or released state, and raises
+//! [`Event::Break`](super::Event::Break), listened with [`Listen`](crate::Listen).
+
+use super::{Advanced, CenterAligned, Error, FTimer, Instance, Polarity, Timer};
+
+// TIMx_BDTR
+const BDTR_DTG: u32 = 0xff;
+const BDTR_LOCK: u32 = 0b11 << 8;
+const BDTR_OSSI: u32 = 1 << 10;
+const BDTR_OSSR: u32 = 1 << 11;
+const BDTR_BKE: u32 = 1 << 12;
+const BDTR_BKP: u32 = 1 << 13;
+const BDTR_AOE: u32 = 1 << 14;
+const BDTR_MOE: u32 = 1 << 15;
+
+/// Break input and off-state configuration
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BreakConfig {
+    /// Active level of BKIN, `None` disables the break input
+    pub input: Option<Polarity>,
+    /// Sets MOE again at the next update event once the break input is inactive
+    pub automatic_output: bool,
+    /// OSSR, disabled channels drive their inactive level instead of being released while MOE
+    /// is set
+    pub off_state_run: bool,
+    /// OSSI, channels drive their idle level instead of being released while MOE is cleared
+    pub off_state_idle: bool,
+}
+
+impl Default for BreakConfig {
+    fn default() -> Self {
+        Self {
+            input: None,
+            automatic_output: true,
+            off_state_run: false,
+            off_state_idle: false,
+        }
+    }
+}
+
+impl BreakConfig {
+    pub fn input(mut self, polarity: Polarity) -> Self {
+        self.input = Some(polarity);
+        self
+    }
+
+    pub fn automatic_output(mut self, automatic_output: bool) -> Self {
+        self.automatic_output = automatic_output;
+        self
+    }
+
+    pub fn off_state_run(mut self, off_state_run: bool) -> Self {
+        self.off_state_run = off_state_run;
+        self
+    }
+
+    pub fn off_state_idle(mut self, off_state_idle: bool) -> Self {
+        self.off_state_idle = off_state_idle;
+        self
+    }
+
+    const fn bdtr(&self) -> u32 {
+        let mut bits = 0;
+        if let Some(p) = self.input {
+            bits |= BDTR_BKE;
+            if let Polarity::ActiveHigh = p {
+                bits |= BDTR_BKP;
+            }
+        }
+        if self.automatic_output {
+            bits |= BDTR_AOE;
+        }
+        if self.off_state_run {
+            bits |= BDTR_OSSR;
+        }
+        if self.off_state_idle {
+            bits |= BDTR_OSSI;
+        }
+        bits
+    }
+}
+
+/// Write protection of the timer configuration
+///
+/// Each level also protects the bits of the lower levels.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LockLevel {
+    /// Dead time, idle states, break input and automatic output enable
+    Level1 = 0b01,
+    /// Channel polarities and off-state selection
+    Level2 = 0b10,
+    /// Output compare modes and preloads
+    Level3 = 0b11,
+}
+
+/// Counting mode
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Alignment {
+    /// Counts up or down, edge-aligned PWM
+    Edge = 0b00,
+    /// Counts up and down, compare flags are set while counting down
+    Center1 = 0b01,
+    /// Counts up and down, compare flags are set while counting up
+    Center2 = 0b10,
+    /// Counts up and down, compare flags are set in both directions
+    Center3 = 0b11,
+}
+
+/// Source of the commutation event, which applies the preloaded channel states
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommutationTrigger {
+    /// Only [`commutate`](Timer::commutate)
+    Software,
+    /// [`commutate`](Timer::commutate) or a rising edge of the slave mode trigger
+    SoftwareOrTrigger,
+}
+
+macro_rules! advanced {
+    () => {
+        /// Configures the break input and off states.
+        ///
+        /// Returns `Error::Locked` after [`Self::configure_break_and_lock`], which write-protects
+        /// these bits.
+        pub fn configure_break(&mut self, config: BreakConfig) -> Result<(), Error> {
+            if self.lock_level().is_some() {
+                return Err(Error::Locked);
+            }
+            TIM::modify_bdtr(
+                BDTR_BKE | BDTR_BKP | BDTR_AOE | BDTR_OSSR | BDTR_OSSI,
+                config.bdtr(),
+            );
+            Ok(())
+        }
+
+        /// Configures the break input, off states and dead time (DTG bits, see
+        /// `Pwm::set_dead_time_bits`), and write-protects the configuration until the next reset.
+        ///
+        /// The lock level is frozen by the first write of BDTR after reset, which also sets the
+        /// other fields, so this must be called before anything else writes BDTR: starting PWM
+        /// outputs or setting the dead time, break or main output. Returns `Error::Locked` if
+        /// the lock level did not take.
+        pub fn configure_break_and_lock(
+            &mut self,
+            config: BreakConfig,
+            dead_time_bits: u8,
+            level: LockLevel,
+        ) -> Result<(), Error> {
+            TIM::modify_bdtr(
+                BDTR_LOCK | BDTR_BKE | BDTR_BKP | BDTR_AOE | BDTR_OSSR | BDTR_OSSI | BDTR_DTG,
+                ((level as u32) << 8) | config.bdtr() | u32::from(dead_time_bits),
+            );
+            if self.lock_level() == Some(level) {
+                Ok(())
+            } else {
+                Err(Error::Locked)
+            }
+        }
+
+        pub fn lock_level(&self) -> Option<LockLevel> {
+            match (TIM::read_bdtr() & BDTR_LOCK) >> 8 {
+                0b01 => Some(LockLevel::Level1),
+                0b10 => Some(LockLevel::Level2),
+                0b11 => Some(LockLevel::Level3),
+                _ => None,
+            }
+        }
+
+        /// Sets or clears the main output enable (MOE)
+        ///
+        /// A break clears MOE, use this to restart the outputs without automatic output enable.
+        pub fn enable_main_output(&mut self, b: bool) {
+            TIM::modify_bdtr(BDTR_MOE, if b { BDTR_MOE } else { 0 });
+        }
+
+        pub fn is_main_output_enabled(&self) -> bool {
+            TIM::read_bdtr() & BDTR_MOE != 0
+        }
+
+        /// Generates the update event, and its interrupt, every `n + 1` counter periods.
+        ///
+        /// In center-aligned mode a period is half of the counting cycle. The value applies
+        /// from the next update event.
+        pub fn set_repetition_counter(&mut self, n: u8) {
+            TIM::set_repetition_counter(n);
+        }
+
+        /// Preloads the channel enables and output compare modes, they are then applied
+        /// together by the commutation event.
+        pub fn enable_commutation_preload(&mut self, trigger: CommutationTrigger) {
+            TIM::enable_com_preload(true, trigger == CommutationTrigger::SoftwareOrTrigger);
+        }
+
+        /// Channel enables and output compare modes apply immediately again.
+        pub fn disable_commutation_preload(&mut self) {
+            TIM::enable_com_preload(false, false);
+        }
+
+        /// Generates a commutation event, raising [`Event::COM`](super::Event::COM).
+        pub fn commutate(&mut self) {
+            TIM::trigger_com();
+        }
+    };
+}
+
+macro_rules! center_aligned {
+    () => {
+        /// Selects edge-aligned or center-aligned counting.
+        ///
+        /// The counter is stopped during the change. In center-aligned mode the counter counts
+        /// up to the auto-reload value and back, so the PWM frequency is halved.
+        pub fn set_alignment(&mut self, alignment: Alignment) {
+            let enabled = self.tim.is_counter_enabled();
+            self.tim.enable_counter(false);
+            self.tim.set_cms(alignment as u8);
+            self.tim.enable_counter(enabled);
+        }
+    };
+}
+
+impl<TIM: Instance + Advanced> Timer<TIM> {
+    advanced!();
+}
+
+impl<TIM: Instance + Advanced, const FREQ: u32> FTimer<TIM, FREQ> {
+    advanced!();
+}
+
+impl<TIM: Instance + CenterAligned> Timer<TIM> {
+    center_aligned!();
+}
+
+impl<TIM: Instance + CenterAligned, const FREQ: u32> FTimer<TIM, FREQ> {
+    center_aligned!();
+}
@@ -0,0 +1,397 @@
+//! Input capture on all timer channels
+//!
+//! The counter runs freely at a chosen frequency and each channel latches it on the edges of its
+//! input. Pins are passed with the same [`ChannelBuilder`](super::pwm::ChannelBuilder)s as for
+//! PWM.
+//!
+//! ```rust,ignore
+//! let channels = (Channel1::new(gpioa.pa0), Channel2::new(gpioa.pa1));
+//! let mut capture = Timer::new(dp.TIM2, &clocks).capture(channels, 1.MHz())?;
+//! capture.configure(Channel::C2, CaptureConfig::default().edge(CaptureEdge::Both));
+//!
+//! let start = block!(capture.read(Channel::C1)).unwrap();
+//! let end = block!(capture.read(Channel::C1)).unwrap();
+//! let period = capture.to_duration(capture.elapsed(start, end));
+//! ```
+//!
+//! [`Capture::ccr`] hands channels over to DMA, each to its own stream, while the timer stays
+//! in use. With double buffering, the two buffers form a ring that is refilled without stopping
+//! the captures:
+//!
+//! ```rust,ignore
+//! let ccr1: CCR1<pac::TIM2> = capture.ccr();
+//! let ccr2: CCR2<pac::TIM2> = capture.ccr();
+//! let rising = Transfer::init_peripheral_to_memory(streams.5, ccr1, buf1, Some(buf2), config);
+//! ```
+
+use super::pwm::Pins;
+use super::{Channel, Error, Instance, Timer, WithPwm, CCR};
+use core::convert::TryFrom;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use enumflags2::BitFlags;
+use fugit::{HertzU32 as Hertz, NanosDurationU64};
+
+/// Edges that trigger a capture
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureEdge {
+    Rising,
+    Falling,
+    Both,
+}
+
+/// Input of a capture channel
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureInput {
+    /// The pin of the same channel
+    Direct = 0b01,
+    /// The pin of the paired channel, 1 with 2 and 3 with 4
+    Indirect = 0b10,
+    /// The trigger input selected by the slave mode controller
+    Trc = 0b11,
+}
+
+/// Number of edges per capture
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CapturePrescaler {
+    Div1 = 0b00,
+    Div2 = 0b01,
+    Div4 = 0b10,
+    Div8 = 0b11,
+}
+
+/// Capture channel configuration
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CaptureConfig {
+    pub edge: CaptureEdge,
+    pub input: CaptureInput,
+    pub prescaler: CapturePrescaler,
+    /// Digital filter (ICxF), 0 disables it and 1 to 15 select the sampling frequency and length
+    pub filter: u8,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            edge: CaptureEdge::Rising,
+            input: CaptureInput::Direct,
+            prescaler: CapturePrescaler::Div1,
+            filter: 0,
+        }
+    }
+}
+
+impl CaptureConfig {
+    pub fn edge(mut self, edge: CaptureEdge) -> Self {
+        self.edge = edge;
+        self
+    }
+
+    pub fn input(mut self, input: CaptureInput) -> Self {
+        self.input = input;
+        self
+    }
+
+    pub fn prescaler(mut self, prescaler: CapturePrescaler) -> Self {
+        self.prescaler = prescaler;
+        self
+    }
+
+    pub fn filter(mut self, filter: u8) -> Self {
+        self.filter = filter;
+        self
+    }
+}
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureError {
+    /// A capture was overwritten before being read, the value is the latest capture
+    Overcapture(u32),
+}
+
+/// Prescaler value dividing `clk` down to `freq`, rounding the counter frequency up.
+pub fn prescaler(clk: Hertz, freq: Hertz) -> Result<u16, Error> {
+    clk.raw()
+        .checked_div(freq.raw())
+        .and_then(|div| div.checked_sub(1))
+        .and_then(|psc| u16::try_from(psc).ok())
+        .ok_or(Error::WrongPrescaler)
+}
+
+/// Ticks from `earlier` to `later` of a counter wrapping after `max`
+const fn elapsed(earlier: u32, later: u32, max: u32) -> u32 {
+    later.wrapping_sub(earlier) & max
+}
+
+/// Adds channel `c` to the channels handed to DMA in `taken`.
+///
+/// # Panics
+///
+/// When the timer has no channel `c`, it has no pin or it is in `taken`.
+fn take_channel(taken: u8, c: u8, ch_number: u8, pins: [bool; 4]) -> u8 {
+    assert!(c < ch_number, "Unknown channel");
+    assert!(pins[c as usize], "Unused channel");
+    assert!(taken & (1 << c) == 0, "Channel already handed to DMA");
+    taken | (1 << c)
+}
+
+/// Converts a number of counter ticks at `tick` into a duration.
+pub const fn ticks_to_duration(ticks: u32, tick: Hertz) -> NanosDurationU64 {
+    NanosDurationU64::from_ticks(ticks as u64 * 1_000_000_000 / tick.raw() as u64)
+}
+
+/// Timer with input capture channels
+pub struct Capture<TIM, PINS>
+where
+    TIM: Instance + WithPwm,
+    PINS: Pins<TIM>,
+{
+    timer: Timer<TIM>,
+    /// Channels handed to DMA, bit `n` for channel `n`
+    dma: u8,
+    _pins: PhantomData<PINS>,
+}
+
+impl<TIM, PINS> Deref for Capture<TIM, PINS>
+where
+    TIM: Instance + WithPwm,
+    PINS: Pins<TIM>,
+{
+    type Target = Timer<TIM>;
+    fn deref(&self) -> &Self::Target {
+        &self.timer
+    }
+}
+
+impl<TIM, PINS> DerefMut for Capture<TIM, PINS>
+where
+    TIM: Instance + WithPwm,
+    PINS: Pins<TIM>,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.timer
+    }
+}
+
+impl<TIM: Instance + WithPwm> Timer<TIM> {
+    /// Starts the counter at `freq` and captures rising edges on the channels of `pins`.
+    ///
+    /// The counter wraps at its maximum value, so captures can be subtracted with
+    /// [`Capture::elapsed`]. Returns [`Error::WrongPrescaler`] if `freq` is above the timer
+    /// clock or below the clock divided by 65536.
+    pub fn capture<PINS>(mut self, _pins: PINS, freq: Hertz) -> Result<Capture<TIM, PINS>, Error>
+    where
+        PINS: Pins<TIM>,
+    {
+        let psc = prescaler(self.clk, freq)?;
+        self.tim.set_prescaler(psc);
+        self.tim.set_auto_reload(TIM::max_auto_reload()).unwrap();
+        // Load the prescaler
+        self.tim.trigger_update();
+
+        let mut capture = Capture {
+            timer: self,
+            dma: 0,
+            _pins: PhantomData,
+        };
+        for (c, used) in [
+            (Channel::C1, PINS::C1),
+            (Channel::C2, PINS::C2),
+            (Channel::C3, PINS::C3),
+            (Channel::C4, PINS::C4),
+        ] {
+            if used && (c as u8) < TIM::CH_NUMBER {
+                capture.configure(c, CaptureConfig::default());
+                capture.enable(c);
+            }
+        }
+        capture.tim.enable_counter(true);
+        Ok(capture)
+    }
+}
+
+impl<TIM, PINS> Capture<TIM, PINS>
+where
+    TIM: Instance + WithPwm,
+    PINS: Pins<TIM>,
+{
+    /// Configures the channel, which is disabled until [`Self::enable`].
+    ///
+    /// A channel without pin can capture the pin of its paired channel with
+    /// [`CaptureInput::Indirect`], to measure both edges of one signal.
+    pub fn configure(&mut self, channel: Channel, config: CaptureConfig) {
+        let channel = if config.input == CaptureInput::Direct {
+            PINS::check_used(channel)
+        } else {
+            channel
+        };
+        let c = channel as u8;
+        TIM::enable_channel(c, false);
+        TIM::set_input_capture(c, config.input as u8, config.prescaler as u8, config.filter);
+        TIM::set_capture_edge(
+            c,
+            config.edge != CaptureEdge::Rising,
+            config.edge == CaptureEdge::Both,
+        );
+    }
+
+    /// Enables captures on the channel
+    #[inline]
+    pub fn enable(&mut self, channel: Channel) {
+        TIM::enable_channel(channel as u8, true)
+    }
+
+    /// Disables captures on the channel
+    #[inline]
+    pub fn disable(&mut self, channel: Channel) {
+        TIM::enable_channel(channel as u8, false)
+    }
+
+    /// Returns the last captured counter value, clearing the capture flag
+    ///
+    /// Returns `WouldBlock` if nothing was captured since the last read.
+    pub fn read(&mut self, channel: Channel) -> nb::Result<u32, CaptureError> {
+        let c = channel as u8;
+        let flags = self.tim.get_interrupt_flag().bits();
+        if flags & (1 << (1 + c)) == 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+        let value = TIM::read_cc_value(c);
+        if flags & (1 << (9 + c)) != 0 {
+            self.tim
+                .clear_interrupt_flag(BitFlags::from_bits_truncate(1 << (9 + c)));
+            return Err(nb::Error::Other(CaptureError::Overcapture(value)));
+        }
+        Ok(value)
+    }
+
+    /// Returns the counter frequency
+    pub fn tick(&self) -> Hertz {
+        self.clk / (u32::from(self.tim.read_prescaler()) + 1)
+    }
+
+    /// Returns the ticks from capture `earlier` to capture `later`, across one counter overflow.
+    pub fn elapsed(&self, earlier: u32, later: u32) -> u32 {
+        elapsed(earlier, later, TIM::max_auto_reload())
+    }
+
+    /// Converts a number of ticks into a duration
+    pub fn to_duration(&self, ticks: u32) -> NanosDurationU64 {
+        ticks_to_duration(ticks, self.tick())
+    }
+
+    /// Enables the DMA request of channel `C` on each capture and returns its capture register
+    /// as DMA source.
+    ///
+    /// Every channel with a pin can be handed to its own DMA stream, the capture keeps serving
+    /// the other ones. [`Self::release_ccr`] gives the channel back when the transfer is over.
+    ///
+    /// # Panics
+    ///
+    /// When the timer has no channel `C`, it has no pin or it is already handed to DMA.
+    pub fn ccr<const C: u8>(&mut self) -> CCR<TIM, C> {
+        self.dma = take_channel(
+            self.dma,
+            C,
+            TIM::CH_NUMBER,
+            [PINS::C1, PINS::C2, PINS::C3, PINS::C4],
+        );
+        TIM::enable_dma_request(C, true);
+        CCR(PhantomData)
+    }
+
+    /// Disables the DMA request of the channel of `ccr`.
+    pub fn release_ccr<const C: u8>(&mut self, _ccr: CCR<TIM, C>) {
+        TIM::enable_dma_request(C, false);
+        self.dma &= !(1 << C);
+    }
+
+    pub fn release(mut self) -> Timer<TIM> {
+        for c in 0..TIM::CH_NUMBER {
+            if self.dma & (1 << c) != 0 {
+                TIM::enable_dma_request(c, false);
+            }
+        }
+        // stop counter
+        self.tim.cr1_reset();
+        self.timer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dma_channels() {
+        let pins = [true, false, true, true];
+        let taken = take_channel(0, 0, 4, pins);
+        assert_eq!(taken, 0b0001);
+        let taken = take_channel(taken, 3, 4, pins);
+        assert_eq!(taken, 0b1001);
+        assert_eq!(take_channel(taken, 2, 4, pins), 0b1101);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown channel")]
+    fn dma_channel_missing() {
+        take_channel(0, 2, 2, [true; 4]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unused channel")]
+    fn dma_channel_without_pin() {
+        take_channel(0, 1, 4, [true, false, true, true]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Channel already handed to DMA")]
+    fn dma_channel_taken_twice() {
+        take_channel(0b0100, 2, 4, [true; 4]);
+    }
+
+    #[test]
+    fn ccr_address() {
+        use crate::dma::traits::PeriAddress;
+        // TIM5 exists on every part
+        use crate::pac::TIM5;
+        let ccr1: CCR<TIM5, 0> = CCR(PhantomData);
+        let ccr4: CCR<TIM5, 3> = CCR(PhantomData);
+        assert_eq!(ccr1.address(), 0x4000_0C34);
+        assert_eq!(ccr4.address(), 0x4000_0C40);
+    }
+
+    #[test]
+    fn elapsed_ticks() {
+        assert_eq!(elapsed(100, 350, 0xffff), 250);
+        // Across an overflow of a 16 and a 32-bit counter
+        assert_eq!(elapsed(0xfff0, 0x0010, 0xffff), 0x20);
+        assert_eq!(elapsed(0xffff_fff0, 0x10, u32::MAX), 0x20);
+        assert_eq!(
+            ticks_to_duration(250, Hertz::MHz(1)),
+            NanosDurationU64::from_ticks(250_000)
+        );
+    }
+
+    #[test]
+    fn capture_prescaler() {
+        let clk = Hertz::MHz(84);
+        assert_eq!(prescaler(clk, Hertz::MHz(1)), Ok(83));
+        assert_eq!(prescaler(clk, clk), Ok(0));
+        assert_eq!(prescaler(clk, Hertz::from_raw(1282)), Ok(65_521));
+        assert_eq!(
+            prescaler(clk, Hertz::from_raw(1281)),
+            Err(Error::WrongPrescaler)
+        );
+        assert_eq!(prescaler(clk, Hertz::MHz(85)), Err(Error::WrongPrescaler));
+        assert_eq!(
+            prescaler(clk, Hertz::from_raw(0)),
+            Err(Error::WrongPrescaler)
+        );
+    }
+}
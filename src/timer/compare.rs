@@ -0,0 +1,322 @@
+//! Output compare and one-pulse mode
+//!
+//! Each channel compares the counter with its compare value and drives its pin according to an
+//! [`Ocm`] mode: set, clear or toggle on match, or force a level. In one-pulse mode the counter
+//! stops after one period, which generates a single pulse after a delay, started by software or
+//! by a trigger input.
+//!
+//! ```rust,ignore
+//! let channels = (Channel1::new(gpioa.pa8), Channel2::new(gpioa.pa9));
+//! let mut oc = dp.TIM1.output_compare_us(channels, 1.millis(), &clocks)?;
+//! oc.set_mode(Channel::C2, Ocm::Toggle);
+//! oc.set_compare(Channel::C2, 250.micros());
+//!
+//! // 10 µs pulse, 40 µs after each rising edge on TI2
+//! oc.one_pulse(Channel::C1, 40.micros(), 10.micros()).unwrap();
+//! oc.trigger_on(TriggerSource::Ti2);
+//! ```
+
+use super::pwm::Pins;
+use super::{
+    Channel, Error, FTimer, Instance, Ocm, Polarity, SlaveMode, SlaveTimer, Timer, Trigger, WithPwm,
+};
+use crate::rcc::Clocks;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use fugit::TimerDurationU32;
+
+pub trait OutputCompareExt
+where
+    Self: Sized + Instance + WithPwm,
+{
+    fn output_compare<PINS, const FREQ: u32>(
+        self,
+        pins: PINS,
+        period: TimerDurationU32<FREQ>,
+        clocks: &Clocks,
+    ) -> Result<OutputCompare<Self, PINS, FREQ>, Error>
+    where
+        PINS: Pins<Self>,
+    {
+        Timer::new(self, clocks).output_compare(pins, period)
+    }
+
+    fn output_compare_us<PINS>(
+        self,
+        pins: PINS,
+        period: TimerDurationU32<1_000_000>,
+        clocks: &Clocks,
+    ) -> Result<OutputCompare<Self, PINS, 1_000_000>, Error>
+    where
+        PINS: Pins<Self>,
+    {
+        self.output_compare::<_, 1_000_000>(pins, period, clocks)
+    }
+}
+
+impl<TIM> OutputCompareExt for TIM where Self: Sized + Instance + WithPwm {}
+
+/// Timer with output compare channels
+pub struct OutputCompare<TIM, PINS, const FREQ: u32>
+where
+    TIM: Instance + WithPwm,
+    PINS: Pins<TIM>,
+{
+    timer: FTimer<TIM, FREQ>,
+    _pins: PhantomData<PINS>,
+}
+
+impl<TIM, PINS, const FREQ: u32> Deref for OutputCompare<TIM, PINS, FREQ>
+where
+    TIM: Instance + WithPwm,
+    PINS: Pins<TIM>,
+{
+    type Target = FTimer<TIM, FREQ>;
+    fn deref(&self) -> &Self::Target {
+        &self.timer
+    }
+}
+
+impl<TIM, PINS, const FREQ: u32> DerefMut for OutputCompare<TIM, PINS, FREQ>
+where
+    TIM: Instance + WithPwm,
+    PINS: Pins<TIM>,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.timer
+    }
+}
+
+/// Prescaler value dividing `clk` exactly down to `freq`
+fn prescaler(clk: u32, freq: u32) -> Result<u16, Error> {
+    match clk.checked_rem(freq) {
+        Some(0) => u16::try_from(clk / freq - 1).map_err(|_| Error::WrongPrescaler),
+        _ => Err(Error::WrongPrescaler),
+    }
+}
+
+/// Auto-reload value of a period of `ticks`, for a counter counting up to `max`
+fn auto_reload(ticks: u32, max: u32) -> Result<u32, Error> {
+    match ticks.checked_sub(1) {
+        Some(arr) if arr > 0 && arr <= max => Ok(arr),
+        _ => Err(Error::WrongAutoReload),
+    }
+}
+
+impl<TIM: Instance + WithPwm, const FREQ: u32> FTimer<TIM, FREQ> {
+    /// Starts the counter with `period` and sets the channels of `pins` to [`Ocm::Frozen`].
+    ///
+    /// Channels are disabled until [`OutputCompare::enable`]. Compare values are not preloaded,
+    /// so new values apply immediately. Returns [`Error::WrongAutoReload`] if `period` is
+    /// shorter than 2 ticks or longer than the counter.
+    pub fn output_compare<PINS>(
+        mut self,
+        _pins: PINS,
+        period: TimerDurationU32<FREQ>,
+    ) -> Result<OutputCompare<TIM, PINS, FREQ>, Error>
+    where
+        PINS: Pins<TIM>,
+    {
+        let arr = auto_reload(period.ticks(), TIM::max_auto_reload())?;
+        for (c, used) in [
+            (Channel::C1, PINS::C1),
+            (Channel::C2, PINS::C2),
+            (Channel::C3, PINS::C3),
+            (Channel::C4, PINS::C4),
+        ] {
+            if used {
+                TIM::set_output_mode(c as u8, Ocm::Frozen, false);
+            }
+        }
+        self.tim.set_auto_reload(arr)?;
+        self.tim.start_pwm();
+        // Load the registers, and set the main output enable of advanced timers
+        self.tim.trigger_update();
+
+        Ok(OutputCompare {
+            timer: self,
+            _pins: PhantomData,
+        })
+    }
+}
+
+impl<TIM: Instance + WithPwm> Timer<TIM> {
+    /// Output compare counting at `FREQ`
+    ///
+    /// Returns [`Error::WrongPrescaler`] if the timer clock is not a multiple of `FREQ` that the
+    /// prescaler can divide by.
+    pub fn output_compare<PINS, const FREQ: u32>(
+        self,
+        pins: PINS,
+        period: TimerDurationU32<FREQ>,
+    ) -> Result<OutputCompare<TIM, PINS, FREQ>, Error>
+    where
+        PINS: Pins<TIM>,
+    {
+        let psc = prescaler(self.clk.raw(), FREQ)?;
+        let mut timer = FTimer::<TIM, FREQ> { tim: self.tim };
+        timer.tim.set_prescaler(psc);
+        timer.output_compare(pins, period)
+    }
+}
+
+impl<TIM, PINS, const FREQ: u32> OutputCompare<TIM, PINS, FREQ>
+where
+    TIM: Instance + WithPwm,
+    PINS: Pins<TIM>,
+{
+    /// Selects what the channel output does on compare match
+    ///
+    /// [`Ocm::ForceActive`] and [`Ocm::ForceInactive`] drive the output at once, regardless of
+    /// the counter.
+    #[inline]
+    pub fn set_mode(&mut self, channel: Channel, mode: Ocm) {
+        TIM::set_output_mode(PINS::check_used(channel) as u8, mode, false);
+    }
+
+    /// Sets the counter value the channel matches on
+    #[inline]
+    pub fn set_compare(&mut self, channel: Channel, compare: TimerDurationU32<FREQ>) {
+        TIM::set_cc_value(PINS::check_used(channel) as u8, compare.ticks());
+    }
+
+    #[inline]
+    pub fn get_compare(&self, channel: Channel) -> TimerDurationU32<FREQ> {
+        TimerDurationU32::from_ticks(TIM::read_cc_value(PINS::check_used(channel) as u8))
+    }
+
+    /// Enables the channel output
+    #[inline]
+    pub fn enable(&mut self, channel: Channel) {
+        TIM::enable_channel(PINS::check_used(channel) as u8, true)
+    }
+
+    /// Disables the channel output
+    #[inline]
+    pub fn disable(&mut self, channel: Channel) {
+        TIM::enable_channel(PINS::check_used(channel) as u8, false)
+    }
+
+    /// Sets the active level of the channel output
+    #[inline]
+    pub fn set_polarity(&mut self, channel: Channel, p: Polarity) {
+        TIM::set_channel_polarity(PINS::check_used(channel) as u8, p);
+    }
+
+    pub fn get_period(&self) -> TimerDurationU32<FREQ> {
+        TimerDurationU32::from_ticks(TIM::read_auto_reload() + 1)
+    }
+
+    /// Returns [`Error::WrongAutoReload`] if `period` is shorter than 2 ticks or longer than
+    /// the counter.
+    pub fn set_period(&mut self, period: TimerDurationU32<FREQ>) -> Result<(), Error> {
+        let arr = auto_reload(period.ticks(), TIM::max_auto_reload())?;
+        self.tim.set_auto_reload(arr)?;
+        self.tim.cnt_reset();
+        Ok(())
+    }
+
+    /// Prepares a single pulse of `width` on the channel, `delay` after the counter starts.
+    ///
+    /// The counter is stopped and the timer switches to one-pulse mode: the period becomes
+    /// `delay + width` and the counter stops at its end. The pulse is started by
+    /// [`Self::trigger`], or by a trigger input with [`Self::trigger_on`]. Other channels share
+    /// the counter and keep their modes.
+    pub fn one_pulse(
+        &mut self,
+        channel: Channel,
+        delay: TimerDurationU32<FREQ>,
+        width: TimerDurationU32<FREQ>,
+    ) -> Result<(), Error> {
+        let c = PINS::check_used(channel) as u8;
+        if width.ticks() == 0 {
+            return Err(Error::WrongAutoReload);
+        }
+        let arr = delay
+            .ticks()
+            .checked_add(width.ticks() - 1)
+            .ok_or(Error::WrongAutoReload)?;
+        self.tim.enable_counter(false);
+        self.tim.set_auto_reload(arr)?;
+        // Inactive while the counter is below the compare value
+        TIM::set_output_mode(c, Ocm::PwmMode2, false);
+        TIM::set_cc_value(c, delay.ticks());
+        self.tim.reset_counter();
+        self.tim.enable_one_pulse(true);
+        TIM::enable_channel(c, true);
+        Ok(())
+    }
+
+    /// Leaves one-pulse mode, the counter runs continuously from the next start.
+    pub fn disable_one_pulse(&mut self) {
+        self.tim.enable_one_pulse(false);
+    }
+
+    /// Starts the counter, which generates the pulse in one-pulse mode.
+    pub fn trigger(&mut self) {
+        self.tim.enable_counter(true);
+    }
+
+    /// Returns `true` while a pulse is pending or being generated in one-pulse mode.
+    pub fn is_running(&self) -> bool {
+        self.tim.is_counter_enabled()
+    }
+
+    pub fn release(mut self) -> FTimer<TIM, FREQ> {
+        // stop counter
+        self.tim.cr1_reset();
+        self.timer
+    }
+}
+
+impl<TIM, PINS, const FREQ: u32> OutputCompare<TIM, PINS, FREQ>
+where
+    TIM: Instance + WithPwm + SlaveTimer,
+    PINS: Pins<TIM>,
+{
+    /// Starts the counter on each rising edge of `trigger` instead of by software.
+    ///
+    /// In one-pulse mode, each trigger generates one pulse. The trigger is ignored while the
+    /// counter runs.
+    pub fn trigger_on(&mut self, trigger: impl Trigger<TIM>) {
+        self.set_slave_mode(SlaveMode::Trigger, trigger);
+    }
+
+    /// Disables the slave mode controller, the counter is only started by software.
+    pub fn trigger_off(&mut self) {
+        self.disable_slave_mode();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_prescaler() {
+        assert_eq!(prescaler(84_000_000, 1_000_000), Ok(83));
+        assert_eq!(prescaler(84_000_000, 84_000_000), Ok(0));
+        assert_eq!(prescaler(84_000_000, 2_000), Ok(41_999));
+        assert_eq!(
+            prescaler(100_000_000, 3_000_000),
+            Err(Error::WrongPrescaler)
+        );
+        assert_eq!(prescaler(84_000_000, 1_000), Err(Error::WrongPrescaler));
+        assert_eq!(
+            prescaler(84_000_000, 168_000_000),
+            Err(Error::WrongPrescaler)
+        );
+        assert_eq!(prescaler(84_000_000, 0), Err(Error::WrongPrescaler));
+    }
+
+    #[test]
+    fn compare_auto_reload() {
+        assert_eq!(auto_reload(1000, 0xffff), Ok(999));
+        assert_eq!(auto_reload(2, 0xffff), Ok(1));
+        assert_eq!(auto_reload(0x1_0000, 0xffff), Ok(0xffff));
+        assert_eq!(auto_reload(0x1_0001, 0xffff), Err(Error::WrongAutoReload));
+        assert_eq!(auto_reload(0x1_0001, u32::MAX), Ok(0x1_0000));
+        assert_eq!(auto_reload(1, 0xffff), Err(Error::WrongAutoReload));
+        assert_eq!(auto_reload(0, 0xffff), Err(Error::WrongAutoReload));
+    }
+}
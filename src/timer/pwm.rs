@@ -38,7 +38,7 @@
 
 use super::{
     compute_arr_presc, Advanced, CPin, Channel, FTimer, IdleState, Instance, NCPin, Ocm, Polarity,
-    Timer, WithPwm,
+    Timer, WithPwm, DMAR,
 };
 pub use super::{Ch, C1, C2, C3, C4};
 use crate::gpio::{OpenDrain, PushPull};
@@ -353,6 +353,12 @@ where
     pub fn split(self) -> PINS::Channels {
         PINS::split()
     }
+
+    /// Splits the channels and returns the running timer as DMA destination for
+    /// [`PwmDmaStream`](super::PwmDmaStream)
+    pub fn split_dma(self) -> (DMAR<TIM>, PINS::Channels) {
+        (DMAR(self.timer.tim), PINS::split())
+    }
 }
 
 impl<TIM, PINS> Deref for PwmHz<TIM, PINS>
@@ -548,6 +554,26 @@ where
     pub fn set_complementary_idle_state(&mut self, channel: Channel, s: IdleState) {
         TIM::idle_state(PINS::check_complementary_used(channel) as u8, true, s);
     }
+
+    /// Set the output compare mode and the enables of both outputs of the channel `channel`
+    ///
+    /// With commutation preload enabled, the new state is applied by the next commutation event.
+    #[inline]
+    pub fn set_commutation_state(
+        &mut self,
+        channel: Channel,
+        mode: Ocm,
+        enable: bool,
+        enable_complementary: bool,
+    ) {
+        let c = PINS::check_used(channel) as u8;
+        if enable_complementary {
+            PINS::check_complementary_used(channel);
+        }
+        TIM::set_output_mode(c, mode, true);
+        TIM::enable_channel(c, enable);
+        TIM::enable_nchannel(c, enable_complementary);
+    }
 }
 
 pub struct Pwm<TIM, PINS, const FREQ: u32>
@@ -568,6 +594,12 @@ where
         PINS::split()
     }
 
+    /// Splits the channels and returns the running timer as DMA destination for
+    /// [`PwmDmaStream`](super::PwmDmaStream)
+    pub fn split_dma(self) -> (DMAR<TIM>, PINS::Channels) {
+        (DMAR(self.timer.tim), PINS::split())
+    }
+
     pub fn release(mut self) -> FTimer<TIM, FREQ> {
         // stop counter
         self.tim.cr1_reset();
@@ -768,6 +800,26 @@ where
     pub fn set_complementary_idle_state(&mut self, channel: Channel, s: IdleState) {
         TIM::idle_state(PINS::check_complementary_used(channel) as u8, true, s);
     }
+
+    /// Set the output compare mode and the enables of both outputs of the channel `channel`
+    ///
+    /// With commutation preload enabled, the new state is applied by the next commutation event.
+    #[inline]
+    pub fn set_commutation_state(
+        &mut self,
+        channel: Channel,
+        mode: Ocm,
+        enable: bool,
+        enable_complementary: bool,
+    ) {
+        let c = PINS::check_used(channel) as u8;
+        if enable_complementary {
+            PINS::check_complementary_used(channel);
+        }
+        TIM::set_output_mode(c, mode, true);
+        TIM::enable_channel(c, enable);
+        TIM::enable_nchannel(c, enable_complementary);
+    }
 }
 
 /// Convert number dead time ticks to raw DTG register bits.
@@ -0,0 +1,158 @@
+//! PWM duty cycles streamed by DMA
+//!
+//! On each update event the timer requests a DMA burst through DMAR, which writes the next
+//! duty cycle of one or several consecutive channels. As the compare registers are preloaded,
+//! a value written at an update event is output during the following period.
+//!
+//! With one buffer the transfer stops after the last value, which stays in the compare
+//! registers. With two buffers the stream alternates between them without stopping, and each
+//! buffer is refilled with [`Transfer::next_transfer`] after its transfer complete event.
+//!
+//! ```rust,ignore
+//! // WS2812: 800 kHz, a 1 is high for 2/3 of the period and a 0 for 1/3
+//! let pwm = dp.TIM3.pwm_hz(Channel1::new(gpioa.pa6), 800.kHz(), &clocks);
+//! let max = pwm.get_max_duty();
+//! let (dmar, ch1) = pwm.split_dma();
+//! for (bit, duty) in colors.iter().zip(BUFFER.iter_mut()) {
+//!     *duty = if *bit { max * 2 / 3 } else { max / 3 };
+//! }
+//! // The last values are 0, so the line stays low for the reset
+//! let mut stream = PwmDmaStream::new(dma1.2, dmar, ch1, &BUFFER[..], None, DmaConfig::default());
+//! stream.start();
+//! stream.wait();
+//! ```
+
+use super::pwm::PwmChannel;
+use super::{Instance, WithDmar, DMAR};
+use crate::dma::{
+    config::DmaConfig,
+    traits::{Channel, DMASet, PeriAddress, Stream},
+    ChannelX, MemoryToPeripheral, Transfer,
+};
+use core::ops::{Deref, DerefMut};
+use embedded_dma::ReadBuffer;
+
+/// Offset of TIMx_CCR1 in words, for DBA
+const CCR1_WORD: u8 = 0x34 / 4;
+
+/// Channels written together on each update event
+///
+/// Implemented for a [`PwmChannel`] and for tuples of consecutive channels, whose duty cycles
+/// are then interleaved in the buffer.
+pub trait BurstChannels<TIM>: crate::Sealed {
+    /// Index of the first channel
+    const FIRST: u8;
+    /// Number of channels
+    const LEN: u8;
+}
+
+impl<TIM, const C: u8, const COMP: bool> crate::Sealed for PwmChannel<TIM, C, COMP> {}
+impl<TIM, const C: u8, const COMP: bool> BurstChannels<TIM> for PwmChannel<TIM, C, COMP> {
+    const FIRST: u8 = C;
+    const LEN: u8 = 1;
+}
+
+macro_rules! burst {
+    ($first:literal: $($C:literal, $COMP:ident),+) => {
+        impl<TIM, $(const $COMP: bool),+> crate::Sealed for ($(PwmChannel<TIM, $C, $COMP>),+) {}
+        impl<TIM, $(const $COMP: bool),+> BurstChannels<TIM> for ($(PwmChannel<TIM, $C, $COMP>),+) {
+            const FIRST: u8 = $first;
+            const LEN: u8 = [$($C),+].len() as u8;
+        }
+    };
+}
+
+burst!(0: 0, COMP1, 1, COMP2);
+burst!(1: 1, COMP2, 2, COMP3);
+burst!(2: 2, COMP3, 3, COMP4);
+burst!(0: 0, COMP1, 1, COMP2, 2, COMP3);
+burst!(1: 1, COMP2, 2, COMP3, 3, COMP4);
+burst!(0: 0, COMP1, 1, COMP2, 2, COMP3, 3, COMP4);
+
+/// DMA transfer of PWM duty cycles
+///
+/// Derefs to the [`Transfer`], for its flags and to change buffers.
+pub struct PwmDmaStream<STREAM, const CHANNEL: u8, TIM, CH, BUF>
+where
+    STREAM: Stream,
+    DMAR<TIM>: PeriAddress,
+{
+    transfer: Transfer<STREAM, CHANNEL, DMAR<TIM>, MemoryToPeripheral, BUF>,
+    channels: CH,
+}
+
+impl<STREAM, const CHANNEL: u8, TIM, CH, BUF> PwmDmaStream<STREAM, CHANNEL, TIM, CH, BUF>
+where
+    STREAM: Stream,
+    ChannelX<CHANNEL>: Channel,
+    TIM: Instance + WithDmar,
+    CH: BurstChannels<TIM>,
+    DMAR<TIM>: PeriAddress + DMASet<STREAM, CHANNEL, MemoryToPeripheral>,
+    BUF: ReadBuffer<Word = <DMAR<TIM> as PeriAddress>::MemSize>,
+{
+    /// Sets the DMA burst of `channels` and prepares the transfer of `buf`.
+    ///
+    /// `stream` must be the one of the TIMx_UP request, DMAR is also served by TIMx_TRIG.
+    ///
+    /// The buffer holds `CH::LEN` duty cycles per period. With `double_buf`, the transfer is
+    /// circular and alternates between the two buffers. Memory increment and double buffering
+    /// are set in `config`, interrupts are left as configured.
+    pub fn new(
+        stream: STREAM,
+        mut dmar: DMAR<TIM>,
+        channels: CH,
+        buf: BUF,
+        double_buf: Option<BUF>,
+        config: DmaConfig,
+    ) -> Self {
+        dmar.0.enable_update_dma(false);
+        dmar.0.set_dma_burst(CCR1_WORD + CH::FIRST, CH::LEN - 1);
+        let config = config
+            .memory_increment(true)
+            .double_buffer(double_buf.is_some());
+        let transfer = Transfer::init_memory_to_peripheral(stream, dmar, buf, double_buf, config);
+        Self { transfer, channels }
+    }
+
+    /// Starts the stream and enables the update DMA request, the first value is output from
+    /// the second update event.
+    pub fn start(&mut self) {
+        self.transfer.start(|dmar| dmar.0.enable_update_dma(true));
+    }
+
+    /// Disables the update DMA request and pauses the stream.
+    pub fn pause(&mut self) {
+        self.transfer.pause(|dmar| dmar.0.enable_update_dma(false));
+    }
+
+    /// Stops the transfer and returns the resources, the timer keeps running.
+    pub fn release(self) -> (STREAM, DMAR<TIM>, CH, BUF, Option<BUF>) {
+        let Self { transfer, channels } = self;
+        let (stream, mut dmar, buf, double_buf) = transfer.release();
+        dmar.0.enable_update_dma(false);
+        dmar.0.set_dma_burst(0, 0);
+        (stream, dmar, channels, buf, double_buf)
+    }
+}
+
+impl<STREAM, const CHANNEL: u8, TIM, CH, BUF> Deref for PwmDmaStream<STREAM, CHANNEL, TIM, CH, BUF>
+where
+    STREAM: Stream,
+    DMAR<TIM>: PeriAddress,
+{
+    type Target = Transfer<STREAM, CHANNEL, DMAR<TIM>, MemoryToPeripheral, BUF>;
+    fn deref(&self) -> &Self::Target {
+        &self.transfer
+    }
+}
+
+impl<STREAM, const CHANNEL: u8, TIM, CH, BUF> DerefMut
+    for PwmDmaStream<STREAM, CHANNEL, TIM, CH, BUF>
+where
+    STREAM: Stream,
+    DMAR<TIM>: PeriAddress,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.transfer
+    }
+}
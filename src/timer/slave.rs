@@ -0,0 +1,245 @@
+//! Slave mode controller and external clock modes
+//!
+//! A slave timer is reset, gated, started or clocked by a trigger: one of its inputs, the ETR
+//! pin, or the TRGO output of another timer set with `set_master_mode`. The connections between
+//! timers are fixed, [`InternalTrigger`] is only implemented for the pairs that exist, so an
+//! invalid master/slave pair does not compile.
+//!
+//! ```rust,ignore
+//! // TIM5 counts the overflows of TIM2
+//! let mut low = dp.TIM2.counter_hz(&clocks);
+//! low.set_master_mode(pac::tim2::cr2::MMS_A::Update);
+//! let mut high = Timer::new(dp.TIM5, &clocks);
+//! high.set_slave_mode(SlaveMode::ExternalClock1, Itr::<pac::TIM2>::new());
+//!
+//! // PWM running only while the ETR pin is high
+//! pwm.configure_etr(EtrConfig::default().filter(4));
+//! pwm.set_slave_mode(SlaveMode::Gated, Etr);
+//! ```
+
+use super::{FTimer, Instance, Polarity, SlaveTimer, Timer, WithEtr};
+use crate::pac;
+use core::marker::PhantomData;
+
+/// Action of the trigger on the counter
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlaveMode {
+    /// The counter runs on the internal clock
+    Disabled = 0b000,
+    /// Rising edges of the trigger reset the counter and update the registers
+    Reset = 0b100,
+    /// The counter runs while the trigger is high
+    Gated = 0b101,
+    /// Rising edges of the trigger start the counter
+    Trigger = 0b110,
+    /// Rising edges of the trigger clock the counter
+    ExternalClock1 = 0b111,
+}
+
+/// Timer input used as trigger
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriggerSource {
+    /// Both edges of TI1
+    Ti1Edge = 0b100,
+    /// Filtered TI1 with the polarity of channel 1
+    Ti1 = 0b101,
+    /// Filtered TI2 with the polarity of channel 2
+    Ti2 = 0b110,
+}
+
+/// External trigger input, filtered and divided as set by `configure_etr`
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Etr;
+
+/// Output of the `MASTER` timer used as trigger
+///
+/// This is TRGO for timers with a master mode and OC1REF for TIM10, TIM11, TIM13 and TIM14.
+pub struct Itr<MASTER>(PhantomData<MASTER>);
+
+impl<MASTER> Itr<MASTER> {
+    pub const fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<MASTER> Default for Itr<MASTER> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<MASTER> Clone for Itr<MASTER> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<MASTER> Copy for Itr<MASTER> {}
+
+/// Trigger selection of the `TIM` slave mode controller
+pub trait Trigger<TIM>: crate::Sealed {
+    /// TS field value
+    fn selection(&self) -> u8;
+}
+
+impl crate::Sealed for TriggerSource {}
+impl<TIM: SlaveTimer> Trigger<TIM> for TriggerSource {
+    #[inline(always)]
+    fn selection(&self) -> u8 {
+        *self as u8
+    }
+}
+
+impl crate::Sealed for Etr {}
+impl<TIM: WithEtr> Trigger<TIM> for Etr {
+    #[inline(always)]
+    fn selection(&self) -> u8 {
+        0b111
+    }
+}
+
+impl<MASTER> crate::Sealed for Itr<MASTER> {}
+impl<TIM: InternalTrigger<MASTER>, MASTER> Trigger<TIM> for Itr<MASTER> {
+    #[inline(always)]
+    fn selection(&self) -> u8 {
+        TIM::ITR
+    }
+}
+
+/// Connection from the `MASTER` timer to an internal trigger input
+pub trait InternalTrigger<MASTER>: SlaveTimer {
+    /// Index of the ITRx input
+    const ITR: u8;
+}
+
+macro_rules! itr {
+    ($SLAVE:ident, $slave:literal: [$($itr:literal: $MASTER:ident, $master:literal;)+]) => {
+        $(
+            #[cfg(all(feature = $slave, feature = $master))]
+            impl InternalTrigger<pac::$MASTER> for pac::$SLAVE {
+                const ITR: u8 = $itr;
+            }
+        )+
+    };
+}
+
+// Devices without one of the timers simply lack the pair
+itr!(TIM1, "tim1": [0: TIM5, "tim5"; 1: TIM2, "tim2"; 2: TIM3, "tim3"; 3: TIM4, "tim4";]);
+itr!(TIM2, "tim2": [0: TIM1, "tim1"; 1: TIM8, "tim8"; 2: TIM3, "tim3"; 3: TIM4, "tim4";]);
+itr!(TIM3, "tim3": [0: TIM1, "tim1"; 1: TIM2, "tim2"; 2: TIM5, "tim5"; 3: TIM4, "tim4";]);
+itr!(TIM4, "tim4": [0: TIM1, "tim1"; 1: TIM2, "tim2"; 2: TIM3, "tim3"; 3: TIM8, "tim8";]);
+itr!(TIM5, "tim5": [0: TIM2, "tim2"; 1: TIM3, "tim3"; 2: TIM4, "tim4"; 3: TIM8, "tim8";]);
+itr!(TIM8, "tim8": [0: TIM1, "tim1"; 1: TIM2, "tim2"; 2: TIM4, "tim4"; 3: TIM5, "tim5";]);
+itr!(TIM9, "tim9": [0: TIM2, "tim2"; 1: TIM3, "tim3"; 2: TIM10, "tim10"; 3: TIM11, "tim11";]);
+itr!(TIM12, "tim12": [0: TIM4, "tim4"; 1: TIM5, "tim5"; 2: TIM13, "tim13"; 3: TIM14, "tim14";]);
+
+/// Division of the ETR input frequency, which must stay below a quarter of the timer clock
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EtrPrescaler {
+    Div1 = 0b00,
+    Div2 = 0b01,
+    Div4 = 0b10,
+    Div8 = 0b11,
+}
+
+/// External trigger input configuration
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EtrConfig {
+    /// `ActiveLow` inverts ETR, so falling edges and low levels are active
+    pub polarity: Polarity,
+    pub prescaler: EtrPrescaler,
+    /// Digital filter (ETF), 0 disables it and 1 to 15 select the sampling frequency and length
+    pub filter: u8,
+}
+
+impl Default for EtrConfig {
+    fn default() -> Self {
+        Self {
+            polarity: Polarity::ActiveHigh,
+            prescaler: EtrPrescaler::Div1,
+            filter: 0,
+        }
+    }
+}
+
+impl EtrConfig {
+    pub fn polarity(mut self, polarity: Polarity) -> Self {
+        self.polarity = polarity;
+        self
+    }
+
+    pub fn prescaler(mut self, prescaler: EtrPrescaler) -> Self {
+        self.prescaler = prescaler;
+        self
+    }
+
+    pub fn filter(mut self, filter: u8) -> Self {
+        self.filter = filter;
+        self
+    }
+}
+
+impl<TIM: Instance + SlaveTimer> Timer<TIM> {
+    /// Selects the trigger and what it does to the counter
+    pub fn set_slave_mode(&mut self, mode: SlaveMode, trigger: impl Trigger<TIM>) {
+        self.tim.slave_mode(mode as u8, trigger.selection());
+    }
+
+    /// Runs the counter on the internal clock
+    pub fn disable_slave_mode(&mut self) {
+        self.tim.slave_mode(SlaveMode::Disabled as u8, 0);
+    }
+}
+
+impl<TIM: Instance + WithEtr> Timer<TIM> {
+    /// Configures the ETR input, used by [`Etr`] triggers and external clock mode 2
+    pub fn configure_etr(&mut self, config: EtrConfig) {
+        self.tim.set_etr(
+            config.polarity == Polarity::ActiveLow,
+            config.prescaler as u8,
+            config.filter,
+        );
+    }
+
+    /// Clocks the counter with the active edges of ETR
+    ///
+    /// This is independent of the slave mode, so the counter can be reset, gated or started
+    /// by another trigger at the same time.
+    pub fn enable_external_clock2(&mut self, b: bool) {
+        self.tim.enable_external_clock2(b);
+    }
+}
+
+impl<TIM: Instance + SlaveTimer, const FREQ: u32> FTimer<TIM, FREQ> {
+    /// Selects the trigger and what it does to the counter
+    pub fn set_slave_mode(&mut self, mode: SlaveMode, trigger: impl Trigger<TIM>) {
+        self.tim.slave_mode(mode as u8, trigger.selection());
+    }
+
+    /// Runs the counter on the internal clock
+    pub fn disable_slave_mode(&mut self) {
+        self.tim.slave_mode(SlaveMode::Disabled as u8, 0);
+    }
+}
+
+impl<TIM: Instance + WithEtr, const FREQ: u32> FTimer<TIM, FREQ> {
+    /// Configures the ETR input, used by [`Etr`] triggers and external clock mode 2
+    pub fn configure_etr(&mut self, config: EtrConfig) {
+        self.tim.set_etr(
+            config.polarity == Polarity::ActiveLow,
+            config.prescaler as u8,
+            config.filter,
+        );
+    }
+
+    /// Clocks the counter with the active edges of ETR
+    ///
+    /// Durations are then counted in ETR edges instead of `FREQ` ticks.
+    pub fn enable_external_clock2(&mut self, b: bool) {
+        self.tim.enable_external_clock2(b);
+    }
+}
@@ -52,6 +52,18 @@ macro_rules! halUart {
                     })
                 });
             }
+
+            #[cfg(feature = "async")]
+            fn tx_waker() -> &'static crate::waker::WakerCell {
+                static WAKER: crate::waker::WakerCell = crate::waker::WakerCell::new();
+                &WAKER
+            }
+
+            #[cfg(feature = "async")]
+            fn rx_waker() -> &'static crate::waker::WakerCell {
+                static WAKER: crate::waker::WakerCell = crate::waker::WakerCell::new();
+                &WAKER
+            }
         }
     };
 }
@@ -75,6 +87,18 @@ impl Instance for pac::UART4 {
     fn set_stopbits(&self, _bits: config::StopBits) {
         todo!()
     }
+
+    #[cfg(feature = "async")]
+    fn tx_waker() -> &'static crate::waker::WakerCell {
+        static WAKER: crate::waker::WakerCell = crate::waker::WakerCell::new();
+        &WAKER
+    }
+
+    #[cfg(feature = "async")]
+    fn rx_waker() -> &'static crate::waker::WakerCell {
+        static WAKER: crate::waker::WakerCell = crate::waker::WakerCell::new();
+        &WAKER
+    }
 }
 
 #[cfg(feature = "uart5")]
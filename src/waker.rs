@@ -0,0 +1,44 @@
+//! Waker storage shared between interrupt handlers and async drivers.
+
+use core::cell::Cell;
+use core::task::Waker;
+
+use cortex_m::interrupt::{self, Mutex};
+
+/// Slot holding the waker of the task waiting on a peripheral or DMA stream.
+pub struct WakerCell {
+    waker: Mutex<Cell<Option<Waker>>>,
+}
+
+impl Default for WakerCell {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WakerCell {
+    pub const fn new() -> Self {
+        Self {
+            waker: Mutex::new(Cell::new(None)),
+        }
+    }
+
+    /// Stores `waker`, replacing the previous one unless both wake the same task.
+    pub fn register(&self, waker: &Waker) {
+        interrupt::free(|cs| {
+            let cell = self.waker.borrow(cs);
+            let waker = match cell.take() {
+                Some(old) if old.will_wake(waker) => old,
+                _ => waker.clone(),
+            };
+            cell.set(Some(waker));
+        });
+    }
+
+    /// Wakes the registered task, if any.
+    pub fn wake(&self) {
+        if let Some(waker) = interrupt::free(|cs| self.waker.borrow(cs).take()) {
+            waker.wake();
+        }
+    }
+}
@@ -1,8 +1,11 @@
 //! Watchdog peripherals
 
-use crate::pac::{DBGMCU, IWDG};
+use crate::pac::{DBGMCU, IWDG, WWDG};
+use crate::rcc::{Clocks, Enable, Reset, ResetReason};
 use core::fmt;
 use embedded_hal_02::watchdog::{Watchdog, WatchdogEnable};
+use fugit::HertzU32 as Hertz;
+use fugit::MicrosDurationU32 as MicroSeconds;
 use fugit::MillisDurationU32 as MilliSeconds;
 
 /// Wraps the Independent Watchdog (IWDG) peripheral
@@ -107,6 +110,13 @@ impl IndependentWatchdog {
         a
     }
 
+    /// Returns `true` if the last reset was caused by the independent watchdog.
+    ///
+    /// The reset flags are kept until they are cleared with [`ResetReason::read_and_clear`](crate::rcc::ResetReason::read_and_clear).
+    pub fn caused_reset() -> bool {
+        ResetReason::read().contains(ResetReason::IndependentWatchdog)
+    }
+
     pub fn start(&mut self, period: MilliSeconds) {
         self.setup(period);
 
@@ -131,3 +141,284 @@ impl Watchdog for IndependentWatchdog {
         self.feed()
     }
 }
+
+/// Window watchdog timing that can not be reached with the PCLK1 frequency
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum WindowError {
+    /// The maximum refresh interval is longer than 64 ticks of the slowest prescaler, or shorter
+    /// than one tick of the fastest
+    Timeout,
+    /// The minimum refresh interval is not shorter than the maximum
+    Window,
+}
+
+/// Window watchdog register values
+///
+/// The counter counts down from `t` every `4096 * 2^wdgtb` PCLK1 cycles and resets the device
+/// when it goes from 0x40 to 0x3F. Refreshing while the counter is above `w` also resets it.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct WindowTiming {
+    /// Timer base, divides by 1, 2, 4 or 8
+    pub wdgtb: u8,
+    /// Counter reload value, 0x40..=0x7F
+    pub t: u8,
+    /// Window value, 0x40..=t
+    pub w: u8,
+}
+
+impl WindowTiming {
+    /// Computes the timing that resets the device when it is not refreshed within `max`, or
+    /// refreshed earlier than `min` after the previous refresh.
+    ///
+    /// The fastest prescaler that reaches `max` is chosen. Both `max` and `min` are rounded down
+    /// to a tick, so the device is never reset later than `max` and a refresh at `min` is
+    /// always accepted.
+    pub fn new(pclk1: Hertz, min: MicroSeconds, max: MicroSeconds) -> Result<Self, WindowError> {
+        if min >= max {
+            return Err(WindowError::Window);
+        }
+        let pclk1 = u64::from(pclk1.raw());
+        for wdgtb in 0..4 {
+            // Tick length in picoseconds keeps precision at 180 MHz
+            let tick = (4096u64 << wdgtb) * 1_000_000_000_000 / pclk1;
+            let n = u64::from(max.ticks()) * 1_000_000 / tick;
+            if n == 0 {
+                return Err(WindowError::Timeout);
+            }
+            if n > 64 {
+                continue;
+            }
+            let m = (u64::from(min.ticks()) * 1_000_000 / tick).min(n - 1);
+            let t = 0x3F + n as u8;
+            return Ok(Self {
+                wdgtb,
+                t,
+                w: t - m as u8,
+            });
+        }
+        Err(WindowError::Timeout)
+    }
+
+    /// Returns the counter tick
+    pub fn tick(&self, pclk1: Hertz) -> MicroSeconds {
+        MicroSeconds::from_ticks(
+            ((4096u64 << self.wdgtb) * 1_000_000 / u64::from(pclk1.raw())) as u32,
+        )
+    }
+
+    /// Returns the time after a refresh before which a new refresh resets the device
+    pub fn min_refresh(&self, pclk1: Hertz) -> MicroSeconds {
+        self.ticks_to_us(pclk1, self.t - self.w)
+    }
+
+    /// Returns the time after a refresh at which the device is reset
+    pub fn max_refresh(&self, pclk1: Hertz) -> MicroSeconds {
+        self.ticks_to_us(pclk1, self.t - 0x3F)
+    }
+
+    fn ticks_to_us(&self, pclk1: Hertz, ticks: u8) -> MicroSeconds {
+        MicroSeconds::from_ticks(
+            (u64::from(ticks) * (4096u64 << self.wdgtb) * 1_000_000 / u64::from(pclk1.raw()))
+                as u32,
+        )
+    }
+}
+
+/// Wraps the Window Watchdog (WWDG) peripheral
+///
+/// ```rust,ignore
+/// let mut wwdg = WindowWatchdog::new(dp.WWDG);
+/// wwdg.listen_early_wakeup();
+/// wwdg.start(5.millis(), 40.millis(), &clocks)?;
+/// loop {
+///     // Work taking between 5 and 40 ms
+///     wwdg.feed();
+/// }
+/// ```
+pub struct WindowWatchdog {
+    wwdg: WWDG,
+    t: u8,
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for WindowWatchdog {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "WindowWatchdog");
+    }
+}
+
+impl fmt::Debug for WindowWatchdog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("WindowWatchdog")
+    }
+}
+
+// WWDG_CR
+const CR_WDGA: u32 = 1 << 7;
+// WWDG_CFR
+const CFR_EWI: u32 = 1 << 9;
+
+impl WindowWatchdog {
+    /// Enables the peripheral clock without starting the watchdog.
+    pub fn new(wwdg: WWDG) -> Self {
+        unsafe {
+            WWDG::enable_unchecked();
+            WWDG::reset_unchecked();
+        }
+        Self { wwdg, t: 0x7F }
+    }
+
+    /// Debug window watchdog stopped when core is halted
+    pub fn stop_on_debug(&self, dbgmcu: &DBGMCU, stop: bool) {
+        dbgmcu.apb1_fz.modify(|_, w| w.dbg_wwdg_stop().bit(stop));
+    }
+
+    /// Starts the watchdog with a refresh window between `min` and `max` after each refresh.
+    ///
+    /// Once started, the watchdog can only be stopped by a reset.
+    pub fn start(
+        &mut self,
+        min: MicroSeconds,
+        max: MicroSeconds,
+        clocks: &Clocks,
+    ) -> Result<WindowTiming, WindowError> {
+        let timing = WindowTiming::new(clocks.pclk1(), min, max)?;
+        self.start_timing(timing);
+        Ok(timing)
+    }
+
+    /// Starts the watchdog with precomputed register values.
+    pub fn start_timing(&mut self, timing: WindowTiming) {
+        self.t = timing.t & 0x7F;
+        self.wwdg.cfr.modify(|r, w| unsafe {
+            w.bits(
+                (r.bits() & CFR_EWI)
+                    | (u32::from(timing.wdgtb & 0b11) << 7)
+                    | u32::from(timing.w & 0x7F),
+            )
+        });
+        self.feed();
+    }
+
+    /// Reloads the counter, which resets the device if done before the window opens.
+    pub fn feed(&mut self) {
+        self.wwdg
+            .cr
+            .write(|w| unsafe { w.bits(CR_WDGA | u32::from(self.t)) });
+    }
+
+    /// Returns the current counter value, the device resets after 0x40
+    pub fn counter(&self) -> u8 {
+        (self.wwdg.cr.read().bits() & 0x7F) as u8
+    }
+
+    /// Enables the early wakeup interrupt, raised when the counter reaches 0x40, one tick before
+    /// the reset.
+    ///
+    /// It can only be disabled by a reset.
+    pub fn listen_early_wakeup(&mut self) {
+        self.wwdg
+            .cfr
+            .modify(|r, w| unsafe { w.bits(r.bits() | CFR_EWI) });
+    }
+
+    /// Returns `true` if the early wakeup interrupt is pending
+    pub fn is_early_wakeup(&self) -> bool {
+        self.wwdg.sr.read().bits() & 1 != 0
+    }
+
+    /// Clears the early wakeup interrupt flag
+    pub fn clear_early_wakeup(&mut self) {
+        self.wwdg.sr.write(|w| unsafe { w.bits(0) });
+    }
+
+    /// Returns `true` if the last reset was caused by the window watchdog.
+    ///
+    /// The reset flags are kept until they are cleared with [`ResetReason::read_and_clear`](crate::rcc::ResetReason::read_and_clear).
+    pub fn caused_reset() -> bool {
+        ResetReason::read().contains(ResetReason::WindowWatchdog)
+    }
+}
+
+impl Watchdog for WindowWatchdog {
+    fn feed(&mut self) {
+        self.feed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn us(us: u32) -> MicroSeconds {
+        MicroSeconds::from_ticks(us)
+    }
+
+    const fn timing(wdgtb: u8, t: u8, w: u8) -> WindowTiming {
+        WindowTiming { wdgtb, t, w }
+    }
+
+    #[test]
+    fn window_timing_42mhz() {
+        let pclk1 = Hertz::MHz(42);
+        // 61.5 ticks of 97.5 us
+        let t = WindowTiming::new(pclk1, us(0), us(6_000)).unwrap();
+        assert_eq!(t, timing(0, 0x3F + 61, 0x3F + 61));
+        assert_eq!(t.tick(pclk1), us(97));
+
+        // 51.3 ticks of 780 us, the refresh window opens after 6.4 ticks
+        let t = WindowTiming::new(pclk1, us(5_000), us(40_000)).unwrap();
+        assert_eq!(t, timing(3, 0x3F + 51, 0x3F + 51 - 6));
+        assert_eq!(t.min_refresh(pclk1), us(4681));
+        assert_eq!(t.max_refresh(pclk1), us(39789));
+
+        assert_eq!(
+            WindowTiming::new(pclk1, us(0), us(60_000)),
+            Err(WindowError::Timeout)
+        );
+        assert_eq!(
+            WindowTiming::new(pclk1, us(0), us(90)),
+            Err(WindowError::Timeout)
+        );
+    }
+
+    #[test]
+    fn window_timing_90mhz() {
+        let pclk1 = Hertz::MHz(90);
+        // 63.7 ticks of 45.5 us, 65.9 don't fit
+        let t = WindowTiming::new(pclk1, us(0), us(2900)).unwrap();
+        assert_eq!(t, timing(0, 0x3F + 63, 0x3F + 63));
+        let t = WindowTiming::new(pclk1, us(0), us(3_000)).unwrap();
+        assert_eq!(t, timing(1, 0x3F + 32, 0x3F + 32));
+
+        // 54.9 ticks of 364 us
+        let t = WindowTiming::new(pclk1, us(1_000), us(20_000)).unwrap();
+        assert_eq!(t, timing(3, 0x3F + 54, 0x3F + 54 - 2));
+        assert_eq!(t.max_refresh(pclk1), us(19660));
+
+        // The whole window shares a tick
+        let t = WindowTiming::new(pclk1, us(19_800), us(20_000)).unwrap();
+        assert_eq!(t, timing(3, 0x3F + 54, 0x3F + 54 - 53));
+
+        let t = WindowTiming::new(pclk1, us(0), us(23_400)).unwrap();
+        assert_eq!(t, timing(3, 0x7F, 0x7F));
+        assert_eq!(
+            WindowTiming::new(pclk1, us(0), us(24_000)),
+            Err(WindowError::Timeout)
+        );
+        assert_eq!(
+            WindowTiming::new(pclk1, us(0), us(40)),
+            Err(WindowError::Timeout)
+        );
+        assert_eq!(
+            WindowTiming::new(pclk1, us(10_000), us(10_000)),
+            Err(WindowError::Window)
+        );
+        assert_eq!(
+            WindowTiming::new(pclk1, us(11_000), us(10_000)),
+            Err(WindowError::Window)
+        );
+    }
+}